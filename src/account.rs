@@ -0,0 +1,937 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use futures::{Stream, StreamExt as _, TryStreamExt as _};
+use kube::{
+    runtime::{
+        watcher::{self, Event},
+        WatchStreamExt as _,
+    },
+    Api, Client as KubeClient, ResourceExt as _,
+};
+use kubizone_common::{FullyQualifiedDomainName, Pattern};
+use tokio::{
+    sync::watch::{Receiver, Sender},
+    task::AbortHandle,
+};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::{
+    cloudflare::{self, CloudFlare, RetryPolicy, TokenStatus, TokenVerification, Zone, ZoneId},
+    crds::{CloudflareCredential, CloudflareCredentialSpec},
+    credentials::{self, CredentialSource, SecretRef},
+    provider::DnsProvider,
+    zone_config::{ZoneConfig, ZoneConfigs},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("credentials: {0}")]
+    Credentials(#[from] credentials::Error),
+    #[error("cloudflare: {0}")]
+    CloudFlare(#[from] cloudflare::Error),
+    #[error("api token is not usable: {0}")]
+    TokenNotUsable(TokenVerification),
+}
+
+/// Settings shared between the clients of all accounts.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub api_url: String,
+    pub records_per_page: u32,
+    pub retry_policy: RetryPolicy,
+    pub zone_refresh_interval: Duration,
+    pub zone_lookup: ZoneLookup,
+    pub token_file_refresh_interval: Duration,
+    pub token_verify_interval: Duration,
+}
+
+/// How the cloudflare zones of an account are found.
+#[derive(clap::ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoneLookup {
+    /// Periodically list every zone accessible by the account.
+    #[default]
+    List,
+    /// Look up zones by name on demand, only for the domains of Zones,
+    /// caching the results for the zone refresh interval.
+    Name,
+}
+
+/// Credentials to use for zones matching any of the given patterns,
+/// written as `pattern[,pattern...]=source`.
+///
+/// The source is either `secret:namespace/name#key` or `file:/path/to/token`.
+#[derive(Debug, Clone)]
+pub struct ZoneCredentials {
+    pub patterns: Vec<Pattern>,
+    pub source: CredentialSource,
+}
+
+impl FromStr for ZoneCredentials {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (patterns, source) = s
+            .split_once('=')
+            .ok_or_else(|| format!("zone credentials {s} must be of the form pattern=source"))?;
+
+        let patterns = patterns
+            .split(',')
+            .map(|pattern| Pattern::try_from(pattern.trim()).map_err(|err| err.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let source = if let Some(secret) = source.strip_prefix("secret:") {
+            CredentialSource::Secret(secret.parse()?)
+        } else if let Some(path) = source.strip_prefix("file:") {
+            CredentialSource::File(path.into())
+        } else {
+            return Err(format!(
+                "credential source {source} must start with either secret: or file:"
+            ));
+        };
+
+        Ok(ZoneCredentials { patterns, source })
+    }
+}
+
+/// A set of credentials, and the cloudflare zones accessible using them.
+///
+/// The background tasks keeping the account up to date are stopped when it is dropped.
+pub struct Account {
+    /// Patterns of zones this account should be used for. Empty matches all zones.
+    patterns: Vec<Pattern>,
+    source: CredentialSource,
+    cloudflare: Receiver<CloudFlare>,
+    zones: Receiver<Vec<Zone>>,
+    /// Zones created by us, until they appear in the refreshed list of zones.
+    created: RwLock<Vec<Zone>>,
+    lookup: ZoneLookup,
+    /// Results of looking up zones by name, keyed by the name looked up.
+    looked_up: RwLock<HashMap<String, LookedUp>>,
+    lookup_ttl: Duration,
+    /// Task listing the zones, unless they are looked up by name.
+    refresh: Option<AbortHandle>,
+    tasks: Vec<AbortHandle>,
+}
+
+impl Account {
+    /// Read and verify the credentials, then start the background tasks keeping
+    /// the credentials and list of zones up to date.
+    ///
+    /// Returns once the initial list of zones has been fetched, if listing them.
+    pub async fn connect(
+        kube: KubeClient,
+        source: CredentialSource,
+        patterns: Vec<Pattern>,
+        settings: &Settings,
+    ) -> Result<Self, Error> {
+        let credentials = source.read(kube.clone()).await?;
+
+        let client = CloudFlare::new(&credentials)?
+            .with_api_url(&settings.api_url)
+            .with_records_per_page(settings.records_per_page)
+            .with_retry_policy(settings.retry_policy);
+
+        if credentials.is_token() {
+            let verification = client.verify_token().await?;
+            if verification.status != TokenStatus::Active {
+                return Err(Error::TokenNotUsable(verification));
+            }
+
+            info!("using {source}: {verification}");
+        } else {
+            info!("authenticating using {source}, skipping token verification");
+        }
+
+        let (cloudflare_tx, cloudflare) = tokio::sync::watch::channel(client);
+
+        let mut tasks = Vec::new();
+        if credentials.is_token() {
+            tasks.push(
+                tokio::spawn(verify_token(
+                    cloudflare.clone(),
+                    settings.token_verify_interval,
+                ))
+                .abort_handle(),
+            );
+        }
+
+        tasks.push(
+            tokio::spawn(source.clone().watch(
+                kube,
+                cloudflare_tx,
+                credentials,
+                settings.token_file_refresh_interval,
+            ))
+            .abort_handle(),
+        );
+
+        let (zones_tx, mut zones) = tokio::sync::watch::channel(vec![]);
+        let refresh = match settings.zone_lookup {
+            ZoneLookup::List => Some(
+                tokio::spawn(refresh_zones(
+                    cloudflare.clone(),
+                    zones_tx,
+                    settings.zone_refresh_interval,
+                ))
+                .abort_handle(),
+            ),
+            ZoneLookup::Name => None,
+        };
+
+        let account = Account {
+            patterns,
+            source,
+            cloudflare,
+            zones: zones.clone(),
+            created: RwLock::default(),
+            lookup: settings.zone_lookup,
+            looked_up: RwLock::default(),
+            lookup_ttl: settings.zone_refresh_interval,
+            refresh,
+            tasks,
+        };
+
+        // Unwrap safety: the sender is owned by the refresh task, which is only
+        // aborted once the account is dropped.
+        if account.refresh.is_some() {
+            zones.changed().await.unwrap();
+        }
+
+        Ok(account)
+    }
+
+    /// Returns true if this account should be used for the given domain.
+    pub fn covers(&self, fqdn: &FullyQualifiedDomainName) -> bool {
+        self.patterns.is_empty() || self.patterns.iter().any(|pattern| pattern.matches(fqdn))
+    }
+
+    /// Current cloudflare client, authenticated with the most recent credentials.
+    pub fn cloudflare(&self) -> CloudFlare {
+        self.cloudflare.borrow().clone()
+    }
+
+    /// Where the credentials of this account are read from.
+    pub fn source(&self) -> &CredentialSource {
+        &self.source
+    }
+
+    /// Returns true if the zone list of this account is still being refreshed.
+    pub fn is_alive(&self) -> bool {
+        self.refresh
+            .as_ref()
+            .map_or(true, |refresh| !refresh.is_finished())
+    }
+
+    /// Current list of zones accessible by this account.
+    ///
+    /// When looking up zones by name, only the zones which have been looked up.
+    pub fn zones(&self) -> Vec<Zone> {
+        let mut zones = self.zones.borrow().clone();
+        zones.extend(
+            self.looked_up
+                .read()
+                .unwrap()
+                .values()
+                .filter_map(|looked_up| looked_up.zone.clone()),
+        );
+
+        let mut created = self.created.write().unwrap();
+        created.retain(|created| !zones.iter().any(|zone| zone.id == created.id));
+        zones.extend(created.iter().cloned());
+
+        zones
+    }
+
+    /// Create a zone for the domain in the cloudflare account, which is
+    /// accessible right away instead of only after the next refresh.
+    pub async fn create_zone(
+        &self,
+        account_id: &str,
+        fqdn: &FullyQualifiedDomainName,
+    ) -> Result<Zone, cloudflare::Error> {
+        let zone = self.cloudflare().create_zone(account_id, fqdn).await?;
+        self.created.write().unwrap().push(zone.clone());
+
+        Ok(zone)
+    }
+
+    /// Forget a zone we deleted, if we created or looked it up ourselves.
+    /// Zones which were listed disappear with the next refresh.
+    pub fn forget_zone(&self, zone_id: &ZoneId) {
+        self.created
+            .write()
+            .unwrap()
+            .retain(|created| &created.id != zone_id);
+        self.looked_up.write().unwrap().retain(|_, looked_up| {
+            looked_up
+                .zone
+                .as_ref()
+                .map_or(true, |zone| &zone.id != zone_id)
+        });
+    }
+
+    /// Look up the zone enclosing the fqdn by name, trying the longest names
+    /// first, unless the results of earlier lookups are still cached.
+    ///
+    /// Does nothing when listing zones, since they are all known already.
+    pub async fn lookup_zone(
+        &self,
+        fqdn: &FullyQualifiedDomainName,
+    ) -> Result<(), cloudflare::Error> {
+        if self.lookup != ZoneLookup::Name {
+            return Ok(());
+        }
+
+        for name in zone_names(fqdn) {
+            let cached = self
+                .looked_up
+                .read()
+                .unwrap()
+                .get(&name)
+                .filter(|looked_up| looked_up.at.elapsed() < self.lookup_ttl)
+                .map(|looked_up| looked_up.zone.is_some());
+
+            let found = match cached {
+                Some(found) => found,
+                None => {
+                    let zone = self.cloudflare().find_zone(&name).await?;
+                    trace!("looked up zone {name} using {self}: {zone:?}");
+
+                    let found = zone.is_some();
+                    self.looked_up.write().unwrap().insert(
+                        name,
+                        LookedUp {
+                            zone,
+                            at: Instant::now(),
+                        },
+                    );
+                    found
+                }
+            };
+
+            if found {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stream yielding every time the list of zones has been refreshed.
+    pub fn zone_refreshes(&self) -> impl Stream<Item = ()> + Send + 'static {
+        futures::stream::unfold(self.zones.clone(), |mut zones| async move {
+            zones.changed().await.ok()?;
+            Some(((), zones))
+        })
+    }
+}
+
+impl Drop for Account {
+    fn drop(&mut self) {
+        if let Some(refresh) = &self.refresh {
+            refresh.abort();
+        }
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl Display for Account {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+/// Every configured account, consulted in order of precedence.
+pub struct Accounts {
+    /// Accounts for specific zones, configured on the command line.
+    zone_accounts: Vec<Arc<Account>>,
+    /// Accounts configured through [`CloudflareCredential`] resources, which
+    /// come last, so they cannot take over zones of the other accounts.
+    dynamic_accounts: DynamicAccounts,
+    /// Defaults of zones from [`CloudflareZoneConfig`](crate::crds::CloudflareZoneConfig)
+    /// resources, along with the accounts they reference.
+    zone_configs: ZoneConfigs,
+    /// Account used for zones not covered by any other account.
+    default_account: Arc<Account>,
+}
+
+impl Accounts {
+    pub fn new(
+        zone_accounts: Vec<Account>,
+        dynamic_accounts: DynamicAccounts,
+        zone_configs: ZoneConfigs,
+        default_account: Account,
+    ) -> Self {
+        Accounts {
+            zone_accounts: zone_accounts.into_iter().map(Arc::new).collect(),
+            dynamic_accounts,
+            zone_configs,
+            default_account: Arc::new(default_account),
+        }
+    }
+
+    /// All accounts, in order of precedence.
+    pub fn all(&self) -> Vec<Arc<Account>> {
+        let dynamic_accounts = self.dynamic_accounts.read().unwrap();
+        let config_accounts = self.zone_configs.accounts().read().unwrap();
+
+        self.zone_accounts
+            .iter()
+            .chain(config_accounts.values().map(|(_, account)| account))
+            .chain(std::iter::once(&self.default_account))
+            .chain(dynamic_accounts.values().map(|(_, account)| account))
+            .cloned()
+            .collect()
+    }
+
+    /// Accounts which should be used for the given domain, in order of precedence.
+    pub fn covering(&self, fqdn: &FullyQualifiedDomainName) -> Vec<Arc<Account>> {
+        let mut accounts = self.all();
+        accounts.retain(|account| account.covers(fqdn));
+        accounts
+    }
+
+    /// Find the closest cloudflare zone enclosing the fqdn, along with a client
+    /// for the account it belongs to.
+    ///
+    /// Like cloudflare itself, the zone with the longest matching suffix wins,
+    /// so sub-zones such as `dev.example.com.` map onto `example.com.`, unless
+    /// `dev.example.com.` is a zone of its own. Ties go to the account with the
+    /// highest precedence.
+    pub fn find_zone(&self, fqdn: &FullyQualifiedDomainName) -> Option<(CloudFlare, Zone)> {
+        let mut found = None::<(Arc<Account>, Zone)>;
+
+        for account in self.covering(fqdn) {
+            let Some(zone) = enclosing_zone(account.zones(), fqdn) else {
+                continue;
+            };
+
+            if found
+                .as_ref()
+                .map_or(true, |(_, found)| zone.fqdn.len() > found.fqdn.len())
+            {
+                found = Some((account, zone));
+            }
+        }
+
+        let (account, zone) = found?;
+        trace!(
+            "{fqdn} matches zone {} ({}) using {account}",
+            zone.fqdn,
+            zone.id
+        );
+        Some((account.cloudflare(), zone))
+    }
+
+    /// Create a zone for the domain using the account with the highest
+    /// precedence for it, along with a client for that account.
+    pub async fn create_zone(
+        &self,
+        account_id: &str,
+        fqdn: &FullyQualifiedDomainName,
+    ) -> Result<(CloudFlare, Zone), cloudflare::Error> {
+        // Unwrap safety: the default account covers every domain.
+        let account = self.covering(fqdn).into_iter().next().unwrap();
+        let zone = account.create_zone(account_id, fqdn).await?;
+
+        Ok((account.cloudflare(), zone))
+    }
+
+    /// Look up the zones enclosing the fqdn in every account covering it,
+    /// see [`Account::lookup_zone`].
+    pub async fn lookup_zone(
+        &self,
+        fqdn: &FullyQualifiedDomainName,
+    ) -> Result<(), cloudflare::Error> {
+        for account in self.covering(fqdn) {
+            account.lookup_zone(fqdn).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Defaults of the zone with the fqdn, see [`ZoneConfigs::get`].
+    pub fn zone_config(&self, fqdn: &FullyQualifiedDomainName) -> ZoneConfig {
+        self.zone_configs.get(fqdn)
+    }
+
+    /// Defaults of zones from [`CloudflareZoneConfig`](crate::crds::CloudflareZoneConfig) resources.
+    pub fn zone_configs(&self) -> &ZoneConfigs {
+        &self.zone_configs
+    }
+
+    /// Forget a deleted zone in every account, see [`Account::forget_zone`].
+    pub fn forget_zone(&self, zone_id: &ZoneId) {
+        for account in self.all() {
+            account.forget_zone(zone_id);
+        }
+    }
+}
+
+/// Result of looking up a zone by name.
+struct LookedUp {
+    zone: Option<Zone>,
+    at: Instant,
+}
+
+/// Names of the zones which may enclose the fqdn, longest first, leaving out
+/// top level domains which cannot be cloudflare zones.
+fn zone_names(fqdn: &FullyQualifiedDomainName) -> Vec<String> {
+    let name = fqdn.to_string();
+    let labels = name.trim_end_matches('.').split('.').collect::<Vec<_>>();
+
+    (0..labels.len().saturating_sub(1))
+        .map(|start| labels[start..].join("."))
+        .collect()
+}
+
+/// Zone with the longest suffix of the fqdn, which is the zone itself if it exists.
+fn enclosing_zone(zones: Vec<Zone>, fqdn: &FullyQualifiedDomainName) -> Option<Zone> {
+    zones
+        .into_iter()
+        .filter(|zone| &zone.fqdn == fqdn || fqdn.is_subdomain_of(&zone.fqdn))
+        .max_by_key(|zone| zone.fqdn.len())
+}
+
+/// Accounts built from [`CloudflareCredential`] resources, keyed by `namespace/name`.
+pub type DynamicAccounts = Arc<RwLock<BTreeMap<String, (CloudflareCredentialSpec, Arc<Account>)>>>;
+
+/// Watch [`CloudflareCredential`] resources across the cluster, keeping the
+/// set of dynamic accounts in sync with them.
+pub async fn watch_credentials(kube: KubeClient, accounts: DynamicAccounts, settings: Settings) {
+    let api = Api::<CloudflareCredential>::all(kube.clone());
+    let mut events = watcher::watcher(api, watcher::Config::default())
+        .default_backoff()
+        .boxed();
+
+    // Credentials seen since the watch (re)started, used to prune deleted
+    // resources once the initial listing is done.
+    let mut seen = None;
+    let mut loader = Loader::default();
+
+    loop {
+        let credential = match events.try_next().await {
+            Ok(Some(Event::Init)) => {
+                seen = Some(BTreeSet::new());
+                continue;
+            }
+            Ok(Some(Event::InitDone)) => {
+                if let Some(seen) = seen.take() {
+                    loader.retain(|key| seen.contains(key));
+                    accounts.write().unwrap().retain(|key, _| {
+                        let keep = seen.contains(key);
+                        if !keep {
+                            info!("removing credentials from {key}, which no longer exists");
+                        }
+                        keep
+                    });
+                }
+                continue;
+            }
+            Ok(Some(Event::Delete(credential))) => {
+                let key = credential_key(&credential);
+                info!("removing credentials from {key}");
+                loader.cancel(&key);
+                accounts.write().unwrap().remove(&key);
+                continue;
+            }
+            Ok(Some(Event::Apply(credential) | Event::InitApply(credential))) => credential,
+            Ok(None) => return,
+            Err(err) => {
+                warn!("watching cloudflare credentials failed: {err}");
+                continue;
+            }
+        };
+
+        let key = credential_key(&credential);
+        if let Some(seen) = seen.as_mut() {
+            seen.insert(key.clone());
+        }
+
+        // Anyone able to create credentials in their namespace would otherwise
+        // be able to claim the zones of every other namespace.
+        if credential.spec.zones.is_empty() {
+            warn!("ignoring credentials from {key}, since they do not list any zones");
+            loader.cancel(&key);
+            accounts.write().unwrap().remove(&key);
+            continue;
+        }
+
+        let secret = SecretRef {
+            namespace: credential.namespace().unwrap_or_default(),
+            name: credential.spec.secret_ref.name.clone(),
+            key: credential.spec.secret_ref.key.clone(),
+        };
+        loader.load(&kube, &accounts, key, secret, credential.spec, &settings);
+    }
+}
+
+/// Tasks setting up dynamic accounts, keyed like [`DynamicAccounts`], so
+/// credentials which take long to connect, or never do, such as when their
+/// zones cannot be listed, do not hold up the others.
+///
+/// The tasks are aborted when the loader is dropped.
+#[derive(Default)]
+pub struct Loader {
+    tasks: HashMap<String, (CloudflareCredentialSpec, SecretRef, AbortHandle)>,
+}
+
+impl Loader {
+    /// Set up the account for the key in the background, see [`load_account`],
+    /// replacing the setup of its previous credentials if still ongoing.
+    pub fn load(
+        &mut self,
+        kube: &KubeClient,
+        accounts: &DynamicAccounts,
+        key: String,
+        secret: SecretRef,
+        spec: CloudflareCredentialSpec,
+        settings: &Settings,
+    ) {
+        if let Some((current, current_secret, task)) = self.tasks.get(&key) {
+            if !task.is_finished() && current == &spec && current_secret == &secret {
+                return;
+            }
+        }
+        self.cancel(&key);
+
+        let task = tokio::spawn({
+            let (kube, accounts, key, secret, spec, settings) = (
+                kube.clone(),
+                accounts.clone(),
+                key.clone(),
+                secret.clone(),
+                spec.clone(),
+                settings.clone(),
+            );
+            async move { load_account(&kube, &accounts, key, secret, spec, &settings).await }
+        });
+
+        self.tasks.insert(key, (spec, secret, task.abort_handle()));
+    }
+
+    /// Stop setting up the account for the key, if still ongoing.
+    pub fn cancel(&mut self, key: &str) {
+        if let Some((_, _, task)) = self.tasks.remove(key) {
+            task.abort();
+        }
+    }
+
+    /// Stop setting up the accounts whose keys do not match the predicate.
+    pub fn retain(&mut self, mut f: impl FnMut(&str) -> bool) {
+        self.tasks.retain(|key, (_, _, task)| {
+            let keep = f(key);
+            if !keep {
+                task.abort();
+            }
+            keep
+        });
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        for (_, _, task) in self.tasks.values() {
+            task.abort();
+        }
+    }
+}
+
+/// Set up the dynamic account for the key with the token in the secret,
+/// unless it already uses that secret for the same zones.
+///
+/// Accounts which cannot be set up are removed, rather than kept around
+/// with outdated credentials.
+async fn load_account(
+    kube: &KubeClient,
+    accounts: &DynamicAccounts,
+    key: String,
+    secret: SecretRef,
+    spec: CloudflareCredentialSpec,
+    settings: &Settings,
+) {
+    let source = CredentialSource::Secret(secret);
+
+    if accounts
+        .read()
+        .unwrap()
+        .get(&key)
+        .is_some_and(|(current, account)| {
+            current == &spec && account.to_string() == source.to_string()
+        })
+    {
+        return;
+    }
+
+    match Account::connect(kube.clone(), source, spec.zones.clone(), settings).await {
+        Ok(account) => {
+            info!("loaded credentials from {key}");
+            accounts
+                .write()
+                .unwrap()
+                .insert(key, (spec, Arc::new(account)));
+        }
+        Err(err) => {
+            warn!("failed to set up cloudflare account from {key}: {err}");
+            accounts.write().unwrap().remove(&key);
+        }
+    }
+}
+
+fn credential_key(credential: &CloudflareCredential) -> String {
+    format!(
+        "{}/{}",
+        credential.namespace().unwrap_or_default(),
+        credential.name_any()
+    )
+}
+
+/// Periodically fetch the list of zones from cloudflare, publishing
+/// changes on the watch channel for as long as anyone is listening.
+async fn refresh_zones<P: DnsProvider + Clone>(
+    cloudflare: Receiver<P>,
+    tx: Sender<Vec<Zone>>,
+    interval: Duration,
+) {
+    loop {
+        let client = cloudflare.borrow().clone();
+
+        match client.list_zones().await {
+            Ok(zones) => {
+                debug!(
+                    "fetched {} zones from cloudflare: {}",
+                    zones.len(),
+                    zones
+                        .iter()
+                        .map(|zone| zone.fqdn.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+
+                if tx.send(zones).is_err() {
+                    warn!("all zone list receivers dropped, stopping zone refresh");
+                    return;
+                }
+            }
+            Err(err) => {
+                error!("failed to refresh zones from cloudflare: {err}");
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Periodically verify the api token currently in use, logging its status.
+async fn verify_token(cloudflare: Receiver<CloudFlare>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let client = cloudflare.borrow().clone();
+
+        match client.verify_token().await {
+            Ok(verification) if verification.status == TokenStatus::Active => {
+                debug!("{verification}");
+            }
+            Ok(verification) => error!("cloudflare api token is not usable: {verification}"),
+            Err(err) => error!("failed to verify cloudflare api token: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn parse_zone_credentials() {
+    let credentials =
+        ZoneCredentials::from_str("example.org,*.example.org=secret:kubizone/example#token")
+            .unwrap();
+
+    assert_eq!(credentials.patterns.len(), 2);
+    assert!(matches!(credentials.source, CredentialSource::Secret(_)));
+
+    let credentials = ZoneCredentials::from_str("example.org=file:/var/run/token").unwrap();
+    assert!(matches!(credentials.source, CredentialSource::File(_)));
+
+    assert!(ZoneCredentials::from_str("example.org").is_err());
+    assert!(ZoneCredentials::from_str("example.org=token:abcd").is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn longest_suffix() {
+    let zones: Vec<Zone> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "example.com"},
+            {"id": "2", "name": "staging.example.com"},
+            {"id": "3", "name": "other-example.com"}
+        ]"#,
+    )
+    .unwrap();
+
+    let find = |fqdn: &str| {
+        let fqdn = FullyQualifiedDomainName::try_from(fqdn).unwrap();
+        enclosing_zone(zones.clone(), &fqdn).map(|zone| zone.id.to_string())
+    };
+
+    assert_eq!(find("example.com.").as_deref(), Some("1"));
+    assert_eq!(find("dev.example.com.").as_deref(), Some("1"));
+    assert_eq!(find("app.staging.example.com.").as_deref(), Some("2"));
+    assert_eq!(find("staging.example.com.").as_deref(), Some("2"));
+    assert_eq!(find("example.org.").as_deref(), None);
+}
+
+#[cfg(test)]
+#[test]
+fn lookup_names() {
+    let fqdn = FullyQualifiedDomainName::try_from("app.staging.example.com.").unwrap();
+    assert_eq!(
+        zone_names(&fqdn),
+        [
+            "app.staging.example.com",
+            "staging.example.com",
+            "example.com"
+        ]
+    );
+
+    let tld = FullyQualifiedDomainName::try_from("com.").unwrap();
+    assert!(zone_names(&tld).is_empty());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn connect_account() {
+    use axum::http::StatusCode;
+    use cloudflare::{mock::MockCloudFlare, Credentials};
+
+    let mock = MockCloudFlare::start().await;
+    mock.add_zone("example.com");
+    let settings = Settings {
+        api_url: mock.url().to_string(),
+        records_per_page: 100,
+        retry_policy: RetryPolicy {
+            max_retries: 0,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        },
+        zone_refresh_interval: Duration::from_secs(60),
+        zone_lookup: ZoneLookup::List,
+        token_file_refresh_interval: Duration::from_secs(60),
+        token_verify_interval: Duration::from_secs(60),
+    };
+    let kube =
+        KubeClient::try_from(kube::Config::new("http://127.0.0.1:9".parse().unwrap())).unwrap();
+    let source = CredentialSource::Static(Credentials::Token("token".to_string()));
+    let patterns = vec![Pattern::try_from("*.com").unwrap()];
+
+    let account = Account::connect(kube.clone(), source.clone(), patterns.clone(), &settings)
+        .await
+        .unwrap();
+    assert_eq!(account.zones().len(), 1);
+    assert!(account.covers(&FullyQualifiedDomainName::try_from("example.com.").unwrap()));
+    assert!(!account.covers(&FullyQualifiedDomainName::try_from("example.org.").unwrap()));
+
+    // Tokens which are not active are rejected.
+    mock.reject_next(
+        StatusCode::OK,
+        r#"{"success": true, "errors": [], "messages": [], "result": {"id": "token", "status": "expired"}}"#,
+    );
+    assert!(matches!(
+        Account::connect(kube.clone(), source.clone(), patterns.clone(), &settings).await,
+        Err(Error::TokenNotUsable(verification)) if verification.status == TokenStatus::Expired
+    ));
+
+    mock.fail_next(
+        StatusCode::FORBIDDEN,
+        None,
+        serde_json::json!([{"code": 10000, "message": "Authentication error"}]),
+    );
+    assert!(matches!(
+        Account::connect(kube.clone(), source, patterns.clone(), &settings).await,
+        Err(Error::CloudFlare(err)) if err.is_unauthorized()
+    ));
+
+    // Credentials which cannot be read fail before reaching cloudflare.
+    let requests = mock.requests().len();
+    let missing = CredentialSource::File("/nonexistent/token".into());
+    assert!(matches!(
+        Account::connect(kube, missing, patterns, &settings).await,
+        Err(Error::Credentials(_))
+    ));
+    assert_eq!(mock.requests().len(), requests);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn dynamic_account_precedence() {
+    use cloudflare::{mock::MockCloudFlare, Credentials};
+
+    let settings = |mock: &MockCloudFlare| Settings {
+        api_url: mock.url().to_string(),
+        records_per_page: 100,
+        retry_policy: RetryPolicy {
+            max_retries: 0,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        },
+        zone_refresh_interval: Duration::from_secs(60),
+        zone_lookup: ZoneLookup::List,
+        token_file_refresh_interval: Duration::from_secs(60),
+        token_verify_interval: Duration::from_secs(60),
+    };
+    let kube =
+        KubeClient::try_from(kube::Config::new("http://127.0.0.1:9".parse().unwrap())).unwrap();
+    let source = CredentialSource::Static(Credentials::Token("token".to_string()));
+
+    let operator = MockCloudFlare::start().await;
+    let default_zone = operator.add_zone("example.com");
+    let default_account =
+        Account::connect(kube.clone(), source.clone(), vec![], &settings(&operator))
+            .await
+            .unwrap();
+
+    // Credentials of another namespace, for a zone of the same name.
+    let tenant = MockCloudFlare::start().await;
+    tenant.add_zone("example.org");
+    let tenant_zone = tenant.add_zone("example.com");
+    let tenant_account = Account::connect(
+        kube,
+        source,
+        vec![Pattern::try_from("example.com").unwrap()],
+        &settings(&tenant),
+    )
+    .await
+    .unwrap();
+
+    let dynamic_accounts = DynamicAccounts::default();
+    dynamic_accounts.write().unwrap().insert(
+        "tenant/cloudflare".to_string(),
+        (
+            CloudflareCredentialSpec {
+                secret_ref: serde_json::from_value(
+                    serde_json::json!({"name": "cloudflare", "key": "token"}),
+                )
+                .unwrap(),
+                zones: vec![Pattern::try_from("example.com").unwrap()],
+            },
+            Arc::new(tenant_account),
+        ),
+    );
+    let accounts = Accounts::new(
+        vec![],
+        dynamic_accounts,
+        ZoneConfigs::default(),
+        default_account,
+    );
+
+    let fqdn = FullyQualifiedDomainName::try_from("www.example.com.").unwrap();
+    assert_eq!(accounts.find_zone(&fqdn).unwrap().1.id, default_zone);
+    assert_ne!(default_zone, tenant_zone);
+}
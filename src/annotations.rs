@@ -0,0 +1,218 @@
+//! Annotations on Zones adjusting how they are synced to cloudflare.
+
+use clap::ValueEnum as _;
+use kube::{api::ListParams, Api, Resource, ResourceExt as _};
+use kubizone_crds::{
+    v1alpha1::{Record, Zone},
+    PARENT_ZONE_LABEL,
+};
+use tracing::warn;
+
+use crate::{crds::GROUP, Mode};
+
+/// Parse the boolean annotation of the resource, if it has a valid one.
+pub fn bool_annotation_value<K>(resource: &K, annotation: &str) -> Option<bool>
+where
+    K: Resource<DynamicType = ()>,
+{
+    let value = resource.annotations().get(annotation)?;
+
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!(
+                "ignoring {annotation} annotation of {} {}, since {value:?} is neither true nor false",
+                K::kind(&()).to_lowercase(),
+                resource.name_any()
+            );
+            None
+        }
+    }
+}
+
+/// Boolean annotation of the resource, or `default` unless it has a valid one.
+pub fn bool_annotation<K>(resource: &K, annotation: &str, default: bool) -> bool
+where
+    K: Resource<DynamicType = ()>,
+{
+    bool_annotation_value(resource, annotation).unwrap_or(default)
+}
+
+/// Annotation setting the minimum TTL of the zone's records in cloudflare.
+pub fn min_ttl_annotation() -> String {
+    format!("{GROUP}/min-ttl")
+}
+
+/// Minimum TTL of the zone's records, as set by its annotation, or `default` otherwise.
+pub fn min_ttl(zone: &Zone, default: Option<u32>) -> Option<u32> {
+    let annotation = min_ttl_annotation();
+    let Some(value) = zone.annotations().get(&annotation) else {
+        return default;
+    };
+
+    match value.parse() {
+        Ok(min_ttl) => Some(min_ttl),
+        Err(err) => {
+            warn!(
+                "ignoring {annotation} annotation of zone {}, since {value:?} is not a valid ttl: {err}",
+                zone.name_any()
+            );
+            default
+        }
+    }
+}
+
+/// Annotation overriding `--mode` for the zone, as either `upsert` or `delete`.
+pub fn mode_annotation() -> String {
+    format!("{GROUP}/mode")
+}
+
+/// Mode of the zone, as set by its annotation, or `default` otherwise.
+pub fn mode(zone: &Zone, default: Mode) -> Mode {
+    let annotation = mode_annotation();
+    let Some(value) = zone.annotations().get(&annotation) else {
+        return default;
+    };
+
+    match Mode::from_str(value, true) {
+        Ok(mode) => mode,
+        Err(_) => {
+            warn!(
+                "ignoring {annotation} annotation of zone {}, since {value:?} is neither upsert nor delete",
+                zone.name_any()
+            );
+            default
+        }
+    }
+}
+
+/// Annotation enabling (`"true"`) or disabling (`"false"`) the deletion of
+/// managed records without an entry in the zone, regardless of its mode.
+pub fn prune_annotation() -> String {
+    format!("{GROUP}/prune")
+}
+
+/// Whether managed records without an entry are deleted from the zone, as
+/// set by its annotation, or `default` otherwise.
+pub fn prune(zone: &Zone, default: bool) -> bool {
+    bool_annotation(zone, &prune_annotation(), default)
+}
+
+/// Annotation suspending (`"true"`) all changes to the zone's records in cloudflare.
+///
+/// Paused zones are still compared with cloudflare, and report the number
+/// of pending changes in their sync stats. Deleted zones keep their finalizer
+/// until they are unpaused, so their records are only cleaned up then.
+pub fn paused_annotation() -> String {
+    format!("{GROUP}/paused")
+}
+
+/// Returns true if changes to the zone are suspended through its annotation.
+pub fn is_paused(zone: &Zone) -> bool {
+    bool_annotation(zone, &paused_annotation(), false)
+}
+
+/// Annotation enabling (`"true"`) or disabling (`"false"`) syncing the zone
+/// to cloudflare at all.
+///
+/// Zones are enabled by default, unless `--require-annotation` is set.
+pub fn enabled_annotation() -> String {
+    format!("{GROUP}/enabled")
+}
+
+/// Whether the zone is synced to cloudflare, as set by its annotation, or `default` otherwise.
+pub fn is_enabled(zone: &Zone, default: bool) -> bool {
+    bool_annotation(zone, &enabled_annotation(), default)
+}
+
+/// Annotation requesting (`"true"`) the cloudflare zone itself to be deleted
+/// once the zone is, rather than only the records managed by the controller.
+///
+/// Only honored with `--allow-zone-deletion`.
+pub fn delete_zone_annotation() -> String {
+    format!("{GROUP}/delete-zone")
+}
+
+/// Returns true if the zone requests its cloudflare zone to be deleted along with it.
+pub fn deletes_zone(zone: &Zone) -> bool {
+    bool_annotation(zone, &delete_zone_annotation(), false)
+}
+
+/// Annotation enabling (`"true"`) DNSSEC for the cloudflare zone.
+///
+/// Removing the annotation does not disable DNSSEC again, since that breaks
+/// resolution of the zone as long as its parent still has the DS record.
+pub fn dnssec_annotation() -> String {
+    format!("{GROUP}/dnssec")
+}
+
+/// Returns true if the zone requests DNSSEC to be enabled.
+pub fn enables_dnssec(zone: &Zone) -> bool {
+    bool_annotation(zone, &dnssec_annotation(), false)
+}
+
+/// Annotation acknowledging the deletion of up to the given number of records
+/// in a single reconcile, exceeding `--max-deletions-per-reconcile`.
+pub fn acknowledged_deletions_annotation() -> String {
+    format!("{GROUP}/acknowledged-deletions")
+}
+
+/// Number of deletions acknowledged through the annotation of the zone, if any.
+pub fn acknowledged_deletions(zone: &Zone) -> Option<usize> {
+    let annotation = acknowledged_deletions_annotation();
+    let value = zone.annotations().get(&annotation)?;
+
+    match value.parse() {
+        Ok(deletions) => Some(deletions),
+        Err(err) => {
+            warn!(
+                "ignoring {annotation} annotation of zone {}, since {value:?} is not a number: {err}",
+                zone.name_any()
+            );
+            None
+        }
+    }
+}
+
+/// Records belonging to the zone, whose annotations adjust how their
+/// records are synced, such as whether they are proxied.
+///
+/// Only the records visible through `apis` are listed.
+pub async fn zone_records(apis: &[Api<Record>], zone: &Zone) -> Result<Vec<Record>, kube::Error> {
+    // Records carry a label referencing their parent zone as `<name>.<namespace>`.
+    let parent = format!(
+        "{}.{}",
+        zone.name_any(),
+        zone.namespace().unwrap_or_default()
+    );
+    let params = ListParams::default().labels(&format!("{PARENT_ZONE_LABEL}={parent}"));
+
+    let mut records = Vec::new();
+    for api in apis {
+        records.extend(api.list(&params).await?.items);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+#[test]
+fn bool_annotations() {
+    let zone: Zone = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {"name": "kubi-zone", "annotations": {
+            paused_annotation(): "true",
+            prune_annotation(): "false",
+            enabled_annotation(): "yes",
+        }},
+        "spec": {"domainName": "kubi.zone.", "delegations": []}
+    }))
+    .unwrap();
+
+    assert!(is_paused(&zone));
+    assert!(!prune(&zone, true));
+    assert_eq!(bool_annotation_value(&zone, &enabled_annotation()), None);
+    assert!(is_enabled(&zone, true));
+    assert!(!deletes_zone(&zone));
+}
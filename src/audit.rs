@@ -0,0 +1,388 @@
+//! Audit log of the changes made to cloudflare, see `--audit-log`.
+//!
+//! Every change to the records of a zone, every creation and deletion of a
+//! zone, and every change to the other resources of a zone, such as its load
+//! balancers, workers, custom hostnames, settings and DNSSEC, is recorded
+//! along with whether cloudflare accepted it, so changes can be correlated
+//! with incidents after the fact.
+
+use std::{
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::Write as _,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use k8s_openapi::chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::cloudflare::{self, Desired, Record, RecordChange};
+
+/// Where the audit log is written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sink {
+    /// Log events with the `audit` target, in the configured log format.
+    Log,
+    /// JSON object per line, appended to the file.
+    File(PathBuf),
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("audit log must be `log` or the path of a file".to_string()),
+            "log" => Ok(Sink::Log),
+            path => Ok(Sink::File(PathBuf::from(path))),
+        }
+    }
+}
+
+/// Value of a record before or after a change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Value {
+    pub rdata: String,
+    pub ttl: u32,
+    pub proxied: bool,
+}
+
+impl From<&Record> for Value {
+    fn from(record: &Record) -> Self {
+        Value {
+            rdata: record.rdata.clone(),
+            ttl: record.ttl,
+            proxied: record.proxied,
+        }
+    }
+}
+
+impl From<Desired<'_>> for Value {
+    fn from(desired: Desired<'_>) -> Self {
+        Value {
+            rdata: desired.rdata.to_string(),
+            ttl: desired.ttl,
+            proxied: desired.proxied,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} with ttl {}", self.rdata, self.ttl)?;
+
+        if self.proxied {
+            f.write_str(", proxied")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A single change made to cloudflare.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    /// Time the change was made, in RFC 3339 format.
+    pub time: String,
+    /// Name of the owner in the markers written by the controller making the change.
+    pub actor: String,
+    /// Kind of change, such as `create` or `delete-zone`.
+    pub action: &'static str,
+    pub cf_zone: String,
+    pub cf_zone_id: String,
+    /// Name of the record changed, unless the zone itself was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<String>,
+    /// Name or id of the resource changed, other than a record, such as a pool
+    /// or a worker route.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+    /// Value of the record before the change, unless it was created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    /// Value of the record after the change, unless it was deleted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+    /// Error cloudflare rejected the change with, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Entry {
+    fn new(
+        actor: &str,
+        action: &'static str,
+        cloudflare_zone: &cloudflare::Zone,
+        error: Option<&cloudflare::Error>,
+    ) -> Self {
+        Entry {
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            actor: actor.to_string(),
+            action,
+            cf_zone: cloudflare_zone.fqdn.to_string(),
+            cf_zone_id: cloudflare_zone.id.to_string(),
+            record: None,
+            resource: None,
+            r#type: None,
+            old: None,
+            new: None,
+            error: error.map(ToString::to_string),
+        }
+    }
+
+    /// Entry for a change to a record of the cloudflare zone.
+    pub fn record(
+        actor: &str,
+        cloudflare_zone: &cloudflare::Zone,
+        change: &RecordChange<'_>,
+        error: Option<&cloudflare::Error>,
+    ) -> Self {
+        let (action, fqdn, r#type, old, new) = match change {
+            RecordChange::Create(desired) => {
+                ("create", desired.fqdn, desired.r#type, None, Some(*desired))
+            }
+            RecordChange::Update(record, desired) => (
+                "update",
+                desired.fqdn,
+                desired.r#type,
+                Some(*record),
+                Some(*desired),
+            ),
+            RecordChange::Adopt(record, desired) => (
+                "adopt",
+                desired.fqdn,
+                desired.r#type,
+                Some(*record),
+                Some(*desired),
+            ),
+            RecordChange::Delete(record) => {
+                ("delete", &record.fqdn, record.r#type, Some(*record), None)
+            }
+        };
+
+        Entry {
+            record: Some(fqdn.to_string()),
+            r#type: Some(r#type.to_string()),
+            old: old.map(Value::from),
+            new: new.map(Value::from),
+            ..Entry::new(actor, action, cloudflare_zone, error)
+        }
+    }
+
+    /// Entry for the creation or deletion of the cloudflare zone itself.
+    pub fn zone(
+        actor: &str,
+        action: &'static str,
+        cloudflare_zone: &cloudflare::Zone,
+        error: Option<&cloudflare::Error>,
+    ) -> Self {
+        Entry::new(actor, action, cloudflare_zone, error)
+    }
+
+    /// Entry for a change to a resource of the cloudflare zone other than its
+    /// records, such as `save-pool` or `update-setting`.
+    pub fn resource(
+        actor: &str,
+        action: &'static str,
+        cloudflare_zone: &cloudflare::Zone,
+        resource: &str,
+        error: Option<&cloudflare::Error>,
+    ) -> Self {
+        Entry {
+            resource: Some(resource.to_string()),
+            ..Entry::new(actor, action, cloudflare_zone, error)
+        }
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.actor, self.action)?;
+
+        if let (Some(r#type), Some(record)) = (&self.r#type, &self.record) {
+            write!(f, " {type} record {record} in")?;
+        }
+        if let Some(resource) = &self.resource {
+            write!(f, " {resource} in")?;
+        }
+        write!(f, " {} ({})", self.cf_zone, self.cf_zone_id)?;
+
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, ": {old} -> {new}")?,
+            (Some(value), None) | (None, Some(value)) => write!(f, ": {value}")?,
+            (None, None) => {}
+        }
+
+        match &self.error {
+            Some(error) => write!(f, ", failed: {error}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Destination of audit entries, discarding them unless a sink is configured.
+#[derive(Debug, Default)]
+pub struct Audit {
+    writer: Option<Writer>,
+}
+
+#[derive(Debug)]
+enum Writer {
+    Log,
+    File(PathBuf, Mutex<File>),
+}
+
+impl Audit {
+    /// Audit log writing to the sink, opening its file if it has one.
+    pub fn open(sink: Option<&Sink>) -> std::io::Result<Self> {
+        let writer = match sink {
+            None => None,
+            Some(Sink::Log) => Some(Writer::Log),
+            Some(Sink::File(path)) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                Some(Writer::File(path.clone(), Mutex::new(file)))
+            }
+        };
+
+        Ok(Audit { writer })
+    }
+
+    /// Record the entry in the audit log.
+    ///
+    /// Failing to write it is logged, rather than failing the change, since
+    /// cloudflare has already applied it at this point.
+    pub fn write(&self, entry: &Entry) {
+        match &self.writer {
+            None => {}
+            Some(Writer::Log) => info!(
+                target: "audit",
+                actor = entry.actor,
+                action = entry.action,
+                zone = entry.cf_zone,
+                cf_zone_id = entry.cf_zone_id,
+                record = entry.record,
+                resource = entry.resource,
+                error = entry.error,
+                "{entry}"
+            ),
+            Some(Writer::File(path, file)) => {
+                let mut line = serde_json::to_string(entry).unwrap();
+                line.push('\n');
+
+                if let Err(err) = file.lock().unwrap().write_all(line.as_bytes()) {
+                    warn!(
+                        "failed to write to audit log {}: {err}, dropping entry: {entry}",
+                        path.display()
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Entries of the changes made within a single sync of a zone, collected
+/// for its change set, see `--change-set-history`.
+#[derive(Debug, Default)]
+pub struct Journal(Mutex<Vec<Entry>>);
+
+impl Journal {
+    pub fn push(&self, entry: Entry) {
+        self.0.lock().unwrap().push(entry);
+    }
+
+    pub fn into_entries(self) -> Vec<Entry> {
+        self.0.into_inner().unwrap()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn audit_file() {
+    use kubizone_common::Type;
+
+    let zone = serde_json::from_value::<cloudflare::Zone>(serde_json::json!({
+        "id": "023e105f4ecef8ad9ca31a8372d0c353",
+        "name": "example.org"
+    }))
+    .unwrap();
+    let record = serde_json::from_value::<Record>(serde_json::json!({
+        "id": "372e67954025e0ba6aaa6d586b9e0b59",
+        "name": "www.example.org",
+        "type": "A",
+        "content": "192.0.2.1",
+        "ttl": 300,
+        "proxied": false
+    }))
+    .unwrap();
+    let desired = Desired {
+        fqdn: &record.fqdn,
+        r#type: Type::A,
+        rdata: "192.0.2.2",
+        ttl: 60,
+        proxied: true,
+    };
+
+    let update = Entry::record(
+        "kubizone",
+        &zone,
+        &RecordChange::Update(&record, desired),
+        None,
+    );
+    assert_eq!(
+        update.to_string(),
+        "kubizone update A record www.example.org. in example.org. (023e105f4ecef8ad9ca31a8372d0c353): \
+         192.0.2.1 with ttl 300 -> 192.0.2.2 with ttl 60, proxied"
+    );
+
+    let path = std::env::temp_dir().join(format!("audit-{:08x}.jsonl", rand::random::<u32>()));
+    let audit = Audit::open(Some(&"log".parse::<Sink>().unwrap())).unwrap();
+    audit.write(&update);
+
+    let audit = Audit::open(Some(&Sink::File(path.clone()))).unwrap();
+    audit.write(&update);
+    let pool = Entry::resource(
+        "kubizone",
+        "save-pool",
+        &zone,
+        "www-example-org-primary",
+        None,
+    );
+    assert_eq!(
+        pool.to_string(),
+        "kubizone save-pool www-example-org-primary in example.org. (023e105f4ecef8ad9ca31a8372d0c353)"
+    );
+    audit.write(&pool);
+    audit.write(&Entry::record(
+        "kubizone",
+        &zone,
+        &RecordChange::Delete(&record),
+        Some(&cloudflare::Error::RateLimited(
+            std::time::Duration::from_secs(1),
+        )),
+    ));
+
+    let lines = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let entries = lines
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0]["action"], "update");
+    assert_eq!(entries[0]["old"]["rdata"], "192.0.2.1");
+    assert_eq!(entries[0]["new"]["proxied"], true);
+    assert!(entries[0].get("resource").is_none());
+    assert_eq!(entries[1]["action"], "save-pool");
+    assert_eq!(entries[1]["resource"], "www-example-org-primary");
+    assert!(entries[1].get("record").is_none());
+    assert_eq!(entries[2]["action"], "delete");
+    assert_eq!(entries[2]["record"], "www.example.org.");
+    assert!(entries[2].get("new").is_none());
+    assert!(entries[2]["error"].is_string());
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::cloudflare::{Applied, Record, ZoneId};
+
+/// Records of cloudflare zones, as last listed and since modified by us.
+///
+/// Zones are listed again once their records have been cached for longer
+/// than the ttl, or they were invalidated, such as after a conflict.
+#[derive(Debug)]
+pub struct RecordCache {
+    ttl: Duration,
+    zones: Mutex<HashMap<ZoneId, Cached>>,
+}
+
+#[derive(Debug)]
+struct Cached {
+    records: Vec<Record>,
+    listed: Instant,
+}
+
+impl RecordCache {
+    /// Cache keeping records for `ttl`. A ttl of zero disables the cache.
+    pub fn new(ttl: Duration) -> Self {
+        RecordCache {
+            ttl,
+            zones: Mutex::default(),
+        }
+    }
+
+    /// Cached records of the zone, unless they have expired.
+    pub fn get(&self, zone_id: &ZoneId) -> Option<Vec<Record>> {
+        let zones = self.zones.lock().unwrap();
+        let cached = zones.get(zone_id)?;
+
+        (cached.listed.elapsed() < self.ttl).then(|| cached.records.clone())
+    }
+
+    /// Cache the freshly listed records of the zone.
+    pub fn insert(&self, zone_id: &ZoneId, records: &[Record]) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        self.zones.lock().unwrap().insert(
+            zone_id.clone(),
+            Cached {
+                records: records.to_vec(),
+                listed: Instant::now(),
+            },
+        );
+    }
+
+    /// Update the cached records of the zone with changes we applied.
+    ///
+    /// The zone is invalidated if the outcome of any change is unknown.
+    pub fn apply(&self, zone_id: &ZoneId, applied: Vec<Applied>) {
+        let mut zones = self.zones.lock().unwrap();
+        let Some(cached) = zones.get_mut(zone_id) else {
+            return;
+        };
+
+        for applied in applied {
+            match applied {
+                Applied::Written(record) => {
+                    cached.records.retain(|cached| cached.id != record.id);
+                    cached.records.push(record);
+                }
+                Applied::Deleted(id) => cached.records.retain(|cached| cached.id != id),
+                Applied::Unknown => {
+                    zones.remove(zone_id);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Forget the records of the zone, so they are listed again next time.
+    pub fn invalidate(&self, zone_id: &ZoneId) {
+        self.zones.lock().unwrap().remove(zone_id);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn write_through() {
+    let records: Vec<Record> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300},
+            {"id": "2", "name": "old.kubi.zone", "type": "A", "content": "127.0.0.2", "ttl": 300}
+        ]"#,
+    )
+    .unwrap();
+    let zone_id: ZoneId = serde_json::from_str(r#""zone""#).unwrap();
+
+    let cache = RecordCache::new(Duration::from_secs(60));
+    assert!(cache.get(&zone_id).is_none());
+
+    cache.insert(&zone_id, &records);
+
+    let updated: Record = serde_json::from_str(
+        r#"{"id": "1", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.3", "ttl": 300}"#,
+    )
+    .unwrap();
+    cache.apply(
+        &zone_id,
+        vec![
+            Applied::Written(updated),
+            Applied::Deleted(records[1].id.clone()),
+        ],
+    );
+
+    let cached = cache.get(&zone_id).unwrap();
+    assert_eq!(cached.len(), 1);
+    assert_eq!(cached[0].rdata, "127.0.0.3");
+
+    cache.apply(&zone_id, vec![Applied::Unknown]);
+    assert!(cache.get(&zone_id).is_none());
+
+    let disabled = RecordCache::new(Duration::ZERO);
+    disabled.insert(&zone_id, &records);
+    assert!(disabled.get(&zone_id).is_none());
+}
@@ -0,0 +1,221 @@
+//! History of the changes made to the cloudflare zones of Zones, persisted as
+//! [`CloudflareChangeSet`] resources, see `--change-set-history`.
+
+use std::collections::BTreeMap;
+
+use kube::{
+    api::{DeleteParams, ListParams, PostParams},
+    Api, Client as KubeClient, Resource as _, ResourceExt,
+};
+use kubizone_crds::v1alpha1::Zone;
+
+use crate::{
+    audit::Entry,
+    crds::{CloudflareChange, CloudflareChangeSet, CloudflareChangeSetSpec, GROUP},
+};
+
+/// Label referencing the uid of the Zone a change set was recorded for, so
+/// the change sets of a zone can be listed without those of every other zone.
+pub fn zone_label() -> String {
+    format!("{GROUP}/zone-uid")
+}
+
+/// Change set of the entries written for the zone within a single sync.
+///
+/// Returns None if no changes were made.
+pub fn change_set(zone: &Zone, entries: &[Entry]) -> Option<CloudflareChangeSet> {
+    let first = entries.first()?;
+
+    let changes = entries
+        .iter()
+        .filter_map(|entry| {
+            Some(CloudflareChange {
+                time: entry.time.clone(),
+                action: entry.action.to_string(),
+                record: entry.record.clone()?,
+                type_: entry.r#type.clone()?,
+                old: entry.old.as_ref().map(ToString::to_string),
+                new: entry.new.as_ref().map(ToString::to_string),
+                error: entry.error.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut change_set = CloudflareChangeSet::new(
+        "",
+        CloudflareChangeSetSpec {
+            zone: zone.name_any(),
+            cf_zone: first.cf_zone.clone(),
+            cf_zone_id: first.cf_zone_id.clone(),
+            actor: first.actor.clone(),
+            failed: changes
+                .iter()
+                .filter(|change| change.error.is_some())
+                .count() as u32,
+            changes,
+        },
+    );
+
+    change_set.metadata.name = None;
+    change_set.metadata.generate_name = Some(format!("{}-", zone.name_any()));
+    change_set.metadata.namespace = zone.namespace();
+    change_set.metadata.labels = zone.uid().map(|uid| BTreeMap::from([(zone_label(), uid)]));
+    change_set.metadata.owner_references = zone.owner_ref(&()).map(|owner| vec![owner]);
+
+    Some(change_set)
+}
+
+/// Names of the change sets of the zone beyond the `history` most recent ones.
+fn expired(change_sets: &[CloudflareChangeSet], zone: &str, history: usize) -> Vec<String> {
+    let mut change_sets = change_sets
+        .iter()
+        .filter(|change_set| change_set.spec.zone == zone)
+        .collect::<Vec<_>>();
+
+    // Newest first, ordering change sets created within the same second by
+    // the time of their last change.
+    change_sets.sort_by(|a, b| {
+        let key = |change_set: &CloudflareChangeSet| {
+            (
+                change_set.creation_timestamp(),
+                change_set
+                    .spec
+                    .changes
+                    .last()
+                    .map(|change| change.time.clone()),
+            )
+        };
+        key(b).cmp(&key(a))
+    });
+
+    change_sets
+        .into_iter()
+        .skip(history)
+        .map(ResourceExt::name_any)
+        .collect()
+}
+
+/// Persist the entries written for the zone within a single sync as a change
+/// set, and delete its change sets beyond the `history` most recent ones.
+pub async fn record(
+    kube: &KubeClient,
+    zone: &Zone,
+    entries: &[Entry],
+    history: usize,
+) -> Result<(), kube::Error> {
+    let Some(change_set) = change_set(zone, entries) else {
+        return Ok(());
+    };
+
+    let api =
+        Api::<CloudflareChangeSet>::namespaced(kube.clone(), &zone.namespace().unwrap_or_default());
+    api.create(&PostParams::default(), &change_set).await?;
+
+    let params = ListParams::default().labels(&format!(
+        "{}={}",
+        zone_label(),
+        zone.uid().unwrap_or_default()
+    ));
+    let change_sets = api.list(&params).await?;
+    for name in expired(&change_sets.items, &zone.name_any(), history) {
+        match api.delete(&name, &DeleteParams::default()).await {
+            Ok(_) => {}
+            // Already deleted, such as by another replica.
+            Err(kube::Error::Api(response)) if response.code == 404 => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn change_set_history() {
+    use crate::{
+        audit::Value,
+        cloudflare::{self, RecordChange},
+    };
+
+    let zone: Zone = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {"name": "kubi-zone", "namespace": "dns", "uid": "6f0ff9a4-0f2c-4cbd-a3bb-7c9e1c8ad0b2"},
+        "spec": {"domainName": "kubi.zone.", "delegations": []}
+    }))
+    .unwrap();
+    let cloudflare_zone = serde_json::from_value::<cloudflare::Zone>(serde_json::json!({
+        "id": "023e105f4ecef8ad9ca31a8372d0c353",
+        "name": "kubi.zone"
+    }))
+    .unwrap();
+    let record = serde_json::from_value::<cloudflare::Record>(serde_json::json!({
+        "id": "372e67954025e0ba6aaa6d586b9e0b59",
+        "name": "www.kubi.zone",
+        "type": "A",
+        "content": "192.0.2.1",
+        "ttl": 300,
+        "proxied": false
+    }))
+    .unwrap();
+
+    assert!(change_set(&zone, &[]).is_none());
+
+    let entries = [
+        Entry::record(
+            "kubizone",
+            &cloudflare_zone,
+            &RecordChange::Delete(&record),
+            None,
+        ),
+        Entry::record(
+            "kubizone",
+            &cloudflare_zone,
+            &RecordChange::Delete(&record),
+            Some(&cloudflare::Error::RateLimited(
+                std::time::Duration::from_secs(1),
+            )),
+        ),
+    ];
+    let change_set = change_set(&zone, &entries).unwrap();
+
+    assert_eq!(
+        change_set.metadata.generate_name.as_deref(),
+        Some("kubi-zone-")
+    );
+    assert_eq!(change_set.metadata.namespace.as_deref(), Some("dns"));
+    assert_eq!(
+        change_set.labels().get(&zone_label()).map(String::as_str),
+        Some("6f0ff9a4-0f2c-4cbd-a3bb-7c9e1c8ad0b2")
+    );
+    assert_eq!(
+        change_set.metadata.owner_references.as_ref().unwrap()[0].name,
+        "kubi-zone"
+    );
+    assert_eq!(change_set.spec.failed, 1);
+    assert_eq!(
+        change_set.spec.changes[0].old,
+        Some(Value::from(&record).to_string())
+    );
+
+    let existing = (0..5)
+        .map(|n| {
+            let mut existing = change_set.clone();
+            existing.metadata.name = Some(format!("kubi-zone-{n}"));
+            existing.spec.changes[1].time = format!("2026-10-14T12:00:0{n}.000Z");
+            existing
+        })
+        .chain([{
+            let mut other = change_set.clone();
+            other.metadata.name = Some("other-0".to_string());
+            other.spec.zone = "other".to_string();
+            other
+        }])
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        expired(&existing, "kubi-zone", 3),
+        vec!["kubi-zone-1".to_string(), "kubi-zone-0".to_string()]
+    );
+    assert!(expired(&existing, "other", 3).is_empty());
+}
@@ -1,143 +1,1399 @@
-use kubizone_crds::{kubizone_common::Type, v1alpha1::ZoneEntry};
+use std::{fmt::Display, time::Duration};
+
+use kubizone_common::{FullyQualifiedDomainName, RecordIdent, Type};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION},
-    Client, IntoUrl, Method,
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, RETRY_AFTER},
+    Client, IntoUrl, Method, Response, StatusCode, Url,
+};
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
 };
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use tracing::error;
+use tracing::{error, info, trace, warn};
 
+use crate::ident;
+
+pub mod content;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 pub mod models;
 
+use content::Content;
 pub use models::*;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("reqwest: {0}")]
     Reqwest(#[from] reqwest::Error),
-    #[error("api: {0}")]
-    Api(#[from] ApiError),
+    #[error("api: {0} ({1})")]
+    Api(ApiErrors, Request),
+    #[error("record already exists: {0} ({1})")]
+    RecordExists(ApiErrors, Request),
+    #[error("identical record already exists: {0} ({1})")]
+    DuplicateRecord(ApiErrors, Request),
+    #[error("invalid zone: {0} ({1})")]
+    InvalidZone(ApiErrors, Request),
+    #[error("authentication failed: {0} ({1})")]
+    Unauthorized(ApiErrors, Request),
+    #[error("token expired: {0} ({1})")]
+    TokenExpired(ApiErrors, Request),
     #[error("deserialization: {0}")]
     Deserialization(#[from] serde_json::Error),
+    #[error("rate limited, retry after {}s", .0.as_secs())]
+    RateLimited(Duration),
+    #[error("{0}")]
+    InvalidRdata(#[from] content::Error),
+    /// Credentials which cannot be sent to cloudflare at all.
+    #[error("invalid credentials: {0}")]
+    InvalidCredentials(String),
+    /// Response which is not an api result at all, such as an html error page.
+    #[error("{method} {url} responded with {status}: {body}")]
+    Status {
+        method: Method,
+        /// Url of the request, without its query.
+        url: String,
+        status: StatusCode,
+        /// Beginning of the response body.
+        body: String,
+    },
+}
+
+/// Error codes with dedicated [`Error`] variants, in the order they take precedence
+/// when cloudflare responds with more than one of them.
+const TOKEN_EXPIRED_CODE: u32 = 9109;
+const UNAUTHORIZED_CODE: u32 = 10000;
+const INVALID_ZONE_CODE: u32 = 1003;
+const RECORD_EXISTS_CODE: u32 = 81057;
+const DUPLICATE_RECORD_CODE: u32 = 81058;
+
+/// Request which cloudflare rejected with api errors.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    /// Url of the request, without its query.
+    pub url: String,
+    pub status: StatusCode,
+}
+
+impl Display for Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} responded with {}",
+            self.method, self.url, self.status
+        )
+    }
+}
+
+impl Error {
+    /// Error for the api errors cloudflare rejected the request with.
+    pub fn api(errors: ApiErrors, request: Request) -> Self {
+        let variant = if errors.has_code(&[TOKEN_EXPIRED_CODE]) {
+            Error::TokenExpired
+        } else if errors.has_code(&[UNAUTHORIZED_CODE]) {
+            Error::Unauthorized
+        } else if errors.has_code(&[INVALID_ZONE_CODE]) {
+            Error::InvalidZone
+        } else if errors.has_code(&[RECORD_EXISTS_CODE]) {
+            Error::RecordExists
+        } else if errors.has_code(&[DUPLICATE_RECORD_CODE]) {
+            Error::DuplicateRecord
+        } else {
+            Error::Api
+        };
+
+        variant(errors, request)
+    }
+
+    /// Returns true if the credentials are not permitted to make the request,
+    /// in which case retrying it, or any other request, is pointless.
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(self, Error::Unauthorized(..) | Error::TokenExpired(..))
+    }
+
+    /// Returns true if a record created by the request already exists.
+    pub fn is_record_exists(&self) -> bool {
+        matches!(self, Error::RecordExists(..) | Error::DuplicateRecord(..))
+    }
+
+    /// Returns true if the request failed because the zone does not permit tags.
+    pub fn is_tags_rejected(&self) -> bool {
+        matches!(self, Error::Api(errors, _) if errors.iter().any(ApiError::is_tags_rejected))
+    }
+
+    /// Time cloudflare asked us to wait before making further requests, if rate limited.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::RateLimited(retry_after) => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the request failed for reasons likely to resolve
+    /// themselves shortly, such as network failures or server errors.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Reqwest(err) => {
+                err.is_connect()
+                    || err.is_timeout()
+                    || err.status().is_some_and(|status| status.is_server_error())
+            }
+            Error::Status { status, .. } => status.is_server_error(),
+            _ => self
+                .request()
+                .is_some_and(|request| request.status.is_server_error()),
+        }
+    }
+
+    /// Request cloudflare rejected with api errors, if it did.
+    pub fn request(&self) -> Option<&Request> {
+        match self {
+            Error::Api(_, request)
+            | Error::RecordExists(_, request)
+            | Error::DuplicateRecord(_, request)
+            | Error::InvalidZone(_, request)
+            | Error::Unauthorized(_, request)
+            | Error::TokenExpired(_, request) => Some(request),
+            _ => None,
+        }
+    }
+
+    /// HTTP status of the response the request failed with, if it got one.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Reqwest(err) => err.status(),
+            Error::RateLimited(_) => Some(StatusCode::TOO_MANY_REQUESTS),
+            Error::Status { status, .. } => Some(*status),
+            Error::Api(_, request)
+            | Error::RecordExists(_, request)
+            | Error::DuplicateRecord(_, request)
+            | Error::InvalidZone(_, request)
+            | Error::Unauthorized(_, request)
+            | Error::TokenExpired(_, request) => Some(request.status),
+            Error::Deserialization(_) | Error::InvalidRdata(_) | Error::InvalidCredentials(_) => {
+                None
+            }
+        }
+    }
+}
+
+/// Number of characters of a response body included in [`Error::Status`].
+const BODY_SNIPPET_LENGTH: usize = 256;
+
+/// Maximum page size permitted by the zone listing endpoint.
+const ZONES_PER_PAGE: u32 = 50;
+
+/// Number of times a rate limited request is retried before giving up.
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
+/// Upper bound on how long we're willing to sleep before retrying a rate limited
+/// request, regardless of what the Retry-After header says.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Wait time used when a rate limited response does not carry a Retry-After header.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Default page size of the dns record listing endpoint.
+pub const DEFAULT_RECORDS_PER_PAGE: u32 = 100;
+
+/// Base url of the cloudflare api.
+pub const API_URL: &str = "https://api.cloudflare.com/client/v4";
+
+/// Governs how idempotent requests are retried when cloudflare responds
+/// with a server error, or the request fails to connect or times out.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Number of retries after the initial attempt. Zero disables retries.
+    pub max_retries: u32,
+    /// Delay before the first retry. Doubled for every following retry.
+    pub base_delay: Duration,
+    /// Upper bound on the delay between two attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff for the given retry (starting at 1), with
+    /// jitter applied to the upper half of the computed delay.
+    fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay);
+
+        delay / 2 + delay.mul_f64(rand::random::<f64>() / 2.0)
+    }
+}
+
+/// Maximum number of changes submitted in a single batch request.
+pub const MAX_BATCH_SIZE: usize = 200;
+
+/// TTL value cloudflare uses to indicate automatic TTL, which proxied records always use.
+pub const AUTOMATIC_TTL: u32 = 1;
+
+/// Lowest TTL cloudflare accepts, other than [`AUTOMATIC_TTL`].
+pub const MIN_TTL: u32 = 60;
+
+/// Highest TTL cloudflare accepts.
+pub const MAX_TTL: u32 = 86400;
+
+/// Returns true if cloudflare accepts records of the type.
+pub fn is_supported(type_: Type) -> bool {
+    matches!(
+        type_,
+        Type::A
+            | Type::AAAA
+            | Type::CAA
+            | Type::CERT
+            | Type::CNAME
+            | Type::DNSKEY
+            | Type::DS
+            | Type::HTTPS
+            | Type::LOC
+            | Type::MX
+            | Type::NAPTR
+            | Type::NS
+            | Type::OPENPGPKEY
+            | Type::PTR
+            | Type::SMIMEA
+            | Type::SRV
+            | Type::SSHFP
+            | Type::SVCB
+            | Type::TLSA
+            | Type::TXT
+            | Type::URI
+    )
+}
+
+/// TTL of a record in cloudflare, for an entry with the given `ttl`.
+///
+/// The ttl is clamped to the range accepted by cloudflare, unless the record
+/// is proxied, in which case it is always automatic.
+pub fn ttl(ttl: u32, proxied: bool) -> u32 {
+    if proxied {
+        AUTOMATIC_TTL
+    } else {
+        ttl.clamp(MIN_TTL, MAX_TTL)
+    }
+}
+
+/// Record as it should exist in cloudflare.
+#[derive(Debug, Clone, Copy)]
+pub struct Desired<'a> {
+    pub fqdn: &'a FullyQualifiedDomainName,
+    pub r#type: Type,
+    /// Rdata of the record in zone file syntax, such as `10 mail.example.org.` for MX records.
+    pub rdata: &'a str,
+    /// TTL of the record, which may differ from the entry's, see [`ttl`].
+    pub ttl: u32,
+    /// Proxy traffic for the record through cloudflare.
+    pub proxied: bool,
+}
+
+impl Desired<'_> {
+    /// Identity of the record, matching that of the [`Record`] it becomes.
+    pub fn ident(&self) -> RecordIdent {
+        ident::ident(self.fqdn, self.r#type, self.rdata)
+    }
+}
+
+/// A single modification of a zone's dns records.
+#[derive(Debug, Clone, Copy)]
+pub enum RecordChange<'a> {
+    Create(Desired<'a>),
+    Update(&'a Record, Desired<'a>),
+    /// Update an existing record, and mark it as managed by us.
+    Adopt(&'a Record, Desired<'a>),
+    Delete(&'a Record),
+}
+
+/// Outcome of a single change to a zone's records, as reported by cloudflare.
+#[derive(Debug)]
+pub enum Applied {
+    Written(Record),
+    Deleted(RecordId),
+    /// A record was written, but cloudflare's response could not be parsed.
+    Unknown,
+}
+
+/// Filter applied by cloudflare when listing records, see [`CloudFlare::find_records`].
+#[derive(Debug, Default, Clone)]
+pub struct RecordFilter {
+    name: Option<FullyQualifiedDomainName>,
+    r#type: Option<Type>,
+    comment: Option<String>,
+}
+
+impl RecordFilter {
+    /// Only records with exactly this name.
+    pub fn with_name(mut self, fqdn: &FullyQualifiedDomainName) -> Self {
+        self.name = Some(fqdn.clone());
+        self
+    }
+
+    /// Only records of this type.
+    pub fn with_type(mut self, r#type: Type) -> Self {
+        self.r#type = Some(r#type);
+        self
+    }
+
+    /// Only records with comments starting with this prefix.
+    pub fn with_comment_prefix(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = Vec::new();
+
+        if let Some(name) = &self.name {
+            query.push(("name", name.to_string().trim_end_matches('.').to_string()));
+        }
+        if let Some(r#type) = self.r#type {
+            query.push(("type", r#type.to_string()));
+        }
+        if let Some(comment) = &self.comment {
+            query.push(("comment.startswith", comment.clone()));
+        }
+
+        query
+    }
+
+    /// Returns true if cloudflare would include the record when listing with this filter.
+    pub fn matches(&self, record: &Record) -> bool {
+        self.name.as_ref().map_or(true, |name| &record.fqdn == name)
+            && self.r#type.map_or(true, |r#type| record.r#type == r#type)
+            && self.comment.as_ref().map_or(true, |comment| {
+                record
+                    .comment
+                    .as_ref()
+                    .is_some_and(|record| record.starts_with(comment))
+            })
+    }
+}
+
+/// How records created or adopted by us are marked as managed by us.
+#[derive(Debug, Clone, Copy)]
+pub enum Marker<'a> {
+    /// `managed-by:<name>` as the comment of the record, followed by a
+    /// description for humans, if any.
+    Comment {
+        owner: &'a str,
+        description: Option<&'a str>,
+    },
+    /// `managed-by:<name>` as one of the tags of the record.
+    Tag(&'a str),
+    /// Records are left unmarked, since ownership is tracked elsewhere.
+    Unmarked,
+}
+
+impl Marker<'_> {
+    /// Comment of the records marked this way, if it is marked through comments.
+    pub fn comment(&self) -> Option<String> {
+        match self {
+            Marker::Comment { owner, description } => Some(owner_comment(owner, *description)),
+            Marker::Tag(_) | Marker::Unmarked => None,
+        }
+    }
+
+    /// Tags of the records marked this way, if it is marked through tags.
+    pub fn tags(&self) -> Vec<String> {
+        match self {
+            Marker::Tag(name) => vec![owner_marker(name)],
+            Marker::Comment { .. } | Marker::Unmarked => vec![],
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CreateRecord<'a> {
+    #[serde(flatten)]
+    pub content: Content,
+    pub name: String,
+    pub proxied: bool,
+    pub r#type: Type,
+    pub ttl: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub id: &'a str,
+    pub tags: Vec<String>,
+    pub zone_id: &'a ZoneId,
+}
+
+impl<'a> CreateRecord<'a> {
+    pub fn new(
+        zone_id: &'a ZoneId,
+        marker: Marker<'_>,
+        desired: Desired<'_>,
+    ) -> Result<Self, content::Error> {
+        Ok(CreateRecord {
+            content: Content::new(desired.r#type, desired.rdata)?,
+            name: desired.fqdn.to_string(),
+            proxied: desired.proxied,
+            r#type: desired.r#type,
+            ttl: desired.ttl,
+            comment: marker.comment(),
+            id: "",
+            tags: marker.tags(),
+            zone_id,
+        })
+    }
+}
+
+/// Complete record, replacing every field of an existing record.
+#[derive(Serialize)]
+struct UpdateRecord {
+    #[serde(flatten)]
+    pub content: Content,
+    pub name: String,
+    pub proxied: bool,
+    pub r#type: Type,
+    pub ttl: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl UpdateRecord {
+    /// Update bringing the `record` in line with the `desired` record.
+    ///
+    /// The comment and tags of the record are carried over, with the `marker`
+    /// added or restored, so the record never loses its ownership marker.
+    /// Marking the record through tags replaces the tags of any other owner.
+    pub fn new(
+        marker: Marker<'_>,
+        record: &Record,
+        desired: Desired<'_>,
+    ) -> Result<Self, content::Error> {
+        let mut tags = record.tags.clone();
+        if matches!(marker, Marker::Tag(_)) {
+            tags.retain(|tag| marked_owner(tag).is_none());
+        }
+        for tag in marker.tags() {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        Ok(UpdateRecord {
+            content: Content::new(desired.r#type, desired.rdata)?,
+            name: desired.fqdn.to_string(),
+            proxied: desired.proxied,
+            r#type: desired.r#type,
+            ttl: desired.ttl,
+            comment: marker.comment().or_else(|| record.comment.clone()),
+            tags,
+        })
+    }
+}
+
+/// Means of authenticating against the cloudflare API.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// Scoped API token, sent as a Bearer token.
+    Token(String),
+    /// Legacy Global API Key, which must be accompanied by the account's email address.
+    GlobalKey { email: String, key: String },
+}
+
+impl Credentials {
+    /// Returns true if these credentials are a scoped API token.
+    pub fn is_token(&self) -> bool {
+        matches!(self, Credentials::Token(_))
+    }
+
+    /// Headers authenticating requests, failing for empty credentials and
+    /// those containing characters which cannot be sent in a header.
+    fn headers(&self) -> Result<HeaderMap, Error> {
+        // The values are secret, so the errors only name them.
+        let header = |name: &str, value: &str, sent: String| {
+            if value.is_empty() {
+                return Err(Error::InvalidCredentials(format!("{name} is empty")));
+            }
+
+            let mut header = HeaderValue::from_str(&sent).map_err(|_| {
+                Error::InvalidCredentials(format!("{name} is not a valid header value"))
+            })?;
+            header.set_sensitive(true);
+            Ok(header)
+        };
+
+        Ok(match self {
+            Credentials::Token(token) => HeaderMap::from_iter([(
+                AUTHORIZATION,
+                header("api token", token, format!("Bearer {token}"))?,
+            )]),
+            Credentials::GlobalKey { email, key } => HeaderMap::from_iter([
+                (
+                    HeaderName::from_static("x-auth-email"),
+                    header("email", email, email.clone())?,
+                ),
+                (
+                    HeaderName::from_static("x-auth-key"),
+                    header("api key", key, key.clone())?,
+                ),
+            ]),
+        })
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::Token(_) => f.write_str("Token(<redacted>)"),
+            Credentials::GlobalKey { email, .. } => f
+                .debug_struct("GlobalKey")
+                .field("email", email)
+                .finish_non_exhaustive(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct CloudFlare {
     client: Client,
+    /// Base url of the api, such as [`API_URL`], without a trailing slash.
+    api_url: String,
+    records_per_page: u32,
+    retry_policy: RetryPolicy,
 }
 
 impl CloudFlare {
-    pub fn new(token: &str) -> Self {
-        let client = Client::builder()
-            .default_headers(HeaderMap::from_iter([(
-                AUTHORIZATION,
-                HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
-            )]))
-            .build()
-            .unwrap();
+    /// Client authenticating with the credentials, which fails if they
+    /// cannot be sent to cloudflare, see [`Error::InvalidCredentials`].
+    pub fn new(credentials: &Credentials) -> Result<Self, Error> {
+        Ok(CloudFlare {
+            client: Self::client(credentials)?,
+            api_url: API_URL.to_string(),
+            records_per_page: DEFAULT_RECORDS_PER_PAGE,
+            retry_policy: RetryPolicy::default(),
+        })
+    }
 
-        CloudFlare { client }
+    /// Send requests to the api at a different base url, such as a proxy in
+    /// front of cloudflare, rather than [`API_URL`].
+    pub fn with_api_url(mut self, api_url: &str) -> Self {
+        self.api_url = api_url.trim_end_matches('/').to_string();
+        self
     }
 
-    async fn request<I, O>(&self, method: Method, url: impl IntoUrl, data: I) -> Result<O, Error>
+    fn client(credentials: &Credentials) -> Result<Client, Error> {
+        Ok(Client::builder()
+            .default_headers(credentials.headers()?)
+            .build()?)
+    }
+
+    /// Produce a copy of this client, authenticating with different credentials.
+    pub fn with_credentials(&self, credentials: &Credentials) -> Result<Self, Error> {
+        Ok(CloudFlare {
+            client: Self::client(credentials)?,
+            ..self.clone()
+        })
+    }
+
+    /// Set the policy used for retrying idempotent requests on transient failures.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set the number of records fetched per request when listing dns records.
+    pub fn with_records_per_page(mut self, records_per_page: u32) -> Self {
+        self.records_per_page = records_per_page;
+        self
+    }
+
+    /// Send the request, retrying it if it is rate limited, or if it is
+    /// idempotent and fails due to server errors or timeouts.
+    async fn send<I>(
+        &self,
+        method: Method,
+        url: impl IntoUrl,
+        query: &[(&str, String)],
+        data: I,
+    ) -> Result<Response, Error>
+    where
+        I: Serialize,
+    {
+        let url = url.into_url()?;
+
+        let retryable = method.is_idempotent();
+
+        let mut rate_limited = 0;
+        let mut retries = 0;
+        let response = loop {
+            let result = self
+                .client
+                .request(method.clone(), url.clone())
+                .query(query)
+                .json(&data)
+                .send()
+                .await;
+
+            let reason = match result {
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let retry_after = retry_after(&response);
+                    if rate_limited >= MAX_RATE_LIMIT_RETRIES {
+                        return Err(Error::RateLimited(retry_after));
+                    }
+
+                    rate_limited += 1;
+                    warn!(
+                        "rate limited by cloudflare on {method} {}, retrying in {}s (attempt {rate_limited}/{MAX_RATE_LIMIT_RETRIES})",
+                        url.path(),
+                        retry_after.as_secs()
+                    );
+                    tokio::time::sleep(retry_after).await;
+                    continue;
+                }
+                Ok(response)
+                    if retryable
+                        && response.status().is_server_error()
+                        && retries < self.retry_policy.max_retries =>
+                {
+                    response.status().to_string()
+                }
+                Ok(response) => break response,
+                Err(err)
+                    if retryable
+                        && (err.is_connect() || err.is_timeout())
+                        && retries < self.retry_policy.max_retries =>
+                {
+                    err.to_string()
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            retries += 1;
+            let delay = self.retry_policy.backoff(retries);
+            warn!(
+                "transient failure on {method} {}: {reason}, retrying in {}ms (retry {retries}/{})",
+                url.path(),
+                delay.as_millis(),
+                self.retry_policy.max_retries
+            );
+            tokio::time::sleep(delay).await;
+        };
+
+        if retries > 0 {
+            info!("{method} {} succeeded after {retries} retries", url.path());
+        }
+
+        Ok(response)
+    }
+
+    /// Send the request, returning its result along with the pagination
+    /// information of listings.
+    async fn api_request<I, O>(
+        &self,
+        method: Method,
+        url: impl IntoUrl,
+        query: &[(&str, String)],
+        data: I,
+    ) -> Result<(O, Option<ResultInfo>), Error>
     where
         I: Serialize,
         O: DeserializeOwned,
     {
-        let body = self
-            .client
-            .request(method, url)
-            .json(&data)
-            .send()
-            .await?
-            .text()
-            .await?;
+        let url = url.into_url()?;
+        let response = self.send(method.clone(), url.clone(), query, data).await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        match serde_json::from_str::<ApiResult<O>>(&body) {
+            Ok(result) => {
+                let result_info = result.result_info();
+                let result = result.into_result().map_err(|errors| {
+                    Error::api(
+                        errors,
+                        Request {
+                            method,
+                            url: redacted(&url),
+                            status,
+                        },
+                    )
+                })?;
 
-        let result = match serde_json::from_str::<ApiResult<O>>(&body) {
-            Ok(result) => result,
+                Ok((result, result_info))
+            }
+            // Failed requests which never reached the api, such as those rejected
+            // by cloudflare's proxies, carry no api result describing the error.
+            Err(err) if !status.is_success() => {
+                error!("failed to deserialize api result of {status} response: {err}, {body}");
+                Err(Error::Status {
+                    method,
+                    url: redacted(&url),
+                    status,
+                    body: body.chars().take(BODY_SNIPPET_LENGTH).collect(),
+                })
+            }
             Err(err) => {
                 error!("failed to deserialize api result: {err}, {body}");
-                return Err(Error::Deserialization(err));
+                Err(Error::Deserialization(err))
             }
+        }
+    }
+
+    async fn request<I, O>(&self, method: Method, url: impl IntoUrl, data: I) -> Result<O, Error>
+    where
+        I: Serialize,
+        O: DeserializeOwned,
+    {
+        let (result, _) = self.api_request(method, url, &[], data).await?;
+        Ok(result)
+    }
+
+    /// Fetch every page of a listing endpoint, `per_page` items at a time.
+    async fn request_all<O>(&self, url: &str, per_page: u32) -> Result<Vec<O>, Error>
+    where
+        O: DeserializeOwned,
+    {
+        let mut items = Vec::new();
+        let mut page = 1u32;
+
+        loop {
+            let (results, result_info): (Vec<O>, _) = self
+                .api_request(
+                    Method::GET,
+                    url,
+                    &[
+                        ("page", page.to_string()),
+                        ("per_page", per_page.to_string()),
+                    ],
+                    (),
+                )
+                .await?;
+
+            let empty = results.is_empty();
+            items.extend(results);
+
+            match result_info {
+                Some(info) if info.has_next_page() && !empty => {
+                    trace!(
+                        "fetched page {}/{} of {url} ({} of {} items)",
+                        info.page,
+                        info.total_pages,
+                        info.count,
+                        info.total_count
+                    );
+                    page += 1
+                }
+                _ => break,
+            }
+        }
+
+        Ok(items)
+    }
+
+    pub async fn verify_token(&self) -> Result<models::TokenVerification, Error> {
+        self.request(
+            Method::GET,
+            format!("{}/user/tokens/verify", self.api_url),
+            (),
+        )
+        .await
+    }
+
+    /// Check whether the credentials permit editing records in the zone, by
+    /// submitting an empty batch of changes.
+    ///
+    /// Cloudflare authorizes the batch like any other write, but has nothing to change.
+    pub async fn can_edit_records(&self, zone_id: &ZoneId) -> Result<bool, Error> {
+        match self.batch(zone_id, Marker::Unmarked, &[]).await {
+            Ok(_) => Ok(true),
+            Err(err) if err.is_unauthorized() => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Export the records of the zone as a BIND zone file.
+    pub async fn export_records(&self, zone_id: &ZoneId) -> Result<String, Error> {
+        let response = self
+            .send(
+                Method::GET,
+                format!("{}/zones/{zone_id}/dns_records/export", self.api_url),
+                &[],
+                (),
+            )
+            .await?;
+
+        let status = response.status();
+        let url = redacted(response.url());
+        let body = response.text().await?;
+
+        if status.is_success() {
+            return Ok(body);
+        }
+
+        // Errors are reported as regular json api results.
+        let Ok(result) = serde_json::from_str::<ApiResult<IgnoredAny>>(&body) else {
+            return Err(Error::Status {
+                method: Method::GET,
+                url,
+                status,
+                body: body.chars().take(BODY_SNIPPET_LENGTH).collect(),
+            });
         };
 
-        Ok(result.into_result()?)
+        let _: IgnoredAny = result.into_result().map_err(|errors| {
+            Error::api(
+                errors,
+                Request {
+                    method: Method::GET,
+                    url,
+                    status,
+                },
+            )
+        })?;
+        Ok(body)
     }
 
-    pub async fn list_zones(&self) -> Result<Vec<models::Zone>, Error> {
+    /// Create a zone for the domain in the account, which cloudflare only
+    /// activates once the domain has been delegated to its nameservers.
+    pub async fn create_zone(
+        &self,
+        account_id: &str,
+        fqdn: &FullyQualifiedDomainName,
+    ) -> Result<models::Zone, Error> {
+        #[derive(Serialize)]
+        struct AccountRef<'a> {
+            id: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct CreateZone<'a> {
+            name: String,
+            account: AccountRef<'a>,
+            r#type: &'static str,
+        }
+
+        self.request(
+            Method::POST,
+            format!("{}/zones", self.api_url),
+            CreateZone {
+                name: fqdn.to_string().trim_end_matches('.').to_string(),
+                account: AccountRef { id: account_id },
+                r#type: "full",
+            },
+        )
+        .await
+    }
+
+    /// Delete the zone, along with all of its records.
+    pub async fn delete_zone(&self, zone_id: &ZoneId) -> Result<ZoneId, Error> {
+        #[derive(Deserialize)]
+        struct DeleteSuccess {
+            id: ZoneId,
+        }
+
+        let response: DeleteSuccess = self
+            .request(
+                Method::DELETE,
+                format!("{}/zones/{zone_id}", self.api_url),
+                (),
+            )
+            .await?;
+
+        Ok(response.id)
+    }
+
+    /// Find the zone with exactly the given name, without a trailing dot.
+    pub async fn find_zone(&self, name: &str) -> Result<Option<models::Zone>, Error> {
+        let url = Url::parse_with_params(&format!("{}/zones", self.api_url), &[("name", name)])
+            .expect("zone names are valid in urls");
+
+        let zones: Vec<models::Zone> = self
+            .request_all_parseable(url.as_str(), ZONES_PER_PAGE, "zone")
+            .await?;
+
+        Ok(zones.into_iter().next())
+    }
+
+    pub async fn dnssec(&self, zone_id: &ZoneId) -> Result<models::Dnssec, Error> {
         self.request(
             Method::GET,
-            "https://api.cloudflare.com/client/v4/zones",
+            format!("{}/zones/{zone_id}/dnssec", self.api_url),
             (),
         )
         .await
     }
 
-    pub async fn records(&self, zone_id: &ZoneId) -> Result<Vec<models::Record>, Error> {
+    pub async fn enable_dnssec(&self, zone_id: &ZoneId) -> Result<models::Dnssec, Error> {
+        #[derive(Serialize)]
+        struct UpdateDnssec {
+            status: &'static str,
+        }
+
+        self.request(
+            Method::PATCH,
+            format!("{}/zones/{zone_id}/dnssec", self.api_url),
+            UpdateDnssec { status: "active" },
+        )
+        .await
+    }
+
+    pub async fn zone_setting(
+        &self,
+        zone_id: &ZoneId,
+        setting_id: &str,
+    ) -> Result<models::ZoneSetting, Error> {
         self.request(
             Method::GET,
-            format!("https://api.cloudflare.com/client/v4/zones/{zone_id}/dns_records"),
+            format!("{}/zones/{zone_id}/settings/{setting_id}", self.api_url),
             (),
         )
         .await
     }
 
-    pub async fn create_record(
+    pub async fn update_zone_setting(
         &self,
         zone_id: &ZoneId,
-        managed_by: &str,
-        entry: &ZoneEntry,
-    ) -> Result<models::RecordId, Error> {
+        setting_id: &str,
+        value: &str,
+    ) -> Result<models::ZoneSetting, Error> {
         #[derive(Serialize)]
-        struct CreateRecord<'a> {
-            pub content: &'a str,
-            pub name: &'a str,
-            pub proxied: bool,
-            pub r#type: Type,
-            pub comment: &'a str,
-            pub id: &'a str,
-            pub tags: Vec<String>,
-            pub zone_id: &'a ZoneId,
-        }
-
-        let result: Record = self
-            .request(
+        struct UpdateSetting<'a> {
+            value: &'a str,
+        }
+
+        self.request(
+            Method::PATCH,
+            format!("{}/zones/{zone_id}/settings/{setting_id}", self.api_url),
+            UpdateSetting { value },
+        )
+        .await
+    }
+
+    pub async fn monitors(&self, account_id: &str) -> Result<Vec<models::Monitor>, Error> {
+        self.request(
+            Method::GET,
+            format!(
+                "{}/accounts/{account_id}/load_balancers/monitors",
+                self.api_url
+            ),
+            (),
+        )
+        .await
+    }
+
+    /// Create the monitor, or replace the existing one if it has an id.
+    pub async fn save_monitor(
+        &self,
+        account_id: &str,
+        monitor: &models::Monitor,
+    ) -> Result<models::Monitor, Error> {
+        self.save(
+            format!(
+                "{}/accounts/{account_id}/load_balancers/monitors",
+                self.api_url
+            ),
+            &monitor.id,
+            monitor,
+        )
+        .await
+    }
+
+    pub async fn delete_monitor(&self, account_id: &str, id: &str) -> Result<(), Error> {
+        self.delete(format!(
+            "{}/accounts/{account_id}/load_balancers/monitors/{id}",
+            self.api_url
+        ))
+        .await
+    }
+
+    pub async fn pools(&self, account_id: &str) -> Result<Vec<models::Pool>, Error> {
+        self.request(
+            Method::GET,
+            format!(
+                "{}/accounts/{account_id}/load_balancers/pools",
+                self.api_url
+            ),
+            (),
+        )
+        .await
+    }
+
+    /// Create the pool, or replace the existing one if it has an id.
+    pub async fn save_pool(
+        &self,
+        account_id: &str,
+        pool: &models::Pool,
+    ) -> Result<models::Pool, Error> {
+        self.save(
+            format!(
+                "{}/accounts/{account_id}/load_balancers/pools",
+                self.api_url
+            ),
+            &pool.id,
+            pool,
+        )
+        .await
+    }
+
+    pub async fn delete_pool(&self, account_id: &str, id: &str) -> Result<(), Error> {
+        self.delete(format!(
+            "{}/accounts/{account_id}/load_balancers/pools/{id}",
+            self.api_url
+        ))
+        .await
+    }
+
+    pub async fn load_balancers(
+        &self,
+        zone_id: &ZoneId,
+    ) -> Result<Vec<models::LoadBalancer>, Error> {
+        self.request(
+            Method::GET,
+            format!("{}/zones/{zone_id}/load_balancers", self.api_url),
+            (),
+        )
+        .await
+    }
+
+    /// Create the load balancer, or replace the existing one if it has an id.
+    pub async fn save_load_balancer(
+        &self,
+        zone_id: &ZoneId,
+        load_balancer: &models::LoadBalancer,
+    ) -> Result<models::LoadBalancer, Error> {
+        self.save(
+            format!("{}/zones/{zone_id}/load_balancers", self.api_url),
+            &load_balancer.id,
+            load_balancer,
+        )
+        .await
+    }
+
+    pub async fn delete_load_balancer(&self, zone_id: &ZoneId, id: &str) -> Result<(), Error> {
+        self.delete(format!(
+            "{}/zones/{zone_id}/load_balancers/{id}",
+            self.api_url
+        ))
+        .await
+    }
+
+    /// Create the object in the collection at `url`, or replace the object
+    /// with the id, unless it is empty.
+    async fn save<T>(&self, url: String, id: &str, object: &T) -> Result<T, Error>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        if id.is_empty() {
+            self.request(Method::POST, url, object).await
+        } else {
+            self.request(Method::PUT, format!("{url}/{id}"), object)
+                .await
+        }
+    }
+
+    async fn delete(&self, url: String) -> Result<(), Error> {
+        let _: IgnoredAny = self.request(Method::DELETE, url, ()).await?;
+        Ok(())
+    }
+
+    pub async fn find_custom_hostname(
+        &self,
+        zone_id: &ZoneId,
+        hostname: &str,
+    ) -> Result<Option<models::CustomHostname>, Error> {
+        let url = Url::parse_with_params(
+            &format!("{}/zones/{zone_id}/custom_hostnames", self.api_url),
+            &[("hostname", hostname)],
+        )
+        .expect("zone ids are valid in urls");
+
+        let custom_hostnames: Vec<models::CustomHostname> =
+            self.request(Method::GET, url, ()).await?;
+
+        Ok(custom_hostnames
+            .into_iter()
+            .find(|custom_hostname| custom_hostname.hostname == hostname))
+    }
+
+    /// Create a custom hostname, with a certificate validated over http.
+    pub async fn create_custom_hostname(
+        &self,
+        zone_id: &ZoneId,
+        hostname: &str,
+    ) -> Result<models::CustomHostname, Error> {
+        #[derive(Serialize)]
+        struct Ssl {
+            method: &'static str,
+            r#type: &'static str,
+        }
+
+        #[derive(Serialize)]
+        struct CreateCustomHostname<'a> {
+            hostname: &'a str,
+            ssl: Ssl,
+        }
+
+        self.request(
+            Method::POST,
+            format!("{}/zones/{zone_id}/custom_hostnames", self.api_url),
+            CreateCustomHostname {
+                hostname,
+                ssl: Ssl {
+                    method: "http",
+                    r#type: "dv",
+                },
+            },
+        )
+        .await
+    }
+
+    pub async fn delete_custom_hostname(&self, zone_id: &ZoneId, id: &str) -> Result<(), Error> {
+        self.delete(format!(
+            "{}/zones/{zone_id}/custom_hostnames/{id}",
+            self.api_url
+        ))
+        .await
+    }
+
+    /// Serve the hostname from the worker, replacing any worker serving it so far.
+    pub async fn attach_worker_domain(
+        &self,
+        account_id: &str,
+        zone_id: &ZoneId,
+        hostname: &str,
+        service: &str,
+        environment: &str,
+    ) -> Result<models::WorkerDomain, Error> {
+        #[derive(Serialize)]
+        struct AttachDomain<'a> {
+            zone_id: &'a ZoneId,
+            hostname: &'a str,
+            service: &'a str,
+            environment: &'a str,
+        }
+
+        self.request(
+            Method::PUT,
+            format!("{}/accounts/{account_id}/workers/domains", self.api_url),
+            AttachDomain {
+                zone_id,
+                hostname,
+                service,
+                environment,
+            },
+        )
+        .await
+    }
+
+    pub async fn detach_worker_domain(&self, account_id: &str, id: &str) -> Result<(), Error> {
+        self.delete(format!(
+            "{}/accounts/{account_id}/workers/domains/{id}",
+            self.api_url
+        ))
+        .await
+    }
+
+    pub async fn worker_routes(&self, zone_id: &ZoneId) -> Result<Vec<models::WorkerRoute>, Error> {
+        self.request(
+            Method::GET,
+            format!("{}/zones/{zone_id}/workers/routes", self.api_url),
+            (),
+        )
+        .await
+    }
+
+    /// Create the route, or replace the existing one if it has an id.
+    pub async fn save_worker_route(
+        &self,
+        zone_id: &ZoneId,
+        route: &models::WorkerRoute,
+    ) -> Result<models::WorkerRoute, Error> {
+        self.save(
+            format!("{}/zones/{zone_id}/workers/routes", self.api_url),
+            &route.id,
+            route,
+        )
+        .await
+    }
+
+    pub async fn delete_worker_route(&self, zone_id: &ZoneId, id: &str) -> Result<(), Error> {
+        self.delete(format!(
+            "{}/zones/{zone_id}/workers/routes/{id}",
+            self.api_url
+        ))
+        .await
+    }
+
+    pub async fn regional_hostnames(
+        &self,
+        zone_id: &ZoneId,
+    ) -> Result<Vec<models::RegionalHostname>, Error> {
+        self.request(
+            Method::GET,
+            format!(
+                "{}/zones/{zone_id}/addressing/regional_hostnames",
+                self.api_url
+            ),
+            (),
+        )
+        .await
+    }
+
+    /// Pin the hostname to the region, either creating its regional hostname
+    /// or changing the region of the existing one.
+    pub async fn save_regional_hostname(
+        &self,
+        zone_id: &ZoneId,
+        hostname: &str,
+        region_key: &str,
+        exists: bool,
+    ) -> Result<models::RegionalHostname, Error> {
+        #[derive(Serialize)]
+        struct SaveRegionalHostname<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            hostname: Option<&'a str>,
+            region_key: &'a str,
+        }
+
+        let url = format!(
+            "{}/zones/{zone_id}/addressing/regional_hostnames",
+            self.api_url
+        );
+        if exists {
+            self.request(
+                Method::PATCH,
+                format!("{url}/{hostname}"),
+                SaveRegionalHostname {
+                    hostname: None,
+                    region_key,
+                },
+            )
+            .await
+        } else {
+            self.request(
                 Method::POST,
-                format!("https://api.cloudflare.com/client/v4/zones/{zone_id}/dns_records"),
-                CreateRecord {
-                    content: &entry.rdata,
-                    name: &entry.fqdn.to_string(),
-                    proxied: false,
-                    r#type: entry.type_,
-                    comment: &format!("managed-by:{managed_by}"),
-                    id: "",
-                    tags: vec![],
-                    zone_id,
+                url,
+                SaveRegionalHostname {
+                    hostname: Some(hostname),
+                    region_key,
                 },
             )
-            .await?;
+            .await
+        }
+    }
+
+    pub async fn delete_regional_hostname(
+        &self,
+        zone_id: &ZoneId,
+        hostname: &str,
+    ) -> Result<(), Error> {
+        self.delete(format!(
+            "{}/zones/{zone_id}/addressing/regional_hostnames/{hostname}",
+            self.api_url
+        ))
+        .await
+    }
+
+    pub async fn list_zones(&self) -> Result<Vec<models::Zone>, Error> {
+        self.request_all_parseable(&format!("{}/zones", self.api_url), ZONES_PER_PAGE, "zone")
+            .await
+    }
+
+    pub async fn records(&self, zone_id: &ZoneId) -> Result<Vec<models::Record>, Error> {
+        self.request_all_parseable(
+            &format!("{}/zones/{zone_id}/dns_records", self.api_url),
+            self.records_per_page,
+            "record",
+        )
+        .await
+    }
+
+    /// Every record of the zone as returned by cloudflare, including those
+    /// [`Self::records`] skips since kubizone cannot represent them.
+    pub async fn raw_records(&self, zone_id: &ZoneId) -> Result<Vec<models::RawRecord>, Error> {
+        self.request_all(
+            &format!("{}/zones/{zone_id}/dns_records", self.api_url),
+            self.records_per_page,
+        )
+        .await
+    }
+
+    /// Fetch every item of a listing endpoint, like [`Self::request_all`].
+    ///
+    /// Items which cannot be parsed, such as those with names kubizone does
+    /// not consider valid, are skipped with a warning naming the `kind` of
+    /// item, rather than failing the whole listing.
+    async fn request_all_parseable<O>(
+        &self,
+        url: &str,
+        per_page: u32,
+        kind: &str,
+    ) -> Result<Vec<O>, Error>
+    where
+        O: DeserializeOwned,
+    {
+        let items: Vec<serde_json::Value> = self.request_all(url, per_page).await?;
+
+        Ok(items
+            .into_iter()
+            .filter_map(|item| {
+                let name = item["name"].as_str().unwrap_or_default().to_string();
+
+                serde_json::from_value(item)
+                    .inspect_err(|err| warn!("skipping cloudflare {kind} {name:?}: {err}"))
+                    .ok()
+            })
+            .collect())
+    }
+
+    /// Records in the zone matching the filter, without listing the entire zone.
+    pub async fn find_records(
+        &self,
+        zone_id: &ZoneId,
+        filter: &RecordFilter,
+    ) -> Result<Vec<models::Record>, Error> {
+        let url = Url::parse_with_params(
+            &format!("{}/zones/{zone_id}/dns_records", self.api_url),
+            filter.query(),
+        )
+        .expect("zone ids are valid in urls");
 
-        Ok(result.id)
+        self.request_all_parseable(url.as_str(), self.records_per_page, "record")
+            .await
     }
 
+    pub async fn create_record(
+        &self,
+        zone_id: &ZoneId,
+        marker: Marker<'_>,
+        desired: Desired<'_>,
+    ) -> Result<models::Record, Error> {
+        self.request(
+            Method::POST,
+            format!("{}/zones/{zone_id}/dns_records", self.api_url),
+            CreateRecord::new(zone_id, marker, desired)?,
+        )
+        .await
+    }
+
+    /// Replace the record with the desired one, and mark it with the `marker`.
     pub async fn update_record(
         &self,
         zone_id: &ZoneId,
-        record_id: &RecordId,
-        entry: &ZoneEntry,
+        marker: Marker<'_>,
+        record: &Record,
+        desired: Desired<'_>,
     ) -> Result<models::Record, Error> {
-        #[derive(Serialize)]
-        struct UpdateRecord<'a> {
-            pub content: &'a str,
-            pub ttl: u32,
-        }
+        let record_id = &record.id;
 
         self.request(
-            Method::PATCH,
-            format!("https://api.cloudflare.com/client/v4/zones/{zone_id}/dns_records/{record_id}"),
-            UpdateRecord {
-                content: &entry.rdata,
-                ttl: entry.ttl,
-            },
+            Method::PUT,
+            format!("{}/zones/{zone_id}/dns_records/{record_id}", self.api_url),
+            UpdateRecord::new(marker, record, desired)?,
         )
         .await
     }
@@ -155,13 +1411,329 @@ impl CloudFlare {
         let response: DeleteSuccess = self
             .request(
                 Method::DELETE,
-                format!(
-                    "https://api.cloudflare.com/client/v4/zones/{zone_id}/dns_records/{record_id}"
-                ),
+                format!("{}/zones/{zone_id}/dns_records/{record_id}", self.api_url),
                 (),
             )
             .await?;
 
         Ok(response.id)
     }
+
+    /// Apply up to [`MAX_BATCH_SIZE`] changes in a single request.
+    ///
+    /// Cloudflare executes batches atomically, so if this fails, none of
+    /// the changes have been applied.
+    pub async fn batch(
+        &self,
+        zone_id: &ZoneId,
+        marker: Marker<'_>,
+        changes: &[RecordChange<'_>],
+    ) -> Result<Vec<Applied>, Error> {
+        #[derive(Serialize)]
+        struct BatchDelete<'a> {
+            pub id: &'a RecordId,
+        }
+
+        #[derive(Serialize)]
+        struct BatchPut<'a> {
+            pub id: &'a RecordId,
+            #[serde(flatten)]
+            pub update: UpdateRecord,
+        }
+
+        #[derive(Default, Serialize)]
+        struct Batch<'a> {
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            pub deletes: Vec<BatchDelete<'a>>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            pub puts: Vec<BatchPut<'a>>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            pub posts: Vec<CreateRecord<'a>>,
+        }
+
+        let mut batch = Batch::default();
+        for change in changes {
+            match change {
+                RecordChange::Create(desired) => batch
+                    .posts
+                    .push(CreateRecord::new(zone_id, marker, *desired)?),
+                RecordChange::Update(record, desired) | RecordChange::Adopt(record, desired) => {
+                    batch.puts.push(BatchPut {
+                        id: &record.id,
+                        update: UpdateRecord::new(marker, record, *desired)?,
+                    })
+                }
+                RecordChange::Delete(record) => batch.deletes.push(BatchDelete { id: &record.id }),
+            }
+        }
+
+        #[derive(Deserialize)]
+        struct Deleted {
+            id: RecordId,
+        }
+
+        #[derive(Default, Deserialize)]
+        struct BatchResult {
+            #[serde(default)]
+            deletes: Vec<Deleted>,
+            #[serde(default)]
+            puts: Vec<serde_json::Value>,
+            #[serde(default)]
+            posts: Vec<serde_json::Value>,
+        }
+
+        // Empty batches have no result at all.
+        let result: Option<BatchResult> = self
+            .request(
+                Method::POST,
+                format!("{}/zones/{zone_id}/dns_records/batch", self.api_url),
+                batch,
+            )
+            .await?;
+
+        let result = result.unwrap_or_default();
+
+        // The changes have been applied at this point, so records which cannot
+        // be parsed must not fail the batch.
+        let written = result.puts.into_iter().chain(result.posts).map(|record| {
+            serde_json::from_value(record).map_or(Applied::Unknown, Applied::Written)
+        });
+
+        Ok(result
+            .deletes
+            .into_iter()
+            .map(|deleted| Applied::Deleted(deleted.id))
+            .chain(written)
+            .collect())
+    }
+}
+
+/// Determine how long to wait before retrying a rate limited request,
+/// bounded by [`MAX_RETRY_AFTER`].
+fn retry_after(response: &Response) -> Duration {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RETRY_AFTER)
+        .min(MAX_RETRY_AFTER)
+}
+
+/// The url without its query or credentials, suitable for logs.
+fn redacted(url: &Url) -> String {
+    format!(
+        "{}://{}{}",
+        url.scheme(),
+        url.host_str().unwrap_or_default(),
+        url.path()
+    )
+}
+
+#[cfg(test)]
+#[test]
+fn backoff_is_bounded() {
+    let policy = RetryPolicy {
+        max_retries: 10,
+        base_delay: Duration::from_secs(1),
+        max_delay: Duration::from_secs(8),
+    };
+
+    for retry in 1..=10 {
+        let expected = Duration::from_secs(1 << (retry - 1).min(3));
+        let delay = policy.backoff(retry);
+
+        assert!(delay >= expected / 2 && delay <= expected);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn typed_error_codes() {
+    let error = |codes: &[u32]| {
+        Error::api(
+            ApiErrors(
+                codes
+                    .iter()
+                    .map(|&code| ApiError {
+                        code,
+                        message: String::new(),
+                    })
+                    .collect(),
+            ),
+            Request {
+                method: Method::POST,
+                url: format!("{API_URL}/zones/023e105f4ecef8ad9ca31a8372d0c353/dns_records/batch"),
+                status: StatusCode::BAD_REQUEST,
+            },
+        )
+    };
+
+    assert!(error(&[81057]).is_record_exists());
+    assert!(error(&[81058]).is_record_exists());
+    assert!(error(&[1003]).to_string().starts_with("invalid zone"));
+    assert!(error(&[81057, 9109]).is_unauthorized());
+    assert!(matches!(error(&[9005]), Error::Api(..)));
+    assert_eq!(error(&[9005]).status(), Some(StatusCode::BAD_REQUEST));
+    assert!(!error(&[9005]).is_transient());
+    assert!(error(&[1003])
+        .to_string()
+        .ends_with("(POST https://api.cloudflare.com/client/v4/zones/023e105f4ecef8ad9ca31a8372d0c353/dns_records/batch responded with 400 Bad Request)"));
+}
+
+#[cfg(test)]
+#[test]
+fn invalid_credentials() {
+    let token = |token: &str| CloudFlare::new(&Credentials::Token(token.to_string()));
+
+    let cloudflare = token("token").unwrap();
+    assert!(matches!(token(""), Err(Error::InvalidCredentials(_))));
+    assert!(matches!(
+        token("to\nken"),
+        Err(Error::InvalidCredentials(_))
+    ));
+    assert!(matches!(
+        cloudflare.with_credentials(&Credentials::GlobalKey {
+            email: "admin@kubi.zone".to_string(),
+            key: String::new(),
+        }),
+        Err(Error::InvalidCredentials(_))
+    ));
+}
+
+#[cfg(test)]
+#[test]
+fn record_filter_query() {
+    let fqdn = FullyQualifiedDomainName::try_from("www.kubi.zone.").unwrap();
+    let filter = RecordFilter::default()
+        .with_name(&fqdn)
+        .with_type(Type::A)
+        .with_comment_prefix("managed-by:kubizone-cloudflare");
+
+    assert_eq!(
+        filter.query(),
+        [
+            ("name", "www.kubi.zone".to_string()),
+            ("type", "A".to_string()),
+            (
+                "comment.startswith",
+                "managed-by:kubizone-cloudflare".to_string()
+            ),
+        ]
+    );
+    assert!(RecordFilter::default().query().is_empty());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn mock_api_responses() {
+    let mock = mock::MockCloudFlare::start().await;
+    let zone_id = mock.add_zone("kubi.zone");
+    for i in 1..=7 {
+        mock.add_record(
+            &zone_id,
+            serde_json::json!({"name": format!("{i}.kubi.zone"), "type": "A", "content": format!("127.0.0.{i}"), "ttl": 300}),
+        );
+    }
+
+    // Every page of the listing is fetched.
+    let cloudflare = mock.client().with_records_per_page(3);
+    assert_eq!(cloudflare.records(&zone_id).await.unwrap().len(), 7);
+    assert_eq!(mock.requests().len(), 3);
+
+    // Records kubizone cannot represent are only listed raw.
+    let invalid = mock.add_record(
+        &zone_id,
+        serde_json::json!({"name": "bad..kubi.zone", "type": "A", "content": "127.0.0.8", "ttl": 300}),
+    );
+    assert_eq!(cloudflare.records(&zone_id).await.unwrap().len(), 7);
+    let raw = cloudflare.raw_records(&zone_id).await.unwrap();
+    assert_eq!(raw.len(), 8);
+    assert!(raw.iter().any(|record| record.id == invalid));
+    cloudflare
+        .delete_record(&zone_id, &serde_json::from_value(invalid.into()).unwrap())
+        .await
+        .unwrap();
+
+    let zone = cloudflare.find_zone("kubi.zone").await.unwrap().unwrap();
+    assert_eq!(zone.id, zone_id);
+    assert!(cloudflare.find_zone("example.org").await.unwrap().is_none());
+
+    // Rate limited requests are retried, up to a point.
+    mock.fail_next(
+        StatusCode::TOO_MANY_REQUESTS,
+        Some(0),
+        serde_json::json!([]),
+    );
+    assert_eq!(cloudflare.records(&zone_id).await.unwrap().len(), 7);
+    for _ in 0..=MAX_RATE_LIMIT_RETRIES {
+        mock.fail_next(
+            StatusCode::TOO_MANY_REQUESTS,
+            Some(0),
+            serde_json::json!([]),
+        );
+    }
+    assert!(cloudflare
+        .records(&zone_id)
+        .await
+        .unwrap_err()
+        .retry_after()
+        .is_some());
+
+    // Server errors are only retried for idempotent requests.
+    let error = serde_json::json!([{"code": 10001, "message": "service unavailable"}]);
+    mock.fail_next(StatusCode::INTERNAL_SERVER_ERROR, None, error.clone());
+    let records = cloudflare.records(&zone_id).await.unwrap();
+    let deleted = cloudflare
+        .delete_record(&zone_id, &records[0].id)
+        .await
+        .unwrap();
+    assert_eq!(deleted, records[0].id);
+
+    // Deletions may succeed without a result.
+    mock.respond_next(serde_json::Value::Null);
+    cloudflare
+        .delete_worker_route(&zone_id, "route")
+        .await
+        .unwrap();
+
+    // Permissions are probed with an empty batch, which has no result.
+    assert!(cloudflare.can_edit_records(&zone_id).await.unwrap());
+    assert_eq!(
+        mock.bodies(&format!("POST /zones/{zone_id}/dns_records/batch")),
+        [serde_json::json!({})]
+    );
+
+    let sent = mock.requests().len();
+    mock.fail_next(StatusCode::INTERNAL_SERVER_ERROR, None, error);
+    assert!(matches!(
+        cloudflare.can_edit_records(&zone_id).await,
+        Err(ref err @ Error::Api(..)) if err.is_transient()
+    ));
+    assert_eq!(mock.requests().len(), sent + 1);
+
+    // Api errors map onto their typed variants.
+    let missing: ZoneId = serde_json::from_str(r#""missing""#).unwrap();
+    assert!(matches!(
+        cloudflare.records(&missing).await,
+        Err(Error::InvalidZone(..))
+    ));
+    mock.fail_next(
+        StatusCode::FORBIDDEN,
+        None,
+        serde_json::json!([{"code": 10000, "message": "Authentication error"}]),
+    );
+    assert!(cloudflare
+        .verify_token()
+        .await
+        .unwrap_err()
+        .is_unauthorized());
+
+    // Responses which are not api results at all carry their status.
+    mock.reject_next(StatusCode::FORBIDDEN, "<html>blocked</html>");
+    assert_eq!(
+        cloudflare.verify_token().await.unwrap_err().status(),
+        Some(StatusCode::FORBIDDEN)
+    );
 }
@@ -0,0 +1,628 @@
+//! Translation between the rdata of zone entries and the way cloudflare
+//! represents the contents of a record, which for some types is split into
+//! a separate priority, or structured `data`.
+
+use kubizone_common::Type;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid {type_} rdata {rdata:?}: {reason}")]
+pub struct Error {
+    pub type_: Type,
+    pub rdata: String,
+    pub reason: &'static str,
+}
+
+/// Content of a record, as sent to cloudflare.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Content {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Data>,
+}
+
+/// Structured contents of a record.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum Data {
+    Srv(Srv),
+    Caa(Caa),
+    Naptr(Naptr),
+    Sshfp(Sshfp),
+    Tlsa(Tlsa),
+    Uri(Uri),
+    Loc(Loc),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Srv {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Caa {
+    pub flags: u8,
+    pub tag: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Naptr {
+    pub order: u16,
+    pub preference: u16,
+    pub flags: String,
+    pub service: String,
+    pub regex: String,
+    pub replacement: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Sshfp {
+    pub algorithm: u8,
+    #[serde(rename = "type")]
+    pub type_: u8,
+    pub fingerprint: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Tlsa {
+    pub usage: u8,
+    pub selector: u8,
+    pub matching_type: u8,
+    pub certificate: String,
+}
+
+/// Data of an URI record, whose priority is sent separately.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Uri {
+    pub weight: u16,
+    pub target: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Loc {
+    pub lat_degrees: u8,
+    pub lat_minutes: u8,
+    pub lat_seconds: f64,
+    pub lat_direction: String,
+    pub long_degrees: u8,
+    pub long_minutes: u8,
+    pub long_seconds: f64,
+    pub long_direction: String,
+    pub altitude: f64,
+    pub size: f64,
+    pub precision_horz: f64,
+    pub precision_vert: f64,
+}
+
+impl Content {
+    /// Build the content of a record of the given type from its rdata.
+    pub fn new(type_: Type, rdata: &str) -> Result<Self, Error> {
+        let error = |reason| Error {
+            type_,
+            rdata: rdata.to_string(),
+            reason,
+        };
+
+        let content = match type_ {
+            Type::MX => {
+                let [priority, exchange] =
+                    fields(rdata).ok_or(error("expected preference and exchange"))?;
+
+                Content {
+                    content: Some(unqualified(exchange)),
+                    priority: Some(number(priority).ok_or(error("invalid preference"))?),
+                    data: None,
+                }
+            }
+            Type::SRV => {
+                let [priority, weight, port, target] =
+                    fields(rdata).ok_or(error("expected priority, weight, port and target"))?;
+
+                Content::data(Data::Srv(Srv {
+                    priority: number(priority).ok_or(error("invalid priority"))?,
+                    weight: number(weight).ok_or(error("invalid weight"))?,
+                    port: number(port).ok_or(error("invalid port"))?,
+                    target: unqualified(target),
+                }))
+            }
+            Type::CAA => {
+                let ([flags, tag], value) =
+                    fields_and_rest(rdata).ok_or(error("expected flags, tag and value"))?;
+
+                Content::data(Data::Caa(Caa {
+                    flags: number(flags).ok_or(error("invalid flags"))?,
+                    tag: tag.to_string(),
+                    value: unquoted(value),
+                }))
+            }
+            Type::NAPTR => {
+                let [order, preference, flags, service, regex, replacement] = strings(rdata)
+                    .ok_or(error(
+                        "expected order, preference, flags, service, regex and replacement",
+                    ))?;
+
+                Content::data(Data::Naptr(Naptr {
+                    order: number(order).ok_or(error("invalid order"))?,
+                    preference: number(preference).ok_or(error("invalid preference"))?,
+                    flags: flags.to_string(),
+                    service: service.to_string(),
+                    regex: regex.to_string(),
+                    replacement: unqualified(replacement),
+                }))
+            }
+            Type::SSHFP => {
+                let ([algorithm, type_], fingerprint) = fields_and_rest(rdata)
+                    .ok_or(error("expected algorithm, type and fingerprint"))?;
+
+                Content::data(Data::Sshfp(Sshfp {
+                    algorithm: number(algorithm).ok_or(error("invalid algorithm"))?,
+                    type_: number(type_).ok_or(error("invalid fingerprint type"))?,
+                    fingerprint: hex(fingerprint),
+                }))
+            }
+            Type::TLSA => {
+                let ([usage, selector, matching_type], certificate) = fields_and_rest(rdata)
+                    .ok_or(error(
+                        "expected usage, selector, matching type and certificate",
+                    ))?;
+
+                Content::data(Data::Tlsa(Tlsa {
+                    usage: number(usage).ok_or(error("invalid usage"))?,
+                    selector: number(selector).ok_or(error("invalid selector"))?,
+                    matching_type: number(matching_type).ok_or(error("invalid matching type"))?,
+                    certificate: hex(certificate),
+                }))
+            }
+            Type::URI => {
+                let [priority, weight, target] =
+                    strings(rdata).ok_or(error("expected priority, weight and target"))?;
+
+                Content {
+                    content: None,
+                    priority: Some(number(priority).ok_or(error("invalid priority"))?),
+                    data: Some(Data::Uri(Uri {
+                        weight: number(weight).ok_or(error("invalid weight"))?,
+                        target: target.to_string(),
+                    })),
+                }
+            }
+            Type::LOC => Content::data(Data::Loc(loc(rdata).ok_or(error("invalid location"))?)),
+            Type::TXT => Content {
+                content: Some(txt(&crate::ident::txt_value(rdata))),
+                priority: None,
+                data: None,
+            },
+            _ => Content {
+                content: Some(rdata.to_string()),
+                priority: None,
+                data: None,
+            },
+        };
+
+        Ok(content)
+    }
+
+    fn data(data: Data) -> Self {
+        Content {
+            content: None,
+            priority: None,
+            data: Some(data),
+        }
+    }
+}
+
+/// Reconstruct the rdata of a record returned by cloudflare, in the form
+/// used by zone entries, so the two can be compared.
+///
+/// Falls back to the plain `content` if the priority or structured data is
+/// missing or malformed.
+pub fn rdata(
+    type_: Type,
+    content: &str,
+    priority: Option<u16>,
+    data: Option<&serde_json::Value>,
+) -> String {
+    fn parse<T: for<'de> Deserialize<'de>>(
+        type_: Type,
+        data: Option<&serde_json::Value>,
+    ) -> Option<T> {
+        match serde_json::from_value(data?.clone()) {
+            Ok(data) => Some(data),
+            Err(err) => {
+                warn!("ignoring malformed data of {type_} record: {err}");
+                None
+            }
+        }
+    }
+
+    let rdata = match type_ {
+        Type::MX => priority.map(|priority| format!("{priority} {}", qualified(content))),
+        Type::SRV => parse::<Srv>(type_, data).map(|srv| {
+            format!(
+                "{} {} {} {}",
+                srv.priority,
+                srv.weight,
+                srv.port,
+                qualified(&srv.target)
+            )
+        }),
+        Type::CAA => parse::<Caa>(type_, data)
+            .map(|caa| format!("{} {} \"{}\"", caa.flags, caa.tag, caa.value)),
+        Type::NAPTR => parse::<Naptr>(type_, data).map(|naptr| {
+            format!(
+                "{} {} \"{}\" \"{}\" \"{}\" {}",
+                naptr.order,
+                naptr.preference,
+                naptr.flags,
+                naptr.service,
+                naptr.regex,
+                qualified(&naptr.replacement)
+            )
+        }),
+        Type::SSHFP => parse::<Sshfp>(type_, data).map(|sshfp| {
+            format!(
+                "{} {} {}",
+                sshfp.algorithm,
+                sshfp.type_,
+                hex(&sshfp.fingerprint)
+            )
+        }),
+        Type::TLSA => parse::<Tlsa>(type_, data).map(|tlsa| {
+            format!(
+                "{} {} {} {}",
+                tlsa.usage,
+                tlsa.selector,
+                tlsa.matching_type,
+                hex(&tlsa.certificate)
+            )
+        }),
+        Type::URI => parse::<Uri>(type_, data)
+            .zip(priority)
+            .map(|(uri, priority)| format!("{priority} {} \"{}\"", uri.weight, uri.target)),
+        Type::LOC => parse::<Loc>(type_, data).map(|loc| {
+            format!(
+                "{} {} {:.3} {} {} {} {:.3} {} {:.2}m {:.2}m {:.2}m {:.2}m",
+                loc.lat_degrees,
+                loc.lat_minutes,
+                loc.lat_seconds,
+                loc.lat_direction,
+                loc.long_degrees,
+                loc.long_minutes,
+                loc.long_seconds,
+                loc.long_direction,
+                loc.altitude,
+                loc.size,
+                loc.precision_horz,
+                loc.precision_vert
+            )
+        }),
+        _ => None,
+    };
+
+    rdata.unwrap_or_else(|| content.to_string())
+}
+
+/// Split the rdata into exactly `N` whitespace separated fields.
+fn fields<const N: usize>(rdata: &str) -> Option<[&str; N]> {
+    rdata.split_whitespace().collect::<Vec<_>>().try_into().ok()
+}
+
+/// Split the first `N` whitespace separated fields from the rest of the rdata.
+fn fields_and_rest<const N: usize>(rdata: &str) -> Option<([&str; N], &str)> {
+    let mut rest = rdata.trim_start();
+    let mut fields = [""; N];
+
+    for field in &mut fields {
+        let (next, remainder) = rest.split_once(char::is_whitespace)?;
+        *field = next;
+        rest = remainder.trim_start();
+    }
+
+    (!rest.is_empty()).then_some((fields, rest.trim_end()))
+}
+
+/// Split the rdata into exactly `N` fields, which are separated by whitespace,
+/// unless they are quoted. The quotes are not part of the fields.
+fn strings<const N: usize>(rdata: &str) -> Option<[&str; N]> {
+    let mut fields = Vec::new();
+    let mut rest = rdata.trim_start();
+
+    while !rest.is_empty() {
+        let (field, remainder) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let (field, remainder) = quoted.split_once('"')?;
+                (field, remainder)
+            }
+            None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+        };
+
+        fields.push(field);
+        rest = remainder.trim_start();
+    }
+
+    fields.try_into().ok()
+}
+
+/// Longest character string permitted in TXT records, in bytes.
+const MAX_TXT_STRING_LENGTH: usize = 255;
+
+/// Quoted content of a TXT record with the given value, split into multiple
+/// strings if it exceeds [`MAX_TXT_STRING_LENGTH`].
+fn txt(value: &str) -> String {
+    let mut strings = vec![String::new()];
+
+    for c in value.chars() {
+        let string = strings.last_mut().unwrap();
+        if string.len() + c.len_utf8() > MAX_TXT_STRING_LENGTH {
+            strings.push(String::new());
+        }
+
+        strings.last_mut().unwrap().push(c);
+    }
+
+    strings
+        .iter()
+        .map(|string| format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Hexadecimal data may be split into multiple fields, and is case insensitive.
+fn hex(data: &str) -> String {
+    data.split_whitespace().collect::<String>().to_lowercase()
+}
+
+/// Parse a location in the format of RFC 1876, where the minutes and seconds
+/// of the coordinates, as well as the size and precisions, are optional.
+fn loc(rdata: &str) -> Option<Loc> {
+    let mut fields = rdata.split_whitespace();
+
+    let (lat_degrees, lat_minutes, lat_seconds, lat_direction) =
+        coordinate(&mut fields, ["N", "S"])?;
+    let (long_degrees, long_minutes, long_seconds, long_direction) =
+        coordinate(&mut fields, ["E", "W"])?;
+
+    let altitude = meters(fields.next()?)?;
+    let size = fields.next().map_or(Some(1.0), meters)?;
+    let precision_horz = fields.next().map_or(Some(10000.0), meters)?;
+    let precision_vert = fields.next().map_or(Some(10.0), meters)?;
+
+    fields.next().is_none().then_some(Loc {
+        lat_degrees,
+        lat_minutes,
+        lat_seconds,
+        lat_direction,
+        long_degrees,
+        long_minutes,
+        long_seconds,
+        long_direction,
+        altitude,
+        size,
+        precision_horz,
+        precision_vert,
+    })
+}
+
+/// Parse the degrees, and optionally minutes and seconds, of a coordinate,
+/// followed by one of the `directions`.
+fn coordinate<'a>(
+    fields: &mut impl Iterator<Item = &'a str>,
+    directions: [&str; 2],
+) -> Option<(u8, u8, f64, String)> {
+    let degrees = number(fields.next()?)?;
+    let mut minutes = 0;
+    let mut seconds = 0.0;
+
+    let mut next = fields.next()?;
+    if !directions.contains(&next) {
+        minutes = number(next)?;
+        next = fields.next()?;
+
+        if !directions.contains(&next) {
+            seconds = number(next)?;
+            next = fields.next()?;
+        }
+    }
+
+    directions
+        .contains(&next)
+        .then(|| (degrees, minutes, seconds, next.to_string()))
+}
+
+fn meters(field: &str) -> Option<f64> {
+    number(field.strip_suffix('m').unwrap_or(field))
+}
+
+/// Strip the quotes surrounding a character string, if any.
+fn unquoted(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+fn number<T: std::str::FromStr>(field: &str) -> Option<T> {
+    field.parse().ok()
+}
+
+/// Cloudflare stores domain names in record contents without the trailing dot.
+fn unqualified(name: &str) -> String {
+    match name.strip_suffix('.') {
+        Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Domain names in zone entries are fully qualified.
+fn qualified(name: &str) -> String {
+    if name.ends_with('.') {
+        name.to_string()
+    } else {
+        format!("{name}.")
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn structured_content() {
+    let long = "a".repeat(300);
+    let content = Content::new(Type::TXT, &long).unwrap();
+    assert_eq!(
+        content.content,
+        Some(format!("\"{}\" \"{}\"", &long[..255], &long[255..]))
+    );
+    assert_eq!(
+        Content::new(Type::TXT, "v=spf1 -all")
+            .unwrap()
+            .content
+            .as_deref(),
+        Some("\"v=spf1 -all\"")
+    );
+
+    let content = Content::new(Type::MX, "10 mail.kubi.zone.").unwrap();
+    assert_eq!(content.content.as_deref(), Some("mail.kubi.zone"));
+    assert_eq!(content.priority, Some(10));
+    assert_eq!(
+        rdata(Type::MX, "mail.kubi.zone", Some(10), None),
+        "10 mail.kubi.zone."
+    );
+
+    let content = Content::new(Type::SRV, "10 5 5060 sip.kubi.zone.").unwrap();
+    assert_eq!(
+        serde_json::to_value(&content).unwrap(),
+        serde_json::json!({
+            "data": {"priority": 10, "weight": 5, "port": 5060, "target": "sip.kubi.zone"}
+        })
+    );
+
+    let data = serde_json::to_value(content.data.unwrap()).unwrap();
+    assert_eq!(
+        rdata(Type::SRV, "5 5060 sip.kubi.zone", Some(10), Some(&data)),
+        "10 5 5060 sip.kubi.zone."
+    );
+
+    assert!(Content::new(Type::SRV, "10 5 sip.kubi.zone.").is_err());
+
+    let content = Content::new(Type::CAA, "0 issue \"letsencrypt.org\"").unwrap();
+    let data = serde_json::to_value(content.data.unwrap()).unwrap();
+    assert_eq!(
+        data,
+        serde_json::json!({"flags": 0, "tag": "issue", "value": "letsencrypt.org"})
+    );
+    assert_eq!(
+        rdata(Type::CAA, "", None, Some(&data)),
+        "0 issue \"letsencrypt.org\""
+    );
+
+    // Every structured type survives the roundtrip through cloudflare.
+    for (type_, entry) in [
+        (
+            Type::NAPTR,
+            "100 10 \"U\" \"E2U+sip\" \"!^.*$!sip:info@kubi.zone!\" .",
+        ),
+        (Type::SSHFP, "4 2 123456789abcdef67890123456789abcdef67890"),
+        (Type::TLSA, "3 1 1 0123456789abcdef"),
+        (Type::URI, "10 1 \"https://kubi.zone/\""),
+        (
+            Type::LOC,
+            "52 22 23.000 N 4 53 32.000 E -2.00m 0.00m 10000.00m 10.00m",
+        ),
+    ] {
+        let content = Content::new(type_, entry).unwrap();
+        let data = serde_json::to_value(content.data.unwrap()).unwrap();
+        assert_eq!(rdata(type_, "", content.priority, Some(&data)), entry);
+    }
+
+    let short = Content::new(Type::LOC, "52 N 4 E 0m").unwrap();
+    let long = Content::new(Type::LOC, "52 0 0 N 4 0 0 E 0m 1m 10000m 10m").unwrap();
+    assert_eq!(short, long);
+}
+
+#[cfg(test)]
+#[test]
+fn invalid_content() {
+    for (type_, rdata, reason) in [
+        (
+            Type::MX,
+            "mail.kubi.zone.",
+            "expected preference and exchange",
+        ),
+        (Type::MX, "high mail.kubi.zone.", "invalid preference"),
+        (Type::SRV, "10 5 65536 sip.kubi.zone.", "invalid port"),
+        (Type::CAA, "0 issue", "expected flags, tag and value"),
+        (Type::CAA, "256 issue \"letsencrypt.org\"", "invalid flags"),
+        (
+            Type::NAPTR,
+            "100 10 \"U\" \"E2U+sip\" \"!^.*$!sip:info@kubi.zone!",
+            "expected order, preference, flags, service, regex and replacement",
+        ),
+        (Type::SSHFP, "4 x 1234", "invalid fingerprint type"),
+        (
+            Type::TLSA,
+            "3 1 1",
+            "expected usage, selector, matching type and certificate",
+        ),
+        (
+            Type::URI,
+            "10 \"https://kubi.zone/\"",
+            "expected priority, weight and target",
+        ),
+        (
+            Type::LOC,
+            "52 22 23.000 X 4 53 32.000 E 0m",
+            "invalid location",
+        ),
+        (
+            Type::LOC,
+            "52 N 4 E 0m 1m 10000m 10m 1m",
+            "invalid location",
+        ),
+    ] {
+        let err = Content::new(type_, rdata).unwrap_err();
+        assert_eq!(err.reason, reason, "{type_} {rdata}");
+    }
+
+    // Hexadecimal data is normalized.
+    let content = Content::new(Type::SSHFP, "4 2 ABCDEF 012345").unwrap();
+    assert_eq!(
+        content.data,
+        Some(Data::Sshfp(Sshfp {
+            algorithm: 4,
+            type_: 2,
+            fingerprint: "abcdef012345".to_string(),
+        }))
+    );
+
+    // Records lacking the priority or with malformed data keep their content.
+    assert_eq!(
+        rdata(Type::MX, "mail.kubi.zone", None, None),
+        "mail.kubi.zone"
+    );
+    assert_eq!(
+        rdata(
+            Type::SRV,
+            "5 5060 sip.kubi.zone",
+            Some(10),
+            Some(&serde_json::json!({"priority": "high"}))
+        ),
+        "5 5060 sip.kubi.zone"
+    );
+    assert_eq!(
+        rdata(
+            Type::URI,
+            "",
+            None,
+            Some(&serde_json::json!({"weight": 1, "target": "https://kubi.zone/"}))
+        ),
+        ""
+    );
+}
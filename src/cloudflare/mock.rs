@@ -0,0 +1,412 @@
+//! Fake cloudflare api for tests, serving the zone and dns record endpoints
+//! from memory on a local port.
+//!
+//! Responses to upcoming requests can be overridden with [`MockCloudFlare::fail_next`]
+//! and [`MockCloudFlare::respond_next`], to exercise rate limiting, error handling
+//! and unusual results.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Bytes,
+    extract::State as AxumState,
+    http::{header::RETRY_AFTER, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use serde_json::{json, Value};
+use tokio::task::AbortHandle;
+
+use super::{CloudFlare, Credentials, RetryPolicy, ZoneId};
+
+/// Response returned instead of handling a request.
+struct Failure {
+    status: StatusCode,
+    retry_after: Option<u64>,
+    body: String,
+}
+
+#[derive(Default)]
+struct State {
+    zones: Vec<Value>,
+    /// Records of each zone, by zone id.
+    records: BTreeMap<String, Vec<Value>>,
+    failures: VecDeque<Failure>,
+    /// Every request received, as `METHOD /path`, along with its json body.
+    requests: Vec<(String, Value)>,
+    next_id: u64,
+}
+
+impl State {
+    fn id(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:032x}", self.next_id)
+    }
+}
+
+/// Fake cloudflare api, stopped once dropped.
+pub struct MockCloudFlare {
+    state: Arc<Mutex<State>>,
+    url: String,
+    server: AbortHandle,
+}
+
+impl Drop for MockCloudFlare {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+impl MockCloudFlare {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/client/v4", listener.local_addr().unwrap());
+
+        let router = Router::new().fallback(handle).with_state(state.clone());
+        let server = tokio::spawn(async move { axum::serve(listener, router).await.unwrap() })
+            .abort_handle();
+
+        MockCloudFlare { state, url, server }
+    }
+
+    /// Base url of the fake api, such as for `--cf-api-url`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Client for the fake api, retrying without delay.
+    pub fn client(&self) -> CloudFlare {
+        CloudFlare::new(&Credentials::Token("token".to_string()))
+            .unwrap()
+            .with_api_url(&self.url)
+            .with_retry_policy(RetryPolicy {
+                max_retries: 2,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(1),
+            })
+    }
+
+    /// Add a zone with the name, without a trailing dot, returning its id.
+    pub fn add_zone(&self, name: &str) -> ZoneId {
+        let mut state = self.state.lock().unwrap();
+        let id = state.id();
+        state
+            .zones
+            .push(json!({"id": id, "name": name, "status": "active", "paused": false}));
+        state.records.insert(id.clone(), Vec::new());
+
+        serde_json::from_value(Value::String(id)).unwrap()
+    }
+
+    /// Add a record to the zone, as returned by the api, returning the id
+    /// assigned to it.
+    pub fn add_record(&self, zone_id: &ZoneId, mut record: Value) -> String {
+        let mut state = self.state.lock().unwrap();
+        let id = state.id();
+        record["id"] = Value::String(id.clone());
+        state
+            .records
+            .get_mut(&zone_id.to_string())
+            .unwrap()
+            .push(record);
+
+        id
+    }
+
+    /// Records of the zone, as returned by the api.
+    pub fn records(&self, zone_id: &ZoneId) -> Vec<Value> {
+        self.state.lock().unwrap().records[&zone_id.to_string()].clone()
+    }
+
+    /// Respond to the next request which has not been overridden yet with the
+    /// status and api errors, rather than handling it.
+    pub fn fail_next(&self, status: StatusCode, retry_after: Option<u64>, errors: Value) {
+        self.state.lock().unwrap().failures.push_back(Failure {
+            status,
+            retry_after,
+            body: json!({"success": false, "errors": errors, "messages": [], "result": null})
+                .to_string(),
+        });
+    }
+
+    /// Respond to the next request which has not been overridden yet with a
+    /// successful api result, rather than handling it.
+    pub fn respond_next(&self, result: Value) {
+        self.state.lock().unwrap().failures.push_back(Failure {
+            status: StatusCode::OK,
+            retry_after: None,
+            body: json!({"success": true, "errors": [], "messages": [], "result": result})
+                .to_string(),
+        });
+    }
+
+    /// Respond to the next request which has not been overridden yet with the
+    /// status and a body which is not an api result, like cloudflare's proxies do.
+    pub fn reject_next(&self, status: StatusCode, body: &str) {
+        self.state.lock().unwrap().failures.push_back(Failure {
+            status,
+            retry_after: None,
+            body: body.to_string(),
+        });
+    }
+
+    /// Every request received so far, as `METHOD /path` relative to the api url.
+    pub fn requests(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .map(|(request, _)| request.clone())
+            .collect()
+    }
+
+    /// Json bodies of the requests received so far matching `request`, given
+    /// as `METHOD /path` like in [`Self::requests`].
+    pub fn bodies(&self, request: &str) -> Vec<Value> {
+        self.state
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .filter(|(received, _)| received == request)
+            .map(|(_, body)| body.clone())
+            .collect()
+    }
+}
+
+fn success(result: Value, result_info: Option<Value>) -> Response {
+    let mut body = json!({"success": true, "errors": [], "messages": [], "result": result});
+    if let Some(result_info) = result_info {
+        body["result_info"] = result_info;
+    }
+
+    (StatusCode::OK, body.to_string()).into_response()
+}
+
+fn error(status: StatusCode, code: u32, message: &str) -> Response {
+    let body = json!({
+        "success": false,
+        "errors": [{"code": code, "message": message}],
+        "messages": [],
+        "result": null
+    });
+
+    (status, body.to_string()).into_response()
+}
+
+fn query(uri: &Uri) -> BTreeMap<String, String> {
+    reqwest::Url::parse(&format!("http://localhost{uri}"))
+        .unwrap()
+        .query_pairs()
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect()
+}
+
+/// Page of the items requested by the `page` and `per_page` query parameters.
+///
+/// Both are clamped to at least 1, so malformed requests get the first page.
+fn paginated(items: Vec<Value>, query: &BTreeMap<String, String>) -> Response {
+    let page = query
+        .get("page")
+        .and_then(|page| page.parse().ok())
+        .unwrap_or(1usize)
+        .max(1);
+    let per_page = query
+        .get("per_page")
+        .and_then(|per_page| per_page.parse().ok())
+        .unwrap_or(100usize)
+        .max(1);
+
+    let total_count = items.len();
+    let result = items
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .collect::<Vec<_>>();
+
+    let result_info = json!({
+        "page": page,
+        "per_page": per_page,
+        "count": result.len(),
+        "total_count": total_count,
+        "total_pages": total_count.div_ceil(per_page),
+    });
+    success(Value::Array(result), Some(result_info))
+}
+
+/// Record as stored by cloudflare, from the body of a request creating or updating it.
+fn stored(id: String, body: &Value) -> Value {
+    let mut record = body.clone();
+    record["id"] = Value::String(id);
+    record["name"] = Value::String(
+        body["name"]
+            .as_str()
+            .unwrap_or_default()
+            .trim_end_matches('.')
+            .to_string(),
+    );
+    if record.get("content").is_none() {
+        record["content"] = Value::String(String::new());
+    }
+
+    record
+}
+
+async fn handle(
+    AxumState(state): AxumState<Arc<Mutex<State>>>,
+    method: Method,
+    uri: Uri,
+    body: Bytes,
+) -> Response {
+    let mut state = state.lock().unwrap();
+    let path = uri.path().trim_start_matches("/client/v4");
+    let body = serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null);
+    state
+        .requests
+        .push((format!("{method} {path}"), body.clone()));
+
+    if let Some(failure) = state.failures.pop_front() {
+        let mut response = (failure.status, failure.body).into_response();
+        if let Some(retry_after) = failure.retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, retry_after.to_string().parse().unwrap());
+        }
+        return response;
+    }
+
+    let query = query(&uri);
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+    if let ["zones", zone_id, ..] = segments[..] {
+        if !state.records.contains_key(zone_id) {
+            return error(StatusCode::BAD_REQUEST, 1003, "Invalid or missing zone id.");
+        }
+    }
+
+    match (method, &segments[..]) {
+        (Method::GET, ["user", "tokens", "verify"]) => {
+            success(json!({"id": "token", "status": "active"}), None)
+        }
+        (Method::GET, ["zones"]) => {
+            let zones = state
+                .zones
+                .iter()
+                .filter(|zone| {
+                    query
+                        .get("name")
+                        .map_or(true, |name| zone["name"] == name.as_str())
+                })
+                .cloned()
+                .collect();
+            paginated(zones, &query)
+        }
+        (Method::GET, ["zones", zone_id, "dns_records"]) => {
+            let records = state.records[*zone_id]
+                .iter()
+                .filter(|record| {
+                    query
+                        .get("name")
+                        .map_or(true, |name| record["name"] == name.as_str())
+                })
+                .filter(|record| {
+                    query
+                        .get("type")
+                        .map_or(true, |type_| record["type"] == type_.as_str())
+                })
+                .cloned()
+                .collect();
+            paginated(records, &query)
+        }
+        (Method::POST, ["zones", zone_id, "dns_records"]) => {
+            let record = stored(state.id(), &body);
+            state
+                .records
+                .get_mut(*zone_id)
+                .unwrap()
+                .push(record.clone());
+            success(record, None)
+        }
+        (Method::POST, ["zones", zone_id, "dns_records", "batch"]) => {
+            let zone_id = zone_id.to_string();
+            let mut result = json!({"deletes": [], "puts": [], "posts": []});
+
+            // Like cloudflare, empty batches have no result at all.
+            let changes = |key: &str| body[key].as_array().cloned().unwrap_or_default();
+            if ["deletes", "puts", "posts"]
+                .iter()
+                .all(|key| changes(key).is_empty())
+            {
+                return success(Value::Null, None);
+            }
+
+            // Batches are atomic, so only apply them if every record exists.
+            let records = &state.records[&zone_id];
+            let exists = |change: &Value| records.iter().any(|record| record["id"] == change["id"]);
+            if !changes("deletes")
+                .iter()
+                .chain(&changes("puts"))
+                .all(exists)
+            {
+                return error(StatusCode::NOT_FOUND, 81044, "Record does not exist.");
+            }
+
+            for delete in changes("deletes") {
+                let records = state.records.get_mut(&zone_id).unwrap();
+                let index = records
+                    .iter()
+                    .position(|record| record["id"] == delete["id"])
+                    .unwrap();
+                let record = records.remove(index);
+                result["deletes"].as_array_mut().unwrap().push(record);
+            }
+            for put in changes("puts") {
+                let record = stored(put["id"].as_str().unwrap().to_string(), &put);
+                let records = state.records.get_mut(&zone_id).unwrap();
+                let index = records
+                    .iter()
+                    .position(|existing| existing["id"] == put["id"])
+                    .unwrap();
+                records[index] = record.clone();
+                result["puts"].as_array_mut().unwrap().push(record);
+            }
+            for post in changes("posts") {
+                let record = stored(state.id(), &post);
+                state
+                    .records
+                    .get_mut(&zone_id)
+                    .unwrap()
+                    .push(record.clone());
+                result["posts"].as_array_mut().unwrap().push(record);
+            }
+
+            success(result, None)
+        }
+        (Method::PUT | Method::PATCH, ["zones", zone_id, "dns_records", record_id]) => {
+            let records = state.records.get_mut(*zone_id).unwrap();
+            let Some(existing) = records.iter_mut().find(|record| record["id"] == *record_id)
+            else {
+                return error(StatusCode::NOT_FOUND, 81044, "Record does not exist.");
+            };
+
+            *existing = stored(record_id.to_string(), &body);
+            success(existing.clone(), None)
+        }
+        (Method::DELETE, ["zones", zone_id, "dns_records", record_id]) => {
+            let records = state.records.get_mut(*zone_id).unwrap();
+            let Some(index) = records.iter().position(|record| record["id"] == *record_id) else {
+                return error(StatusCode::NOT_FOUND, 81044, "Record does not exist.");
+            };
+
+            records.remove(index);
+            success(json!({"id": record_id}), None)
+        }
+        _ => error(StatusCode::NOT_FOUND, 7003, "No route for that URI"),
+    }
+}
@@ -1,10 +1,15 @@
 use std::{fmt::Display, hash::Hash};
 
 use kubizone_common::{DomainSegment, FullyQualifiedDomainName, RecordIdent, Type};
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::value::{StrDeserializer, UnitDeserializer},
+    Deserialize, Serialize,
+};
 use tracing::trace;
 
-#[derive(Debug, Deserialize, Serialize)]
+use super::content;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(transparent)]
 pub struct RecordId(String);
 
@@ -14,8 +19,29 @@ impl Display for RecordId {
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(from = "InternalRecord")]
+/// Name returned by cloudflare which is not a valid domain name.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid domain name {name:?}: {reason}")]
+pub struct InvalidName {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Parse a name as returned by cloudflare, which omits the trailing dot.
+///
+/// Internationalized names are not supported: cloudflare returns them as
+/// punycode (`xn--` labels), and kubizone's [`DomainSegment`] accepts neither
+/// that nor the unicode form, so no kubizone zone can refer to them. Until
+/// kubizone-common can represent them, such names fail to parse here.
+fn parse_fqdn(name: &str) -> Result<FullyQualifiedDomainName, InvalidName> {
+    Result::from_iter(name.split('.').map(DomainSegment::try_from)).map_err(|err| InvalidName {
+        name: name.to_string(),
+        reason: err.to_string(),
+    })
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawRecord")]
 pub struct Record {
     pub id: RecordId,
     pub fqdn: FullyQualifiedDomainName,
@@ -24,15 +50,12 @@ pub struct Record {
     pub comment: Option<String>,
     pub tags: Vec<String>,
     pub ttl: u32,
+    pub proxied: bool,
 }
 
 impl From<&Record> for RecordIdent {
     fn from(value: &Record) -> Self {
-        RecordIdent {
-            fqdn: value.fqdn.clone(),
-            r#type: value.r#type,
-            rdata: value.rdata.clone(),
-        }
+        crate::ident::ident(&value.fqdn, value.r#type, &value.rdata)
     }
 }
 
@@ -44,43 +67,161 @@ impl Hash for Record {
     }
 }
 
+/// Prefix of the comments and tags marking records as managed by a controller.
+pub const MANAGED_BY_PREFIX: &str = "managed-by:";
+
+/// Longest comment cloudflare accepts on records, in characters.
+pub const MAX_COMMENT_LENGTH: usize = 100;
+
+/// Length of the `~<hash>` suffix of shortened owner names.
+const OWNER_HASH_LENGTH: usize = 9;
+
+/// Controller named by a `managed-by:<name>` comment or tag, ignoring any
+/// description following the name.
+pub fn marked_owner(marker: &str) -> Option<&str> {
+    marker
+        .strip_prefix(MANAGED_BY_PREFIX)?
+        .split_whitespace()
+        .next()
+}
+
+/// `managed-by:<name>` marker of the owner.
+///
+/// Names too long to fit in a comment are cut short and suffixed with a
+/// hash of the full name, so they remain distinct from one another.
+pub fn owner_marker(owner: &str) -> String {
+    let available = MAX_COMMENT_LENGTH - MANAGED_BY_PREFIX.len();
+    if owner.chars().count() <= available {
+        return format!("{MANAGED_BY_PREFIX}{owner}");
+    }
+
+    let shortened: String = owner.chars().take(available - OWNER_HASH_LENGTH).collect();
+    let hash = crate::shard::fnv1a(owner.as_bytes()) as u32;
+    format!("{MANAGED_BY_PREFIX}{shortened}~{hash:08x}")
+}
+
+/// Comment marking records as managed by the owner, followed by as much of
+/// the description as fits within the comment length limit.
+pub fn owner_comment(owner: &str, description: Option<&str>) -> String {
+    let mut comment = owner_marker(owner);
+
+    if let Some(description) = description {
+        let available = MAX_COMMENT_LENGTH.saturating_sub(comment.chars().count() + 1);
+        let description: String = description.chars().take(available).collect();
+        let description = description.trim_end();
+
+        if !description.is_empty() {
+            comment.push(' ');
+            comment.push_str(description);
+        }
+    }
+
+    comment
+}
+
+/// Returns true if the comment or tag marks a record as managed by the owner.
+///
+/// Besides markers written by [`owner_marker`], this accepts markers which
+/// were cut off at the comment length limit by cloudflare itself.
+pub fn is_marked_by(marker: &str, owner: &str) -> bool {
+    let Some(marked) = marked_owner(marker) else {
+        return false;
+    };
+
+    marked == owner
+        || Some(marked) == marked_owner(&owner_marker(owner))
+        || (marker.chars().count() == MAX_COMMENT_LENGTH
+            && marker.ends_with(marked)
+            && owner.starts_with(marked))
+}
+
 impl Record {
-    pub fn is_managed_by(&self, controller_name: &str) -> bool {
-        let tag = format!("managed-by:{controller_name}");
-        self.tags.contains(&tag) || self.comment == Some(tag)
+    /// Name of the controller managing this record, if any.
+    pub fn managed_by(&self) -> Option<&str> {
+        self.tags
+            .iter()
+            .chain(&self.comment)
+            .find_map(|marker| marked_owner(marker))
+    }
+
+    /// Returns true if the comment of this record marks it as managed by the owner.
+    pub fn is_commented_by(&self, owner: &str) -> bool {
+        self.comment
+            .as_deref()
+            .is_some_and(|comment| is_marked_by(comment, owner))
+    }
+
+    /// Returns true if any tag or the comment of this record marks it as managed by the owner.
+    pub fn is_marked_by(&self, owner: &str) -> bool {
+        self.tags.iter().any(|tag| is_marked_by(tag, owner)) || self.is_commented_by(owner)
     }
 }
 
-#[derive(Deserialize)]
-struct InternalRecord {
+/// Record exactly as returned by cloudflare, with its name and type as plain
+/// strings, for consumers which do not use kubizone's types.
+///
+/// Unlike [`Record`], every record can be represented, including those with
+/// names or types kubizone does not accept.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawRecord {
     pub id: String,
+    /// Name of the record, without the trailing dot.
     pub name: String,
-    pub r#type: Type,
+    pub r#type: String,
+    /// Content as returned by cloudflare, which for some types omits the
+    /// `priority` and structured `data` of the record.
     pub content: String,
     #[serde(default)]
     pub comment: Option<String>,
     #[serde(default)]
     pub tags: Vec<String>,
     pub ttl: u32,
+    #[serde(default)]
+    pub proxied: bool,
+    #[serde(default)]
+    pub priority: Option<u16>,
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+}
+
+/// Record returned by cloudflare which kubizone cannot represent.
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidRecord {
+    #[error(transparent)]
+    Name(#[from] InvalidName),
+    #[error("unsupported record type {0:?}")]
+    Type(String),
 }
 
-impl From<InternalRecord> for Record {
-    fn from(record: InternalRecord) -> Self {
-        let fqdn = Result::from_iter(record.name.split('.').map(DomainSegment::try_from)).unwrap();
+impl TryFrom<RawRecord> for Record {
+    type Error = InvalidRecord;
 
-        Record {
+    fn try_from(record: RawRecord) -> Result<Self, Self::Error> {
+        let fqdn = parse_fqdn(&record.name)?;
+        let r#type = Type::deserialize(StrDeserializer::<serde::de::value::Error>::new(
+            &record.r#type,
+        ))
+        .map_err(|_| InvalidRecord::Type(record.r#type.clone()))?;
+
+        Ok(Record {
             id: RecordId(record.id),
             fqdn,
-            r#type: record.r#type,
-            rdata: record.content,
+            r#type,
+            rdata: content::rdata(
+                r#type,
+                &record.content,
+                record.priority,
+                record.data.as_ref(),
+            ),
             comment: record.comment,
             tags: record.tags,
             ttl: record.ttl,
-        }
+            proxied: record.proxied,
+        })
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(transparent)]
 pub struct ZoneId(String);
 
@@ -91,23 +232,305 @@ impl Display for ZoneId {
 }
 
 #[derive(Debug, Clone, Deserialize)]
-#[serde(from = "InternalZone")]
+#[serde(try_from = "InternalZone")]
 pub struct Zone {
     pub id: ZoneId,
     pub fqdn: FullyQualifiedDomainName,
+    /// Nameservers assigned to the zone by cloudflare, which it must be delegated to.
+    pub name_servers: Vec<String>,
+    pub status: ZoneStatus,
+    /// Whether cloudflare's services, including DNS, are paused for the zone.
+    pub paused: bool,
+    /// Plan of the zone, such as `free` or `enterprise`, if cloudflare listed it.
+    pub plan: Option<String>,
+}
+
+impl Zone {
+    /// Returns true if records can be synced to the zone, rather than it still
+    /// waiting for its nameservers to be verified, or being paused.
+    pub fn is_active(&self) -> bool {
+        self.status == ZoneStatus::Active && !self.paused
+    }
+}
+
+/// Status of a zone, as far as cloudflare is concerned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZoneStatus {
+    /// Zones listed without a status are assumed to be active.
+    #[default]
+    Active,
+    /// Waiting for the domain to be delegated to the nameservers of the zone.
+    Pending,
+    Initializing,
+    /// The domain has been delegated to other nameservers.
+    Moved,
+    Deleted,
+    Deactivated,
+    #[serde(other)]
+    Unknown,
+}
+
+impl Display for ZoneStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            ZoneStatus::Active => "active",
+            ZoneStatus::Pending => "pending",
+            ZoneStatus::Initializing => "initializing",
+            ZoneStatus::Moved => "moved",
+            ZoneStatus::Deleted => "deleted",
+            ZoneStatus::Deactivated => "deactivated",
+            ZoneStatus::Unknown => "unknown",
+        };
+
+        f.write_str(status)
+    }
+}
+
+/// DNSSEC settings of a zone. Everything but the status is only set once
+/// DNSSEC has been enabled.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Dnssec {
+    pub status: DnssecStatus,
+    /// DS record to add to the parent zone, such as
+    /// `example.com. 3600 IN DS 2371 13 2 1F98...`.
+    pub ds: Option<String>,
+    pub key_tag: Option<u16>,
+    pub algorithm: Option<String>,
+    pub digest_type: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl Dnssec {
+    /// Rdata of the DS record to add to the parent zone, if DNSSEC is enabled.
+    pub fn ds_rdata(&self) -> Option<String> {
+        Some(format!(
+            "{} {} {} {}",
+            self.key_tag?,
+            self.algorithm.as_ref()?,
+            self.digest_type.as_ref()?,
+            self.digest.as_ref()?
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DnssecStatus {
+    Active,
+    /// Enabled, but waiting for the DS record to show up in the parent zone.
+    Pending,
+    Disabled,
+    PendingDisabled,
+    Error,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Health check of the origins of load balancer pools.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Monitor {
+    #[serde(default, skip_serializing)]
+    pub id: String,
+    #[serde(default)]
+    pub description: String,
+    /// Protocol of the health check, such as `http`, `https` or `tcp`.
+    #[serde(rename = "type")]
+    pub r#type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_codes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+}
+
+/// Group of origins load balancers steer traffic to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Pool {
+    #[serde(default, skip_serializing)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub origins: Vec<Origin>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Origin {
+    pub name: String,
+    pub address: String,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Load balancer serving a hostname of a zone from a set of pools.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoadBalancer {
+    #[serde(default, skip_serializing)]
+    pub id: String,
+    /// Hostname of the load balancer, without a trailing dot.
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Ids of the pools used by default, in order of failover.
+    pub default_pools: Vec<String>,
+    pub fallback_pool: String,
+    #[serde(default)]
+    pub steering_policy: String,
+    /// Ids of the pools used for clients in each region, with geo steering.
+    #[serde(default)]
+    pub region_pools: std::collections::BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub proxied: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+}
+
+/// Hostname of a customer served by a zone, through SSL for SaaS.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomHostname {
+    pub id: String,
+    pub hostname: String,
+    pub status: String,
+    pub ssl: Option<CustomHostnameSsl>,
+    pub ownership_verification: Option<OwnershipVerification>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomHostnameSsl {
+    pub status: String,
+    #[serde(default)]
+    pub validation_records: Vec<ValidationRecord>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidationRecord {
+    pub txt_name: Option<String>,
+    pub txt_value: Option<String>,
+    pub http_url: Option<String>,
+    pub http_body: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OwnershipVerification {
+    pub name: String,
+    pub value: String,
+}
+
+/// Hostname served by a worker, including the DNS record cloudflare creates for it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkerDomain {
+    pub id: String,
+    pub service: String,
+    pub environment: String,
+}
+
+/// Route sending requests matching the pattern to a worker script.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkerRoute {
+    #[serde(default, skip_serializing)]
+    pub id: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub script: String,
+}
+
+/// Hostname whose traffic is only ever handled within the region.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegionalHostname {
+    pub hostname: String,
+    pub region_key: String,
+}
+
+/// A setting of a zone, such as its `ssl` mode.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZoneSetting {
+    pub value: serde_json::Value,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 struct InternalZone {
     pub id: ZoneId,
     pub name: String,
+    #[serde(default)]
+    pub name_servers: Vec<String>,
+    #[serde(default)]
+    pub status: ZoneStatus,
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub plan: Option<InternalPlan>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct InternalPlan {
+    pub legacy_id: String,
+}
+
+impl TryFrom<InternalZone> for Zone {
+    type Error = InvalidName;
+
+    fn try_from(zone: InternalZone) -> Result<Self, Self::Error> {
+        Ok(Zone {
+            fqdn: parse_fqdn(&zone.name)?,
+            id: zone.id,
+            name_servers: zone.name_servers,
+            status: zone.status,
+            paused: zone.paused,
+            plan: zone.plan.map(|plan| plan.legacy_id),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenStatus {
+    Active,
+    Disabled,
+    Expired,
+}
+
+impl Display for TokenStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenStatus::Active => f.write_str("active"),
+            TokenStatus::Disabled => f.write_str("disabled"),
+            TokenStatus::Expired => f.write_str("expired"),
+        }
+    }
+}
+
+/// Result of verifying an API token.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenVerification {
+    pub id: String,
+    pub status: TokenStatus,
+    #[serde(default)]
+    pub expires_on: Option<String>,
 }
 
-impl From<InternalZone> for Zone {
-    fn from(zone: InternalZone) -> Self {
-        let fqdn = Result::from_iter(zone.name.split('.').map(DomainSegment::try_from)).unwrap();
+impl Display for TokenVerification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "token {} is {}", self.id, self.status)?;
+
+        if let Some(expires_on) = &self.expires_on {
+            write!(f, ", expires on {expires_on}")?;
+        }
 
-        Zone { id: zone.id, fqdn }
+        Ok(())
     }
 }
 
@@ -117,12 +540,49 @@ pub struct ApiError {
     pub message: String,
 }
 
+impl ApiError {
+    /// Returns true if cloudflare rejected the tags of a record, which happens
+    /// when the plan of the zone does not include any tags, such as
+    /// `DNS record has 1 tags, exceeding the quota of 0.`
+    pub fn is_tags_rejected(&self) -> bool {
+        self.message.contains("tags") && self.message.contains("quota")
+    }
+}
+
 impl Display for ApiError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}: {}", self.code, self.message)
     }
 }
 
+/// Every error cloudflare responded with to a failed request.
+///
+/// Usually there is just one, but batches report each invalid change separately.
+#[derive(Debug, Clone, thiserror::Error)]
+pub struct ApiErrors(pub Vec<ApiError>);
+
+impl ApiErrors {
+    pub fn iter(&self) -> impl Iterator<Item = &ApiError> {
+        self.0.iter()
+    }
+
+    /// Returns true if any of the errors has one of the `codes`.
+    pub fn has_code(&self, codes: &[u32]) -> bool {
+        self.iter().any(|err| codes.contains(&err.code))
+    }
+}
+
+impl Display for ApiErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("request failed without any errors");
+        }
+
+        let errors = self.iter().map(ToString::to_string).collect::<Vec<_>>();
+        f.write_str(&errors.join("; "))
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Message {
     pub code: u32,
@@ -135,17 +595,52 @@ impl Display for Message {
     }
 }
 
+/// Pagination information returned alongside results from listing endpoints.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ResultInfo {
+    pub page: u32,
+    #[serde(default)]
+    pub count: u32,
+    #[serde(default)]
+    pub total_count: u32,
+    #[serde(default)]
+    pub total_pages: u32,
+}
+
+impl ResultInfo {
+    /// Returns true if there are more pages after this one.
+    pub fn has_next_page(&self) -> bool {
+        self.page < self.total_pages
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
-#[serde(from = "InternalApiResult<T>")]
+#[serde(try_from = "InternalApiResult<T>")]
 pub enum ApiResult<T> {
-    Success { result: T, messages: Vec<Message> },
-    Error { errors: Vec<ApiError> },
+    Success {
+        result: T,
+        result_info: Option<ResultInfo>,
+        messages: Vec<Message>,
+    },
+    Error {
+        errors: Vec<ApiError>,
+    },
 }
 
 impl<T> ApiResult<T> {
-    pub fn into_result(self) -> Result<T, ApiError> {
+    /// Pagination information, if the result is a successful page of a listing.
+    pub fn result_info(&self) -> Option<ResultInfo> {
+        match self {
+            ApiResult::Success { result_info, .. } => *result_info,
+            ApiResult::Error { .. } => None,
+        }
+    }
+
+    pub fn into_result(self) -> Result<T, ApiErrors> {
         match self {
-            ApiResult::Success { result, messages } => {
+            ApiResult::Success {
+                result, messages, ..
+            } => {
                 if !messages.is_empty() {
                     trace!(
                         "unpacking ApiResult, but dropping messages: {}",
@@ -158,7 +653,7 @@ impl<T> ApiResult<T> {
                 }
                 Ok(result)
             }
-            ApiResult::Error { mut errors } => Err(errors.pop().unwrap()),
+            ApiResult::Error { errors } => Err(ApiErrors(errors)),
         }
     }
 }
@@ -171,23 +666,94 @@ struct InternalApiResult<T> {
     messages: Vec<Message>,
     success: bool,
     result: Option<T>,
+    #[serde(default)]
+    result_info: Option<ResultInfo>,
 }
 
-impl<'de, T: Deserialize<'de>> From<InternalApiResult<T>> for ApiResult<T> {
-    fn from(value: InternalApiResult<T>) -> Self {
-        if value.success {
-            ApiResult::Success {
-                result: value.result.unwrap(),
-                messages: value.messages,
-            }
-        } else {
-            ApiResult::Error {
+/// Successful api result without a result, such as of deletions and empty batches.
+#[derive(Debug, thiserror::Error)]
+#[error("successful api result is missing its result")]
+pub struct MissingResult;
+
+impl<'de, T: Deserialize<'de>> TryFrom<InternalApiResult<T>> for ApiResult<T> {
+    type Error = MissingResult;
+
+    fn try_from(value: InternalApiResult<T>) -> Result<Self, MissingResult> {
+        if !value.success {
+            return Ok(ApiResult::Error {
                 errors: value.errors,
-            }
+            });
         }
+
+        // Serde maps `null` onto the missing result even for optional results,
+        // so results which may be absent, like `Option` and `()`, are
+        // deserialized from nothing instead.
+        let result = match value.result {
+            Some(result) => result,
+            None => T::deserialize(UnitDeserializer::<serde::de::value::Error>::new())
+                .map_err(|_| MissingResult)?,
+        };
+
+        Ok(ApiResult::Success {
+            result,
+            result_info: value.result_info,
+            messages: value.messages,
+        })
     }
 }
 
+#[cfg(test)]
+#[test]
+fn missing_result() {
+    // Deletions and empty batches succeed without a result.
+    for body in [
+        r#"{"result": null, "success": true, "errors": [], "messages": []}"#,
+        r#"{"success": true, "errors": [], "messages": []}"#,
+    ] {
+        let result = serde_json::from_str::<ApiResult<Option<serde_json::Value>>>(body).unwrap();
+        assert!(result.into_result().unwrap().is_none());
+        let result = serde_json::from_str::<ApiResult<serde::de::IgnoredAny>>(body).unwrap();
+        assert!(result.into_result().is_ok());
+        assert!(serde_json::from_str::<ApiResult<Record>>(body).is_err());
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn parse_paginated() {
+    let result = serde_json::from_str::<ApiResult<Vec<Zone>>>(
+        r#"{
+            "result": [
+                {
+                    "id": "023e105f4ecef8ad9ca31a8372d0c353",
+                    "name": "kubi.zone",
+                    "name_servers": ["ada.ns.cloudflare.com", "bob.ns.cloudflare.com"],
+                    "status": "pending",
+                    "paused": false
+                }
+            ],
+            "result_info": {
+                "page": 1,
+                "per_page": 1,
+                "count": 1,
+                "total_count": 2,
+                "total_pages": 2
+            },
+            "success": true,
+            "errors": [],
+            "messages": []
+        }"#,
+    )
+    .unwrap();
+
+    assert!(result.result_info().unwrap().has_next_page());
+    let zones = result.into_result().unwrap();
+    assert_eq!(zones.len(), 1);
+    assert_eq!(zones[0].name_servers.len(), 2);
+    assert_eq!(zones[0].status, ZoneStatus::Pending);
+    assert!(!zones[0].is_active());
+}
+
 #[cfg(test)]
 #[test]
 fn parse() {
@@ -208,3 +774,74 @@ fn parse() {
     )
     .unwrap();
 }
+
+#[cfg(test)]
+#[test]
+fn parse_raw_record() {
+    let unsupported =
+        r#"{"id": "1", "name": "kubi.zone", "type": "SPF", "content": "v=spf1 -all", "ttl": 300}"#;
+    assert!(serde_json::from_str::<Record>(unsupported).is_err());
+    assert!(serde_json::from_str::<RawRecord>(unsupported).is_ok());
+
+    let invalid =
+        r#"{"id": "1", "name": "bad..kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300}"#;
+    assert_eq!(
+        serde_json::from_str::<RawRecord>(invalid).unwrap().name,
+        "bad..kubi.zone"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn invalid_record_name() {
+    let invalid = serde_json::from_str::<Record>(
+        r#"{"id": "1", "name": "bad..kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300}"#,
+    );
+    assert!(invalid.is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn api_errors() {
+    let errors = serde_json::from_str::<ApiResult<Record>>(
+        r#"{
+            "result": null,
+            "success": false,
+            "errors": [
+                {"code": 9005, "message": "Content for A record is invalid."},
+                {"code": 81057, "message": "Record already exists."}
+            ],
+            "messages": []
+        }"#,
+    )
+    .unwrap()
+    .into_result()
+    .unwrap_err();
+    assert!(errors.has_code(&[81057]));
+    assert_eq!(
+        errors.to_string(),
+        "9005: Content for A record is invalid.; 81057: Record already exists."
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn comment_length_limit() {
+    let comment = owner_comment("kubizone", Some(&"x".repeat(200)));
+    assert_eq!(comment.chars().count(), MAX_COMMENT_LENGTH);
+    assert!(is_marked_by(&comment, "kubizone"));
+
+    let owner = "kubizone-".repeat(12);
+    let comment = owner_comment(&owner, Some("managed by kubizone"));
+    assert!(comment.chars().count() <= MAX_COMMENT_LENGTH);
+    assert!(is_marked_by(&comment, &owner));
+    assert!(!is_marked_by(&comment, &format!("{owner}other")));
+
+    // Comments cut off by cloudflare rather than by us.
+    let cut_off: String = format!("{MANAGED_BY_PREFIX}{owner}")
+        .chars()
+        .take(MAX_COMMENT_LENGTH)
+        .collect();
+    assert!(is_marked_by(&cut_off, &owner));
+    assert!(!is_marked_by(&comment, "kubizone"));
+}
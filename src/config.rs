@@ -0,0 +1,520 @@
+//! Options loaded from a configuration file, see `--config`.
+//!
+//! The file sets the defaults of the command line options, so options given on
+//! the command line or through the environment still take precedence over it.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{ArgMatches, CommandFactory as _, FromArgMatches as _};
+use serde_yaml::Value;
+use tracing::{debug, warn};
+
+use crate::Args;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{}: {1}", .0.display())]
+    Io(PathBuf, std::io::Error),
+    #[error("{}: {1}", .0.display())]
+    Yaml(PathBuf, serde_yaml::Error),
+    #[error("{}: {1} must be a string, number, boolean or list of them", .0.display())]
+    InvalidValue(PathBuf, String),
+    #[error("{}: unknown options {}", .0.display(), .1.join(", "))]
+    UnknownOptions(PathBuf, Vec<String>),
+}
+
+/// Values of options, by their long name.
+type Options = BTreeMap<String, Vec<String>>;
+
+/// Configuration file, setting options by their long name without the
+/// leading dashes, such as `mode: delete` or `record-types: [A, AAAA]`.
+///
+/// Options only applying to one subcommand can be set in a section named
+/// after it, such as `reconcile: {requeue-time-secs: 60}`, which takes
+/// precedence over the same option outside of it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config {
+    path: PathBuf,
+    options: Options,
+    sections: BTreeMap<String, Options>,
+}
+
+impl Config {
+    /// Read the configuration file, which must be YAML, or JSON.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|err| Error::Io(path.to_owned(), err))?;
+
+        Self::parse(path, &contents)
+    }
+
+    fn parse(path: &Path, contents: &str) -> Result<Self, Error> {
+        let mut config = Config {
+            path: path.to_owned(),
+            ..Config::default()
+        };
+
+        let values: Option<BTreeMap<String, Value>> =
+            serde_yaml::from_str(contents).map_err(|err| Error::Yaml(path.to_owned(), err))?;
+        for (key, value) in values.unwrap_or_default() {
+            let key = normalize(&key);
+            match value {
+                Value::Mapping(mapping) => {
+                    let section = config.sections.entry(key.clone()).or_default();
+                    for (option, value) in section_values(path, &key, mapping)? {
+                        let option = normalize(&option);
+                        let values = option_values(path, &format!("{key}.{option}"), value)?;
+                        section.insert(option, values);
+                    }
+                }
+                value => {
+                    let values = option_values(path, &key, value)?;
+                    config.options.insert(key, values);
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The command with the options of this configuration as defaults.
+    ///
+    /// Fails if the configuration sets options which do not exist.
+    pub fn apply(&self, command: clap::Command) -> Result<clap::Command, Error> {
+        let mut used = BTreeSet::new();
+        let options = self
+            .options
+            .iter()
+            .map(|(key, values)| (key.clone(), (key.clone(), values.clone())))
+            .collect();
+
+        let mut command = with_defaults(command, &options, &mut used);
+        for name in command
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name().to_string())
+            .collect::<Vec<_>>()
+        {
+            let mut options = options.clone();
+            for (key, values) in self.sections.get(&name).into_iter().flatten() {
+                options.insert(key.clone(), (format!("{name}.{key}"), values.clone()));
+            }
+
+            command = command.mut_subcommand(&name, |subcommand| {
+                with_defaults(subcommand, &options, &mut used)
+            });
+        }
+
+        let unknown =
+            self.options
+                .keys()
+                .cloned()
+                .chain(self.sections.iter().flat_map(|(name, section)| {
+                    section.keys().map(move |key| format!("{name}.{key}"))
+                }))
+                .filter(|key| !used.contains(key))
+                .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            return Err(Error::UnknownOptions(self.path.clone(), unknown));
+        }
+
+        Ok(command)
+    }
+}
+
+/// Set the `options` as defaults of the arguments of the `command` with
+/// their long name, recording the keys they were set by as used.
+fn with_defaults(
+    mut command: clap::Command,
+    options: &BTreeMap<String, (String, Vec<String>)>,
+    used: &mut BTreeSet<String>,
+) -> clap::Command {
+    let arguments = command
+        .get_arguments()
+        .filter_map(|arg| Some((arg.get_id().clone(), arg.get_long()?.to_string())))
+        .collect::<Vec<_>>();
+
+    let mut defaulted = Vec::new();
+    for (id, long) in arguments {
+        let Some((key, values)) = options.get(&long).filter(|_| long != CONFIG_OPTION) else {
+            continue;
+        };
+
+        used.insert(key.clone());
+        command = command.mut_arg(&id, |arg| {
+            arg.required(false).default_values(values.clone())
+        });
+        defaulted.push(id);
+    }
+
+    // Defaults never satisfy required groups, such as the credentials, on their own.
+    // Arguments only join their groups once the command is built.
+    let mut built = command.clone();
+    built.build();
+    let satisfied = built
+        .get_groups()
+        .filter(|group| group.is_required_set())
+        .filter(|group| group.get_args().any(|arg| defaulted.contains(arg)))
+        .map(|group| group.get_id().clone())
+        .collect::<Vec<_>>();
+    for group in satisfied {
+        command = command.mut_group(group, |group| group.required(false));
+    }
+
+    command
+}
+
+/// Option naming the configuration file.
+const CONFIG_OPTION: &str = "config";
+
+/// Options are spelled like on the command line, but underscores are accepted as well.
+fn normalize(key: &str) -> String {
+    key.trim().replace('_', "-")
+}
+
+fn section_values(
+    path: &Path,
+    name: &str,
+    section: serde_yaml::Mapping,
+) -> Result<Vec<(String, Value)>, Error> {
+    section
+        .into_iter()
+        .map(|(key, value)| match key {
+            Value::String(key) => Ok((key, value)),
+            _ => Err(Error::InvalidValue(path.to_owned(), name.to_string())),
+        })
+        .collect()
+}
+
+fn option_values(path: &Path, key: &str, value: Value) -> Result<Vec<String>, Error> {
+    let scalar = |value: Value| match value {
+        Value::String(value) => Ok(value),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        _ => Err(Error::InvalidValue(path.to_owned(), key.to_string())),
+    };
+
+    match value {
+        Value::Null => Ok(Vec::new()),
+        Value::Sequence(values) => values.into_iter().map(scalar).collect(),
+        value => Ok(vec![scalar(value)?]),
+    }
+}
+
+/// Path of the configuration file given by `--config` in the `args`, or
+/// otherwise the `CONFIG` environment variable.
+pub fn path(args: &[OsString]) -> Option<PathBuf> {
+    let flag = format!("--{CONFIG_OPTION}");
+    let mut args = args.iter().map(|arg| arg.to_string_lossy());
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == flag {
+            return args.next().map(|path| PathBuf::from(path.as_ref()));
+        }
+        if let Some(path) = arg.strip_prefix(&format!("{flag}=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    std::env::var_os("CONFIG").map(PathBuf::from)
+}
+
+/// Parse the arguments of the controller, with the options of the
+/// configuration file as defaults, if one is given.
+pub fn parse(args: Vec<OsString>) -> Result<Args, clap::Error> {
+    let config = match path(&args) {
+        Some(path) => Some(
+            Config::load(&path)
+                .map_err(|err| Args::command().error(clap::error::ErrorKind::InvalidValue, err))?,
+        ),
+        None => None,
+    };
+
+    Args::from_arg_matches(&matches(&args, config.as_ref())?)
+}
+
+fn matches(args: &[OsString], config: Option<&Config>) -> Result<ArgMatches, clap::Error> {
+    let mut command = Args::command();
+    if let Some(config) = config {
+        command = config
+            .apply(command)
+            .map_err(|err| Args::command().error(clap::error::ErrorKind::InvalidValue, err))?;
+    }
+
+    command.try_get_matches_from(args)
+}
+
+/// Long names of the options whose values differ between the `old` and `new`
+/// matches of the `command`, including those of the subcommand they share.
+fn changes(command: &clap::Command, old: &ArgMatches, new: &ArgMatches) -> BTreeSet<String> {
+    let values = |matches: &ArgMatches, id: &str| {
+        matches
+            .try_get_raw(id)
+            .ok()
+            .flatten()
+            .map(|values| values.map(OsStr::to_owned).collect::<Vec<_>>())
+    };
+
+    let mut changes = command
+        .get_arguments()
+        .filter(|arg| values(old, arg.get_id().as_str()) != values(new, arg.get_id().as_str()))
+        .filter_map(|arg| arg.get_long().map(String::from))
+        .collect::<BTreeSet<_>>();
+
+    if let (Some((name, old)), Some((new_name, new))) = (old.subcommand(), new.subcommand()) {
+        if let Some(subcommand) = command.find_subcommand(name).filter(|_| name == new_name) {
+            changes.extend(self::changes(subcommand, old, new));
+        }
+    }
+
+    changes
+}
+
+/// Configuration file watched for changes, see [`Reload::changed`].
+pub struct Reload {
+    args: Vec<OsString>,
+    path: PathBuf,
+    contents: String,
+    matches: ArgMatches,
+}
+
+impl Reload {
+    /// Watch the configuration file given in the `args`, if any.
+    pub fn new(args: Vec<OsString>) -> Option<Self> {
+        let path = path(&args)?;
+        let contents = std::fs::read_to_string(&path).ok()?;
+        let config = Config::parse(&path, &contents).ok()?;
+        let matches = matches(&args, Some(&config)).ok()?;
+
+        Some(Reload {
+            args,
+            path,
+            contents,
+            matches,
+        })
+    }
+
+    /// Wait until the contents of the configuration file change any of the
+    /// options, checking it every `interval`.
+    ///
+    /// Returns the long names of the changed options, along with the arguments
+    /// parsed with the new contents. Invalid contents are reported and ignored.
+    pub async fn changed(&mut self, interval: Duration) -> (BTreeSet<String>, Args) {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let contents = match tokio::fs::read_to_string(&self.path).await {
+                Ok(contents) if contents != self.contents => contents,
+                Ok(_) => continue,
+                Err(err) => {
+                    warn!("failed to re-read {}: {err}", self.path.display());
+                    continue;
+                }
+            };
+            self.contents = contents;
+
+            let parsed = Config::parse(&self.path, &self.contents)
+                .map_err(|err| err.to_string())
+                .and_then(|config| {
+                    matches(&self.args, Some(&config)).map_err(|err| first_line(&err))
+                });
+            let matches = match parsed {
+                Ok(matches) => matches,
+                Err(err) => {
+                    warn!("ignoring changes to {}: {err}", self.path.display());
+                    continue;
+                }
+            };
+
+            let changes = changes(&Args::command(), &self.matches, &matches);
+            if changes.is_empty() {
+                debug!(
+                    "{} changed without changing any options",
+                    self.path.display()
+                );
+                continue;
+            }
+
+            match Args::from_arg_matches(&matches) {
+                Ok(args) => {
+                    self.matches = matches;
+                    return (changes, args);
+                }
+                Err(err) => warn!(
+                    "ignoring changes to {}: {}",
+                    self.path.display(),
+                    first_line(&err)
+                ),
+            }
+        }
+    }
+}
+
+/// Message of a clap error, without the usage it is followed by.
+fn first_line(err: &clap::Error) -> String {
+    let message = err.to_string();
+    let line = message.lines().next().unwrap_or_default();
+    line.strip_prefix("error: ").unwrap_or(line).to_string()
+}
+
+#[cfg(test)]
+#[test]
+fn defaults_from_config() {
+    let path = Path::new("config.yaml");
+    let config = Config::parse(
+        path,
+        r#"
+mode: delete
+dry_run: true
+cf-api-token-file: /var/run/secrets/cloudflare/token
+record-types: [A, AAAA]
+reconcile:
+  requeue-time-secs: 60
+  mode: upsert
+"#,
+    )
+    .unwrap();
+
+    let args = |args: &[&str]| {
+        let command = config.apply(Args::command()).unwrap();
+        let matches = command
+            .try_get_matches_from(
+                std::iter::once("kubizone-cloudflare").chain(args.iter().copied()),
+            )
+            .unwrap();
+        Args::from_arg_matches(&matches).unwrap()
+    };
+
+    let crate::Command::SyncOnce { sync, dry_run, .. } = args(&["sync-once"]).command else {
+        panic!("not parsed as sync-once");
+    };
+    assert_eq!(sync.mode, crate::Mode::Delete);
+    assert_eq!(
+        sync.record_types,
+        [kubizone_common::Type::A, kubizone_common::Type::AAAA]
+    );
+    assert!(dry_run);
+
+    // Sections take precedence, and the command line over either.
+    let crate::Command::Reconcile {
+        sync,
+        requeue_time_secs,
+        ..
+    } = args(&["reconcile"]).command
+    else {
+        panic!("not parsed as reconcile");
+    };
+    assert_eq!(sync.mode, crate::Mode::Upsert);
+    assert_eq!(requeue_time_secs, 60);
+
+    let crate::Command::SyncOnce { sync, .. } = args(&["sync-once", "--mode", "upsert"]).command
+    else {
+        panic!("not parsed as sync-once");
+    };
+    assert_eq!(sync.mode, crate::Mode::Upsert);
+
+    let unknown = Config::parse(path, "modes: delete\nplan: {requeue-time-secs: 60}").unwrap();
+    assert!(matches!(
+        unknown.apply(Args::command()),
+        Err(Error::UnknownOptions(_, options)) if options == ["modes", "plan.requeue-time-secs"]
+    ));
+
+    assert_eq!(
+        self::path(&["reconcile".into(), "--config=/etc/config.yaml".into()]),
+        Some(PathBuf::from("/etc/config.yaml"))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn changed_options() {
+    let path = Path::new("config.yaml");
+    let args = [
+        "kubizone-cloudflare",
+        "reconcile",
+        "--record-cache-secs",
+        "60",
+    ]
+    .map(OsString::from);
+    let matches = |contents: &str| {
+        let config = Config::parse(path, contents).unwrap();
+        matches(&args, Some(&config)).unwrap()
+    };
+
+    let old = matches("cf-api-token-file: /token\nmode: upsert\nmin-ttl: 60\n");
+    let new = matches("cf-api-token-file: /token\nmode: delete\nrecord-cache-secs: 300\nreconcile: {requeue-time-secs: 60}\n");
+
+    // Options given on the command line are unaffected by the file.
+    assert_eq!(
+        changes(&Args::command(), &old, &new),
+        BTreeSet::from(["min-ttl", "mode", "requeue-time-secs"].map(String::from))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn invalid_config() {
+    let path = Path::new("config.yaml");
+
+    assert!(matches!(
+        Config::load(Path::new("/nonexistent/config.yaml")),
+        Err(Error::Io(..))
+    ));
+    assert!(matches!(
+        Config::parse(path, "mode: [delete"),
+        Err(Error::Yaml(..))
+    ));
+    assert!(matches!(
+        Config::parse(path, "record-types: [{type: A}]"),
+        Err(Error::InvalidValue(_, key)) if key == "record-types"
+    ));
+    assert!(matches!(
+        Config::parse(path, "reconcile: {mode: {delete: true}}"),
+        Err(Error::InvalidValue(_, key)) if key == "reconcile.mode"
+    ));
+    assert!(matches!(
+        Config::parse(path, "reconcile: {1: delete}"),
+        Err(Error::InvalidValue(_, key)) if key == "reconcile"
+    ));
+
+    // Empty files and options set nothing.
+    assert_eq!(
+        Config::parse(path, "").unwrap(),
+        Config {
+            path: path.to_owned(),
+            ..Config::default()
+        }
+    );
+    assert_eq!(
+        Config::parse(path, "record_types:").unwrap().options["record-types"],
+        Vec::<String>::new()
+    );
+
+    // Arguments after `--` are not options.
+    assert_eq!(
+        self::path(&[
+            "reconcile".into(),
+            "--config".into(),
+            "/etc/config.yaml".into()
+        ]),
+        Some(PathBuf::from("/etc/config.yaml"))
+    );
+    assert_eq!(
+        self::path(&["--".into(), "--config=/etc/config.yaml".into()]),
+        std::env::var_os("CONFIG").map(PathBuf::from)
+    );
+
+    let err = matches(
+        &["kubizone-cloudflare", "reconcile", "--mode", "sometimes"].map(OsString::from),
+        None,
+    )
+    .unwrap_err();
+    assert!(first_line(&err).starts_with("invalid value 'sometimes' for '--mode"));
+}
@@ -0,0 +1,195 @@
+use kube::CustomResource;
+use kubizone_common::Pattern;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// API group of the resources and annotations owned by this controller.
+#[cfg(feature = "dev")]
+pub const GROUP: &str = "dev.cloudflare.kubi.zone";
+/// API group of the resources and annotations owned by this controller.
+#[cfg(not(feature = "dev"))]
+pub const GROUP: &str = "cloudflare.kubi.zone";
+
+/// Reference to a key within a Secret in the same namespace as the referencing resource.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, Hash, PartialEq, Eq)]
+pub struct SecretKeyRef {
+    pub name: String,
+    pub key: String,
+}
+
+/// Cloudflare API token to use for the zones matching any of the given patterns.
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema, Hash, PartialEq, Eq)]
+// The 'dev' feature flag puts the resource in a separate dev.cloudflare.kubi.zone group,
+// matching the behaviour of the kubizone resources themselves.
+#[cfg_attr(
+    feature = "dev",
+    kube(
+        group = "dev.cloudflare.kubi.zone",
+        version = "v1alpha1",
+        kind = "CloudflareCredential",
+        namespaced
+    )
+)]
+#[cfg_attr(
+    not(feature = "dev"),
+    kube(
+        group = "cloudflare.kubi.zone",
+        version = "v1alpha1",
+        kind = "CloudflareCredential",
+        namespaced
+    )
+)]
+#[kube(printcolumn = r#"{"name":"secret", "jsonPath": ".spec.secretRef.name", "type": "string"}"#)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudflareCredentialSpec {
+    /// Secret key containing the Cloudflare API token.
+    pub secret_ref: SecretKeyRef,
+
+    /// Patterns of zones this credential is used for. Credentials without any
+    /// patterns are ignored, and zones available to the controller's own
+    /// credentials are never taken over.
+    #[serde(default)]
+    pub zones: Vec<Pattern>,
+}
+
+/// Reference to a key within a Secret in the given namespace.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, Hash, PartialEq, Eq)]
+pub struct NamespacedSecretKeyRef {
+    pub namespace: String,
+    pub name: String,
+    pub key: String,
+}
+
+/// Defaults for the zones matching any of the given patterns.
+///
+/// These take precedence over the command line flags, but the annotations
+/// of a zone still take precedence over them.
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema, Hash, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "dev",
+    kube(
+        group = "dev.cloudflare.kubi.zone",
+        version = "v1alpha1",
+        kind = "CloudflareZoneConfig"
+    )
+)]
+#[cfg_attr(
+    not(feature = "dev"),
+    kube(
+        group = "cloudflare.kubi.zone",
+        version = "v1alpha1",
+        kind = "CloudflareZoneConfig"
+    )
+)]
+#[kube(printcolumn = r#"{"name":"account", "jsonPath": ".spec.accountId", "type": "string"}"#)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudflareZoneConfigSpec {
+    /// Patterns of zones this config applies to. An empty list matches any zone.
+    #[serde(default)]
+    pub zones: Vec<Pattern>,
+
+    /// Whether records are proxied through cloudflare, unless they or their
+    /// zone say otherwise through the proxied annotation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxied: Option<bool>,
+
+    /// Minimum TTL of records, overriding `--min-ttl`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_ttl: Option<u32>,
+
+    /// Whether managed records without an entry in the zone are deleted,
+    /// overriding `--mode`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prune: Option<bool>,
+
+    /// Cloudflare account zones are created in, and load balancers and
+    /// workers belong to, overriding `--cf-account-id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+
+    /// Secret key containing the Cloudflare API token used for the zones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_ref: Option<NamespacedSecretKeyRef>,
+
+    /// Records which are never changed or deleted, as `<fqdn> <type> [rdata]`,
+    /// like in the protected annotation of zones.
+    #[serde(default)]
+    pub protected: Vec<String>,
+}
+
+/// Changes made by the controller to the cloudflare zone of a Zone within a
+/// single sync, including those cloudflare rejected.
+///
+/// Created in the namespace of the Zone, which owns them, so they are deleted
+/// along with it. Only the most recent ones are kept for each Zone.
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema, Hash, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "dev",
+    kube(
+        group = "dev.cloudflare.kubi.zone",
+        version = "v1alpha1",
+        kind = "CloudflareChangeSet",
+        namespaced
+    )
+)]
+#[cfg_attr(
+    not(feature = "dev"),
+    kube(
+        group = "cloudflare.kubi.zone",
+        version = "v1alpha1",
+        kind = "CloudflareChangeSet",
+        namespaced
+    )
+)]
+#[kube(printcolumn = r#"{"name":"zone", "jsonPath": ".spec.zone", "type": "string"}"#)]
+#[kube(printcolumn = r#"{"name":"cloudflare zone", "jsonPath": ".spec.cfZone", "type": "string"}"#)]
+#[kube(printcolumn = r#"{"name":"failed", "jsonPath": ".spec.failed", "type": "integer"}"#)]
+#[kube(
+    printcolumn = r#"{"name":"age", "jsonPath": ".metadata.creationTimestamp", "type": "date"}"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudflareChangeSetSpec {
+    /// Name of the Zone the changes were made for.
+    pub zone: String,
+
+    /// Name of the cloudflare zone the changes were made to.
+    pub cf_zone: String,
+
+    pub cf_zone_id: String,
+
+    /// Owner in the markers of the controller which made the changes.
+    pub actor: String,
+
+    /// Number of the changes which cloudflare rejected.
+    pub failed: u32,
+
+    pub changes: Vec<CloudflareChange>,
+}
+
+/// A single change to a record, as made by the controller.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, Hash, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudflareChange {
+    /// Time the change was made, in RFC 3339 format.
+    pub time: String,
+
+    /// Kind of change, one of `create`, `update`, `adopt` or `delete`.
+    pub action: String,
+
+    pub record: String,
+
+    #[serde(rename = "type")]
+    pub type_: String,
+
+    /// Value of the record before the change, unless it was created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+
+    /// Value of the record after the change, unless it was deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+
+    /// Error cloudflare rejected the change with, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
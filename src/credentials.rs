@@ -0,0 +1,251 @@
+use std::{fmt::Display, path::PathBuf, str::FromStr, time::Duration};
+
+use futures::{StreamExt as _, TryStreamExt as _};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{
+    runtime::{watcher, WatchStreamExt as _},
+    Api, Client as KubeClient,
+};
+use tokio::sync::watch::Sender;
+use tracing::{info, warn};
+
+use crate::cloudflare::{CloudFlare, Credentials};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("kube: {0}")]
+    Kube(#[from] kube::Error),
+    #[error("secret {0} does not contain the key")]
+    MissingKey(SecretRef),
+    #[error("secret {0} value is not valid utf-8")]
+    InvalidToken(SecretRef),
+    #[error("token file {}: {1}", .0.display())]
+    File(PathBuf, std::io::Error),
+}
+
+/// Where the cloudflare credentials are read from.
+#[derive(Debug, Clone)]
+pub enum CredentialSource {
+    /// Credentials passed directly as arguments.
+    Static(Credentials),
+    /// Token stored in a Kubernetes Secret, watched for changes.
+    Secret(SecretRef),
+    /// Token stored in a file, re-read periodically.
+    File(PathBuf),
+}
+
+impl CredentialSource {
+    /// Read the current credentials.
+    pub async fn read(&self, client: KubeClient) -> Result<Credentials, Error> {
+        match self {
+            CredentialSource::Static(credentials) => Ok(credentials.clone()),
+            CredentialSource::Secret(secret) => secret.read(client).await.map(Credentials::Token),
+            CredentialSource::File(path) => read_token_file(path).await.map(Credentials::Token),
+        }
+    }
+
+    /// Returns true if the credentials are an api token, rather than a global api key.
+    pub fn is_token(&self) -> bool {
+        !matches!(
+            self,
+            CredentialSource::Static(Credentials::GlobalKey { .. })
+        )
+    }
+
+    /// Keep the cloudflare client up to date with the credential source, until
+    /// the last receiver is dropped.
+    ///
+    /// Static credentials never change, so this returns immediately for those.
+    /// Token files are re-read every `file_refresh_interval`.
+    pub async fn watch(
+        self,
+        client: KubeClient,
+        tx: Sender<CloudFlare>,
+        current: Credentials,
+        file_refresh_interval: Duration,
+    ) {
+        match self {
+            CredentialSource::Static(_) => {}
+            CredentialSource::Secret(secret) => secret.watch(client, tx, current).await,
+            CredentialSource::File(path) => {
+                watch_token_file(path, file_refresh_interval, tx, current).await
+            }
+        }
+    }
+}
+
+impl Display for CredentialSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CredentialSource::Static(Credentials::Token(_)) => f.write_str("command line"),
+            CredentialSource::Static(Credentials::GlobalKey { email, .. }) => {
+                write!(f, "global api key for {email}")
+            }
+            CredentialSource::Secret(secret) => write!(f, "secret {secret}"),
+            CredentialSource::File(path) => write!(f, "file {}", path.display()),
+        }
+    }
+}
+
+async fn read_token_file(path: &PathBuf) -> Result<String, Error> {
+    tokio::fs::read_to_string(path)
+        .await
+        .map(|token| token.trim().to_string())
+        .map_err(|err| Error::File(path.clone(), err))
+}
+
+/// Periodically re-read the token file, replacing the cloudflare client
+/// with one using the new token whenever the contents change.
+async fn watch_token_file(
+    path: PathBuf,
+    refresh_interval: Duration,
+    tx: Sender<CloudFlare>,
+    mut current: Credentials,
+) {
+    loop {
+        tokio::time::sleep(refresh_interval).await;
+
+        match read_token_file(&path).await.map(Credentials::Token) {
+            Ok(credentials) if credentials == current => {}
+            Ok(credentials) => {
+                let cloudflare = match tx.borrow().with_credentials(&credentials) {
+                    Ok(cloudflare) => cloudflare,
+                    Err(err) => {
+                        warn!("ignoring token file {}: {err}", path.display());
+                        continue;
+                    }
+                };
+                info!(
+                    "api token in {} changed, rebuilding cloudflare client",
+                    path.display()
+                );
+                current = credentials;
+
+                if tx.send(cloudflare).is_err() {
+                    return;
+                }
+            }
+            Err(err) => warn!("failed to refresh api token: {err}"),
+        }
+    }
+}
+
+/// Reference to a single key within a Kubernetes Secret, written as `namespace/name#key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretRef {
+    pub namespace: String,
+    pub name: String,
+    pub key: String,
+}
+
+impl FromStr for SecretRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, key) = s
+            .split_once('#')
+            .ok_or_else(|| format!("secret reference {s} is missing a #key"))?;
+
+        let (namespace, name) = path
+            .split_once('/')
+            .ok_or_else(|| format!("secret reference {s} is missing a namespace/"))?;
+
+        if namespace.is_empty() || name.is_empty() || key.is_empty() {
+            return Err(format!(
+                "secret reference {s} must be of the form namespace/name#key"
+            ));
+        }
+
+        Ok(SecretRef {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            key: key.to_string(),
+        })
+    }
+}
+
+impl Display for SecretRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}#{}", self.namespace, self.name, self.key)
+    }
+}
+
+impl SecretRef {
+    fn api(&self, client: KubeClient) -> Api<Secret> {
+        Api::namespaced(client, &self.namespace)
+    }
+
+    /// Extract the referenced value from the secret.
+    fn value(&self, secret: &Secret) -> Result<String, Error> {
+        let value = secret
+            .data
+            .as_ref()
+            .and_then(|data| data.get(&self.key))
+            .ok_or_else(|| Error::MissingKey(self.clone()))?;
+
+        String::from_utf8(value.0.clone())
+            .map(|value| value.trim().to_string())
+            .map_err(|_| Error::InvalidToken(self.clone()))
+    }
+
+    /// Read the current value of the referenced key.
+    pub async fn read(&self, client: KubeClient) -> Result<String, Error> {
+        let secret = self.api(client).get(&self.name).await?;
+
+        self.value(&secret)
+    }
+
+    /// Watch the secret for changes, replacing the cloudflare client with one
+    /// using the new token whenever the value changes.
+    pub async fn watch(self, client: KubeClient, tx: Sender<CloudFlare>, mut current: Credentials) {
+        let config = watcher::Config::default().fields(&format!("metadata.name={}", self.name));
+
+        let mut secrets = watcher(self.api(client), config)
+            .default_backoff()
+            .applied_objects()
+            .boxed();
+
+        loop {
+            match secrets.try_next().await {
+                Ok(Some(secret)) => match self.value(&secret).map(Credentials::Token) {
+                    Ok(credentials) if credentials == current => {}
+                    Ok(credentials) => {
+                        let cloudflare = match tx.borrow().with_credentials(&credentials) {
+                            Ok(cloudflare) => cloudflare,
+                            Err(err) => {
+                                warn!("ignoring update to secret {self}: {err}");
+                                continue;
+                            }
+                        };
+                        info!("api token in secret {self} changed, rebuilding cloudflare client");
+                        current = credentials;
+
+                        if tx.send(cloudflare).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => warn!("ignoring update to secret {self}: {err}"),
+                },
+                Ok(None) => return,
+                Err(err) => warn!("watching secret {self} failed: {err}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn parse_secret_ref() {
+    assert_eq!(
+        SecretRef::from_str("kubizone/cloudflare#token"),
+        Ok(SecretRef {
+            namespace: "kubizone".to_string(),
+            name: "cloudflare".to_string(),
+            key: "token".to_string(),
+        })
+    );
+
+    assert!(SecretRef::from_str("kubizone/cloudflare").is_err());
+    assert!(SecretRef::from_str("cloudflare#token").is_err());
+    assert!(SecretRef::from_str("/cloudflare#token").is_err());
+}
@@ -0,0 +1,142 @@
+//! Custom Hostnames (SSL for SaaS) of the cloudflare zone, declared through an
+//! annotation on Records, and synced with `--custom-hostnames`.
+//!
+//! The status of each custom hostname, including the records needed to
+//! validate it, is published in an annotation of its Record.
+
+use kube::ResourceExt as _;
+use kubizone_crds::v1alpha1::Record;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{cloudflare::CustomHostname, crds::GROUP};
+
+/// Annotation on a Record listing the custom hostnames to create for it,
+/// separated by commas, such as `shop.customer.example,www.customer.example`.
+///
+/// The hostnames of customers are expected to point at the record through a CNAME.
+pub fn custom_hostnames_annotation() -> String {
+    format!("{GROUP}/custom-hostnames")
+}
+
+/// Annotation the [`HostnameStatus`] of the custom hostnames of a Record are stored in, as JSON.
+pub fn status_annotation() -> String {
+    format!("{GROUP}/custom-hostnames-status")
+}
+
+/// Status of a custom hostname, as published on its Record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostnameStatus {
+    pub hostname: String,
+    pub id: String,
+    /// Status of the custom hostname itself, such as `pending` or `active`.
+    pub status: String,
+    /// Status of its certificate, such as `pending_validation` or `active`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssl_status: Option<String>,
+    /// Records proving ownership of the hostname, or validating its certificate,
+    /// as `<name> <value>` for TXT records, or `<url> <body>` for http validation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub validation: Vec<String>,
+}
+
+impl From<&CustomHostname> for HostnameStatus {
+    fn from(custom_hostname: &CustomHostname) -> Self {
+        let mut validation = Vec::new();
+
+        if let Some(ownership) = &custom_hostname.ownership_verification {
+            validation.push(format!("{} {}", ownership.name, ownership.value));
+        }
+
+        for record in custom_hostname
+            .ssl
+            .iter()
+            .flat_map(|ssl| &ssl.validation_records)
+        {
+            if let (Some(name), Some(value)) = (&record.txt_name, &record.txt_value) {
+                validation.push(format!("{name} {value}"));
+            }
+            if let (Some(url), Some(body)) = (&record.http_url, &record.http_body) {
+                validation.push(format!("{url} {body}"));
+            }
+        }
+
+        HostnameStatus {
+            hostname: custom_hostname.hostname.clone(),
+            id: custom_hostname.id.clone(),
+            status: custom_hostname.status.clone(),
+            ssl_status: custom_hostname.ssl.as_ref().map(|ssl| ssl.status.clone()),
+            validation,
+        }
+    }
+}
+
+/// Custom hostnames declared by the record, in lowercase and without a trailing dot.
+pub fn declared(record: &Record) -> Vec<String> {
+    let Some(value) = record.annotations().get(&custom_hostnames_annotation()) else {
+        return Vec::new();
+    };
+
+    let mut hostnames = value
+        .split(',')
+        .map(|hostname| hostname.trim().trim_end_matches('.').to_lowercase())
+        .filter(|hostname| !hostname.is_empty())
+        .collect::<Vec<_>>();
+    hostnames.dedup();
+
+    hostnames
+}
+
+/// Status of the custom hostnames of the record, as last published.
+pub fn current(record: &Record) -> Vec<HostnameStatus> {
+    let annotation = status_annotation();
+    let Some(value) = record.annotations().get(&annotation) else {
+        return Vec::new();
+    };
+
+    match serde_json::from_str(value) {
+        Ok(current) => current,
+        Err(err) => {
+            warn!(
+                "ignoring {annotation} annotation of record {}, since it is not valid: {err}",
+                record.name_any()
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Custom hostnames created for the record, which it no longer declares.
+pub fn orphaned<'a>(current: &'a [HostnameStatus], declared: &[String]) -> Vec<&'a HostnameStatus> {
+    current
+        .iter()
+        .filter(|status| !declared.contains(&status.hostname))
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn declared_hostnames() {
+    let record: Record = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Record",
+        "metadata": {"name": "customers", "annotations": {
+            custom_hostnames_annotation(): "Shop.Customer.example., www.customer.example,",
+            status_annotation(): r#"[
+                {"hostname": "shop.customer.example", "id": "1", "status": "active"},
+                {"hostname": "old.customer.example", "id": "2", "status": "pending"}
+            ]"#
+        }},
+        "spec": {"domainName": "customers.kubi.zone.", "type": "CNAME", "rdata": "fallback.kubi.zone."}
+    }))
+    .unwrap();
+
+    let declared = declared(&record);
+    assert_eq!(declared, ["shop.customer.example", "www.customer.example"]);
+
+    let current = current(&record);
+    let orphaned = orphaned(&current, &declared);
+    assert_eq!(orphaned.len(), 1);
+    assert_eq!(orphaned[0].id, "2");
+}
@@ -0,0 +1,76 @@
+//! Delegation of DNSSEC to kubizone parent zones, through a DS Record
+//! created alongside zones with DNSSEC enabled in cloudflare.
+
+use kube::{api::ObjectMeta, Resource as _, ResourceExt as _};
+use kubizone_common::{Class, DomainName, Type};
+use kubizone_crds::v1alpha1::{DomainExt as _, Record, RecordSpec, Zone, ZoneRef};
+
+use crate::cloudflare::Dnssec;
+
+/// DS Record delegating DNSSEC of the zone from its kubizone parent zone,
+/// if it has one, and DNSSEC is enabled for its cloudflare zone.
+///
+/// The record references the parent zone directly, since the zone itself
+/// covers the name of the record too. It is owned by the zone, so it is
+/// deleted along with it.
+pub fn ds_record(zone: &Zone, dnssec: &Dnssec) -> Option<Record> {
+    let (name, namespace) = crate::parent_zone(zone)?;
+    let fqdn = zone.fqdn()?;
+    let rdata = dnssec.ds_rdata()?;
+
+    Some(Record {
+        metadata: ObjectMeta {
+            name: Some(format!("{}-ds", zone.name_any())),
+            namespace: zone.namespace(),
+            owner_references: zone.owner_ref(&()).map(|owner| vec![owner]),
+            ..Default::default()
+        },
+        spec: RecordSpec {
+            domain_name: DomainName::from(fqdn.clone()),
+            zone_ref: Some(ZoneRef {
+                name: name.to_string(),
+                namespace: Some(namespace.to_string()),
+            }),
+            type_: Type::DS,
+            class: Class::IN,
+            ttl: None,
+            rdata,
+        },
+        status: None,
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn delegate_to_parent() {
+    let zone = |labels: serde_json::Value| -> Zone {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "kubi.zone/v1alpha1",
+            "kind": "Zone",
+            "metadata": {"name": "dev", "namespace": "dns", "uid": "1", "labels": labels},
+            "spec": {"domainName": "dev", "delegations": []},
+            "status": {"fqdn": "dev.kubi.zone.", "entries": []}
+        }))
+        .unwrap()
+    };
+    let dnssec: Dnssec = serde_json::from_value(serde_json::json!({
+        "status": "pending",
+        "ds": "dev.kubi.zone. 3600 IN DS 2371 13 2 1F98",
+        "key_tag": 2371,
+        "algorithm": "13",
+        "digest_type": "2",
+        "digest": "1F98"
+    }))
+    .unwrap();
+
+    let parent = zone(serde_json::json!({kubizone_crds::PARENT_ZONE_LABEL: "kubi.zone.dns"}));
+    let record = ds_record(&parent, &dnssec).unwrap();
+    assert_eq!(record.name_any(), "dev-ds");
+    assert_eq!(record.spec.rdata, "2371 13 2 1F98");
+    let zone_ref = record.spec.zone_ref.unwrap();
+    assert_eq!(zone_ref.name, "kubi.zone");
+    assert_eq!(zone_ref.namespace.as_deref(), Some("dns"));
+    assert_eq!(record.metadata.owner_references.unwrap().len(), 1);
+
+    assert!(ds_record(&zone(serde_json::json!({})), &dnssec).is_none());
+}
@@ -0,0 +1,547 @@
+//! Diagnosis of misconfigurations, for the `doctor` subcommand.
+//!
+//! Rather than failing on the first problem, every check is run, and each of
+//! them reports findings along with hints on how to resolve them.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use k8s_openapi::{
+    api::{
+        authorization::v1::{
+            ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec,
+        },
+        coordination::v1::Lease,
+        core::v1::Secret,
+        events::v1::Event,
+    },
+    chrono::{DateTime, Duration, Utc},
+};
+use kube::{api::PostParams, Api, Client as KubeClient, Resource};
+use kubizone_crds::v1alpha1::{DomainExt as _, Record, Zone};
+
+use crate::{
+    account::Accounts,
+    cloudflare::{self, CloudFlare, TokenStatus},
+    crds::{CloudflareChangeSet, CloudflareCredential, CloudflareZoneConfig},
+    credentials::CredentialSource,
+    ownership::Ownership,
+    CloudflareArgs, SyncArgs,
+};
+
+/// Api tokens expiring within this time are reported.
+const EXPIRY_WARNING_DAYS: i64 = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Ok => f.write_str("ok"),
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// Outcome of a check, with a hint on how to resolve it, unless it is ok.
+#[derive(Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+
+        if let Some(hint) = &self.hint {
+            write!(f, "\n    hint: {hint}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Findings printed so far.
+#[derive(Debug, Default)]
+pub struct Report {
+    warnings: usize,
+    errors: usize,
+}
+
+impl Report {
+    fn add(&mut self, finding: Finding) {
+        match finding.severity {
+            Severity::Ok => {}
+            Severity::Warning => self.warnings += 1,
+            Severity::Error => self.errors += 1,
+        }
+
+        println!("  {finding}");
+    }
+
+    fn ok(&mut self, message: impl Into<String>) {
+        self.add(Finding {
+            severity: Severity::Ok,
+            message: message.into(),
+            hint: None,
+        });
+    }
+
+    fn warning(&mut self, message: impl Into<String>, hint: impl Into<String>) {
+        self.add(Finding {
+            severity: Severity::Warning,
+            message: message.into(),
+            hint: Some(hint.into()),
+        });
+    }
+
+    fn error(&mut self, message: impl Into<String>, hint: impl Into<String>) {
+        self.add(Finding {
+            severity: Severity::Error,
+            message: message.into(),
+            hint: Some(hint.into()),
+        });
+    }
+
+    /// Returns true if no errors were found.
+    pub fn is_healthy(&self) -> bool {
+        self.errors == 0
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} errors, {} warnings", self.errors, self.warnings)
+    }
+}
+
+/// Access to resources in the cluster which the controller needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permission {
+    pub verb: &'static str,
+    pub group: String,
+    pub resource: String,
+    /// Namespace of the resources, or None for all namespaces, and cluster scoped resources.
+    pub namespace: Option<String>,
+    /// Name of the single resource needed, if any.
+    pub name: Option<String>,
+}
+
+impl Permission {
+    fn of<K: Resource<DynamicType = ()>>(verb: &'static str, namespace: Option<&str>) -> Self {
+        Permission {
+            verb,
+            group: K::group(&()).into_owned(),
+            resource: K::plural(&()).into_owned(),
+            namespace: namespace.map(String::from),
+            name: None,
+        }
+    }
+
+    fn named(self, name: &str) -> Self {
+        Permission {
+            name: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    async fn is_allowed(&self, kube: &KubeClient) -> Result<bool, kube::Error> {
+        let review = SelfSubjectAccessReview {
+            spec: SelfSubjectAccessReviewSpec {
+                resource_attributes: Some(ResourceAttributes {
+                    verb: Some(self.verb.to_string()),
+                    group: Some(self.group.clone()),
+                    resource: Some(self.resource.clone()),
+                    namespace: self.namespace.clone(),
+                    name: self.name.clone(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let review = Api::<SelfSubjectAccessReview>::all(kube.clone())
+            .create(&PostParams::default(), &review)
+            .await?;
+
+        Ok(review.status.is_some_and(|status| status.allowed))
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.verb, self.resource)?;
+
+        if !self.group.is_empty() {
+            write!(f, ".{}", self.group)?;
+        }
+        if let Some(name) = &self.name {
+            write!(f, " {name}")?;
+        }
+        if let Some(namespace) = &self.namespace {
+            write!(f, " in namespace {namespace}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Permissions the controller needs in the cluster to sync the zones, and read its credentials.
+pub fn permissions(sync: &SyncArgs, cloudflare: &CloudflareArgs) -> Vec<Permission> {
+    let mut permissions = Vec::new();
+
+    for namespace in sync.scopes() {
+        for verb in ["list", "watch", "patch"] {
+            permissions.push(Permission::of::<Zone>(verb, namespace));
+            permissions.push(Permission::of::<Record>(verb, namespace));
+        }
+        permissions.push(Permission::of::<Event>("create", namespace));
+
+        if sync.change_set_history > 0 {
+            for verb in ["create", "list", "delete"] {
+                permissions.push(Permission::of::<CloudflareChangeSet>(verb, namespace));
+            }
+        }
+    }
+
+    let secrets = cloudflare.cf_api_token_secret.iter().chain(
+        cloudflare
+            .cf_zone_credentials
+            .iter()
+            .filter_map(|credentials| match &credentials.source {
+                CredentialSource::Secret(secret) => Some(secret),
+                _ => None,
+            }),
+    );
+    for secret in secrets {
+        for verb in ["get", "list", "watch"] {
+            permissions
+                .push(Permission::of::<Secret>(verb, Some(&secret.namespace)).named(&secret.name));
+        }
+    }
+
+    if cloudflare.watch_credentials {
+        for verb in ["list", "watch"] {
+            permissions.push(Permission::of::<CloudflareCredential>(verb, None));
+        }
+    }
+    if cloudflare.watch_zone_configs {
+        for verb in ["list", "watch"] {
+            permissions.push(Permission::of::<CloudflareZoneConfig>(verb, None));
+        }
+    }
+
+    permissions
+}
+
+/// Check that the cluster is reachable, and grants the permissions.
+pub async fn check_kube(report: &mut Report, kube: &KubeClient, permissions: &[Permission]) {
+    match kube.apiserver_version().await {
+        Ok(version) => report.ok(format!("connected to kubernetes {}", version.git_version)),
+        Err(err) => {
+            report.error(
+                format!("cannot connect to kubernetes: {err}"),
+                "check the kubeconfig, or the service account when running in a pod",
+            );
+            return;
+        }
+    }
+
+    let mut denied = 0;
+    for permission in permissions {
+        match permission.is_allowed(kube).await {
+            Ok(true) => {}
+            Ok(false) => {
+                denied += 1;
+                report.error(
+                    format!("not permitted to {permission}"),
+                    "grant it to the controller's service account through its (Cluster)Role",
+                );
+            }
+            Err(err) => {
+                report.error(
+                    format!("cannot check whether permitted to {permission}: {err}"),
+                    "permit creating selfsubjectaccessreviews.authorization.k8s.io, or check the permission manually",
+                );
+                return;
+            }
+        }
+    }
+
+    if denied == 0 {
+        report.ok(format!(
+            "all {} required permissions granted",
+            permissions.len()
+        ));
+    }
+}
+
+/// Check that the lease used for leader election can be taken, if enabled.
+pub async fn check_lease(report: &mut Report, kube: &KubeClient, namespace: &str, name: &str) {
+    for verb in ["get", "create", "update"] {
+        let permission = Permission::of::<Lease>(verb, Some(namespace)).named(name);
+        match permission.is_allowed(kube).await {
+            Ok(true) => {}
+            Ok(false) => report.error(
+                format!("not permitted to {permission}, needed for --leader-election"),
+                "grant it to the controller's service account through its Role",
+            ),
+            Err(err) => report.error(
+                format!("cannot check whether permitted to {permission}: {err}"),
+                "permit creating selfsubjectaccessreviews.authorization.k8s.io, or check the permission manually",
+            ),
+        }
+    }
+}
+
+/// Check the status and expiry of the credentials of every account.
+pub async fn check_credentials(report: &mut Report, accounts: &Accounts) {
+    for account in accounts.all() {
+        if !account.source().is_token() {
+            report.warning(
+                format!("{account} uses the global api key, with every permission of the account"),
+                "prefer scoped api tokens, with only the Zone:Read and DNS:Edit permissions",
+            );
+            continue;
+        }
+
+        match account.cloudflare().verify_token().await {
+            Ok(verification) if verification.status != TokenStatus::Active => report.error(
+                format!("{account}: {verification}"),
+                "create a new api token, and replace the current one with it",
+            ),
+            Ok(verification) => match expiry(verification.expires_on.as_deref()) {
+                Some(expires_on)
+                    if expires_on - Utc::now() < Duration::days(EXPIRY_WARNING_DAYS) =>
+                {
+                    report.warning(
+                        format!("{account}: {verification}"),
+                        "roll the api token, or extend its expiry, before it expires",
+                    )
+                }
+                _ => report.ok(format!("{account}: {verification}")),
+            },
+            Err(err) => report.error(
+                format!("{account}: failed to verify token: {err}"),
+                "check that the token is complete, and not an api key",
+            ),
+        }
+    }
+}
+
+fn expiry(expires_on: Option<&str>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(expires_on?)
+        .ok()
+        .map(|expires_on| expires_on.with_timezone(&Utc))
+}
+
+/// Check which cloudflare zones the zones selected for sync map to, and
+/// whether their records can be edited.
+pub async fn check_zones(
+    report: &mut Report,
+    kube: &KubeClient,
+    accounts: &Accounts,
+    sync: &SyncArgs,
+) {
+    let zones = match sync.list_zones(kube).await {
+        Ok(zones) => zones,
+        Err(err) => {
+            report.error(
+                format!("cannot list zones: {err}"),
+                "check the permissions to list zones above",
+            );
+            return;
+        }
+    };
+
+    if zones.is_empty() {
+        report.warning(
+            "no zones are selected for sync",
+            "check --namespaces, --zone-label-selector, --require-annotation and the shard options",
+        );
+    }
+
+    // Several zones may map onto the same cloudflare zone, which is only checked once.
+    let mut cloudflare_zones = BTreeMap::<String, (CloudFlare, cloudflare::Zone)>::new();
+
+    for zone in zones {
+        let Some(fqdn) = zone.fqdn() else {
+            report.warning(
+                format!("{zone} has no fully qualified domain name yet"),
+                "check that the kubizone controller is running, and that the zone's parent exists",
+            );
+            continue;
+        };
+
+        if let Err(err) = accounts.lookup_zone(fqdn).await {
+            report.error(
+                format!("{zone} ({fqdn}): failed to look up cloudflare zone: {err}"),
+                "check the permissions of the credentials covering the zone",
+            );
+            continue;
+        }
+
+        let Some((cloudflare, cloudflare_zone)) = accounts.find_zone(fqdn) else {
+            if sync.create_zones {
+                report.ok(format!(
+                    "{zone} ({fqdn}) => no matching cloudflare zone yet, created by --create-zones"
+                ));
+            } else {
+                report.error(
+                    format!("{zone} ({fqdn}) => no matching cloudflare zone"),
+                    "add the zone to cloudflare and grant the credentials access to it, or enable --create-zones",
+                );
+            }
+            continue;
+        };
+
+        if cloudflare_zone.is_active() {
+            report.ok(format!(
+                "{zone} ({fqdn}) => {} ({})",
+                cloudflare_zone.fqdn, cloudflare_zone.id
+            ));
+        } else {
+            report.warning(
+                format!(
+                    "{zone} ({fqdn}) => {} ({}), which is {}, so records are not synced",
+                    cloudflare_zone.fqdn,
+                    cloudflare_zone.id,
+                    if cloudflare_zone.paused {
+                        "paused".to_string()
+                    } else {
+                        format!("{:?}", cloudflare_zone.status).to_lowercase()
+                    }
+                ),
+                format!(
+                    "delegate the domain to the cloudflare nameservers {}, and unpause the zone",
+                    cloudflare_zone.name_servers.join(", ")
+                ),
+            );
+        }
+
+        cloudflare_zones
+            .entry(cloudflare_zone.id.to_string())
+            .or_insert((cloudflare, cloudflare_zone));
+    }
+
+    for (cloudflare, cloudflare_zone) in cloudflare_zones.values() {
+        check_cloudflare_zone(report, cloudflare, cloudflare_zone, sync).await;
+    }
+}
+
+/// Check that the records of the cloudflare zone can be read and edited, as
+/// needed for syncing them.
+async fn check_cloudflare_zone(
+    report: &mut Report,
+    cloudflare: &CloudFlare,
+    cloudflare_zone: &cloudflare::Zone,
+    sync: &SyncArgs,
+) {
+    let name = &cloudflare_zone.fqdn;
+
+    match cloudflare.records(&cloudflare_zone.id).await {
+        Ok(records) => report.ok(format!("{name}: {} records readable", records.len())),
+        Err(err) => report.error(
+            format!("{name}: cannot list records: {err}"),
+            "grant the api token the DNS:Read permission for the zone",
+        ),
+    }
+
+    match cloudflare.can_edit_records(&cloudflare_zone.id).await {
+        Ok(true) => report.ok(format!("{name}: editing records permitted")),
+        Ok(false) => report.error(
+            format!("{name}: editing records NOT permitted"),
+            "grant the api token the DNS:Edit permission for the zone",
+        ),
+        Err(err) => report.error(
+            format!("{name}: failed to check permission to edit records: {err}"),
+            "check that the zone is accessible with the credentials",
+        ),
+    }
+
+    if let Some(finding) = tags_finding(cloudflare_zone, sync.ownership) {
+        report.add(finding);
+    }
+}
+
+/// Finding about the availability of tags in the cloudflare zone, when
+/// tracking ownership through tags.
+fn tags_finding(cloudflare_zone: &cloudflare::Zone, ownership: Ownership) -> Option<Finding> {
+    if ownership != Ownership::Tags || cloudflare_zone.plan.as_deref() != Some("free") {
+        return None;
+    }
+
+    Some(Finding {
+        severity: Severity::Warning,
+        message: format!(
+            "{}: the free plan does not include record tags, so ownership is tracked through comments instead",
+            cloudflare_zone.fqdn
+        ),
+        hint: Some(
+            "use --ownership comment, or a plan including tags, so all zones are tracked alike"
+                .to_string(),
+        ),
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn required_permissions() {
+    use clap::Parser as _;
+
+    let args = crate::Args::try_parse_from([
+        "kubizone-cloudflare",
+        "doctor",
+        "--cf-api-token-secret",
+        "kube-system/cloudflare#token",
+        "--namespaces",
+        "dns",
+        "--watch-zone-configs",
+    ])
+    .unwrap();
+    let crate::Command::Doctor {
+        cloudflare, sync, ..
+    } = args.command
+    else {
+        panic!("expected the doctor command");
+    };
+
+    let permissions = permissions(&sync, &cloudflare)
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    assert!(permissions.contains(&format!(
+        "patch zones.{} in namespace dns",
+        Zone::group(&())
+    )));
+    assert!(permissions.contains(&"create events.events.k8s.io in namespace dns".to_string()));
+    assert!(permissions.contains(&"watch secrets cloudflare in namespace kube-system".to_string()));
+    assert!(permissions.contains(&format!(
+        "list cloudflarezoneconfigs.{}",
+        crate::crds::GROUP
+    )));
+    assert!(!permissions
+        .iter()
+        .any(|permission| permission.contains("cloudflarecredentials")));
+
+    let zone = serde_json::from_value::<cloudflare::Zone>(serde_json::json!({
+        "id": "023e105f4ecef8ad9ca31a8372d0c353",
+        "name": "example.org",
+        "plan": {"id": "0feeeeeeeeeeeeeeeeeeeeeeeeeeeeee", "legacy_id": "free"}
+    }))
+    .unwrap();
+    assert!(tags_finding(&zone, Ownership::Comment).is_none());
+    assert_eq!(
+        tags_finding(&zone, Ownership::Tags).unwrap().severity,
+        Severity::Warning
+    );
+}
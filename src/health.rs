@@ -0,0 +1,129 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use tracing::{error, info, warn};
+
+use crate::{account::Accounts, metrics::Metrics};
+
+/// Shared health state of the controller, served on `/healthz` and `/readyz`,
+/// along with its metrics on `/metrics`.
+#[derive(Clone)]
+pub struct Health {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// Set once the initial zone lists have been fetched.
+    accounts: OnceLock<Arc<Accounts>>,
+    /// Time of the most recent reconciliation, successful or not.
+    last_reconcile: Mutex<Option<Instant>>,
+    /// Maximum time between reconciliations before the controller is considered stalled.
+    stall_timeout: Duration,
+    metrics: Metrics,
+}
+
+impl Health {
+    pub fn new(stall_timeout: Duration) -> Self {
+        Health {
+            inner: Arc::new(Inner {
+                accounts: OnceLock::new(),
+                last_reconcile: Mutex::new(None),
+                stall_timeout,
+                metrics: Metrics::default(),
+            }),
+        }
+    }
+
+    /// Mark the controller as ready, now that the accounts are connected.
+    pub fn ready(&self, accounts: Arc<Accounts>) {
+        let _ = self.inner.accounts.set(accounts);
+    }
+
+    /// Record that a reconciliation has just finished.
+    pub fn reconciled(&self) {
+        *self.inner.last_reconcile.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Record whether this replica is the leader.
+    ///
+    /// Replicas which lose leadership stop reconciling, so they are no longer
+    /// considered stalled until they become the leader again.
+    pub fn set_leader(&self, leader: bool) {
+        if self.inner.metrics.set_leader(leader) && !leader {
+            *self.inner.last_reconcile.lock().unwrap() = None;
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.inner.accounts.get().is_some()
+    }
+
+    /// The controller is alive as long as the zone refresh tasks are running,
+    /// and reconciliations keep happening once they have started.
+    fn is_alive(&self) -> bool {
+        if let Some(accounts) = self.inner.accounts.get() {
+            if let Some(account) = accounts.all().iter().find(|account| !account.is_alive()) {
+                warn!("zone refresh for {account} is no longer running");
+                return false;
+            }
+        }
+
+        if let Some(last_reconcile) = *self.inner.last_reconcile.lock().unwrap() {
+            if last_reconcile.elapsed() > self.inner.stall_timeout {
+                warn!(
+                    "no reconciliation has happened in {}s, controller appears stalled",
+                    last_reconcile.elapsed().as_secs()
+                );
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Serve the health endpoints on the given address, until the process exits.
+    pub async fn serve(self, address: SocketAddr) {
+        let router = Router::new()
+            .route("/healthz", get(healthz))
+            .route("/readyz", get(readyz))
+            .route("/metrics", get(metrics))
+            .with_state(self);
+
+        let listener = match tokio::net::TcpListener::bind(address).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("failed to bind health endpoint to {address}: {err}");
+                return;
+            }
+        };
+
+        info!("serving health endpoints on {address}");
+        if let Err(err) = axum::serve(listener, router).await {
+            error!("health endpoint failed: {err}");
+        }
+    }
+}
+
+fn status(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+async fn healthz(State(health): State<Health>) -> StatusCode {
+    status(health.is_alive())
+}
+
+async fn readyz(State(health): State<Health>) -> StatusCode {
+    status(health.is_ready())
+}
+
+async fn metrics(State(health): State<Health>) -> String {
+    health.inner.metrics.render()
+}
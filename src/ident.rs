@@ -0,0 +1,182 @@
+//! Identities of records, used to match the entries of a zone with the records in cloudflare.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use kubizone_common::{FullyQualifiedDomainName, RecordIdent, Type};
+
+/// Identity of a record, with its rdata in canonical form, so records
+/// which only differ in how they are spelled are considered the same.
+pub fn ident(fqdn: &FullyQualifiedDomainName, r#type: Type, rdata: &str) -> RecordIdent {
+    RecordIdent {
+        fqdn: fqdn.clone(),
+        r#type,
+        rdata: canonical_rdata(r#type, rdata),
+    }
+}
+
+/// Canonical form of the rdata of a record.
+///
+/// Domain names in the rdata are lowercased and fully qualified, since
+/// cloudflare returns them lowercased and without the trailing dot. TXT
+/// rdata is reduced to its value, since cloudflare quotes it and splits
+/// long values into several strings. Addresses are written in their
+/// shortest form, and hexadecimal data in lowercase without whitespace.
+///
+/// Rdata which is malformed for its type is left as is, other than
+/// surrounding whitespace.
+pub fn canonical_rdata(r#type: Type, rdata: &str) -> String {
+    let rdata = rdata.trim();
+
+    let canonical = match r#type {
+        Type::A => rdata.parse::<Ipv4Addr>().ok().map(|addr| addr.to_string()),
+        Type::AAAA => rdata.parse::<Ipv6Addr>().ok().map(|addr| addr.to_string()),
+        Type::CNAME | Type::NS | Type::PTR | Type::DNAME => Some(domain_name(rdata)),
+        Type::MX => fields(rdata).map(|[preference, exchange]| {
+            format!("{} {}", number(preference), domain_name(exchange))
+        }),
+        Type::SRV => fields(rdata).map(|[priority, weight, port, target]| {
+            format!(
+                "{} {} {} {}",
+                number(priority),
+                number(weight),
+                number(port),
+                domain_name(target)
+            )
+        }),
+        Type::CAA => {
+            let mut fields = rdata.splitn(3, char::is_whitespace);
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(flags), Some(tag), Some(value)) => Some(format!(
+                    "{} {} \"{}\"",
+                    number(flags),
+                    tag.to_ascii_lowercase(),
+                    txt_value(value)
+                )),
+                _ => None,
+            }
+        }
+        Type::SSHFP | Type::TLSA => {
+            let fields = rdata.split_whitespace().collect::<Vec<_>>();
+            let parameters = if r#type == Type::SSHFP { 2 } else { 3 };
+
+            (fields.len() > parameters).then(|| {
+                let (parameters, data) = fields.split_at(parameters);
+                format!(
+                    "{} {}",
+                    parameters
+                        .iter()
+                        .map(|field| number(field))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    data.concat().to_ascii_lowercase()
+                )
+            })
+        }
+        Type::TXT => Some(txt_value(rdata)),
+        _ => None,
+    };
+
+    canonical.unwrap_or_else(|| rdata.to_string())
+}
+
+/// Split the rdata into exactly `N` whitespace separated fields.
+fn fields<const N: usize>(rdata: &str) -> Option<[&str; N]> {
+    rdata.split_whitespace().collect::<Vec<_>>().try_into().ok()
+}
+
+/// Numeric field without leading zeroes, or the field itself if it is not a number.
+fn number(field: &str) -> String {
+    field
+        .parse::<u32>()
+        .map(|number| number.to_string())
+        .unwrap_or_else(|_| field.to_string())
+}
+
+/// Value of TXT rdata, which is the concatenation of its character strings.
+///
+/// Rdata which does not start with a quote is taken as the value itself,
+/// which is how TXT entries are commonly written.
+pub fn txt_value(rdata: &str) -> String {
+    let rdata = rdata.trim();
+    if !rdata.starts_with('"') {
+        return rdata.to_string();
+    }
+
+    let mut value = String::new();
+    let mut chars = rdata.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => value.extend(chars.next()),
+                        c => value.push(c),
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            // Unquoted strings between quoted ones end at the next whitespace.
+            c => {
+                value.push(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    value
+}
+
+/// Lowercased, fully qualified domain name.
+fn domain_name(name: &str) -> String {
+    let name = name.trim().to_ascii_lowercase();
+
+    if name.ends_with('.') {
+        name
+    } else {
+        format!("{name}.")
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn canonical_forms() {
+    assert_eq!(
+        canonical_rdata(Type::CNAME, "WWW.Kubi.Zone"),
+        "www.kubi.zone."
+    );
+    assert_eq!(
+        canonical_rdata(Type::NS, "ns1.kubi.zone."),
+        "ns1.kubi.zone."
+    );
+    assert_eq!(
+        canonical_rdata(Type::MX, "10   Mail.kubi.zone"),
+        "10 mail.kubi.zone."
+    );
+    assert_eq!(canonical_rdata(Type::A, "127.0.0.1"), "127.0.0.1");
+    assert_eq!(
+        canonical_rdata(Type::AAAA, "2001:0DB8:0000:0000:0000:0000:0000:0001"),
+        "2001:db8::1"
+    );
+    assert_eq!(
+        canonical_rdata(Type::SRV, "10 05 5060 SIP.kubi.zone"),
+        "10 5 5060 sip.kubi.zone."
+    );
+    assert_eq!(
+        canonical_rdata(Type::CAA, "0 ISSUE letsencrypt.org"),
+        r#"0 issue "letsencrypt.org""#
+    );
+    assert_eq!(
+        canonical_rdata(Type::SSHFP, "1 1 ABCD EF01"),
+        "1 1 abcdef01"
+    );
+    assert_eq!(
+        canonical_rdata(Type::TXT, r#""v=spf1 " "-all""#),
+        "v=spf1 -all"
+    );
+    assert_eq!(canonical_rdata(Type::TXT, "v=spf1 -all"), "v=spf1 -all");
+    assert_eq!(canonical_rdata(Type::TXT, r#""say \"hi\"""#), r#"say "hi""#);
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+
+use kube::api::ObjectMeta;
+use kubizone_common::{DomainName, FullyQualifiedDomainName, Pattern};
+use kubizone_crds::v1alpha1::{Delegation, Record, RecordDelegation, RecordSpec, Zone, ZoneSpec};
+
+use crate::cloudflare::{self, AUTOMATIC_TTL};
+
+/// Kubizone resources describing the records of a cloudflare zone.
+pub struct Manifests<'a> {
+    pub zone: Zone,
+    pub records: Vec<Record>,
+    /// Records at the apex of the zone, which cannot be imported, since zone
+    /// delegations can only describe records below the apex.
+    pub skipped: Vec<&'a cloudflare::Record>,
+}
+
+/// Build a Zone for `fqdn`, and a Record for each cloudflare record within it.
+///
+/// The zone delegates every name below it to Records in `namespace`.
+pub fn manifests<'a>(
+    fqdn: &FullyQualifiedDomainName,
+    namespace: &str,
+    records: &'a [cloudflare::Record],
+) -> Manifests<'a> {
+    let zone_name = resource_name(fqdn);
+
+    let zone = Zone {
+        metadata: ObjectMeta {
+            name: Some(zone_name.clone()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        spec: zone_spec(fqdn, namespace),
+        status: None,
+    };
+
+    let mut skipped = Vec::new();
+    let mut names = BTreeMap::<String, usize>::new();
+    let mut imported = Vec::new();
+
+    for record in records {
+        if &record.fqdn == fqdn {
+            skipped.push(record);
+            continue;
+        }
+
+        // Records sharing name and type are numbered, to keep resource names unique.
+        let base = format!(
+            "{}-{}",
+            resource_name(&record.fqdn),
+            record.r#type.to_string().to_lowercase()
+        );
+        let count = names.entry(base.clone()).or_default();
+        *count += 1;
+        let name = if *count == 1 {
+            base
+        } else {
+            format!("{base}-{count}")
+        };
+
+        imported.push(Record {
+            metadata: ObjectMeta {
+                name: Some(name),
+                namespace: Some(namespace.to_string()),
+                ..Default::default()
+            },
+            spec: RecordSpec {
+                domain_name: DomainName::from(record.fqdn.clone()),
+                zone_ref: None,
+                type_: record.r#type,
+                class: kubizone_common::Class::IN,
+                ttl: (record.ttl != AUTOMATIC_TTL).then_some(record.ttl),
+                rdata: record.rdata.clone(),
+            },
+            status: None,
+        });
+    }
+
+    Manifests {
+        zone,
+        records: imported,
+        skipped,
+    }
+}
+
+/// Spec of a zone delegating all of its subdomains to records in `namespace`.
+fn zone_spec(fqdn: &FullyQualifiedDomainName, namespace: &str) -> ZoneSpec {
+    // Built through serde, so the SOA values use kubizone's defaults.
+    let mut spec: ZoneSpec = serde_json::from_value(serde_json::json!({
+        "domainName": fqdn.to_string(),
+        "delegations": [],
+    }))
+    .unwrap();
+
+    spec.delegations.push(Delegation {
+        namespaces: vec![namespace.to_string()],
+        zones: Vec::new(),
+        records: vec![RecordDelegation {
+            pattern: Pattern::try_from("*").unwrap(),
+            types: Vec::new(),
+        }],
+    });
+
+    spec
+}
+
+/// Kubernetes resource name for the domain, such as `www-example-org` for `www.example.org.`
+fn resource_name(fqdn: &FullyQualifiedDomainName) -> String {
+    fqdn.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+#[test]
+fn import_records() {
+    let records: Vec<cloudflare::Record> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 1},
+            {"id": "2", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.2", "ttl": 300},
+            {"id": "3", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.3", "ttl": 1}
+        ]"#,
+    )
+    .unwrap();
+
+    let fqdn = FullyQualifiedDomainName::try_from("kubi.zone.").unwrap();
+    let manifests = manifests(&fqdn, "dns", &records);
+
+    assert_eq!(manifests.zone.metadata.name.as_deref(), Some("kubi-zone"));
+    assert_eq!(manifests.skipped.len(), 1);
+
+    let names = manifests
+        .records
+        .iter()
+        .map(|record| record.metadata.name.as_deref().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["www-kubi-zone-a", "www-kubi-zone-a-2"]);
+
+    assert_eq!(manifests.records[0].spec.ttl, Some(300));
+    assert_eq!(manifests.records[1].spec.ttl, None);
+}
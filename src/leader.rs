@@ -0,0 +1,166 @@
+//! Leader election through a coordination.k8s.io Lease, so only one of
+//! several replicas reconciles zones at a time.
+
+use std::time::Duration;
+
+use k8s_openapi::{
+    api::coordination::v1::{Lease, LeaseSpec},
+    apimachinery::pkg::apis::meta::v1::MicroTime,
+    chrono::{DateTime, Utc},
+};
+use kube::{
+    api::{ObjectMeta, PostParams},
+    Api,
+};
+use tokio::sync::watch::Sender;
+use tracing::{debug, info, warn};
+
+use crate::health::Health;
+
+/// Contender for the leadership of the controller.
+pub struct LeaderElection {
+    leases: Api<Lease>,
+    name: String,
+    identity: String,
+    lease_duration: Duration,
+}
+
+impl LeaderElection {
+    pub fn new(leases: Api<Lease>, name: String, identity: String) -> Self {
+        LeaderElection {
+            leases,
+            name,
+            identity,
+            lease_duration: Duration::from_secs(15),
+        }
+    }
+
+    pub fn with_lease_duration(mut self, lease_duration: Duration) -> Self {
+        self.lease_duration = lease_duration;
+        self
+    }
+
+    /// Keep trying to acquire and renew the lease, publishing whether this
+    /// replica is the leader through `leader`, until the receivers are dropped.
+    pub async fn run(self, leader: Sender<bool>, health: Health) {
+        let retry_period = self.lease_duration / 3;
+        let mut renewed = None::<tokio::time::Instant>;
+
+        while !leader.is_closed() {
+            let leading = match self.try_acquire().await {
+                Ok(leading) => {
+                    renewed = leading.then(tokio::time::Instant::now);
+                    leading
+                }
+                Err(err) => {
+                    warn!("failed to renew leader election lease {}: {err}", self.name);
+
+                    // Other replicas may take over once the lease has expired.
+                    renewed.is_some_and(|renewed| renewed.elapsed() < self.lease_duration)
+                }
+            };
+
+            if *leader.borrow() != leading {
+                if leading {
+                    info!("{} became the leader", self.identity);
+                } else {
+                    warn!("{} is no longer the leader", self.identity);
+                }
+            }
+
+            health.set_leader(leading);
+            leader.send_if_modified(|current| std::mem::replace(current, leading) != leading);
+
+            tokio::time::sleep(retry_period).await;
+        }
+    }
+
+    /// Acquire or renew the lease, returning true if this replica holds it.
+    async fn try_acquire(&self) -> Result<bool, kube::Error> {
+        let now = Utc::now();
+
+        let Some(mut lease) = self.leases.get_opt(&self.name).await? else {
+            let lease = Lease {
+                metadata: ObjectMeta {
+                    name: Some(self.name.clone()),
+                    ..Default::default()
+                },
+                spec: Some(self.acquired(now, 0)),
+            };
+
+            return match self.leases.create(&PostParams::default(), &lease).await {
+                Ok(_) => Ok(true),
+                // Another replica created the lease first.
+                Err(kube::Error::Api(err)) if err.code == 409 => Ok(false),
+                Err(err) => Err(err),
+            };
+        };
+
+        let spec = lease.spec.get_or_insert_with(LeaseSpec::default);
+
+        if spec.holder_identity.as_deref() == Some(&self.identity) {
+            spec.renew_time = Some(MicroTime(now));
+            spec.lease_duration_seconds = Some(self.lease_duration_seconds());
+        } else if is_expired(spec, now) {
+            debug!(
+                "taking over expired leader election lease {} from {:?}",
+                self.name, spec.holder_identity
+            );
+            *spec = self.acquired(now, spec.lease_transitions.unwrap_or_default() + 1);
+        } else {
+            return Ok(false);
+        }
+
+        // The resource version of the lease ensures only one replica succeeds.
+        match self
+            .leases
+            .replace(&self.name, &PostParams::default(), &lease)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(kube::Error::Api(err)) if err.code == 409 => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn acquired(&self, now: DateTime<Utc>, transitions: i32) -> LeaseSpec {
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            lease_duration_seconds: Some(self.lease_duration_seconds()),
+            acquire_time: Some(MicroTime(now)),
+            renew_time: Some(MicroTime(now)),
+            lease_transitions: Some(transitions),
+        }
+    }
+
+    fn lease_duration_seconds(&self) -> i32 {
+        self.lease_duration.as_secs().try_into().unwrap_or(i32::MAX)
+    }
+}
+
+/// Returns true if the holder of the lease has not renewed it in time.
+fn is_expired(spec: &LeaseSpec, now: DateTime<Utc>) -> bool {
+    let (Some(renew_time), Some(duration)) = (&spec.renew_time, spec.lease_duration_seconds) else {
+        return true;
+    };
+
+    renew_time.0 + k8s_openapi::chrono::Duration::seconds(duration.into()) < now
+}
+
+#[cfg(test)]
+#[test]
+fn lease_expiry() {
+    let now = Utc::now();
+    let spec = |renewed: i64| LeaseSpec {
+        holder_identity: Some("other".to_string()),
+        lease_duration_seconds: Some(15),
+        renew_time: Some(MicroTime(
+            now - k8s_openapi::chrono::Duration::seconds(renewed),
+        )),
+        ..Default::default()
+    };
+
+    assert!(!is_expired(&spec(10), now));
+    assert!(is_expired(&spec(20), now));
+    assert!(is_expired(&LeaseSpec::default(), now));
+}
@@ -0,0 +1,17 @@
+//! Client for the cloudflare api, as used by the kubizone cloudflare controller.
+//!
+//! The client takes care of pagination, retries of rate limited and failed
+//! requests, and maps api errors onto typed [`cloudflare::Error`]s.
+//!
+//! Records are written as [`cloudflare::Desired`] and listed as
+//! [`cloudflare::Record`], both of which use kubizone's types for names and
+//! record types, so listings skip records kubizone cannot represent. Tools
+//! which do not use kubizone can list every record as plain strings through
+//! [`cloudflare::CloudFlare::raw_records`] instead.
+//!
+//! With the `mock` feature, `cloudflare::mock::MockCloudFlare` serves a fake
+//! api for testing tools built on the client.
+
+pub mod cloudflare;
+pub mod ident;
+pub mod shard;
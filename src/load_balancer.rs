@@ -0,0 +1,377 @@
+//! Cloudflare load balancers for Records which need weighted or geo steering,
+//! declared through an annotation on the Record, and synced with
+//! `--load-balancers` instead of the plain record.
+//!
+//! Each load balancer gets its own pools, and optionally a monitor, all of
+//! which are marked as managed by the controller in their description.
+
+use std::collections::BTreeMap;
+
+use kube::ResourceExt as _;
+use kubizone_common::FullyQualifiedDomainName;
+use kubizone_crds::v1alpha1::{DomainExt as _, Record, ZoneEntry};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    cloudflare::{self, owner_comment, LoadBalancer, Monitor, Origin, Pool},
+    crds::GROUP,
+    proxy::is_proxiable,
+};
+
+/// Annotation on a Record replacing it with a load balancer, as JSON such as
+/// `{"steering": "geo", "pools": [{"name": "eu", "regions": ["WEU"],
+/// "origins": [{"address": "192.0.2.1", "weight": 0.5}]}]}`.
+///
+/// Pools are used in the given order for failover. An optional `monitor`
+/// such as `{"type": "https", "path": "/healthz"}` health checks the origins.
+pub fn load_balancer_annotation() -> String {
+    format!("{GROUP}/load-balancer")
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadBalancerSpec {
+    /// Steering policy of the load balancer, such as `off`, `random` or `geo`.
+    #[serde(default = "default_steering")]
+    pub steering: String,
+    pub pools: Vec<PoolSpec>,
+    #[serde(default)]
+    pub monitor: Option<MonitorSpec>,
+    #[serde(default = "default_proxied")]
+    pub proxied: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolSpec {
+    pub name: String,
+    pub origins: Vec<OriginSpec>,
+    /// Regions, such as `WEU`, whose clients are steered to this pool with geo steering.
+    #[serde(default)]
+    pub regions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OriginSpec {
+    pub address: String,
+    #[serde(default)]
+    pub weight: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonitorSpec {
+    #[serde(rename = "type", default = "default_monitor_type")]
+    pub r#type: String,
+    pub path: Option<String>,
+    pub expected_codes: Option<String>,
+    pub port: Option<u16>,
+}
+
+fn default_steering() -> String {
+    "off".to_string()
+}
+
+fn default_proxied() -> bool {
+    true
+}
+
+fn default_monitor_type() -> String {
+    "http".to_string()
+}
+
+/// Load balancer declared by a Record.
+#[derive(Debug, Clone)]
+pub struct Desired {
+    pub hostname: FullyQualifiedDomainName,
+    pub spec: LoadBalancerSpec,
+    pub ttl: Option<u32>,
+}
+
+impl Desired {
+    /// Hostname of the load balancer in the cloudflare api, without a trailing dot.
+    pub fn name(&self) -> String {
+        hostname(&self.hostname)
+    }
+
+    /// Description marking the monitor, pools and load balancer of this
+    /// hostname as managed by the owner.
+    pub fn description(&self, owner: &str) -> String {
+        owner_comment(owner, Some(&self.name()))
+    }
+
+    pub fn monitor(&self, owner: &str) -> Option<Monitor> {
+        let spec = self.spec.monitor.as_ref()?;
+
+        Some(Monitor {
+            id: String::new(),
+            description: self.description(owner),
+            r#type: spec.r#type.clone(),
+            path: spec.path.clone(),
+            expected_codes: spec.expected_codes.clone(),
+            port: spec.port,
+        })
+    }
+
+    /// Pools of the load balancer, health checked by the monitor, if any.
+    pub fn pools(&self, owner: &str, monitor: Option<&str>) -> Vec<Pool> {
+        self.spec
+            .pools
+            .iter()
+            .map(|pool| Pool {
+                id: String::new(),
+                name: pool_name(&self.name(), &pool.name),
+                description: self.description(owner),
+                origins: pool
+                    .origins
+                    .iter()
+                    .map(|origin| Origin {
+                        name: origin.address.clone(),
+                        address: origin.address.clone(),
+                        weight: origin.weight.unwrap_or(1.0),
+                        enabled: true,
+                    })
+                    .collect(),
+                monitor: monitor.map(str::to_string),
+            })
+            .collect()
+    }
+
+    /// Load balancer using the pools with the given ids, in the order of [`Desired::pools`].
+    pub fn load_balancer(&self, owner: &str, pool_ids: &[String]) -> LoadBalancer {
+        let mut region_pools = BTreeMap::<String, Vec<String>>::new();
+        for (pool, id) in self.spec.pools.iter().zip(pool_ids) {
+            for region in &pool.regions {
+                region_pools
+                    .entry(region.clone())
+                    .or_default()
+                    .push(id.clone());
+            }
+        }
+
+        LoadBalancer {
+            id: String::new(),
+            name: self.name(),
+            description: self.description(owner),
+            default_pools: pool_ids.to_vec(),
+            // Load balancers are only declared with at least one pool.
+            fallback_pool: pool_ids.last().cloned().unwrap_or_default(),
+            steering_policy: self.spec.steering.clone(),
+            region_pools,
+            proxied: self.spec.proxied,
+            // Cloudflare ignores the ttl of proxied load balancers.
+            ttl: self.ttl.filter(|_| !self.spec.proxied),
+        }
+    }
+}
+
+/// Load balancers declared by the records, skipping those with invalid annotations.
+pub fn desired(records: &[Record]) -> Vec<Desired> {
+    let annotation = load_balancer_annotation();
+
+    records
+        .iter()
+        .filter_map(|record| {
+            let value = record.annotations().get(&annotation)?;
+            let hostname = record.fqdn()?;
+
+            match serde_json::from_str::<LoadBalancerSpec>(value) {
+                Ok(spec) if !spec.pools.is_empty() => Some(Desired {
+                    hostname: hostname.clone(),
+                    spec,
+                    ttl: record.spec.ttl,
+                }),
+                Ok(_) => {
+                    warn!(
+                        "ignoring {annotation} annotation of record {}, since it has no pools",
+                        record.name_any()
+                    );
+                    None
+                }
+                Err(err) => {
+                    warn!(
+                        "ignoring {annotation} annotation of record {}, since it is not a valid load balancer: {err}",
+                        record.name_any()
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Returns true if the entry is served by one of the load balancers, rather
+/// than a plain record.
+pub fn is_balanced(load_balancers: &[Desired], entry: &ZoneEntry) -> bool {
+    is_proxiable(entry.type_)
+        && load_balancers
+            .iter()
+            .any(|load_balancer| load_balancer.hostname == entry.fqdn)
+}
+
+/// Hostname in the cloudflare api, without a trailing dot.
+pub fn hostname(fqdn: &FullyQualifiedDomainName) -> String {
+    fqdn.to_string().trim_end_matches('.').to_string()
+}
+
+/// Name of a pool of the load balancer, which are unique across the account
+/// and only consist of alphanumeric characters, hyphens and underscores.
+fn pool_name(hostname: &str, pool: &str) -> String {
+    format!("{hostname}-{pool}")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Returns true if the existing monitor differs from the desired one in anything it sets.
+pub fn monitor_differs(desired: &Monitor, existing: &Monitor) -> bool {
+    desired.r#type != existing.r#type
+        || desired.path.is_some() && desired.path != existing.path
+        || desired.expected_codes.is_some() && desired.expected_codes != existing.expected_codes
+        || desired.port.is_some() && desired.port != existing.port
+}
+
+pub fn pool_differs(desired: &Pool, existing: &Pool) -> bool {
+    desired.origins != existing.origins
+        || desired.monitor != existing.monitor
+        || desired.description != existing.description
+}
+
+pub fn load_balancer_differs(desired: &LoadBalancer, existing: &LoadBalancer) -> bool {
+    desired.default_pools != existing.default_pools
+        || desired.fallback_pool != existing.fallback_pool
+        || desired.steering_policy != existing.steering_policy
+        || desired.region_pools != existing.region_pools
+        || desired.proxied != existing.proxied
+        || desired.ttl.is_some() && desired.ttl != existing.ttl
+        || desired.description != existing.description
+}
+
+/// Returns true if the description marks the object as managed by the owner
+/// for the hostname, see [`Desired::description`].
+pub fn is_managed(description: &str, owner: &str, hostname: &str) -> bool {
+    cloudflare::is_marked_by(description, owner)
+        && description.split_whitespace().nth(1) == Some(hostname)
+}
+
+#[cfg(test)]
+#[test]
+fn geo_steering() {
+    let record: Record = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Record",
+        "metadata": {"name": "www", "annotations": {load_balancer_annotation(): r#"{
+            "steering": "geo",
+            "pools": [
+                {"name": "eu", "regions": ["WEU", "EEU"], "origins": [{"address": "192.0.2.1"}]},
+                {"name": "us", "regions": ["ENAM"], "origins": [{"address": "192.0.2.2", "weight": 0.5}]}
+            ]
+        }"#}},
+        "spec": {"domainName": "www.kubi.zone.", "type": "A", "rdata": "192.0.2.1"},
+        "status": {"fqdn": "www.kubi.zone."}
+    }))
+    .unwrap();
+
+    let desired = desired(&[record]);
+    assert_eq!(desired.len(), 1);
+
+    let pools = desired[0].pools("kubizone", None);
+    assert_eq!(pools[0].name, "www-kubi-zone-eu");
+    assert_eq!(pools[1].origins[0].weight, 0.5);
+    assert!(is_managed(
+        &pools[0].description,
+        "kubizone",
+        "www.kubi.zone"
+    ));
+    assert!(!is_managed(
+        &pools[0].description,
+        "kubizone",
+        "api.kubi.zone"
+    ));
+
+    let load_balancer = desired[0].load_balancer("kubizone", &["1".to_string(), "2".to_string()]);
+    assert_eq!(load_balancer.name, "www.kubi.zone");
+    assert_eq!(load_balancer.fallback_pool, "2");
+    assert_eq!(load_balancer.region_pools["WEU"], ["1"]);
+    assert_eq!(load_balancer.region_pools["ENAM"], ["2"]);
+}
+
+#[cfg(test)]
+#[test]
+fn load_balancer_annotations() {
+    let record = |name: &str, annotation: &str| {
+        serde_json::from_value::<Record>(serde_json::json!({
+            "apiVersion": "kubi.zone/v1alpha1",
+            "kind": "Record",
+            "metadata": {"name": name, "annotations": {load_balancer_annotation(): annotation}},
+            "spec": {"domainName": format!("{name}.kubi.zone."), "type": "A", "ttl": 60, "rdata": "192.0.2.1"},
+            "status": {"fqdn": format!("{name}.kubi.zone.")}
+        }))
+        .unwrap()
+    };
+
+    // Annotations without pools, or which are not valid load balancers, are ignored.
+    let desired = desired(&[
+        record(
+            "www",
+            r#"{"pools": [{"name": "main", "origins": [{"address": "192.0.2.1"}]}], "monitor": {}}"#,
+        ),
+        record("api", r#"{"pools": []}"#),
+        record("mail", r#"{"steering": "geo"}"#),
+        record("ftp", "geo"),
+    ]);
+    assert_eq!(desired.len(), 1);
+
+    let spec = &desired[0].spec;
+    assert_eq!(spec.steering, "off");
+    assert!(spec.proxied);
+    let monitor = desired[0].monitor("kubizone").unwrap();
+    assert_eq!(monitor.r#type, "http");
+
+    // Cloudflare ignores the ttl of proxied load balancers.
+    let load_balancer = desired[0].load_balancer("kubizone", &["1".to_string()]);
+    assert_eq!(load_balancer.ttl, None);
+    let unproxied = Desired {
+        spec: LoadBalancerSpec {
+            proxied: false,
+            ..spec.clone()
+        },
+        ..desired[0].clone()
+    };
+    assert_eq!(
+        unproxied.load_balancer("kubizone", &["1".to_string()]).ttl,
+        Some(60)
+    );
+
+    // Only what the monitor sets is compared.
+    let existing = Monitor {
+        path: Some("/healthz".to_string()),
+        port: Some(8080),
+        ..monitor.clone()
+    };
+    assert!(!monitor_differs(&monitor, &existing));
+    assert!(monitor_differs(
+        &Monitor {
+            path: Some("/".to_string()),
+            ..monitor.clone()
+        },
+        &existing
+    ));
+
+    let entry = |type_: &str| {
+        serde_json::from_value::<ZoneEntry>(serde_json::json!(
+            {"fqdn": "www.kubi.zone.", "type": type_, "class": "IN", "ttl": 60, "rdata": "192.0.2.1"}
+        ))
+        .unwrap()
+    };
+    assert!(is_balanced(&desired, &entry("A")));
+    assert!(!is_balanced(&desired, &entry("TXT")));
+}
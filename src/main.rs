@@ -1,49 +1,201 @@
-mod cloudflare;
+mod account;
+mod annotations;
+mod audit;
+mod cache;
+mod change_sets;
+mod config;
+mod crds;
+mod credentials;
+mod custom_hostnames;
+mod dnssec;
+mod doctor;
+mod health;
+mod import;
+mod leader;
+mod load_balancer;
+mod metrics;
+mod ownership;
+mod plan;
+mod predicates;
+mod protection;
+mod provider;
+mod proxy;
+mod regional;
+mod status;
+mod webhook;
+mod workers;
+mod zone_config;
+mod zone_settings;
 
 use std::{
     collections::{HashMap, HashSet},
-    sync::Arc,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
-use clap::{Parser, Subcommand, ValueEnum};
-use cloudflare::CloudFlare;
-use futures::StreamExt as _;
+use account::{Account, Accounts, DynamicAccounts, ZoneCredentials, ZoneLookup};
+use audit::{Audit, Journal};
+use cache::RecordCache;
+use clap::{ArgGroup, CommandFactory as _, Parser, Subcommand, ValueEnum};
+use cloudflare::{
+    owner_marker, CloudFlare, Credentials, Desired, DnssecStatus, Marker, RecordChange,
+    RecordFilter, RetryPolicy, ZoneId, ZoneStatus,
+};
+use crds::{CloudflareChangeSet, CloudflareCredential, CloudflareZoneConfig};
+use credentials::{CredentialSource, SecretRef};
+use futures::{FutureExt as _, Stream, StreamExt as _};
+use health::Health;
+use k8s_openapi::NamespaceResourceScope;
+use k8s_openapi::{
+    api::core::v1::ObjectReference,
+    apimachinery::pkg::apis::meta::v1::Time,
+    chrono::{SubsecRound as _, Utc},
+};
 use kube::{
-    runtime::{controller::Action, watcher, Controller},
-    Api, Client as KubeClient, ResourceExt as _,
+    api::{ListParams, Patch, PatchParams},
+    core::{
+        admission::{AdmissionRequest, AdmissionResponse, AdmissionReview},
+        DynamicObject,
+    },
+    runtime::{
+        controller::Action,
+        events::{Event, EventType, Recorder},
+        finalizer::{self, finalizer},
+        reflector::{self, ObjectRef},
+        watcher, Controller, WatchStreamExt as _,
+    },
+    Api, Client as KubeClient, CustomResourceExt as _, Resource as _, ResourceExt as _,
 };
-use kubizone_common::{FullyQualifiedDomainName, RecordIdent};
+use kubizone_cloudflare::{cloudflare, ident, shard};
+use kubizone_common::{DomainName, FullyQualifiedDomainName, RecordIdent, Type};
 use kubizone_crds::v1alpha1::DomainExt;
-use kubizone_crds::v1alpha1::Zone;
-use tokio::sync::watch::Receiver;
-use tracing::{debug, error, info, info_span, trace, warn};
+use kubizone_crds::v1alpha1::{Record, Zone, ZoneEntry};
+use kubizone_crds::PARENT_ZONE_LABEL;
+use leader::LeaderElection;
+use ownership::{Owners, Ownership};
+use plan::{Plan, PlannedChange, Target};
+use protection::Protected;
+use provider::DnsProvider;
+use proxy::Proxied;
+use status::SyncStats;
+use tracing::{debug, error, info, warn};
+use zone_config::{ZoneConfig, ZoneConfigs};
+
+/// Name records are tagged with, unless overridden through `--controller-name`.
+const DEFAULT_CONTROLLER_NAME: &str = "kubizone-cloudflare";
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// Format of the log output.
+    ///
+    /// json: one JSON object per line, with the `zone`, `record`, `cf_zone_id`
+    /// and `action` fields of record changes as top-level keys.
+    #[arg(value_enum, env, long, global = true, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Tokio runtime the controller runs on.
+    ///
+    /// multi-thread: spreads reconciliations and requests over several
+    /// threads, see `--worker-threads`.
+    #[arg(value_enum, env, long, global = true, default_value_t = Runtime::CurrentThread)]
+    pub runtime: Runtime,
+
+    /// Number of worker threads of the multi-threaded runtime, defaulting
+    /// to the number of CPU cores. Ignored by the current-thread runtime.
+    #[arg(env, long, global = true, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub worker_threads: Option<usize>,
+
+    /// YAML file setting any of the options by their long name, such as
+    /// `mode: delete`, optionally within a section named after a subcommand,
+    /// such as `reconcile: {requeue-time-secs: 60}`.
+    ///
+    /// Options given on the command line or through the environment take
+    /// precedence over the file.
+    #[arg(env, long, global = true)]
+    pub config: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+#[derive(ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    /// Install the global tracing subscriber using this format.
+    fn init(self) {
+        let builder = tracing_subscriber::fmt();
+
+        match self {
+            LogFormat::Text => builder.init(),
+            LogFormat::Json => builder
+                .json()
+                .flatten_event(true)
+                .with_current_span(false)
+                .with_span_list(false)
+                .init(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    #[default]
+    CurrentThread,
+    MultiThread,
+}
+
+impl Runtime {
+    /// Build a runtime of this flavor.
+    fn build(self, worker_threads: Option<usize>) -> std::io::Result<tokio::runtime::Runtime> {
+        let mut builder = match self {
+            Runtime::CurrentThread => tokio::runtime::Builder::new_current_thread(),
+            Runtime::MultiThread => {
+                let mut builder = tokio::runtime::Builder::new_multi_thread();
+                if let Some(worker_threads) = worker_threads {
+                    builder.worker_threads(worker_threads);
+                }
+                builder
+            }
+        };
+
+        builder.enable_all().build()
+    }
+}
+
+impl Command {
+    /// Sync arguments of the subcommand, if it has any.
+    fn sync(&self) -> Option<&SyncArgs> {
+        match self {
+            Command::Reconcile { sync, .. }
+            | Command::Plan { sync, .. }
+            | Command::SyncOnce { sync, .. }
+            | Command::Cleanup { sync, .. }
+            | Command::Doctor { sync, .. }
+            | Command::Verify { sync, .. }
+            | Command::List { sync, .. }
+            | Command::Export { sync, .. } => Some(sync),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Subcommand)]
 enum Command {
     /// Run reconciliation loop
     Reconcile {
-        /// Cloudflare API key used to access zones.
-        #[arg(env, long)]
-        cf_api_key: String,
+        #[command(flatten)]
+        cloudflare: Box<CloudflareArgs>,
 
-        /// Mode determines whether this controller is allowed to delete records.
-        ///
-        /// upsert: the controller will only create and update records.
-        /// delete: the controller will delete records, if they are removed from a zone.
-        ///
-        /// Note that in all cases, the controller will only update or delete records
-        /// which are managed by the controller. The controller tags the records it
-        /// creates in cloudflare to track ownership.
-        #[arg(value_enum, env, long, default_value_t = Mode::Upsert)]
-        mode: Mode,
+        #[command(flatten)]
+        sync: SyncArgs,
 
         /// Default time between reconciliation of zones.
         ///
@@ -51,237 +203,4850 @@ enum Command {
         #[arg(env, long, default_value_t = 30)]
         requeue_time_secs: u64,
 
-        /// Name used to tag records created in cloudflare.
+        /// Percentage of the requeue time, and of the backoff after failures,
+        /// added at random to every requeue.
+        ///
+        /// Spreads out the reconciliation of zones over time, instead of
+        /// reconciling all of them at once after every requeue time.
+        #[arg(env, long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(0..=100))]
+        requeue_jitter_percent: u8,
+
+        #[command(flatten)]
+        leader_election: LeaderElectionArgs,
+
+        /// Address to serve the `/healthz`, `/readyz` and `/metrics` endpoints on.
+        #[arg(env, long, default_value = "0.0.0.0:8080")]
+        health_address: SocketAddr,
+
+        /// Time without any reconciliation after which `/healthz` reports the
+        /// controller as stalled. Should be well above `--requeue-time-secs`.
+        #[arg(env, long, default_value_t = 900)]
+        stall_timeout_secs: u64,
+
+        /// Minimum time between updates of the last successful sync time in
+        /// a zone's sync stats annotation.
+        ///
+        /// The stats are always updated immediately when any of the counts
+        /// change, but every update triggers another reconciliation of the
+        /// zone, so refreshing only the timestamp on every sync would loop.
+        #[arg(env, long, default_value_t = 300)]
+        stats_refresh_secs: u64,
+
+        /// Upper bound on the time before a zone which keeps failing to
+        /// reconcile is retried.
+        ///
+        /// The delay doubles with every consecutive failure, starting from a
+        /// delay depending on the kind of error, and resets once the zone
+        /// reconciles successfully. Rate limited zones are retried once
+        /// cloudflare permits it.
+        #[arg(env, long, default_value_t = 900)]
+        max_backoff_secs: u64,
+
+        /// Also watch kubizone Record resources, and sync each of them to
+        /// cloudflare individually, storing the outcome as a condition in the
+        /// `cloudflare.kubi.zone/synced` annotation of the Record.
+        ///
+        /// Gives feedback on every Record, such as cloudflare rejecting it,
+        /// rather than only on their zone. Deleting records is left to the
+        /// reconciliation of their zone, which knows about all of its entries.
+        #[arg(env, long)]
+        reconcile_records: bool,
+
+        /// Time the records of a cloudflare zone are cached for, instead of
+        /// listing them on every reconciliation. Zero disables the cache.
+        ///
+        /// The cache is kept up to date with the changes made by the
+        /// controller, and discarded when cloudflare rejects changes, but
+        /// changes made by others are only noticed once it expires.
+        #[arg(env, long, default_value_t = 0)]
+        record_cache_secs: u64,
+
+        /// Compute and log the changes needed to bring cloudflare in line
+        /// with the zones, without making any changes to cloudflare.
+        ///
+        /// No events, sync status or finalizers are written to the zones
+        /// either, since nothing is synced, so deleted zones are not cleaned up.
+        #[arg(env, long)]
+        dry_run: bool,
+
+        /// Time between checks of the `--config` file for changes. Zero
+        /// disables reloading it.
         ///
-        /// This can be overridden if you have multiple controllers managing separate
-        /// parts of a singular zone, and don't want them to interfere with each other.
+        /// Changes to the options shared with `plan` and `sync-once`, such as
+        /// `--mode`, the zone filters and `--min-ttl`, are applied by restarting
+        /// the controllers. Changes to any other options only take effect
+        /// once the controller itself is restarted.
+        #[arg(env, long, default_value_t = 30)]
+        config_reload_secs: u64,
+
+        /// Record every change made to cloudflare in an audit log: `log` to
+        /// emit log events with the `audit` target, or the path of a file to
+        /// append an entry to as a JSON object per line.
         ///
-        /// If two controllers are running with the same name, but they have access
-        /// to different Kubizone Zone resources, they will constantly identify records
-        /// created by the other controller as to-be-deleted.
-        #[arg(env, long, default_value = "kubizone-cloudflare")]
-        controller_name: String,
+        /// Entries cover the changes to records, with their values before and
+        /// after, the creation and deletion of zones, and the changes to load
+        /// balancers, workers, custom hostnames, regional hostnames, settings
+        /// and DNSSEC, along with the errors of any changes cloudflare rejected.
+        #[arg(env, long)]
+        audit_log: Option<audit::Sink>,
     },
-}
+    /// Print the changes needed to bring cloudflare in line with every zone, then exit.
+    ///
+    /// Nothing is changed in either cloudflare or the cluster.
+    Plan {
+        #[command(flatten)]
+        cloudflare: Box<CloudflareArgs>,
 
-#[derive(ValueEnum, Default, Debug, Clone, PartialEq, Eq)]
-pub enum Mode {
-    #[default]
-    Upsert,
-    Delete,
+        #[command(flatten)]
+        sync: SyncArgs,
+    },
+    /// Reconcile every zone exactly once, then exit.
+    ///
+    /// Exits with a non-zero status if any of the zones failed to reconcile.
+    /// Intended to be run as a (Cron)Job, instead of the long-running controller.
+    /// Zones are not given the cleanup finalizer, so records are left behind in
+    /// cloudflare when zones are deleted, see `cleanup`.
+    SyncOnce {
+        #[command(flatten)]
+        cloudflare: Box<CloudflareArgs>,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+
+        /// Compute and log the changes needed, without making any changes to cloudflare.
+        #[arg(env, long)]
+        dry_run: bool,
+
+        /// Record every change made to cloudflare in an audit log, see `reconcile --audit-log`.
+        #[arg(env, long)]
+        audit_log: Option<audit::Sink>,
+    },
+    /// Delete every record managed by this controller, across all cloudflare
+    /// zones accessible with the configured credentials, then exit.
+    ///
+    /// Intended for decommissioning the controller. Records are deleted
+    /// regardless of whether any zones in the cluster still reference them.
+    ///
+    /// Takes the same options as the controller, so records it leaves alone,
+    /// such as those of `--skip-types`, are left alone here too. Records are
+    /// deleted regardless of `--mode`, and zones and their other resources
+    /// in cloudflare are never deleted.
+    Cleanup {
+        #[command(flatten)]
+        cloudflare: Box<CloudflareArgs>,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+
+        /// Only log the records which would be deleted.
+        #[arg(env, long)]
+        dry_run: bool,
+
+        /// Record every change made to cloudflare in an audit log, see `reconcile --audit-log`.
+        #[arg(env, long)]
+        audit_log: Option<audit::Sink>,
+    },
+    /// Verify the credentials and their permissions, and show which cloudflare
+    /// zones the zones selected for sync map to, then exit.
+    ///
+    /// Exits with a non-zero status if any of the accessible zones cannot be
+    /// edited, or any selected zone has no corresponding cloudflare zone.
+    Verify {
+        #[command(flatten)]
+        cloudflare: Box<CloudflareArgs>,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+    },
+    /// Diagnose misconfigurations of the controller, printing a finding for
+    /// every check along with hints on how to resolve problems, then exit.
+    ///
+    /// Checks the connection to the cluster and the permissions granted in it,
+    /// the validity and expiry of the credentials, which cloudflare zone each
+    /// of the zones selected for sync maps to, whether their records can be
+    /// edited, and whether their plans include tags, if used for ownership.
+    /// Exits with a non-zero status if any errors were found.
+    Doctor {
+        #[command(flatten)]
+        cloudflare: Box<CloudflareArgs>,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+
+        #[command(flatten)]
+        leader_election: LeaderElectionArgs,
+    },
+    /// Print the desired entries and actual cloudflare records of every zone
+    /// selected for sync, along with their ownership, then exit.
+    ///
+    /// Lines marked with `!` are out of sync. Nothing is changed.
+    List {
+        #[command(flatten)]
+        cloudflare: Box<CloudflareArgs>,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+    },
+    /// Export the records of the cloudflare zones used by the zones selected
+    /// for sync as BIND zone files, then exit.
+    Export {
+        #[command(flatten)]
+        cloudflare: Box<CloudflareArgs>,
+
+        #[command(flatten)]
+        sync: SyncArgs,
+
+        /// Export every zone accessible with the configured credentials,
+        /// not just those used by the zones selected for sync.
+        #[arg(long)]
+        all: bool,
+
+        /// Directory to write the zone files to, as `<zone>.zone`.
+        ///
+        /// If not given, all zone files are printed to stdout.
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+    },
+    /// Generate kubizone Zone and Record resources matching the records of
+    /// an existing cloudflare zone, then exit.
+    ///
+    /// The resources are printed as YAML, or applied to the cluster with `--apply`.
+    /// Imported records are not tagged as managed by the controller.
+    Import {
+        #[command(flatten)]
+        cloudflare: Box<CloudflareArgs>,
+
+        /// Fully qualified name of the cloudflare zone to import, such as `example.org.`
+        #[arg(long, value_parser = parse_fqdn)]
+        zone: FullyQualifiedDomainName,
+
+        /// Namespace to create the resources in.
+        #[arg(long, default_value = "default")]
+        namespace: String,
+
+        /// Apply the resources to the cluster, instead of printing them.
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Serve a validating admission webhook, rejecting Zones and Records
+    /// which cloudflare would refuse when they are applied.
+    ///
+    /// Records of unsupported types or with malformed rdata are denied, while
+    /// TTLs outside the range accepted by cloudflare, CNAME records at the apex
+    /// of zones, and descriptions too long for the comments of records only
+    /// cause warnings. Register it in a ValidatingWebhookConfiguration for the
+    /// CREATE and UPDATE of zones and records.
+    Webhook {
+        #[command(flatten)]
+        sync: SyncArgs,
+
+        /// Address to serve the webhook on, at `/validate`.
+        #[arg(env, long, default_value = "0.0.0.0:8443")]
+        webhook_address: SocketAddr,
+
+        /// PEM encoded certificate chain the webhook is served with.
+        ///
+        /// Re-read whenever it changes, so rotated certificates are picked up.
+        #[arg(env, long)]
+        tls_cert_file: PathBuf,
+
+        /// PEM encoded private key of `--tls-cert-file`.
+        #[arg(env, long)]
+        tls_key_file: PathBuf,
+
+        /// Only warn about zones and records cloudflare would refuse, rather
+        /// than denying them.
+        #[arg(env, long)]
+        warn_only: bool,
+    },
+    /// Print the custom resource definitions used by the controller, as YAML.
+    DumpCrds,
 }
 
-struct Context {
-    controller_name: String,
-    cloudflare: CloudFlare,
-    requeue_time: Duration,
-    cf_domains: Receiver<Vec<cloudflare::Zone>>,
-    mode: Mode,
+/// Arguments for electing a leader among several replicas of the controller.
+#[derive(Debug, clap::Args)]
+struct LeaderElectionArgs {
+    /// Only reconcile zones while holding the leader election lease, so
+    /// several replicas can be run for failover without racing each other.
+    #[arg(env, long)]
+    leader_election: bool,
+
+    /// Name of the Lease used for leader election, defaulting to the
+    /// `--controller-name`, so differently named controllers elect their
+    /// leaders separately.
+    #[arg(env, long)]
+    leader_election_lease_name: Option<String>,
+
+    /// Namespace of the Lease used for leader election, defaulting to the
+    /// namespace the controller runs in.
+    #[arg(env, long)]
+    leader_election_namespace: Option<String>,
+
+    /// Identity of this replica in the Lease, defaulting to its hostname,
+    /// which is the pod name when running in kubernetes.
+    #[arg(env, long)]
+    leader_election_identity: Option<String>,
+
+    /// Time after which other replicas take over the Lease, if the leader
+    /// has not renewed it.
+    #[arg(env, long, default_value_t = 15)]
+    leader_election_lease_duration_secs: u64,
 }
 
-impl Context {
-    pub fn find_cloudflare_zone(
+impl LeaderElectionArgs {
+    /// Start contending for leadership, if enabled.
+    ///
+    /// Returns a receiver reporting whether this replica is the leader, or
+    /// None if leader election is disabled, and this replica always leads.
+    fn start(
         &self,
-        fqdn: &FullyQualifiedDomainName,
-    ) -> Result<cloudflare::Zone, Error> {
-        let managed_zones = self.cf_domains.borrow();
+        kube: &KubeClient,
+        controller_name: &str,
+        health: Health,
+    ) -> Option<tokio::sync::watch::Receiver<bool>> {
+        if !self.leader_election {
+            return None;
+        }
 
-        let Some(zone) = managed_zones
-            .iter()
-            .find(|zone| &zone.fqdn == fqdn)
-            .cloned()
-        else {
-            warn!(
-                "{fqdn} does not match any zones in {}",
-                managed_zones
-                    .iter()
-                    .map(|zone| zone.fqdn.to_string())
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            );
+        let (namespace, lease_name) = self.lease(kube, controller_name);
+        let identity = self.leader_election_identity.clone().unwrap_or_else(|| {
+            std::env::var("HOSTNAME")
+                .unwrap_or_else(|_| format!("kubizone-cloudflare-{:08x}", rand::random::<u32>()))
+        });
 
-            return Err(Error::ZoneNotFound(fqdn.clone()));
-        };
+        info!("contending for leadership through lease {namespace}/{lease_name} as {identity}");
+
+        let election = LeaderElection::new(
+            Api::namespaced(kube.clone(), namespace),
+            lease_name,
+            identity,
+        )
+        .with_lease_duration(Duration::from_secs(
+            self.leader_election_lease_duration_secs,
+        ));
+
+        let (leader_tx, leader) = tokio::sync::watch::channel(false);
+        tokio::spawn(election.run(leader_tx, health));
 
-        Ok(zone)
+        Some(leader)
     }
-}
 
-#[derive(Debug, thiserror::Error)]
-enum Error {
-    #[error("cloudflare: {0}")]
-    CloudFlare(#[from] cloudflare::Error),
-    #[error("kube: {0}")]
-    Kube(#[from] kube::Error),
-    #[error("zone not found in cloudflare: {0}")]
-    ZoneNotFound(FullyQualifiedDomainName),
-    #[error("zone has no entries: {0}")]
-    ZoneHasNoEntries(String),
+    /// Namespace and name of the Lease used for leader election.
+    fn lease<'a>(&'a self, kube: &'a KubeClient, controller_name: &str) -> (&'a str, String) {
+        let namespace = self
+            .leader_election_namespace
+            .as_deref()
+            .unwrap_or(kube.default_namespace());
+        let name = self
+            .leader_election_lease_name
+            .clone()
+            .unwrap_or_else(|| controller_name.to_string());
+
+        (namespace, name)
+    }
 }
 
-async fn reconcile(zone: Arc<Zone>, ctx: Arc<Context>) -> Result<Action, Error> {
-    let Some(fqdn) = zone.fqdn() else {
-        debug!("zone {zone} does not yet have a fully qualified domain name");
-        return Ok(Action::requeue(ctx.requeue_time));
-    };
+/// Arguments determining which changes are made to cloudflare, shared between subcommands.
+#[derive(Debug, Clone, clap::Args)]
+struct SyncArgs {
+    /// Mode determines whether this controller is allowed to delete records.
+    ///
+    /// upsert: the controller will only create and update records.
+    /// delete: the controller will delete records, if they are removed from a zone.
+    ///
+    /// Note that in all cases, the controller will only update or delete records
+    /// which are managed by the controller. The controller tags the records it
+    /// creates in cloudflare to track ownership.
+    ///
+    /// Zones can override the mode through the `cloudflare.kubi.zone/mode`
+    /// annotation. The `cloudflare.kubi.zone/prune` annotation takes precedence
+    /// over either, deleting records if it is "true", and never if it is "false".
+    #[arg(value_enum, env, long, default_value_t = Mode::Upsert)]
+    mode: Mode,
 
-    let cloudflare_zone = ctx.find_cloudflare_zone(fqdn)?;
+    /// Name used to tag records created in cloudflare.
+    ///
+    /// This can be overridden if you have multiple controllers managing separate
+    /// parts of a singular zone, and don't want them to interfere with each other.
+    ///
+    /// If two controllers are running with the same name, but they have access
+    /// to different Kubizone Zone resources, they will constantly identify records
+    /// created by the other controller as to-be-deleted.
+    ///
+    /// Controllers with a different name also use their own finalizer and
+    /// leader election lease, so it must be a valid kubernetes name.
+    #[arg(env, long, default_value = DEFAULT_CONTROLLER_NAME)]
+    controller_name: String,
 
-    // Collect all existing entries in (RecordIdent, Record) map.
-    let records = ctx
-        .cloudflare
-        .records(&cloudflare_zone.id)
-        .await?
-        .into_iter()
-        .map(|record| (RecordIdent::from(&record), record))
-        .collect::<HashMap<_, _>>();
+    /// Description added to the comment of records, after the
+    /// `managed-by:<controller-name>` marker, such as
+    /// `managed by {controller} from {cluster}/{namespace}/{zone}`.
+    ///
+    /// `{controller}`, `{cluster}`, `{namespace}` and `{zone}` are replaced
+    /// with the controller name, `--cluster-name`, and the namespace and name
+    /// of the zone. Only used when tracking ownership through comments.
+    #[arg(env, long)]
+    comment_template: Option<String>,
 
-    // Collect all desired entries in (RecordIdent, ZoneEntry) map.
-    let entries = zone
-        .status
-        .as_ref()
-        .map(|status| &status.entries)
-        .ok_or(Error::ZoneHasNoEntries(zone.name_any()))?
-        .iter()
-        .filter(|entry| !entry.type_.is_soa())
-        .map(|entry| (RecordIdent::from(entry), entry))
-        .collect::<HashMap<_, _>>();
+    /// Name of the cluster, used in `--comment-template`.
+    #[arg(env, long)]
+    cluster_name: Option<String>,
 
-    // Create missing entries
-    for missing_entry in entries
-        .iter()
-        .filter_map(|(ident, entry)| (!records.contains_key(ident)).then_some(entry))
-    {
-        info_span!("create");
+    #[command(flatten)]
+    cluster: ClusterArgs,
 
-        info!(
-            "creating record {missing_entry:?} in {} with value {}",
-            cloudflare_zone.fqdn, missing_entry.rdata
-        );
+    /// Take ownership of existing records in cloudflare which match an entry
+    /// of a zone, but are not managed by any controller.
+    ///
+    /// Without this, such records are left alone.
+    #[arg(env, long)]
+    adopt_existing: bool,
 
-        ctx.cloudflare
-            .create_record(&cloudflare_zone.id, &ctx.controller_name, missing_entry)
-            .await?;
+    /// Comma separated names of other owners, such as a previous controller name or
+    /// an external-dns owner id, whose records are taken over by this controller.
+    ///
+    /// Their records are treated as managed by us: records matching an entry are
+    /// adopted, replacing the marker of the other owner with ours. Records marked
+    /// by anyone else are never changed.
+    #[arg(env, long, value_delimiter = ',')]
+    take_ownership_from: Vec<String>,
+
+    /// Create zones in cloudflare for Zones which do not match any existing
+    /// cloudflare zone, in the account given by `--cf-account-id`.
+    ///
+    /// Cloudflare only activates the zone once the domain has been delegated to
+    /// its nameservers, which are published in an event and the sync condition
+    /// of the zone until then, and in its sync stats after. Records are synced
+    /// once the zone is active.
+    #[arg(env, long, requires = "cf_account_id")]
+    create_zones: bool,
+
+    /// Id of the cloudflare account zones are created in, see `--create-zones`.
+    #[arg(env, long)]
+    cf_account_id: Option<String>,
+
+    /// How ownership of the records in cloudflare is written and detected.
+    ///
+    /// comment: records are marked with `managed-by:<controller-name>` in their comment.
+    /// tags: records are tagged with `managed-by:<controller-name>`, which requires
+    /// a cloudflare plan supporting tags, but leaves the comment to the user.
+    /// Zones rejecting tags fall back to comments.
+    /// txt-registry: each name and type is claimed by a companion TXT record, such as
+    /// `a-www.example.org.` for the A records of `www.example.org.`, in the format
+    /// used by external-dns' TXT registry, with `--controller-name` as the owner id.
+    ///
+    /// With `--cluster-id`, every marker names `<controller-name>@<cluster-id>` instead.
+    /// Records at the apex of a zone cannot be claimed through TXT records.
+    #[arg(value_enum, env, long, default_value_t = Ownership::Comment)]
+    ownership: Ownership,
+
+    /// Lowest TTL of records in cloudflare.
+    ///
+    /// Entries with lower TTLs are synced with this TTL instead, and a warning
+    /// event is published on their zone. Zones can set their own minimum
+    /// through the `cloudflare.kubi.zone/min-ttl` annotation.
+    #[arg(env, long)]
+    min_ttl: Option<u32>,
+
+    /// Comma separated record types which are never created, updated or
+    /// deleted, even if they appear in a zone, such as `MX,NS`.
+    #[arg(env, long, value_delimiter = ',', value_parser = parse_record_type)]
+    exclude_record_types: Vec<Type>,
+
+    /// Comma separated record types managed by the controller, such as
+    /// `A,AAAA,CNAME,TXT`. Records of other types, both in zones and in
+    /// cloudflare, are left alone.
+    ///
+    /// All types are managed by default.
+    #[arg(env, long, value_delimiter = ',', value_parser = parse_record_type)]
+    record_types: Vec<Type>,
+
+    /// Permit deleting NS records at the apex of cloudflare zones in delete mode.
+    ///
+    /// Without this, they are never deleted, since that would break the
+    /// delegation of the zone to cloudflare.
+    #[arg(env, long)]
+    delete_apex_ns: bool,
+
+    /// Permit deleting the cloudflare zone itself once a Zone is deleted, if
+    /// it requests so through the `cloudflare.kubi.zone/delete-zone` annotation.
+    ///
+    /// Only cloudflare zones matching the domain of the Zone exactly are
+    /// deleted, and only if no other Zones map onto them. Otherwise, only the
+    /// managed records are cleaned up as usual.
+    #[arg(env, long)]
+    allow_zone_deletion: bool,
+
+    /// Sync settings of cloudflare zones declared through annotations of their
+    /// Zone, such as `cloudflare.kubi.zone/ssl: strict`.
+    ///
+    /// Supported are `ssl` (off, flexible, full or strict), `always-use-https`
+    /// (on or off) and `min-tls-version` (1.0 to 1.3). Settings without an
+    /// annotation are left alone.
+    #[arg(env, long)]
+    sync_zone_settings: bool,
+
+    /// Sync load balancers declared through the `cloudflare.kubi.zone/load-balancer`
+    /// annotation of Records, in place of their plain records.
+    ///
+    /// Pools and monitors are created in the account given by `--cf-account-id`.
+    /// In delete mode, load balancers within the zone which are no longer
+    /// declared are deleted, along with their pools and monitors.
+    #[arg(env, long, requires = "cf_account_id")]
+    load_balancers: bool,
+
+    /// Create Custom Hostnames (SSL for SaaS) listed in the
+    /// `cloudflare.kubi.zone/custom-hostnames` annotation of Records.
+    ///
+    /// Their status, and the records needed to validate them, are published in
+    /// the `cloudflare.kubi.zone/custom-hostnames-status` annotation of the Record.
+    /// In delete mode, custom hostnames removed from the annotation are deleted.
+    #[arg(env, long)]
+    custom_hostnames: bool,
+
+    /// Serve hostnames of Records from Cloudflare Workers, through custom domains
+    /// declared by the `cloudflare.kubi.zone/worker` annotation, or routes
+    /// declared by the `cloudflare.kubi.zone/worker-route` annotation.
+    ///
+    /// Custom domains are attached in the account given by `--cf-account-id`.
+    /// In delete mode, custom domains and routes are removed again once their
+    /// annotation is.
+    #[arg(env, long, requires = "cf_account_id")]
+    workers: bool,
+
+    /// Pin hostnames of Records to the region given by their
+    /// `cloudflare.kubi.zone/region` annotation, using regional hostnames.
+    ///
+    /// In delete mode, hostnames are unpinned again once the annotation is removed.
+    #[arg(env, long)]
+    regional_hostnames: bool,
+
+    /// Comma separated types of zone entries which are not synced to cloudflare,
+    /// and whose records in cloudflare are left alone.
+    ///
+    /// Set this to `SOA,NS` to leave the NS records of sub-delegations alone.
+    /// SOA records are always skipped, since cloudflare manages the SOA record
+    /// of its zones.
+    #[arg(env, long, value_delimiter = ',', value_parser = parse_record_type, default_value = "SOA")]
+    skip_types: Vec<Type>,
+
+    /// Most managed records deleted from a zone in a single reconcile.
+    ///
+    /// If more would be deleted, none are, and the sync of the zone fails
+    /// until the deletions are acknowledged by setting the
+    /// `cloudflare.kubi.zone/acknowledged-deletions` annotation of the zone
+    /// to the number of records to delete.
+    #[arg(env, long)]
+    max_deletions_per_reconcile: Option<usize>,
+
+    /// Largest percentage of a zone's managed records deleted in a single reconcile.
+    ///
+    /// If a larger share would be deleted, or the zone has no entries at all,
+    /// the deletions are held back like with `--max-deletions-per-reconcile`,
+    /// since the zone's status is most likely incomplete.
+    #[arg(env, long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    max_deletion_percentage: Option<u8>,
+
+    /// Label selector restricting which zones are reconciled, such as
+    /// `environment=production`.
+    ///
+    /// Allows running several controllers in one cluster, each reconciling
+    /// a separate set of zones.
+    #[arg(env, long)]
+    zone_label_selector: Option<String>,
+
+    /// Comma separated namespaces whose zones and records are reconciled.
+    ///
+    /// All namespaces are watched by default, which requires permission to
+    /// list zones and records across the cluster.
+    #[arg(env, long, value_delimiter = ',')]
+    namespaces: Vec<String>,
+
+    /// Only sync zones which opted in through the
+    /// `cloudflare.kubi.zone/enabled: "true"` annotation, ignoring all others.
+    #[arg(env, long)]
+    require_annotation: bool,
+
+    /// Total number of shards the zones are divided into, each reconciled
+    /// by a separate replica of the controller.
+    ///
+    /// Zones are assigned to shards by their UID, using consistent hashing.
+    /// When combined with `--leader-election`, each shard needs its own
+    /// `--leader-election-lease-name`.
+    #[arg(env, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    shard_count: u32,
+
+    /// Shard of the zones reconciled by this replica, starting at 0.
+    #[arg(env, long, default_value_t = 0)]
+    shard_index: u32,
+
+    /// Maximum number of concurrent requests to cloudflare when changes to a
+    /// zone are applied individually, after cloudflare rejected their batch.
+    ///
+    /// Rate limited requests are retried according to the retry settings,
+    /// so raising this mostly helps zones with many changes when latency,
+    /// not the rate limit, is the bottleneck.
+    #[arg(env, long, default_value_t = 4, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_concurrent_requests: usize,
+
+    /// Number of CloudflareChangeSet resources kept for every zone, each
+    /// recording the changes made to its records within a single sync,
+    /// including those cloudflare rejected. Zero disables them.
+    ///
+    /// Change sets are created in the namespace of their zone, and deleted
+    /// along with it. Requires the CloudflareChangeSet CRD to be installed.
+    #[arg(env, long, default_value_t = 0)]
+    change_set_history: usize,
+}
+
+impl SyncArgs {
+    /// Check the combinations of options which cannot be validated one by one.
+    fn validate(&self) -> Result<(), String> {
+        if self.shard_index >= self.shard_count {
+            return Err(format!(
+                "--shard-index {} must be below --shard-count {}",
+                self.shard_index, self.shard_count
+            ));
+        }
+
+        Ok(())
     }
 
-    // Delete unexpected records (that we manage)
-    for (ident, unexpected_record) in records
-        .iter()
-        .filter(|(ident, _)| !entries.contains_key(ident))
+    /// Namespaces which are reconciled, or a single None if all of them are.
+    fn scopes(&self) -> Vec<Option<&str>> {
+        if self.namespaces.is_empty() {
+            return vec![None];
+        }
+
+        self.namespaces
+            .iter()
+            .map(|namespace| Some(namespace.as_str()))
+            .collect()
+    }
+
+    /// Apis for the resources in the namespaces which are reconciled.
+    fn apis<K>(&self, kube: &KubeClient) -> Vec<Api<K>>
+    where
+        K: kube::Resource<DynamicType = (), Scope = NamespaceResourceScope>,
     {
-        info_span!("delete");
-        if !unexpected_record.is_managed_by(&ctx.controller_name) {
-            debug!("unexpected record {ident:?} found in zone {cloudflare_zone:?} has no corresponding entry in zone {zone}, but record is not managed by us.");
-            continue;
+        self.scopes()
+            .into_iter()
+            .map(|namespace| match namespace {
+                Some(namespace) => Api::namespaced(kube.clone(), namespace),
+                None => Api::all(kube.clone()),
+            })
+            .collect()
+    }
+
+    /// Zones which are reconciled.
+    async fn list_zones(&self, kube: &KubeClient) -> Result<Vec<Zone>, kube::Error> {
+        let params = self.zone_list_params();
+
+        let mut zones = Vec::new();
+        for api in self.apis::<Zone>(kube) {
+            zones.extend(api.list(&params).await?.items);
         }
+        zones.retain(|zone| self.is_in_shard(zone) && self.is_enabled(zone));
 
-        if ctx.mode == Mode::Delete {
-            info!(
-                "deleting record {ident:?} in {} with id {}",
-                cloudflare_zone.fqdn, unexpected_record.id
-            );
-            ctx.cloudflare
-                .delete_record(&cloudflare_zone.id, &unexpected_record.id)
-                .await?;
-        } else {
-            info!("not deleting {ident:?}, since controller is running in 'upsert' mode");
+        Ok(zones)
+    }
+
+    /// Returns true if the zone is assigned to the shard of this replica, see `--shard-count`.
+    fn is_in_shard(&self, zone: &Zone) -> bool {
+        if self.shard_count == 1 {
+            return true;
         }
+
+        let key = zone.uid().unwrap_or_else(|| zone.to_string());
+        shard::shard(&key, self.shard_count) == self.shard_index
     }
 
-    // Update records (that we manage) with new information
-    for (ident, entry, record) in entries
-        .keys()
-        .collect::<HashSet<_>>()
-        .intersection(&records.keys().collect::<HashSet<_>>())
-        .filter_map(|ident| Some((ident, entries.get(ident)?, records.get(ident)?)))
-    {
-        info_span!("update");
+    /// Returns true if the zone is synced to cloudflare, see `--require-annotation`.
+    fn is_enabled(&self, zone: &Zone) -> bool {
+        annotations::is_enabled(zone, !self.require_annotation)
+    }
 
-        if !record.is_managed_by(&ctx.controller_name) {
-            info!("entry {ident:?} appears in zone {zone}, but the corresponding record in cloudflare is not managed by us");
-            continue;
+    /// Parameters for listing the zones which are reconciled.
+    fn zone_list_params(&self) -> ListParams {
+        match &self.zone_label_selector {
+            Some(selector) => ListParams::default().labels(selector),
+            None => ListParams::default(),
         }
+    }
 
-        if entry.rdata == record.rdata && entry.ttl == record.ttl {
-            trace!("record {ident:?} already up to date");
-            continue;
+    /// Configuration for watching the zones which are reconciled.
+    fn zone_watcher_config(&self) -> watcher::Config {
+        match &self.zone_label_selector {
+            Some(selector) => watcher::Config::default().labels(selector),
+            None => watcher::Config::default(),
         }
+    }
 
-        // Update record.
-        info!(
-            "updating record {ident:?} in {} from {} with ttl {} => {} with ttl {}",
-            cloudflare_zone.fqdn, entry.rdata, entry.ttl, record.rdata, record.ttl
-        );
+    /// Name of the owner in the markers of records written by us, see `--cluster-id`.
+    fn owner(&self) -> String {
+        self.cluster.owner(&self.controller_name)
+    }
 
-        ctx.cloudflare
-            .update_record(&cloudflare_zone.id, &record.id, entry)
-            .await?;
+    /// Options for the `cleanup` subcommand, which deletes the managed records
+    /// regardless of the mode, but nothing else.
+    fn for_cleanup(self) -> Self {
+        SyncArgs {
+            mode: Mode::Delete,
+            create_zones: false,
+            allow_zone_deletion: false,
+            sync_zone_settings: false,
+            load_balancers: false,
+            custom_hostnames: false,
+            workers: false,
+            regional_hostnames: false,
+            change_set_history: 0,
+            ..self
+        }
+    }
+
+    /// Returns true if records of the type are synced, see [`plan::Options::manages`].
+    fn manages(&self, type_: Type) -> bool {
+        plan::manages(
+            type_,
+            &self.record_types,
+            &self.exclude_record_types,
+            &self.skip_types,
+        )
+    }
+
+    /// Description of the zone's records in their comments, see `--comment-template`.
+    fn describe(&self, zone: &Zone) -> Option<String> {
+        self.comment_template.as_ref().map(|template| {
+            ownership::describe(
+                template,
+                &self.controller_name,
+                self.cluster_name.as_deref(),
+                zone,
+            )
+        })
+    }
+
+    fn plan_options<'a>(
+        &'a self,
+        owner: &'a str,
+        zone: &Zone,
+        config: &ZoneConfig,
+        apex: &'a FullyQualifiedDomainName,
+        proxied: &'a Proxied,
+        protected: &'a Protected,
+    ) -> plan::Options<'a> {
+        let mode = match config.prune {
+            Some(true) => Mode::Delete,
+            Some(false) => Mode::Upsert,
+            None => self.mode.clone(),
+        };
+
+        plan::Options {
+            owner,
+            legacy_owner: self.cluster.legacy_owner(&self.controller_name),
+            take_over_from: &self.take_ownership_from,
+            delete: annotations::prune(zone, annotations::mode(zone, mode) == Mode::Delete),
+            apex,
+            delete_apex_ns: self.delete_apex_ns,
+            adopt: self.adopt_existing,
+            ownership: self.ownership,
+            proxied,
+            protected,
+            min_ttl: annotations::min_ttl(zone, config.min_ttl.or(self.min_ttl)),
+            types: &self.record_types,
+            exclude_types: &self.exclude_record_types,
+            skip_types: &self.skip_types,
+        }
     }
 
-    Ok(Action::requeue(ctx.requeue_time))
+    /// Cloudflare account of zones with the config, see `--cf-account-id`.
+    fn account_id<'a>(&'a self, config: &'a ZoneConfig) -> Option<&'a str> {
+        config
+            .account_id
+            .as_deref()
+            .or(self.cf_account_id.as_deref())
+    }
 }
 
-fn error_policy(zone: Arc<Zone>, error: &Error, _ctx: Arc<Context>) -> Action {
-    error!(
-        "zone {} reconciliation encountered error: {error}",
-        zone.name_any()
-    );
-    Action::requeue(Duration::from_secs(60))
+/// Arguments identifying the cluster in ownership markers, shared between subcommands.
+#[derive(Debug, Clone, clap::Args)]
+struct ClusterArgs {
+    /// Identifier of the cluster, added to the ownership marker of records
+    /// as `managed-by:<controller-name>@<cluster-id>`.
+    ///
+    /// Controllers in several clusters managing records in the same cloudflare
+    /// zones need distinct identifiers, so they leave each other's records alone.
+    #[arg(env, long, value_parser = parse_cluster_id)]
+    cluster_id: Option<String>,
+
+    /// Also treat records marked as `managed-by:<controller-name>`, as written
+    /// before `--cluster-id` was set, as managed by this controller.
+    ///
+    /// Their markers are replaced once the records are next updated.
+    #[arg(env, long, requires = "cluster_id")]
+    read_legacy_markers: bool,
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() {
-    tracing_subscriber::fmt::init();
+impl ClusterArgs {
+    fn owner(&self, controller_name: &str) -> String {
+        ownership::owner(controller_name, self.cluster_id.as_deref())
+    }
 
-    let args = Args::parse();
+    /// Name of the owner in legacy markers which are accepted as ours, if any.
+    fn legacy_owner<'a>(&self, controller_name: &'a str) -> Option<&'a str> {
+        (self.read_legacy_markers && self.cluster_id.is_some()).then_some(controller_name)
+    }
+}
 
-    let client = KubeClient::try_default().await.unwrap();
+/// Arguments for connecting to cloudflare, shared between subcommands.
+#[derive(Debug, clap::Args)]
+#[command(group(ArgGroup::new("credentials").required(true)))]
+struct CloudflareArgs {
+    /// Cloudflare API key used to access zones.
+    #[arg(env, long, group = "credentials")]
+    cf_api_key: Option<String>,
 
-    match args.command {
-        Command::Reconcile {
-            requeue_time_secs,
-            cf_api_key,
-            mode,
-            controller_name,
-        } => {
-            let cloudflare = CloudFlare::new(&cf_api_key);
+    /// Kubernetes Secret containing the Cloudflare API token, as `namespace/name#key`.
+    ///
+    /// The secret is watched, and the new token is used as soon as its value changes.
+    #[arg(env, long, group = "credentials")]
+    cf_api_token_secret: Option<SecretRef>,
 
-            let (tx, mut rx) = tokio::sync::watch::channel(vec![]);
+    /// File containing the Cloudflare API token, such as a mounted Secret.
+    ///
+    /// The file is re-read periodically, so the token can be rotated without
+    /// restarting the controller.
+    #[arg(env, long, group = "credentials")]
+    cf_api_token_file: Option<PathBuf>,
 
-            let cf_clone = cloudflare.clone();
-            tokio::spawn(async move {
-                //let mut domains = tx;
+    /// Legacy Cloudflare Global API Key, used instead of a scoped API token.
+    ///
+    /// Requires `--cf-api-email` to be set as well. Prefer scoped API tokens
+    /// where possible.
+    #[arg(env, long, group = "credentials", requires = "cf_api_email")]
+    cf_global_key: Option<String>,
 
-                if let Ok(zones) = cf_clone.list_zones().await {
-                    println!("new zones just dropped! {zones:#?}");
-                    tx.send(zones).unwrap();
-                }
+    /// Email address of the account the `--cf-global-key` belongs to.
+    #[arg(env, long, requires = "cf_global_key")]
+    cf_api_email: Option<String>,
 
-                tokio::time::sleep(std::time::Duration::from_secs(60 * 5)).await;
-            });
+    /// Credentials used for specific zones, as `pattern[,pattern...]=source`.
+    ///
+    /// The source is either `secret:namespace/name#key` or `file:/path/to/token`.
+    /// Zones are matched against the patterns in the order the credentials are
+    /// given, and zones matching none of them use the default credentials above.
+    ///
+    /// Can be specified multiple times, to serve zones across multiple accounts.
+    #[arg(env, long, value_delimiter = ';')]
+    cf_zone_credentials: Vec<ZoneCredentials>,
 
-            rx.changed().await.unwrap();
+    /// Watch CloudflareCredential resources in all namespaces, and use the
+    /// credentials they reference for their matching zones.
+    ///
+    /// These are consulted after `--cf-zone-credentials`, but before the default
+    /// credentials. Requires the CloudflareCredential CRD to be installed.
+    #[arg(env, long)]
+    watch_credentials: bool,
 
-            let context = Context {
-                controller_name,
-                requeue_time: std::time::Duration::from_secs(requeue_time_secs),
-                cloudflare,
-                cf_domains: rx,
-                mode,
-            };
+    /// Watch cluster-scoped CloudflareZoneConfig resources, and use them as
+    /// the defaults of their matching zones.
+    ///
+    /// Configs take precedence over the command line flags, but not over the
+    /// annotations of zones. Accounts for the credentials they reference are
+    /// consulted after CloudflareCredential resources, but before the default
+    /// credentials. Requires the CloudflareZoneConfig CRD to be installed.
+    #[arg(env, long)]
+    watch_zone_configs: bool,
 
-            let zones = Api::<Zone>::all(client.clone());
+    /// Time between re-reads of the `--cf-api-token-file`.
+    #[arg(env, long, default_value_t = 60)]
+    cf_api_token_file_refresh_secs: u64,
 
-            Controller::new(zones.clone(), watcher::Config::default())
-                .shutdown_on_signal()
-                .run(reconcile, error_policy, Arc::new(context))
-                .for_each(|res| async move {
-                    match res {
-                        Ok(o) => info!("reconciled: {:?}", o),
-                        Err(e) => warn!("reconciliation failed: {}", e),
-                    }
-                })
-                .await;
+    /// Time between verifications of the api token.
+    ///
+    /// The token is always verified on startup, and the controller refuses to
+    /// start if the token is invalid. Later verifications only log the status.
+    #[arg(env, long, default_value_t = 3600)]
+    cf_api_token_verify_secs: u64,
+
+    /// Time between refreshes of the list of zones available in cloudflare.
+    ///
+    /// Zones added to (or removed from) the cloudflare account are picked up
+    /// on the next refresh. When looking up zones by name, how long the
+    /// results of a lookup are cached instead.
+    #[arg(env, long, default_value_t = 300)]
+    zone_refresh_secs: u64,
+
+    /// How the cloudflare zones of the accounts are found.
+    ///
+    /// list: every zone of the account is listed periodically.
+    /// name: only the zones enclosing the domain of a Zone are looked up, when
+    /// it is reconciled. This is much faster for accounts with thousands of zones,
+    /// but `cleanup --all` and `export --all` only cover the zones looked up.
+    #[arg(value_enum, env, long, default_value_t = ZoneLookup::List)]
+    zone_lookup: ZoneLookup,
+
+    /// Number of records fetched per request when listing a zone's records.
+    ///
+    /// All pages are always fetched, but larger pages mean fewer requests
+    /// against the cloudflare API for big zones.
+    #[arg(env, long, default_value_t = cloudflare::DEFAULT_RECORDS_PER_PAGE)]
+    cf_records_per_page: u32,
+
+    /// Base url of the cloudflare api, such as that of a proxy in front of it.
+    #[arg(env, long, default_value = cloudflare::API_URL)]
+    cf_api_url: String,
+
+    /// Number of times idempotent cloudflare requests are retried, with
+    /// exponential backoff, when they fail due to server errors or timeouts.
+    #[arg(env, long, default_value_t = RetryPolicy::default().max_retries)]
+    cf_max_retries: u32,
+}
+
+#[derive(ValueEnum, Default, Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Upsert,
+    Delete,
+}
+
+struct Context {
+    kube: KubeClient,
+    sync: SyncArgs,
+    /// Name of the owner in the markers of records written by us.
+    owner: String,
+    accounts: Arc<Accounts>,
+    requeue_time: Duration,
+    stats_refresh_time: Duration,
+    dry_run: bool,
+    /// Upper bound on the time before a failing zone is reconciled again.
+    max_backoff: Duration,
+    /// Fraction of the requeue time added at random, see `--requeue-jitter-percent`.
+    requeue_jitter: f64,
+    /// What is learned while reconciling, kept when the context is rebuilt.
+    caches: Arc<Caches>,
+    /// Log of the changes made to cloudflare, see `--audit-log`.
+    audit: Arc<Audit>,
+}
+
+/// State of the controller outliving its [`Context`], which is rebuilt
+/// whenever the sync options are reloaded or leadership is regained.
+struct Caches {
+    /// Cloudflare zones which rejected tags, and in which ownership is
+    /// therefore tracked through comments instead.
+    untagged_zones: Mutex<HashSet<ZoneId>>,
+    /// Records of the cloudflare zones, see `--record-cache-secs`.
+    record_cache: RecordCache,
+    /// Zones whose cloudflare zone could not be found, by the fqdn looked for.
+    missing_zones: Mutex<HashMap<ObjectRef<Zone>, FullyQualifiedDomainName>>,
+    /// Number of consecutive failed reconciliations of each zone, by `namespace/name`.
+    failures: Mutex<HashMap<String, u32>>,
+}
+
+impl Caches {
+    /// Empty caches, keeping records for `record_ttl`, see [`RecordCache::new`].
+    fn new(record_ttl: Duration) -> Arc<Self> {
+        Arc::new(Caches {
+            untagged_zones: Mutex::default(),
+            record_cache: RecordCache::new(record_ttl),
+            missing_zones: Mutex::default(),
+            failures: Mutex::default(),
+        })
+    }
+}
+
+impl Context {
+    /// Context of the commands running outside of the controller, which sync
+    /// every zone at most once, so nothing is requeued and nothing is cached.
+    ///
+    /// Stats are recorded with every sync, since it is the last one for a while.
+    fn detached(
+        kube: KubeClient,
+        sync: SyncArgs,
+        accounts: Accounts,
+        dry_run: bool,
+        audit: Arc<Audit>,
+    ) -> Self {
+        Context {
+            kube,
+            owner: sync.owner(),
+            sync,
+            accounts: Arc::new(accounts),
+            requeue_time: Duration::ZERO,
+            stats_refresh_time: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            requeue_jitter: 0.0,
+            caches: Caches::new(Duration::ZERO),
+            dry_run,
+            audit,
         }
-    };
+    }
+
+    /// Records of the cloudflare zone, from the cache if they are recent enough.
+    async fn records(
+        &self,
+        cloudflare: &impl DnsProvider,
+        zone_id: &ZoneId,
+    ) -> Result<Vec<cloudflare::Record>, cloudflare::Error> {
+        if let Some(records) = self.caches.record_cache.get(zone_id) {
+            return Ok(records);
+        }
+
+        let records = cloudflare.records(zone_id).await?;
+        self.caches.record_cache.insert(zone_id, &records);
+
+        Ok(records)
+    }
+
+    /// Record the change to a record of the cloudflare zone in the audit log
+    /// and the journal, along with the error cloudflare rejected it with, if any.
+    fn audit(
+        &self,
+        journal: &Journal,
+        cloudflare_zone: &cloudflare::Zone,
+        change: &RecordChange<'_>,
+        error: Option<&cloudflare::Error>,
+    ) {
+        let entry = audit::Entry::record(&self.owner, cloudflare_zone, change, error);
+        self.audit.write(&entry);
+        journal.push(entry);
+    }
+
+    /// Record the change to a resource of the cloudflare zone other than its
+    /// records in the audit log, along with the error cloudflare rejected it
+    /// with, if any.
+    fn audit_resource<T>(
+        &self,
+        cloudflare_zone: &cloudflare::Zone,
+        action: &'static str,
+        resource: &str,
+        result: &Result<T, cloudflare::Error>,
+    ) {
+        self.audit.write(&audit::Entry::resource(
+            &self.owner,
+            action,
+            cloudflare_zone,
+            resource,
+            result.as_ref().err(),
+        ));
+    }
+
+    /// Persist the changes in the journal as a change set of the zone, see
+    /// `--change-set-history`.
+    ///
+    /// Failing to do so is logged, rather than failing the reconciliation,
+    /// since the changes have already been applied at this point.
+    async fn record_change_set(&self, zone: &Zone, journal: Journal) {
+        let history = self.sync.change_set_history;
+        if history == 0 {
+            return;
+        }
+
+        let entries = journal.into_entries();
+        if let Err(err) = change_sets::record(&self.kube, zone, &entries, history).await {
+            warn!(
+                zone = %zone.name_any(),
+                "failed to record change set of zone {}: {err}",
+                zone.name_any()
+            );
+        }
+    }
+
+    /// Reconcile the zone again after `delay`, extended by a random jitter.
+    fn requeue(&self, delay: Duration) -> Action {
+        Action::requeue(delay + delay.mul_f64(self.requeue_jitter * rand::random::<f64>()))
+    }
+
+    /// Owners of the records in a cloudflare zone, as seen by the controller.
+    fn owners<'r>(&self, records: &'r [cloudflare::Record]) -> Owners<'r> {
+        Owners::new(self.sync.ownership, &self.owner, records)
+            .with_legacy_owner(self.sync.cluster.legacy_owner(&self.sync.controller_name))
+            .with_take_over_from(&self.sync.take_ownership_from)
+    }
+
+    /// How records written to the cloudflare zone are marked as managed by us,
+    /// with the description added to comments.
+    fn marker<'a>(&'a self, zone_id: &ZoneId, description: Option<&'a str>) -> Marker<'a> {
+        match self.sync.ownership.marker(&self.owner, description) {
+            Marker::Tag(owner) if self.caches.untagged_zones.lock().unwrap().contains(zone_id) => {
+                Marker::Comment { owner, description }
+            }
+            marker => marker,
+        }
+    }
+
+    /// Fall back to tracking ownership through comments in the cloudflare zone,
+    /// if `err` indicates that the zone does not permit tags.
+    ///
+    /// Returns true if the failed request should be retried.
+    fn fall_back_to_comments(
+        &self,
+        cloudflare_zone: &cloudflare::Zone,
+        marker: Marker<'_>,
+        err: &cloudflare::Error,
+    ) -> bool {
+        if !matches!(marker, Marker::Tag(_)) || !err.is_tags_rejected() {
+            return false;
+        }
+
+        warn!(
+            zone = %cloudflare_zone.fqdn,
+            cf_zone_id = %cloudflare_zone.id,
+            "cloudflare zone {} does not permit tags ({err}), tracking ownership through comments instead",
+            cloudflare_zone.fqdn
+        );
+
+        self.caches
+            .untagged_zones
+            .lock()
+            .unwrap()
+            .insert(cloudflare_zone.id.clone());
+
+        true
+    }
+
+    /// Find the cloudflare zone matching the fqdn, along with a client for the
+    /// account it belongs to.
+    pub async fn find_cloudflare_zone(
+        &self,
+        fqdn: &FullyQualifiedDomainName,
+    ) -> Result<(CloudFlare, cloudflare::Zone), Error> {
+        self.accounts.lookup_zone(fqdn).await?;
+
+        if let Some(found) = self.accounts.find_zone(fqdn) {
+            return Ok(found);
+        }
+
+        warn!(
+            "{fqdn} does not match any zones in {}",
+            self.accounts
+                .covering(fqdn)
+                .iter()
+                .flat_map(|account| account.zones())
+                .map(|zone| zone.fqdn.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        Err(Error::ZoneNotFound(fqdn.clone()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum Error {
+    #[error("cloudflare: {0}")]
+    CloudFlare(#[from] cloudflare::Error),
+    #[error("kube: {0}")]
+    Kube(#[from] kube::Error),
+    #[error("zone not found in cloudflare: {0}")]
+    ZoneNotFound(FullyQualifiedDomainName),
+    #[error("cloudflare zone {} is {}", .0.fqdn, zone_state(.0))]
+    ZoneNotActive(cloudflare::Zone),
+    #[error("zone has no entries: {0}")]
+    ZoneHasNoEntries(String),
+    #[error("{}: {1}", .0.display())]
+    File(PathBuf, std::io::Error),
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("yaml: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("finalizer: {0}")]
+    Finalizer(#[source] Box<finalizer::Error<Error>>),
+    #[error("not deleting {deletions} records, since it exceeds the maximum of {max} per reconcile; set the {} annotation to {deletions} to delete them anyway", annotations::acknowledged_deletions_annotation())]
+    TooManyDeletions { deletions: usize, max: usize },
+    #[error("not deleting {deletions} of {managed} managed records, since the zone only has {entries} entries; set the {} annotation to {deletions} to delete them anyway", annotations::acknowledged_deletions_annotation())]
+    DesiredStateCollapsed {
+        entries: usize,
+        deletions: usize,
+        managed: usize,
+    },
+    #[error("changes to cloudflare are paused through the {} annotation, so the records are only cleaned up once it is removed", annotations::paused_annotation())]
+    Paused,
+}
+
+impl Error {
+    /// Reason used for the sync condition when reconciliation fails with this error.
+    fn reason(&self) -> &'static str {
+        match self {
+            Error::CloudFlare(err) if err.is_unauthorized() => "Unauthorized",
+            Error::CloudFlare(_) => "CloudflareError",
+            Error::Kube(_) => "KubeError",
+            Error::ZoneNotFound(_) => "ZoneNotFound",
+            Error::ZoneNotActive(_) => "ZoneNotActive",
+            Error::ZoneHasNoEntries(_) => "ZoneHasNoEntries",
+            Error::File(..) => "FileError",
+            Error::Json(_) | Error::Yaml(_) => "SerializationError",
+            Error::Finalizer(_) => "FinalizerError",
+            Error::TooManyDeletions { .. } => "TooManyDeletions",
+            Error::DesiredStateCollapsed { .. } => "DesiredStateCollapsed",
+            Error::Paused => "Paused",
+        }
+    }
+
+    /// Time before retrying a zone which failed to reconcile with this error,
+    /// after `failures` consecutive failures, including this one.
+    fn backoff(&self, failures: u32, max: Duration) -> Duration {
+        let base = match self {
+            Error::Finalizer(err) => match err.as_ref() {
+                finalizer::Error::ApplyFailed(err) | finalizer::Error::CleanupFailed(err) => {
+                    return err.backoff(failures, max);
+                }
+                _ => Duration::from_secs(60),
+            },
+            Error::CloudFlare(err) => match err.retry_after() {
+                Some(retry_after) => return retry_after.min(max),
+                // Credentials are unlikely to be fixed within minutes.
+                None if err.is_unauthorized() => Duration::from_secs(300),
+                None if err.is_transient() => Duration::from_secs(5),
+                None => Duration::from_secs(60),
+            },
+            Error::Kube(_) => Duration::from_secs(5),
+            // Cloudflare zones are rarely created right after their zone.
+            Error::ZoneNotFound(_) | Error::ZoneNotActive(_) => Duration::from_secs(300),
+            _ => Duration::from_secs(60),
+        };
+
+        base.saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+            .min(max)
+    }
+}
+
+/// Zones in `namespace` (or any namespace) which failed to reconcile because
+/// their cloudflare zone was missing or not active, as soon as it shows up
+/// in cloudflare and is active.
+fn found_zones(
+    ctx: Arc<Context>,
+    namespace: Option<String>,
+) -> impl Stream<Item = ObjectRef<Zone>> {
+    // Accounts added later through credential resources are not watched,
+    // their zones are picked up by the periodic reconciliation instead.
+    let refreshes = ctx
+        .accounts
+        .all()
+        .iter()
+        .map(|account| account.zone_refreshes().boxed())
+        .collect::<Vec<_>>();
+
+    futures::stream::select_all(refreshes).flat_map(move |()| {
+        let mut missing_zones = ctx.caches.missing_zones.lock().unwrap();
+
+        let found = missing_zones
+            .iter()
+            .filter(|(zone, _)| namespace.is_none() || zone.namespace == namespace)
+            .filter(|(_, fqdn)| {
+                ctx.accounts
+                    .find_zone(fqdn)
+                    .is_some_and(|(_, zone)| zone.is_active())
+            })
+            .map(|(zone, _)| zone.clone())
+            .collect::<Vec<_>>();
+
+        for zone in &found {
+            if let Some(fqdn) = missing_zones.remove(zone) {
+                info!("{fqdn} became active in cloudflare, reconciling zone {zone}");
+            }
+        }
+
+        futures::stream::iter(found)
+    })
+}
+
+/// Finalizer preventing zones from being deleted, before the records of
+/// the controller have been removed from cloudflare.
+///
+/// Controllers with names other than the default use their own finalizer,
+/// so each of them gets to clean up its own records.
+fn finalizer_name(controller_name: &str) -> String {
+    if controller_name == DEFAULT_CONTROLLER_NAME {
+        format!("{}/cleanup", crds::GROUP)
+    } else {
+        format!("{}/cleanup.{controller_name}", crds::GROUP)
+    }
+}
+
+async fn reconcile(zone: Arc<Zone>, ctx: Arc<Context>) -> Result<Action, Error> {
+    // Zones of other shards, including their cleanup, are left to their replica.
+    if !ctx.sync.is_in_shard(&zone) {
+        debug!("ignoring zone {zone}, since it belongs to another shard");
+        return Ok(Action::await_change());
+    }
+
+    // Zones which were synced before being disabled still carry the finalizer,
+    // and have their records cleaned up once they are deleted.
+    if !ctx.sync.is_enabled(&zone)
+        && !zone
+            .finalizers()
+            .contains(&finalizer_name(&ctx.sync.controller_name))
+    {
+        debug!("ignoring zone {zone}, since it is not enabled");
+        return Ok(Action::await_change());
+    }
+
+    let key = zone.to_string();
+
+    // Dry runs leave the finalizers alone, since adding them is a change to the
+    // cluster, and releasing them would let zones go without cleaning up.
+    if ctx.dry_run {
+        if zone.meta().deletion_timestamp.is_some() {
+            info!("not cleaning up records of zone {zone} in dry run");
+            return Ok(Action::await_change());
+        }
+
+        let action = apply(zone, &ctx).await?;
+        ctx.caches.failures.lock().unwrap().remove(&key);
+        return Ok(action);
+    }
+
+    let zones = Api::<Zone>::namespaced(ctx.kube.clone(), &zone.namespace().unwrap_or_default());
+    let action = finalizer(
+        &zones,
+        &finalizer_name(&ctx.sync.controller_name),
+        zone,
+        |event| async {
+            match event {
+                finalizer::Event::Apply(zone) => apply(zone, &ctx).await,
+                finalizer::Event::Cleanup(zone) => cleanup(zone, &ctx).await,
+            }
+        },
+    )
+    .await
+    .map_err(|err| Error::Finalizer(Box::new(err)))?;
+
+    ctx.caches.failures.lock().unwrap().remove(&key);
+
+    Ok(action)
+}
+
+/// Remove all records managed by this controller within the zone from
+/// cloudflare, since the zone is being deleted.
+///
+/// Zones which cannot be found in cloudflare have nothing to clean up,
+/// and are released immediately.
+async fn cleanup(zone: Arc<Zone>, ctx: &Context) -> Result<Action, Error> {
+    let Some(fqdn) = zone.fqdn() else {
+        return Ok(Action::await_change());
+    };
+
+    // Deleting records, let alone the cloudflare zone, is a change like any
+    // other, so the finalizer is kept until the zone is unpaused.
+    if annotations::is_paused(&zone) {
+        info!(
+            zone = %fqdn,
+            "not cleaning up records of {fqdn} while zone {} is paused",
+            zone.name_any()
+        );
+        return Err(Error::Paused);
+    }
+
+    let (cloudflare, cloudflare_zone) = match ctx.find_cloudflare_zone(fqdn).await {
+        Ok(found) => found,
+        Err(Error::ZoneNotFound(_)) => {
+            warn!(zone = %fqdn, "not cleaning up records of {fqdn}, since it is not in cloudflare");
+            return Ok(Action::await_change());
+        }
+        Err(err) => return Err(err),
+    };
+
+    info!(
+        zone = %fqdn,
+        "cleaning up records of {fqdn}, since zone {} is being deleted",
+        zone.name_any()
+    );
+    let nested = nested_zones(ctx, &zone, &cloudflare_zone).await?;
+
+    if ctx.sync.allow_zone_deletion && annotations::deletes_zone(&zone) {
+        if &cloudflare_zone.fqdn != fqdn {
+            warn!(
+                zone = %fqdn,
+                "not deleting cloudflare zone {}, since {fqdn} is only a sub-zone of it",
+                cloudflare_zone.fqdn
+            );
+        } else if !nested.is_empty() {
+            warn!(
+                zone = %fqdn,
+                "not deleting cloudflare zone {}, since other zones map onto it",
+                cloudflare_zone.fqdn
+            );
+        } else {
+            return delete_cloudflare_zone(ctx, &cloudflare, &cloudflare_zone).await;
+        }
+    }
+
+    let zone_records = annotations::zone_records(&ctx.sync.apis(&ctx.kube), &zone).await?;
+    let config = ctx.accounts.zone_config(fqdn);
+    let protected = Protected::new(&zone, &zone_records, &config.protected);
+
+    cleanup_zone(
+        ctx,
+        &cloudflare,
+        &cloudflare_zone,
+        Some(&zone),
+        &protected,
+        &nested,
+    )
+    .await?;
+
+    Ok(Action::await_change())
+}
+
+/// Delete the cloudflare zone along with all of its records, managed or not,
+/// since the zone is being deleted and requested so through its annotation.
+async fn delete_cloudflare_zone(
+    ctx: &Context,
+    cloudflare: &CloudFlare,
+    cloudflare_zone: &cloudflare::Zone,
+) -> Result<Action, Error> {
+    info!(
+        zone = %cloudflare_zone.fqdn,
+        cf_zone_id = %cloudflare_zone.id,
+        "deleting cloudflare zone {}, since its zone is being deleted",
+        cloudflare_zone.fqdn
+    );
+
+    match cloudflare.delete_zone(&cloudflare_zone.id).await {
+        Ok(_) => ctx.audit.write(&audit::Entry::zone(
+            &ctx.owner,
+            "delete-zone",
+            cloudflare_zone,
+            None,
+        )),
+        // Already deleted, such as by a previous attempt.
+        Err(cloudflare::Error::InvalidZone(..)) => {}
+        Err(err) => {
+            ctx.audit.write(&audit::Entry::zone(
+                &ctx.owner,
+                "delete-zone",
+                cloudflare_zone,
+                Some(&err),
+            ));
+            return Err(err.into());
+        }
+    }
+
+    ctx.accounts.forget_zone(&cloudflare_zone.id);
+    ctx.caches.record_cache.invalidate(&cloudflare_zone.id);
+
+    Ok(Action::await_change())
+}
+
+async fn apply(zone: Arc<Zone>, ctx: &Context) -> Result<Action, Error> {
+    if !ctx.sync.is_enabled(&zone) {
+        debug!("not syncing zone {zone}, since it is not enabled");
+        return Ok(Action::await_change());
+    }
+
+    let Some(fqdn) = zone.fqdn() else {
+        debug!("zone {zone} does not yet have a fully qualified domain name");
+        return Ok(ctx.requeue(ctx.requeue_time));
+    };
+
+    let result = sync_zone(&zone, fqdn, ctx).await;
+
+    // Reconciled as soon as the cloudflare zone appears, see `found_zones`.
+    let zone_ref = ObjectRef::from_obj(zone.as_ref());
+    match &result {
+        Err(Error::ZoneNotFound(_) | Error::ZoneNotActive(_)) => {
+            ctx.caches
+                .missing_zones
+                .lock()
+                .unwrap()
+                .insert(zone_ref, fqdn.clone());
+        }
+        _ => {
+            ctx.caches.missing_zones.lock().unwrap().remove(&zone_ref);
+        }
+    }
+
+    let condition = match &result {
+        Ok(stats) if annotations::is_paused(&zone) => status::synced_condition(
+            zone.as_ref(),
+            stats.pending == 0,
+            "Paused",
+            format!(
+                "changes to cloudflare are paused, {} changes are pending",
+                stats.pending
+            ),
+        ),
+        Ok(_) => status::synced_condition(
+            zone.as_ref(),
+            true,
+            "Synced",
+            "records are in sync with cloudflare".to_string(),
+        ),
+        Err(err) => status::synced_condition(zone.as_ref(), false, err.reason(), err.to_string()),
+    };
+
+    let stats = result.as_ref().ok().cloned();
+
+    // The zone is not actually in sync after a dry run, so leave its status alone.
+    if ctx.dry_run {
+        return result.map(|_| ctx.requeue(ctx.requeue_time));
+    }
+
+    if let Err(err) = status::update(
+        ctx.kube.clone(),
+        &zone,
+        condition,
+        stats,
+        ctx.stats_refresh_time,
+    )
+    .await
+    {
+        warn!(
+            zone = %fqdn,
+            "failed to update sync status of zone {}: {err}",
+            zone.name_any()
+        );
+    }
+
+    result.map(|_| ctx.requeue(ctx.requeue_time))
+}
+
+/// Name and namespace of the parent zone of the record or zone, from its label
+/// referencing the zone as `<name>.<namespace>`.
+///
+/// Zone names may contain dots, but namespaces cannot, so the label is split
+/// at its last dot, unlike by kubizone's own `parent()`.
+fn parent_zone<K: kube::ResourceExt>(resource: &K) -> Option<(&str, &str)> {
+    resource.labels().get(PARENT_ZONE_LABEL)?.rsplit_once('.')
+}
+
+/// Sync a single kubizone Record to cloudflare, and store the outcome on the
+/// Record, see `reconcile --reconcile-records`.
+async fn reconcile_record(record: Arc<Record>, ctx: Arc<Context>) -> Result<Action, Error> {
+    let Some((name, namespace)) = parent_zone(record.as_ref()) else {
+        debug!("ignoring record {record}, since it does not belong to a zone");
+        return Ok(Action::await_change());
+    };
+
+    let Some(zone) = Api::<Zone>::namespaced(ctx.kube.clone(), namespace)
+        .get_opt(name)
+        .await?
+    else {
+        debug!("ignoring record {record}, since its zone {namespace}/{name} does not exist");
+        return Ok(Action::await_change());
+    };
+
+    // Records of zones being deleted are cleaned up along with their zone.
+    if !ctx.sync.is_in_shard(&zone)
+        || !ctx.sync.is_enabled(&zone)
+        || zone.metadata.deletion_timestamp.is_some()
+    {
+        debug!("ignoring record {record}, since its zone {zone} is not synced");
+        return Ok(Action::await_change());
+    }
+
+    let result = sync_record(&record, &zone, &ctx).await;
+
+    let condition = match &result {
+        Ok(outcome) => status::synced_condition(
+            record.as_ref(),
+            outcome.synced,
+            outcome.reason,
+            outcome.message.clone(),
+        ),
+        Err(err) => status::synced_condition(record.as_ref(), false, err.reason(), err.to_string()),
+    };
+
+    // The record is not actually in sync after a dry run, so leave its status alone.
+    if !ctx.dry_run {
+        if let Err(err) = status::update_record(ctx.kube.clone(), &record, condition).await {
+            warn!("failed to update sync status of record {record}: {err}");
+        }
+    }
+
+    result.map(|_| ctx.requeue(ctx.requeue_time))
+}
+
+/// Outcome of syncing a single Record, stored as its [`status::SYNCED`] condition.
+struct RecordOutcome {
+    synced: bool,
+    reason: &'static str,
+    message: String,
+}
+
+impl RecordOutcome {
+    fn new(synced: bool, reason: &'static str, message: impl Into<String>) -> Self {
+        RecordOutcome {
+            synced,
+            reason,
+            message: message.into(),
+        }
+    }
+}
+
+/// Bring the cloudflare records with the name and type of the Record in line
+/// with the entries of its zone.
+///
+/// Only the entries sharing the name and type of the Record are planned,
+/// and managed records without an entry are never deleted.
+async fn sync_record(record: &Record, zone: &Zone, ctx: &Context) -> Result<RecordOutcome, Error> {
+    let (Some(fqdn), Some(zone_fqdn)) = (record.fqdn(), zone.fqdn()) else {
+        return Ok(RecordOutcome::new(
+            false,
+            "Pending",
+            "record has no fully qualified domain name yet",
+        ));
+    };
+
+    let type_ = record.spec.type_;
+    let ident = ident::ident(fqdn, type_, &record.spec.rdata);
+
+    let entries = zone
+        .status
+        .as_ref()
+        .map(|status| status.entries.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter(|entry| &entry.fqdn == fqdn && entry.type_ == type_)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if !entries
+        .iter()
+        .any(|entry| plan::entry_ident(entry) == ident)
+    {
+        return Ok(RecordOutcome::new(
+            false,
+            "Pending",
+            format!("record is not an entry of zone {zone} yet"),
+        ));
+    }
+
+    let (cloudflare, cloudflare_zone) = ctx.find_cloudflare_zone(zone_fqdn).await?;
+    if !cloudflare_zone.is_active() {
+        return Err(Error::ZoneNotActive(cloudflare_zone));
+    }
+
+    let zone_records = annotations::zone_records(&ctx.sync.apis(&ctx.kube), zone).await?;
+    let config = ctx.accounts.zone_config(zone_fqdn);
+    let proxied = Proxied::new(zone, &zone_records, config.proxied);
+    let protected = Protected::new(zone, &zone_records, &config.protected);
+    let options = ctx.sync.plan_options(
+        &ctx.owner,
+        zone,
+        &config,
+        &cloudflare_zone.fqdn,
+        &proxied,
+        &protected,
+    );
+
+    if !options.manages(type_) {
+        return Ok(RecordOutcome::new(
+            true,
+            "Skipped",
+            format!("{type_} records are not synced to cloudflare"),
+        ));
+    }
+
+    let records = ctx.records(&cloudflare, &cloudflare_zone.id).await?;
+    let mut plan = Plan::new(&entries, &records, &options);
+    plan.changes.retain(|change| change.action() != "delete");
+
+    let is_record = |change: &PlannedChange| {
+        change
+            .entry()
+            .is_some_and(|entry| plan::entry_ident(entry) == ident)
+    };
+
+    if plan.protected.iter().any(is_record) {
+        return Ok(RecordOutcome::new(
+            false,
+            "Protected",
+            "the record in cloudflare is protected, so it is not changed",
+        ));
+    }
+
+    let owners = ctx.owners(&records);
+    let existing = records
+        .iter()
+        .find(|existing| RecordIdent::from(*existing) == ident);
+
+    if !plan.changes.iter().any(is_record)
+        && existing.is_some_and(|existing| !owners.is_managed(existing))
+    {
+        let message = match existing.and_then(cloudflare::Record::managed_by) {
+            Some(owner) => format!("the record in cloudflare is managed by {owner}"),
+            None => "the record in cloudflare is not managed by this controller".to_string(),
+        };
+        return Ok(RecordOutcome::new(false, "Unmanaged", message));
+    }
+
+    if annotations::is_paused(zone) && !plan.changes.is_empty() {
+        return Ok(RecordOutcome::new(
+            false,
+            "Paused",
+            "changes to cloudflare are paused",
+        ));
+    }
+
+    for change in &plan.changes {
+        info!(
+            zone = %cloudflare_zone.fqdn,
+            cf_zone_id = %cloudflare_zone.id,
+            record = %change.fqdn(),
+            action = change.action(),
+            "{}: {change}",
+            cloudflare_zone.fqdn
+        );
+    }
+
+    let changes = plan
+        .changes
+        .iter()
+        .map(PlannedChange::change)
+        .collect::<Vec<_>>();
+
+    let description = ctx.sync.describe(zone);
+    let journal = Journal::default();
+    let result = apply_changes(
+        ctx,
+        &cloudflare,
+        &cloudflare_zone,
+        description.as_deref(),
+        &changes,
+        &journal,
+    )
+    .await;
+    ctx.record_change_set(zone, journal).await;
+    result?;
+
+    Ok(RecordOutcome::new(
+        true,
+        "Synced",
+        "record is in sync with cloudflare",
+    ))
+}
+
+/// Returns true if the name is the fqdn itself, or a subdomain of it.
+fn is_within(name: &FullyQualifiedDomainName, fqdn: &FullyQualifiedDomainName) -> bool {
+    name == fqdn || name.is_subdomain_of(fqdn)
+}
+
+/// Names of the zones delegated from the zone, which are synced into the
+/// same cloudflare zone, so their records are left to them.
+async fn nested_zones(
+    ctx: &Context,
+    zone: &Zone,
+    cloudflare_zone: &cloudflare::Zone,
+) -> Result<Vec<FullyQualifiedDomainName>, Error> {
+    let params = ListParams::default().labels(&format!(
+        "{PARENT_ZONE_LABEL}={}.{}",
+        zone.name_any(),
+        zone.namespace().unwrap_or_default()
+    ));
+
+    let mut nested = Vec::new();
+    for api in ctx.sync.apis::<Zone>(&ctx.kube) {
+        for child in api.list(&params).await? {
+            let Some(fqdn) = child.fqdn() else {
+                continue;
+            };
+
+            ctx.accounts.lookup_zone(fqdn).await?;
+            if ctx
+                .accounts
+                .find_zone(fqdn)
+                .is_some_and(|(_, found)| found.id == cloudflare_zone.id)
+            {
+                nested.push(fqdn.clone());
+            }
+        }
+    }
+
+    Ok(nested)
+}
+
+/// Records of the cloudflare zone which belong to the zone.
+///
+/// Zones may be synced into a cloudflare zone enclosing them, which they share
+/// with their parent and their own sub-zones, so the records outside of the
+/// zone or within its nested zones are left out.
+async fn zone_records(
+    ctx: &Context,
+    cloudflare: &CloudFlare,
+    cloudflare_zone: &cloudflare::Zone,
+    zone: &Zone,
+    fqdn: &FullyQualifiedDomainName,
+) -> Result<Vec<cloudflare::Record>, Error> {
+    let nested = nested_zones(ctx, zone, cloudflare_zone).await?;
+
+    let mut records = ctx.records(cloudflare, &cloudflare_zone.id).await?;
+    records.retain(|record| {
+        is_within(&record.fqdn, fqdn)
+            && !nested.iter().any(|nested| is_within(&record.fqdn, nested))
+    });
+
+    Ok(records)
+}
+
+/// Bring the records in cloudflare in line with the entries of the zone.
+async fn sync_zone(
+    zone: &Zone,
+    fqdn: &FullyQualifiedDomainName,
+    ctx: &Context,
+) -> Result<SyncStats, Error> {
+    let (cloudflare, cloudflare_zone) = match ctx.find_cloudflare_zone(fqdn).await {
+        Err(Error::ZoneNotFound(_)) if ctx.sync.create_zones => {
+            create_cloudflare_zone(ctx, zone, fqdn).await?
+        }
+        found => found?,
+    };
+
+    if !cloudflare_zone.is_active() {
+        return Err(Error::ZoneNotActive(cloudflare_zone));
+    }
+
+    let records = zone_records(ctx, &cloudflare, &cloudflare_zone, zone, fqdn).await?;
+
+    // Nameservers of the enclosing zone are of no use for delegating a sub-zone.
+    let name_servers = if &cloudflare_zone.fqdn == fqdn {
+        cloudflare_zone.name_servers.clone()
+    } else {
+        Vec::new()
+    };
+
+    let entries = zone
+        .status
+        .as_ref()
+        .map(|status| &status.entries)
+        .ok_or(Error::ZoneHasNoEntries(zone.name_any()))?;
+
+    let zone_records = annotations::zone_records(&ctx.sync.apis(&ctx.kube), zone).await?;
+
+    // Hostnames served by load balancers or worker custom domains have no plain records.
+    let load_balancers = if ctx.sync.load_balancers {
+        load_balancer::desired(&zone_records)
+    } else {
+        Vec::new()
+    };
+    let custom_domains = if ctx.sync.workers {
+        workers::custom_domains(&zone_records)
+    } else {
+        Vec::new()
+    };
+    let entries = entries
+        .iter()
+        .filter(|entry| !load_balancer::is_balanced(&load_balancers, entry))
+        .filter(|entry| !workers::is_custom_domain(&custom_domains, entry))
+        .cloned()
+        .collect::<Vec<_>>();
+    let config = ctx.accounts.zone_config(fqdn);
+    let proxied = Proxied::new(zone, &zone_records, config.proxied);
+    let protected = Protected::new(zone, &zone_records, &config.protected);
+    let options = ctx.sync.plan_options(
+        &ctx.owner,
+        zone,
+        &config,
+        &cloudflare_zone.fqdn,
+        &proxied,
+        &protected,
+    );
+    let mut plan = Plan::new(&entries, &records, &options);
+
+    if annotations::is_paused(zone) {
+        let current = status::current_stats(zone);
+
+        for change in &plan.changes {
+            info!(
+                zone = %cloudflare_zone.fqdn,
+                cf_zone_id = %cloudflare_zone.id,
+                record = %change.fqdn(),
+                action = change.action(),
+                "{}: not applying {change}, since the zone is paused",
+                cloudflare_zone.fqdn
+            );
+        }
+
+        return Ok(SyncStats {
+            managed: plan.managed,
+            unmanaged: plan.unmanaged,
+            pending: plan.changes.len(),
+            last_successful_sync: current
+                .as_ref()
+                .and_then(|stats| stats.last_successful_sync.clone()),
+            name_servers,
+            ds: current.and_then(|stats| stats.ds),
+            ..Default::default()
+        });
+    }
+
+    // Held back deletions fail the sync, after applying all other changes.
+    let desired = entries
+        .iter()
+        .filter(|entry| options.manages(entry.type_))
+        .count();
+    let held_back = hold_back_deletions(&ctx.sync, zone, &plan, desired);
+
+    if held_back.is_some() {
+        plan.changes.retain(|change| change.action() != "delete");
+    }
+
+    for change in &plan.changes {
+        info!(
+            zone = %cloudflare_zone.fqdn,
+            cf_zone_id = %cloudflare_zone.id,
+            record = %change.fqdn(),
+            action = change.action(),
+            "{}: {change}",
+            cloudflare_zone.fqdn
+        );
+    }
+
+    for change in &plan.protected {
+        info!(
+            zone = %cloudflare_zone.fqdn,
+            cf_zone_id = %cloudflare_zone.id,
+            record = %change.fqdn(),
+            action = change.action(),
+            "{}: not applying {change}, since the record is protected",
+            cloudflare_zone.fqdn
+        );
+    }
+
+    let changes = plan
+        .changes
+        .iter()
+        .map(PlannedChange::change)
+        .collect::<Vec<_>>();
+
+    let description = ctx.sync.describe(zone);
+    let journal = Journal::default();
+    let result = apply_changes(
+        ctx,
+        &cloudflare,
+        &cloudflare_zone,
+        description.as_deref(),
+        &changes,
+        &journal,
+    )
+    .await;
+    ctx.record_change_set(zone, journal).await;
+    result?;
+    publish_events(ctx, zone, &plan).await;
+
+    if let Some(min_ttl) = options.min_ttl {
+        warn_below_min_ttl(ctx, zone, &plan, &entries, &options, min_ttl).await;
+    }
+
+    let ds = sync_dnssec(ctx, zone, fqdn, &cloudflare, &cloudflare_zone).await?;
+    if ctx.sync.sync_zone_settings {
+        sync_settings(ctx, zone, fqdn, &cloudflare, &cloudflare_zone).await?;
+    }
+    if ctx.sync.custom_hostnames {
+        sync_custom_hostnames(
+            ctx,
+            &cloudflare,
+            &cloudflare_zone,
+            &zone_records,
+            options.delete,
+        )
+        .await?;
+    }
+    if ctx.sync.regional_hostnames {
+        sync_regional_hostnames(
+            ctx,
+            &cloudflare,
+            &cloudflare_zone,
+            &zone_records,
+            options.delete,
+        )
+        .await?;
+    }
+    // Only ever unset if neither is enabled, since both require `--cf-account-id`.
+    let account_id = ctx.sync.account_id(&config).unwrap_or_default();
+    if ctx.sync.workers {
+        sync_workers(
+            ctx,
+            account_id,
+            &cloudflare,
+            &cloudflare_zone,
+            &zone_records,
+            options.delete,
+        )
+        .await?;
+    }
+    if ctx.sync.load_balancers {
+        sync_load_balancers(
+            ctx,
+            account_id,
+            zone,
+            &cloudflare,
+            &cloudflare_zone,
+            &load_balancers,
+            options.delete,
+        )
+        .await?;
+    }
+
+    if let Some(err) = held_back {
+        warn!(zone = %zone.name_any(), "{err}");
+        publish_warning(ctx, zone, err.reason(), err.to_string()).await;
+        return Err(err);
+    }
+
+    Ok(SyncStats {
+        managed: plan.managed,
+        unmanaged: plan.unmanaged,
+        created: plan.count("create"),
+        updated: plan.count("update"),
+        deleted: plan.count("delete"),
+        adopted: plan.count("adopt"),
+        pending: 0,
+        last_successful_sync: Some(Time(Utc::now().trunc_subsecs(0))),
+        name_servers,
+        ds,
+    })
+}
+
+/// Pin the hostnames of the records to the regions they declare, see [`regional`].
+///
+/// With `delete`, hostnames pinned by us are unpinned once their annotation is removed.
+async fn sync_regional_hostnames(
+    ctx: &Context,
+    cloudflare: &CloudFlare,
+    cloudflare_zone: &cloudflare::Zone,
+    records: &[Record],
+    delete: bool,
+) -> Result<(), Error> {
+    let mut existing = None;
+
+    for record in records {
+        let (declared, current) = (regional::declared(record), regional::current(record));
+        let Some(fqdn) = record
+            .fqdn()
+            .filter(|_| declared.is_some() || current.is_some())
+        else {
+            continue;
+        };
+        let hostname = load_balancer::hostname(fqdn);
+
+        // Listed once per zone, and only if any record is pinned to a region.
+        if existing.is_none() {
+            existing = Some(cloudflare.regional_hostnames(&cloudflare_zone.id).await?);
+        }
+        // Unwrap safety: the regional hostnames were just listed.
+        let pinned = existing
+            .as_ref()
+            .unwrap()
+            .iter()
+            .find(|regional| regional.hostname == hostname)
+            .map(|regional| regional.region_key.as_str());
+
+        let status = match declared {
+            Some(region) if pinned == Some(region) => Some(region),
+            Some(region) if ctx.dry_run => {
+                info!(record = %record.name_any(), "not pinning {hostname} to region {region} in dry run");
+                continue;
+            }
+            Some(region) => {
+                info!(record = %record.name_any(), "pinning {hostname} to region {region}");
+                let result = cloudflare
+                    .save_regional_hostname(
+                        &cloudflare_zone.id,
+                        &hostname,
+                        region,
+                        pinned.is_some(),
+                    )
+                    .await;
+                ctx.audit_resource(
+                    cloudflare_zone,
+                    "save-regional-hostname",
+                    &hostname,
+                    &result,
+                );
+                result?;
+                Some(region)
+            }
+            None if !delete || ctx.dry_run => continue,
+            None => {
+                if pinned.is_some() {
+                    info!(record = %record.name_any(), "unpinning {hostname} from its region");
+                    let result = cloudflare
+                        .delete_regional_hostname(&cloudflare_zone.id, &hostname)
+                        .await;
+                    ctx.audit_resource(
+                        cloudflare_zone,
+                        "delete-regional-hostname",
+                        &hostname,
+                        &result,
+                    );
+                    result?;
+                }
+                None
+            }
+        };
+
+        if status != current {
+            status::annotate_record(
+                ctx.kube.clone(),
+                record,
+                regional::status_annotation(),
+                status.unwrap_or_default().to_string(),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Attach the worker custom domains and create the worker routes declared by
+/// the records, publishing them on the records, see [`workers`].
+///
+/// Routes which match the pattern of a declared one, but were not created by
+/// us, are left alone. With `delete`, custom domains and routes whose
+/// annotation was removed are removed as well, along with routes of earlier
+/// patterns, which are kept in the status until then.
+async fn sync_workers(
+    ctx: &Context,
+    account_id: &str,
+    cloudflare: &CloudFlare,
+    cloudflare_zone: &cloudflare::Zone,
+    records: &[Record],
+    delete: bool,
+) -> Result<(), Error> {
+    let mut routes = None;
+
+    for record in records {
+        let Some(declared) = workers::declared(record) else {
+            continue;
+        };
+        let current = workers::current(record);
+        if declared.domain.is_none() && declared.route.is_none() && current == Default::default() {
+            continue;
+        }
+
+        if ctx.dry_run {
+            info!(record = %record.name_any(), "not syncing workers of {} in dry run", declared.hostname);
+            continue;
+        }
+
+        let mut status = workers::WorkerStatus::default();
+        let hostname = &declared.hostname;
+
+        match (&declared.domain, current.domain) {
+            (Some((service, environment)), Some(current))
+                if &current.service == service && &current.environment == environment =>
+            {
+                status.domain = Some(current);
+            }
+            (Some((service, environment)), _) => {
+                info!(record = %record.name_any(), "serving {hostname} from worker {service} ({environment})");
+                let result = cloudflare
+                    .attach_worker_domain(
+                        account_id,
+                        &cloudflare_zone.id,
+                        hostname,
+                        service,
+                        environment,
+                    )
+                    .await;
+                ctx.audit_resource(cloudflare_zone, "attach-worker-domain", hostname, &result);
+                let domain = result?;
+                status.domain = Some(workers::DomainStatus {
+                    id: domain.id,
+                    service: domain.service,
+                    environment: domain.environment,
+                });
+            }
+            (None, Some(current)) if delete => {
+                info!(record = %record.name_any(), "detaching {hostname} from worker {}", current.service);
+                let result = cloudflare
+                    .detach_worker_domain(account_id, &current.id)
+                    .await;
+                ctx.audit_resource(cloudflare_zone, "detach-worker-domain", hostname, &result);
+                result?;
+            }
+            (None, current) => status.domain = current,
+        }
+
+        // Routes of earlier patterns, or of removed annotations, which are
+        // kept in the status until they are deleted in delete mode.
+        status.previous_routes = current.previous_routes;
+
+        match (&declared.route, current.route) {
+            (Some((pattern, script)), current) => {
+                // Listed once per zone, and only if any record declares a route.
+                if routes.is_none() {
+                    routes = Some(cloudflare.worker_routes(&cloudflare_zone.id).await?);
+                }
+
+                // Unwrap safety: the routes were just listed.
+                let existing = routes
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .find(|route| &route.pattern == pattern);
+                let ours = existing.is_some_and(|existing| {
+                    current
+                        .iter()
+                        .chain(&status.previous_routes)
+                        .any(|route| route.id == existing.id)
+                });
+
+                match existing {
+                    Some(_) if !ours => warn!(
+                        record = %record.name_any(),
+                        "not routing {pattern} to worker {script}, since its route was not created by {}",
+                        ctx.owner
+                    ),
+                    Some(existing) if &existing.script == script => {
+                        status.route = Some(workers::RouteStatus {
+                            id: existing.id.clone(),
+                            pattern: pattern.clone(),
+                            script: script.clone(),
+                        });
+                    }
+                    existing => {
+                        info!(record = %record.name_any(), "routing {pattern} to worker {script}");
+                        let result = cloudflare
+                            .save_worker_route(
+                                &cloudflare_zone.id,
+                                &cloudflare::WorkerRoute {
+                                    id: existing
+                                        .map(|existing| existing.id.clone())
+                                        .unwrap_or_default(),
+                                    pattern: pattern.clone(),
+                                    script: script.clone(),
+                                },
+                            )
+                            .await;
+                        ctx.audit_resource(cloudflare_zone, "save-worker-route", pattern, &result);
+                        let route = result?;
+                        status.route = Some(workers::RouteStatus {
+                            id: route.id,
+                            pattern: pattern.clone(),
+                            script: script.clone(),
+                        });
+                    }
+                }
+
+                // Routes of an earlier pattern the record returned to are current again.
+                if let Some(route) = &status.route {
+                    status
+                        .previous_routes
+                        .retain(|previous| previous.id != route.id);
+                }
+
+                if let Some(current) = current.filter(|current| &current.pattern != pattern) {
+                    if !delete {
+                        info!(
+                            record = %record.name_any(),
+                            "leaving route of {} to worker {} alone, since records are not deleted",
+                            current.pattern,
+                            current.script
+                        );
+                    }
+                    status.previous_routes.push(current);
+                }
+            }
+            (None, Some(current)) if delete => status.previous_routes.push(current),
+            (None, current) => status.route = current,
+        }
+
+        if delete {
+            for previous in std::mem::take(&mut status.previous_routes) {
+                info!(record = %record.name_any(), "removing route of {} to worker {}", previous.pattern, previous.script);
+                let result = cloudflare
+                    .delete_worker_route(&cloudflare_zone.id, &previous.id)
+                    .await;
+                ctx.audit_resource(
+                    cloudflare_zone,
+                    "delete-worker-route",
+                    &previous.pattern,
+                    &result,
+                );
+                result?;
+            }
+        }
+
+        if status != workers::current(record) {
+            status::annotate_record(
+                ctx.kube.clone(),
+                record,
+                workers::status_annotation(),
+                serde_json::to_string(&status)?,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Create the custom hostnames declared by the records, publishing their
+/// status on the records, see [`custom_hostnames`].
+///
+/// With `delete`, custom hostnames removed from the annotation of a record are deleted.
+async fn sync_custom_hostnames(
+    ctx: &Context,
+    cloudflare: &CloudFlare,
+    cloudflare_zone: &cloudflare::Zone,
+    records: &[Record],
+    delete: bool,
+) -> Result<(), Error> {
+    for record in records {
+        let declared = custom_hostnames::declared(record);
+        let current = custom_hostnames::current(record);
+        if declared.is_empty() && current.is_empty() {
+            continue;
+        }
+
+        let mut statuses = Vec::new();
+        for hostname in &declared {
+            let custom_hostname = match cloudflare
+                .find_custom_hostname(&cloudflare_zone.id, hostname)
+                .await?
+            {
+                Some(custom_hostname) => custom_hostname,
+                None if ctx.dry_run => {
+                    info!(record = %record.name_any(), "not creating custom hostname {hostname} in dry run");
+                    continue;
+                }
+                None => {
+                    info!(record = %record.name_any(), "creating custom hostname {hostname} in {}", cloudflare_zone.fqdn);
+                    let result = cloudflare
+                        .create_custom_hostname(&cloudflare_zone.id, hostname)
+                        .await;
+                    ctx.audit_resource(
+                        cloudflare_zone,
+                        "create-custom-hostname",
+                        hostname,
+                        &result,
+                    );
+                    result?
+                }
+            };
+
+            statuses.push(custom_hostnames::HostnameStatus::from(&custom_hostname));
+        }
+
+        for orphaned in custom_hostnames::orphaned(&current, &declared) {
+            if !delete || ctx.dry_run {
+                statuses.push(orphaned.clone());
+                continue;
+            }
+
+            info!(
+                record = %record.name_any(),
+                "deleting custom hostname {}, since it is no longer declared",
+                orphaned.hostname
+            );
+            let result = cloudflare
+                .delete_custom_hostname(&cloudflare_zone.id, &orphaned.id)
+                .await;
+            ctx.audit_resource(
+                cloudflare_zone,
+                "delete-custom-hostname",
+                &orphaned.hostname,
+                &result,
+            );
+            result?;
+        }
+
+        if statuses != current && !ctx.dry_run {
+            status::annotate_record(
+                ctx.kube.clone(),
+                record,
+                custom_hostnames::status_annotation(),
+                serde_json::to_string(&statuses)?,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Create or update the load balancers declared by the records of the zone,
+/// along with their pools and monitor, see [`load_balancer::Desired`].
+///
+/// Load balancers, pools and monitors not managed by us are never changed.
+/// With `delete`, managed load balancers within the zone which are no longer
+/// declared are deleted.
+async fn sync_load_balancers(
+    ctx: &Context,
+    account_id: &str,
+    zone: &Zone,
+    cloudflare: &CloudFlare,
+    cloudflare_zone: &cloudflare::Zone,
+    desired: &[load_balancer::Desired],
+    delete: bool,
+) -> Result<(), Error> {
+    let owner = &ctx.owner;
+
+    let existing = cloudflare.load_balancers(&cloudflare_zone.id).await?;
+    let pools = cloudflare.pools(account_id).await?;
+    let monitors = cloudflare.monitors(account_id).await?;
+
+    'balancers: for desired in desired {
+        let name = desired.name();
+        let managed = |description: &str| load_balancer::is_managed(description, owner, &name);
+
+        let current = existing.iter().find(|existing| existing.name == name);
+        if current.is_some_and(|current| !managed(&current.description)) {
+            warn!(zone = %zone.name_any(), "not syncing load balancer {name}, since it is not managed by {owner}");
+            continue;
+        }
+
+        if ctx.dry_run {
+            info!(zone = %zone.name_any(), "not syncing load balancer {name} in dry run");
+            continue;
+        }
+
+        let monitor = match desired.monitor(owner) {
+            Some(mut monitor) => {
+                let current = monitors
+                    .iter()
+                    .find(|current| managed(&current.description));
+                if let Some(current) = current {
+                    monitor.id.clone_from(&current.id);
+                }
+
+                if current.map_or(true, |current| {
+                    load_balancer::monitor_differs(&monitor, current)
+                }) {
+                    info!(zone = %zone.name_any(), "saving monitor of load balancer {name}");
+                    let result = cloudflare.save_monitor(account_id, &monitor).await;
+                    ctx.audit_resource(cloudflare_zone, "save-monitor", &name, &result);
+                    monitor = result?;
+                }
+
+                Some(monitor.id)
+            }
+            None => None,
+        };
+
+        let mut pool_ids = Vec::new();
+        for mut pool in desired.pools(owner, monitor.as_deref()) {
+            let current = pools.iter().find(|current| current.name == pool.name);
+            match current {
+                Some(current) if !managed(&current.description) => {
+                    warn!(
+                        zone = %zone.name_any(),
+                        "not syncing load balancer {name}, since its pool {} is not managed by {owner}",
+                        pool.name
+                    );
+                    continue 'balancers;
+                }
+                Some(current) => pool.id.clone_from(&current.id),
+                None => {}
+            }
+
+            if current.map_or(true, |current| load_balancer::pool_differs(&pool, current)) {
+                info!(zone = %zone.name_any(), "saving pool {} of load balancer {name}", pool.name);
+                let result = cloudflare.save_pool(account_id, &pool).await;
+                ctx.audit_resource(cloudflare_zone, "save-pool", &pool.name, &result);
+                pool = result?;
+            }
+
+            pool_ids.push(pool.id);
+        }
+
+        let mut load_balancer = desired.load_balancer(owner, &pool_ids);
+        if let Some(current) = current {
+            load_balancer.id.clone_from(&current.id);
+        }
+
+        if current.map_or(true, |current| {
+            load_balancer::load_balancer_differs(&load_balancer, current)
+        }) {
+            info!(zone = %zone.name_any(), "saving load balancer {name} using pools {}", pool_ids.join(", "));
+            let result = cloudflare
+                .save_load_balancer(&cloudflare_zone.id, &load_balancer)
+                .await;
+            ctx.audit_resource(cloudflare_zone, "save-load-balancer", &name, &result);
+            result?;
+        }
+
+        // Pools and monitors the load balancer no longer uses.
+        for pool in pools
+            .iter()
+            .filter(|pool| managed(&pool.description) && !pool_ids.contains(&pool.id))
+        {
+            info!(zone = %zone.name_any(), "deleting pool {} no longer used by load balancer {name}", pool.name);
+            let result = cloudflare.delete_pool(account_id, &pool.id).await;
+            ctx.audit_resource(cloudflare_zone, "delete-pool", &pool.name, &result);
+            result?;
+        }
+
+        for unused in monitors
+            .iter()
+            .filter(|unused| managed(&unused.description) && monitor.as_ref() != Some(&unused.id))
+        {
+            info!(zone = %zone.name_any(), "deleting monitor {} no longer used by load balancer {name}", unused.id);
+            let result = cloudflare.delete_monitor(account_id, &unused.id).await;
+            ctx.audit_resource(cloudflare_zone, "delete-monitor", &unused.id, &result);
+            result?;
+        }
+    }
+
+    if !delete {
+        return Ok(());
+    }
+
+    let Some(fqdn) = zone.fqdn() else {
+        return Ok(());
+    };
+    let nested = nested_zones(ctx, zone, cloudflare_zone).await?;
+
+    for undeclared in &existing {
+        let Ok(hostname) = FullyQualifiedDomainName::try_from(format!("{}.", undeclared.name))
+        else {
+            continue;
+        };
+
+        let managed =
+            |description: &str| load_balancer::is_managed(description, owner, &undeclared.name);
+        if !managed(&undeclared.description)
+            || !is_within(&hostname, fqdn)
+            || nested.iter().any(|nested| is_within(&hostname, nested))
+            || desired.iter().any(|desired| desired.hostname == hostname)
+        {
+            continue;
+        }
+
+        if ctx.dry_run {
+            info!(zone = %zone.name_any(), "not deleting load balancer {} in dry run", undeclared.name);
+            continue;
+        }
+
+        info!(zone = %zone.name_any(), "deleting load balancer {}, since it is no longer declared", undeclared.name);
+        let result = cloudflare
+            .delete_load_balancer(&cloudflare_zone.id, &undeclared.id)
+            .await;
+        ctx.audit_resource(
+            cloudflare_zone,
+            "delete-load-balancer",
+            &undeclared.name,
+            &result,
+        );
+        result?;
+
+        for pool in pools.iter().filter(|pool| managed(&pool.description)) {
+            let result = cloudflare.delete_pool(account_id, &pool.id).await;
+            ctx.audit_resource(cloudflare_zone, "delete-pool", &pool.name, &result);
+            result?;
+        }
+        for monitor in monitors
+            .iter()
+            .filter(|monitor| managed(&monitor.description))
+        {
+            let result = cloudflare.delete_monitor(account_id, &monitor.id).await;
+            ctx.audit_resource(cloudflare_zone, "delete-monitor", &monitor.id, &result);
+            result?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Update the settings of the cloudflare zone declared by the zone, see
+/// [`zone_settings::desired`], publishing an event for every setting updated.
+async fn sync_settings(
+    ctx: &Context,
+    zone: &Zone,
+    fqdn: &FullyQualifiedDomainName,
+    cloudflare: &CloudFlare,
+    cloudflare_zone: &cloudflare::Zone,
+) -> Result<(), Error> {
+    // Sub-zones mapped onto an enclosing cloudflare zone have no settings of their own.
+    if &cloudflare_zone.fqdn != fqdn {
+        return Ok(());
+    }
+
+    for (setting, value) in zone_settings::desired(zone) {
+        let current = cloudflare
+            .zone_setting(&cloudflare_zone.id, setting.id)
+            .await?;
+        let current = match current.value.as_str() {
+            Some(current) if current == value => continue,
+            Some(current) => current.to_string(),
+            None => current.value.to_string(),
+        };
+
+        if ctx.dry_run {
+            info!(
+                zone = %fqdn,
+                cf_zone_id = %cloudflare_zone.id,
+                "not updating {} of {fqdn} from {current} to {value} in dry run",
+                setting.id
+            );
+            continue;
+        }
+
+        let note = format!(
+            "updated {} of cloudflare zone {fqdn} from {current} to {value}",
+            setting.id
+        );
+
+        let result = cloudflare
+            .update_zone_setting(&cloudflare_zone.id, setting.id, value)
+            .await;
+        ctx.audit_resource(cloudflare_zone, "update-setting", setting.id, &result);
+        result?;
+        info!(zone = %fqdn, cf_zone_id = %cloudflare_zone.id, "{note}");
+        publish_event(ctx, zone, EventType::Normal, "ZoneSettingUpdated", note).await;
+    }
+
+    Ok(())
+}
+
+/// Enable DNSSEC for the cloudflare zone if the zone requests so through its
+/// annotation, returning the DS record to add to its parent.
+///
+/// Zones with a kubizone parent zone get a DS Record delegating to them
+/// whenever the DS record changes, see [`dnssec::ds_record`].
+async fn sync_dnssec(
+    ctx: &Context,
+    zone: &Zone,
+    fqdn: &FullyQualifiedDomainName,
+    cloudflare: &CloudFlare,
+    cloudflare_zone: &cloudflare::Zone,
+) -> Result<Option<String>, Error> {
+    // Sub-zones mapped onto an enclosing cloudflare zone have no DNSSEC of their own.
+    if !annotations::enables_dnssec(zone) || &cloudflare_zone.fqdn != fqdn {
+        return Ok(None);
+    }
+
+    let mut dnssec = cloudflare.dnssec(&cloudflare_zone.id).await?;
+
+    if matches!(
+        dnssec.status,
+        DnssecStatus::Disabled | DnssecStatus::PendingDisabled
+    ) {
+        if ctx.dry_run {
+            info!(zone = %fqdn, cf_zone_id = %cloudflare_zone.id, "not enabling DNSSEC for {fqdn} in dry run");
+            return Ok(None);
+        }
+
+        info!(zone = %fqdn, cf_zone_id = %cloudflare_zone.id, "enabling DNSSEC for {fqdn}");
+        let result = cloudflare.enable_dnssec(&cloudflare_zone.id).await;
+        ctx.audit.write(&audit::Entry::zone(
+            &ctx.owner,
+            "enable-dnssec",
+            cloudflare_zone,
+            result.as_ref().err(),
+        ));
+        dnssec = result?;
+    }
+
+    let changed = status::current_stats(zone).and_then(|stats| stats.ds) != dnssec.ds;
+    if let Some(record) = dnssec::ds_record(zone, &dnssec).filter(|_| changed && !ctx.dry_run) {
+        info!(
+            zone = %fqdn,
+            "applying DS record {}/{} delegating DNSSEC of {fqdn}: {}",
+            record.namespace().unwrap_or_default(),
+            record.name_any(),
+            record.spec.rdata
+        );
+
+        Api::<Record>::namespaced(ctx.kube.clone(), &record.namespace().unwrap_or_default())
+            .patch(
+                &record.name_any(),
+                &PatchParams::apply(&ctx.sync.controller_name).force(),
+                &Patch::Apply(&record),
+            )
+            .await?;
+    }
+
+    Ok(dnssec.ds)
+}
+
+/// Create the cloudflare zone for the domain of the zone, publishing the
+/// nameservers it must be delegated to in an event.
+///
+/// Zones are neither created for paused zones, nor during a dry run.
+async fn create_cloudflare_zone(
+    ctx: &Context,
+    zone: &Zone,
+    fqdn: &FullyQualifiedDomainName,
+) -> Result<(CloudFlare, cloudflare::Zone), Error> {
+    let config = ctx.accounts.zone_config(fqdn);
+    // Unwrap safety: `--create-zones` requires `--cf-account-id`.
+    let account_id = ctx.sync.account_id(&config).unwrap();
+
+    if ctx.dry_run || annotations::is_paused(zone) {
+        info!(zone = %zone.name_any(), "not creating cloudflare zone {fqdn} in account {account_id}");
+        return Err(Error::ZoneNotFound(fqdn.clone()));
+    }
+
+    let (cloudflare, cloudflare_zone) = ctx.accounts.create_zone(account_id, fqdn).await?;
+    ctx.audit.write(&audit::Entry::zone(
+        &ctx.owner,
+        "create-zone",
+        &cloudflare_zone,
+        None,
+    ));
+
+    let note = format!(
+        "created cloudflare zone {fqdn} ({}), delegate the domain to {} to activate it",
+        cloudflare_zone.id,
+        cloudflare_zone.name_servers.join(", ")
+    );
+    info!(zone = %zone.name_any(), "{note}");
+    publish_event(ctx, zone, EventType::Normal, "ZoneCreated", note).await;
+
+    Ok((cloudflare, cloudflare_zone))
+}
+
+/// Error holding back the deletions planned for the zone, if they look like
+/// a mistake and have not been acknowledged through the annotation.
+///
+/// Deletions are held back if there are more than permitted per reconcile, or
+/// if the zone has no `desired` entries at all, or deletes a larger share of
+/// the managed records than permitted, since that usually means the zone's
+/// status is temporarily incomplete.
+fn hold_back_deletions(sync: &SyncArgs, zone: &Zone, plan: &Plan, desired: usize) -> Option<Error> {
+    let deletions = plan.count("delete");
+
+    if deletions == 0
+        || annotations::acknowledged_deletions(zone).is_some_and(|acked| acked >= deletions)
+    {
+        return None;
+    }
+
+    if let Some(max) = sync
+        .max_deletions_per_reconcile
+        .filter(|max| deletions > *max)
+    {
+        return Some(Error::TooManyDeletions { deletions, max });
+    }
+
+    let exceeds_percentage = sync
+        .max_deletion_percentage
+        .is_some_and(|percentage| deletions * 100 > plan.managed * usize::from(percentage));
+
+    (desired == 0 || exceeds_percentage).then_some(Error::DesiredStateCollapsed {
+        entries: desired,
+        deletions,
+        managed: plan.managed,
+    })
+}
+
+/// Apply changes to the cloudflare zone in batches, falling back to
+/// individual requests for any batch which cloudflare rejects.
+async fn apply_changes(
+    ctx: &Context,
+    cloudflare: &impl DnsProvider,
+    cloudflare_zone: &cloudflare::Zone,
+    description: Option<&str>,
+    changes: &[RecordChange<'_>],
+    journal: &Journal,
+) -> Result<(), Error> {
+    if ctx.dry_run {
+        if !changes.is_empty() {
+            info!(
+                zone = %cloudflare_zone.fqdn,
+                cf_zone_id = %cloudflare_zone.id,
+                "dry run: not applying {} changes to {}",
+                changes.len(),
+                cloudflare_zone.fqdn
+            );
+        }
+        return Ok(());
+    }
+
+    for batch in changes.chunks(cloudflare::MAX_BATCH_SIZE) {
+        let marker = ctx.marker(&cloudflare_zone.id, description);
+        let mut result = cloudflare.batch(&cloudflare_zone.id, marker, batch).await;
+
+        // Batches are atomic, so nothing has been applied yet.
+        if let Err(err) = &result {
+            if ctx.fall_back_to_comments(cloudflare_zone, marker, err) {
+                let marker = ctx.marker(&cloudflare_zone.id, description);
+                result = cloudflare.batch(&cloudflare_zone.id, marker, batch).await;
+            }
+        }
+
+        let err = match result {
+            Ok(applied) => {
+                for change in batch {
+                    ctx.audit(journal, cloudflare_zone, change, None);
+                }
+                ctx.caches.record_cache.apply(&cloudflare_zone.id, applied);
+                continue;
+            }
+            Err(err) => err,
+        };
+
+        // The records were most likely not what we expected them to be.
+        ctx.caches.record_cache.invalidate(&cloudflare_zone.id);
+
+        // Individual requests would fail the same way.
+        if err.is_unauthorized() {
+            for change in batch {
+                ctx.audit(journal, cloudflare_zone, change, Some(&err));
+            }
+            return Err(err.into());
+        }
+
+        warn!(
+            zone = %cloudflare_zone.fqdn,
+            cf_zone_id = %cloudflare_zone.id,
+            "batch of {} changes to {} failed: {err}, falling back to individual requests",
+            batch.len(),
+            cloudflare_zone.fqdn
+        );
+
+        // Deletions go first and creations last, like within a batch, so
+        // a record can be replaced by one which would conflict with it.
+        for phase in 0..3 {
+            let mut requests = Vec::new();
+            for change in batch {
+                if change_phase(change) == phase {
+                    requests.push(apply_individually(
+                        ctx,
+                        cloudflare,
+                        cloudflare_zone,
+                        description,
+                        change,
+                        journal,
+                    ));
+                }
+            }
+
+            futures::stream::iter(requests)
+                .buffer_unordered(ctx.sync.max_concurrent_requests)
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .collect::<Result<(), _>>()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Order in which changes are applied individually: deletions, updates, then creations.
+fn change_phase(change: &RecordChange) -> u8 {
+    match change {
+        RecordChange::Delete(_) => 0,
+        RecordChange::Update(..) | RecordChange::Adopt(..) => 1,
+        RecordChange::Create(_) => 2,
+    }
+}
+
+/// Apply a single change, skipping it if it conflicts with an existing record.
+async fn apply_individually(
+    ctx: &Context,
+    cloudflare: &impl DnsProvider,
+    cloudflare_zone: &cloudflare::Zone,
+    description: Option<&str>,
+    change: &RecordChange<'_>,
+    journal: &Journal,
+) -> Result<(), Error> {
+    let marker = ctx.marker(&cloudflare_zone.id, description);
+    let mut result = cloudflare.apply(&cloudflare_zone.id, marker, change).await;
+    if let Err(err) = &result {
+        if ctx.fall_back_to_comments(cloudflare_zone, marker, err) {
+            let marker = ctx.marker(&cloudflare_zone.id, description);
+            result = cloudflare.apply(&cloudflare_zone.id, marker, change).await;
+        }
+    }
+
+    match result {
+        Err(err) if err.is_record_exists() => {
+            if let RecordChange::Create(desired) = change {
+                if adopt_existing(
+                    ctx,
+                    cloudflare,
+                    cloudflare_zone,
+                    description,
+                    *desired,
+                    journal,
+                )
+                .await?
+                {
+                    return Ok(());
+                }
+            }
+
+            warn!(
+                zone = %cloudflare_zone.fqdn,
+                cf_zone_id = %cloudflare_zone.id,
+                "skipping change to {}: {err}",
+                cloudflare_zone.fqdn
+            );
+        }
+        result => {
+            ctx.audit(journal, cloudflare_zone, change, result.as_ref().err());
+            result?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Adopt the record cloudflare reports as already existing when creating the
+/// `desired` record, such as when the response to an earlier successful create
+/// was lost.
+///
+/// Returns false if no matching record exists, or it is managed by someone else.
+async fn adopt_existing(
+    ctx: &Context,
+    cloudflare: &impl DnsProvider,
+    cloudflare_zone: &cloudflare::Zone,
+    description: Option<&str>,
+    desired: Desired<'_>,
+    journal: &Journal,
+) -> Result<bool, Error> {
+    let filter = RecordFilter::default()
+        .with_name(desired.fqdn)
+        .with_type(desired.r#type);
+    let records = cloudflare
+        .find_records(&cloudflare_zone.id, &filter)
+        .await?;
+
+    let ident = desired.ident();
+    let Some(record) = records.iter().find(|record| {
+        RecordIdent::from(*record) == ident
+            && (record.managed_by().is_none()
+                || record.is_marked_by(&ctx.owner)
+                || ctx
+                    .sync
+                    .take_ownership_from
+                    .iter()
+                    .any(|owner| record.is_marked_by(owner))
+                || ctx
+                    .sync
+                    .cluster
+                    .legacy_owner(&ctx.sync.controller_name)
+                    .is_some_and(|legacy| record.is_marked_by(legacy)))
+    }) else {
+        return Ok(false);
+    };
+
+    info!(
+        zone = %cloudflare_zone.fqdn,
+        cf_zone_id = %cloudflare_zone.id,
+        "{} record {} already exists in {}, adopting it",
+        desired.r#type,
+        desired.fqdn,
+        cloudflare_zone.fqdn
+    );
+
+    let marker = ctx.marker(&cloudflare_zone.id, description);
+    let result = cloudflare
+        .update_record(&cloudflare_zone.id, marker, record, desired)
+        .await;
+    ctx.audit(
+        journal,
+        cloudflare_zone,
+        &RecordChange::Adopt(record, desired),
+        result.as_ref().err(),
+    );
+    result?;
+
+    Ok(true)
+}
+
+/// Kubernetes Event describing a change made to a record in cloudflare.
+fn record_event(change: &PlannedChange) -> Event {
+    let (action, note) = match change {
+        PlannedChange::Create(Target { entry, .. }) => (
+            "Created",
+            format!(
+                "created {} record {} with value {}",
+                entry.type_, entry.fqdn, entry.rdata
+            ),
+        ),
+        PlannedChange::Update(_, Target { entry, ttl, .. }) => (
+            "Updated",
+            format!(
+                "updated {} record {} to {} with ttl {}",
+                entry.type_, entry.fqdn, entry.rdata, ttl
+            ),
+        ),
+        PlannedChange::Adopt(_, Target { entry, .. }) => (
+            "Adopted",
+            format!(
+                "adopted existing {} record {} with value {}",
+                entry.type_, entry.fqdn, entry.rdata
+            ),
+        ),
+        PlannedChange::Delete(record) => (
+            "Deleted",
+            format!(
+                "deleted {} record {} with value {}",
+                record.r#type, record.fqdn, record.rdata
+            ),
+        ),
+    };
+
+    Event {
+        type_: EventType::Normal,
+        reason: format!("Record{action}"),
+        note: Some(note),
+        action: action.to_string(),
+        secondary: None,
+    }
+}
+
+/// Publish events on the zone, so the changes made to it show up in `kubectl describe zone`.
+///
+/// Failing to publish an event does not fail the reconciliation, since the
+/// changes have already been applied at this point.
+async fn publish_events(ctx: &Context, zone: &Zone, plan: &Plan<'_>) {
+    if (plan.changes.is_empty() && plan.protected.is_empty()) || ctx.dry_run {
+        return;
+    }
+
+    let recorder = Recorder::new(
+        ctx.kube.clone(),
+        ctx.sync.controller_name.clone().into(),
+        zone.object_ref(&()),
+    );
+
+    let protected = plan.protected.iter().map(|change| Event {
+        type_: EventType::Warning,
+        reason: "RecordProtected".to_string(),
+        note: Some(format!(
+            "not applying {change}, since the record is protected by the {} annotation",
+            protection::protected_annotation()
+        )),
+        action: "Skipped".to_string(),
+        secondary: None,
+    });
+
+    for event in plan.changes.iter().map(record_event).chain(protected) {
+        if let Err(err) = recorder.publish(event).await {
+            warn!(
+                zone = %zone.name_any(),
+                "failed to publish event for zone {}: {err}",
+                zone.name_any()
+            );
+        }
+    }
+}
+
+/// Publish events on the zone for the protected records left behind while
+/// cleaning up the zone, just like [`publish_events`] does when syncing.
+async fn publish_protected_deletions(ctx: &Context, zone: &Zone, records: &[&cloudflare::Record]) {
+    if records.is_empty() || ctx.dry_run {
+        return;
+    }
+
+    let recorder = Recorder::new(
+        ctx.kube.clone(),
+        ctx.sync.controller_name.clone().into(),
+        zone.object_ref(&()),
+    );
+
+    for record in records {
+        let event = Event {
+            type_: EventType::Warning,
+            reason: "RecordProtected".to_string(),
+            note: Some(format!(
+                "not deleting {} record {}, since the record is protected by the {} annotation",
+                record.r#type,
+                record.fqdn,
+                protection::protected_annotation()
+            )),
+            action: "Skipped".to_string(),
+            secondary: None,
+        };
+
+        if let Err(err) = recorder.publish(event).await {
+            warn!(
+                zone = %zone.name_any(),
+                "failed to publish event for zone {}: {err}",
+                zone.name_any()
+            );
+        }
+    }
+}
+
+/// Warn about entries whose TTL is below the minimum, and which are therefore
+/// synced with a higher TTL than the zone specifies.
+///
+/// Only entries whose records were written in this sync are warned about in
+/// an event, rather than on every reconcile for as long as the TTL is too low.
+async fn warn_below_min_ttl(
+    ctx: &Context,
+    zone: &Zone,
+    plan: &Plan<'_>,
+    entries: &[ZoneEntry],
+    options: &plan::Options<'_>,
+    min_ttl: u32,
+) {
+    let below = plan
+        .raised_ttls(options)
+        .into_iter()
+        .map(|entry| format!("{} {}", entry.fqdn, entry.type_))
+        .collect::<Vec<_>>();
+
+    if below.is_empty() {
+        let unchanged = entries
+            .iter()
+            .filter(|entry| options.manages(entry.type_) && entry.ttl < min_ttl)
+            .count();
+        if unchanged > 0 {
+            debug!(
+                zone = %zone.name_any(),
+                "{unchanged} entries are synced with the minimum ttl of {min_ttl}, instead of their own"
+            );
+        }
+        return;
+    }
+
+    let note = format!(
+        "raised the ttl of {} entries to the minimum of {min_ttl}: {}",
+        below.len(),
+        below.join(", ")
+    );
+    warn!(zone = %zone.name_any(), "{note}");
+
+    publish_warning(ctx, zone, "TtlBelowMinimum", note).await;
+}
+
+/// Publish a warning event on the zone, unless this is a dry run.
+async fn publish_warning(ctx: &Context, zone: &Zone, reason: &str, note: String) {
+    publish_event(ctx, zone, EventType::Warning, reason, note).await;
+}
+
+/// Publish an event on the zone, unless this is a dry run.
+async fn publish_event(ctx: &Context, zone: &Zone, type_: EventType, reason: &str, note: String) {
+    if ctx.dry_run {
+        return;
+    }
+
+    let recorder = Recorder::new(
+        ctx.kube.clone(),
+        ctx.sync.controller_name.clone().into(),
+        zone.object_ref(&()),
+    );
+
+    let event = Event {
+        type_,
+        reason: reason.to_string(),
+        note: Some(note),
+        action: "Sync".to_string(),
+        secondary: None,
+    };
+
+    if let Err(err) = recorder.publish(event).await {
+        warn!(
+            zone = %zone.name_any(),
+            "failed to publish event for zone {}: {err}",
+            zone.name_any()
+        );
+    }
+}
+
+/// State of a cloudflare zone which is not active, mentioning the nameservers
+/// it must be delegated to while pending.
+fn zone_state(zone: &cloudflare::Zone) -> String {
+    if zone.paused {
+        "paused".to_string()
+    } else if zone.status == ZoneStatus::Pending && !zone.name_servers.is_empty() {
+        format!(
+            "pending until the domain is delegated to {}",
+            zone.name_servers.join(", ")
+        )
+    } else {
+        zone.status.to_string()
+    }
+}
+
+fn parse_fqdn(fqdn: &str) -> Result<FullyQualifiedDomainName, String> {
+    FullyQualifiedDomainName::try_from(fqdn).map_err(|err| format!("{fqdn}: {err}"))
+}
+
+fn parse_cluster_id(cluster_id: &str) -> Result<String, String> {
+    if cluster_id.is_empty() || cluster_id.contains(char::is_whitespace) {
+        return Err("cluster id must be non-empty and contain no whitespace".to_string());
+    }
+
+    Ok(cluster_id.to_string())
+}
+
+fn parse_record_type(type_: &str) -> Result<Type, String> {
+    serde_json::from_value(serde_json::Value::String(type_.trim().to_uppercase()))
+        .map_err(|_| format!("unknown record type: {type_}"))
+}
+
+/// Check the Zone or Record under review against the constraints of
+/// cloudflare, see [`webhook`].
+///
+/// Other resources, and deletions, are always allowed.
+async fn review_admission(
+    kube: &KubeClient,
+    sync: &SyncArgs,
+    warn_only: bool,
+    review: AdmissionReview<DynamicObject>,
+) -> AdmissionResponse {
+    let request: AdmissionRequest<DynamicObject> = match review.try_into() {
+        Ok(request) => request,
+        Err(err) => return AdmissionResponse::invalid(err),
+    };
+    let Some(object) = request
+        .object
+        .as_ref()
+        .and_then(|object| serde_json::to_value(object).ok())
+    else {
+        return AdmissionResponse::from(&request);
+    };
+
+    let findings = match request.kind.kind.as_str() {
+        "Zone" => match serde_json::from_value::<Zone>(object) {
+            Ok(zone) => webhook::check_zone(&zone, &sync.owner(), sync.describe(&zone).as_deref()),
+            Err(err) => return AdmissionResponse::invalid(err),
+        },
+        "Record" => match serde_json::from_value::<Record>(object) {
+            Ok(record) => {
+                let at_apex = match &record.spec.domain_name {
+                    DomainName::Full(fqdn) if record.spec.type_ == Type::CNAME => {
+                        match sync.list_zones(kube).await {
+                            Ok(zones) => zones.iter().any(|zone| zone.fqdn() == Some(fqdn)),
+                            Err(err) => {
+                                warn!("failed to list zones to check {fqdn} against: {err}");
+                                false
+                            }
+                        }
+                    }
+                    _ => false,
+                };
+
+                webhook::check_record(&record, sync.manages(record.spec.type_), at_apex)
+            }
+            Err(err) => return AdmissionResponse::invalid(err),
+        },
+        _ => webhook::Findings::default(),
+    };
+
+    findings.respond(&request, warn_only)
+}
+
+/// Generate kubizone resources for the records of the cloudflare zone, and
+/// either print or apply them.
+async fn import_zone(
+    kube: KubeClient,
+    accounts: &Accounts,
+    fqdn: &FullyQualifiedDomainName,
+    namespace: &str,
+    apply: bool,
+) -> Result<(), Error> {
+    accounts.lookup_zone(fqdn).await?;
+    let (cloudflare, cloudflare_zone) = accounts
+        .find_zone(fqdn)
+        .filter(|(_, cloudflare_zone)| &cloudflare_zone.fqdn == fqdn)
+        .ok_or_else(|| Error::ZoneNotFound(fqdn.clone()))?;
+
+    let records = cloudflare.records(&cloudflare_zone.id).await?;
+    let manifests = import::manifests(fqdn, namespace, &records);
+
+    for record in &manifests.skipped {
+        warn!(
+            "not importing {} record {} with value {}, since records at the apex of a zone cannot be delegated",
+            record.r#type, record.fqdn, record.rdata
+        );
+    }
+
+    if !apply {
+        print!("---\n{}", serde_yaml::to_string(&manifests.zone)?);
+        for record in &manifests.records {
+            print!("---\n{}", serde_yaml::to_string(record)?);
+        }
+        return Ok(());
+    }
+
+    let params = PatchParams::apply("kubizone-cloudflare-import");
+
+    Api::<Zone>::namespaced(kube.clone(), namespace)
+        .patch(
+            &manifests.zone.name_any(),
+            &params,
+            &Patch::Apply(&manifests.zone),
+        )
+        .await?;
+    info!("applied zone {namespace}/{}", manifests.zone.name_any());
+
+    let api = Api::<Record>::namespaced(kube, namespace);
+    for record in &manifests.records {
+        api.patch(&record.name_any(), &params, &Patch::Apply(record))
+            .await?;
+    }
+    info!("applied {} records to {namespace}", manifests.records.len());
+
+    Ok(())
+}
+
+/// Export the cloudflare zones as BIND zone files, either into `output_dir`
+/// or to stdout.
+///
+/// Unless `all` is set, only cloudflare zones used by a zone selected for
+/// sync are exported.
+async fn export(
+    kube: KubeClient,
+    accounts: &Accounts,
+    sync: &SyncArgs,
+    all: bool,
+    output_dir: Option<PathBuf>,
+) -> Result<(), Error> {
+    let used = if all {
+        None
+    } else {
+        let zones = sync.list_zones(&kube).await?;
+
+        let mut used = HashSet::new();
+        for fqdn in zones.iter().filter_map(Zone::fqdn) {
+            accounts.lookup_zone(fqdn).await?;
+            if let Some((_, cloudflare_zone)) = accounts.find_zone(fqdn) {
+                used.insert(cloudflare_zone.id.to_string());
+            }
+        }
+
+        Some(used)
+    };
+
+    let mut seen = HashSet::new();
+    for account in accounts.all() {
+        let cloudflare = account.cloudflare();
+
+        for cloudflare_zone in account.zones() {
+            let id = cloudflare_zone.id.to_string();
+            if used.as_ref().is_some_and(|used| !used.contains(&id)) || !seen.insert(id) {
+                continue;
+            }
+
+            let zonefile = cloudflare.export_records(&cloudflare_zone.id).await?;
+
+            match &output_dir {
+                Some(dir) => {
+                    let name = cloudflare_zone.fqdn.to_string();
+                    let path = dir.join(format!("{}.zone", name.trim_end_matches('.')));
+
+                    tokio::fs::write(&path, zonefile)
+                        .await
+                        .map_err(|err| Error::File(path.clone(), err))?;
+                    info!("exported {} to {}", cloudflare_zone.fqdn, path.display());
+                }
+                None => println!(
+                    ";; {} ({})\n{zonefile}",
+                    cloudflare_zone.fqdn, cloudflare_zone.id
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the entries of every zone selected for sync alongside the records
+/// in the corresponding cloudflare zone.
+///
+/// Records are matched up and attributed the same way a sync does, leaving
+/// out the records of nested zones and the types which are not synced.
+async fn list(ctx: &Context) -> Result<(), Error> {
+    let zones = ctx.sync.list_zones(&ctx.kube).await?;
+
+    for zone in zones {
+        let Some(fqdn) = zone.fqdn() else {
+            println!("{zone}: no fully qualified domain name yet\n");
+            continue;
+        };
+
+        ctx.accounts.lookup_zone(fqdn).await?;
+        let Some((cloudflare, cloudflare_zone)) = ctx.accounts.find_zone(fqdn) else {
+            println!("{zone}: {fqdn} is not in cloudflare\n");
+            continue;
+        };
+
+        let records = zone_records(ctx, &cloudflare, &cloudflare_zone, &zone, fqdn).await?;
+
+        let owners = ctx.owners(&records);
+        let entries = zone
+            .status
+            .as_ref()
+            .map(|status| status.entries.as_slice())
+            .unwrap_or_default();
+
+        println!("{zone} ({}, {}):", cloudflare_zone.fqdn, cloudflare_zone.id);
+
+        println!("  desired:");
+        for entry in entries.iter().filter(|entry| ctx.sync.manages(entry.type_)) {
+            let ident = plan::entry_ident(entry);
+            let state = match records
+                .iter()
+                .find(|record| RecordIdent::from(*record) == ident)
+            {
+                None => "! missing".to_string(),
+                Some(record) if !owners.is_managed(record) => {
+                    "! exists, but is not managed by us".to_string()
+                }
+                Some(record) if record.ttl != cloudflare::ttl(entry.ttl, record.proxied) => {
+                    format!("! ttl is {} in cloudflare", record.ttl)
+                }
+                Some(_) => "in sync".to_string(),
+            };
+
+            println!(
+                "    {} {} IN {} {}: {state}",
+                entry.fqdn, entry.ttl, entry.type_, entry.rdata
+            );
+        }
+
+        println!("  actual:");
+        for record in &records {
+            let ident = RecordIdent::from(record);
+            let desired = entries
+                .iter()
+                .any(|entry| plan::entry_ident(entry) == ident);
+
+            let state = match (owners.is_managed(record), record.managed_by()) {
+                _ if !ctx.sync.manages(record.r#type) => "type is not synced".to_string(),
+                (true, _) if desired => "managed by us".to_string(),
+                (true, _) => "! managed by us, but not desired".to_string(),
+                (false, Some(owner)) => format!("managed by {owner}"),
+                (false, None) => "unmanaged".to_string(),
+            };
+
+            println!(
+                "    {} {} IN {} {}: {state}",
+                record.fqdn, record.ttl, record.r#type, record.rdata
+            );
+        }
+
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Print the status and permissions of every account, and the cloudflare zone
+/// each zone selected for sync maps to.
+///
+/// Returns false if any problems were found.
+async fn verify(kube: KubeClient, accounts: &Accounts, sync: &SyncArgs) -> bool {
+    let mut ok = true;
+
+    for account in accounts.all() {
+        let cloudflare = account.cloudflare();
+        let zones = account.zones();
+
+        println!("{account}:");
+
+        if account.source().is_token() {
+            match cloudflare.verify_token().await {
+                Ok(verification) => println!("  {verification}"),
+                Err(err) => {
+                    println!("  failed to verify token: {err}");
+                    ok = false;
+                }
+            }
+        } else {
+            println!("  global api key, permissions of the account apply");
+        }
+
+        println!("  {} zones accessible", zones.len());
+
+        for zone in zones {
+            let read = match cloudflare.records(&zone.id).await {
+                Ok(records) => format!("{} records", records.len()),
+                Err(err) => {
+                    ok = false;
+                    format!("cannot list records: {err}")
+                }
+            };
+
+            let edit = match cloudflare.can_edit_records(&zone.id).await {
+                Ok(true) => "dns edit permitted".to_string(),
+                Ok(false) => {
+                    ok = false;
+                    "dns edit NOT permitted".to_string()
+                }
+                Err(err) => {
+                    ok = false;
+                    format!("failed to check dns edit permission: {err}")
+                }
+            };
+
+            println!("  {} ({}): {read}, {edit}", zone.fqdn, zone.id);
+        }
+
+        println!();
+    }
+
+    let zones = match sync.list_zones(&kube).await {
+        Ok(zones) => zones,
+        Err(err) => {
+            println!("failed to list zones in the cluster: {err}");
+            return false;
+        }
+    };
+
+    println!("zones in cluster:");
+    for zone in zones {
+        let Some(fqdn) = zone.fqdn() else {
+            println!("  {zone}: no fully qualified domain name yet");
+            continue;
+        };
+
+        if let Err(err) = accounts.lookup_zone(fqdn).await {
+            println!("  {zone} ({fqdn}) => failed to look up cloudflare zone: {err}");
+            ok = false;
+            continue;
+        }
+
+        match accounts.find_zone(fqdn) {
+            Some((_, cloudflare_zone)) => println!(
+                "  {zone} ({fqdn}) => {} ({})",
+                cloudflare_zone.fqdn, cloudflare_zone.id
+            ),
+            None => {
+                println!("  {zone} ({fqdn}) => no matching cloudflare zone");
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
+
+/// Delete the records managed by this controller in every cloudflare zone.
+///
+/// Returns false if the records of any zone could not be deleted.
+async fn cleanup_all(ctx: &Context) -> bool {
+    let mut complete = true;
+    let mut seen = HashSet::new();
+
+    for account in ctx.accounts.all() {
+        let cloudflare = account.cloudflare();
+
+        for cloudflare_zone in account.zones() {
+            // Multiple accounts may have access to the same zone.
+            if !seen.insert(cloudflare_zone.id.to_string()) {
+                continue;
+            }
+
+            let config = ctx.accounts.zone_config(&cloudflare_zone.fqdn);
+            let protected =
+                Protected::from_config(&cloudflare_zone.fqdn.to_string(), &config.protected);
+
+            if let Err(err) =
+                cleanup_zone(ctx, &cloudflare, &cloudflare_zone, None, &protected, &[]).await
+            {
+                error!(
+                    zone = %cloudflare_zone.fqdn,
+                    cf_zone_id = %cloudflare_zone.id,
+                    "failed to clean up records in {}: {err}",
+                    cloudflare_zone.fqdn
+                );
+                complete = false;
+            }
+        }
+    }
+
+    complete
+}
+
+/// Delete the records managed by this controller in the cloudflare zone,
+/// optionally limited to those at or below the `zone`, but not within any of
+/// the `nested` zones.
+///
+/// Protected records, and records of types which are not synced, are left
+/// alone, just like when syncing.
+async fn cleanup_zone(
+    ctx: &Context,
+    cloudflare: &impl DnsProvider,
+    cloudflare_zone: &cloudflare::Zone,
+    zone: Option<&Zone>,
+    protected: &Protected,
+    nested: &[FullyQualifiedDomainName],
+) -> Result<(), Error> {
+    let within = zone.and_then(|zone| zone.fqdn());
+
+    let legacy_owner = ctx.sync.cluster.legacy_owner(&ctx.sync.controller_name);
+
+    // With comments, cloudflare can find the managed records without listing the entire zone.
+    // Legacy markers are a prefix of the current ones, so finding them finds both.
+    let records = if ctx.sync.ownership == Ownership::Comment {
+        let filter = RecordFilter::default()
+            .with_comment_prefix(owner_marker(legacy_owner.unwrap_or(&ctx.owner)));
+        cloudflare
+            .find_records(&cloudflare_zone.id, &filter)
+            .await?
+    } else {
+        cloudflare.records(&cloudflare_zone.id).await?
+    };
+    let owners =
+        Owners::new(ctx.sync.ownership, &ctx.owner, &records).with_legacy_owner(legacy_owner);
+
+    let records = records
+        .iter()
+        .filter(|record| owners.is_managed(record))
+        .filter(|record| {
+            ctx.sync.delete_apex_ns || !plan::is_apex_ns(record, &cloudflare_zone.fqdn)
+        })
+        .filter(|record| within.map_or(true, |fqdn| is_within(&record.fqdn, fqdn)))
+        .filter(|record| !nested.iter().any(|fqdn| is_within(&record.fqdn, fqdn)))
+        .filter(|record| ctx.sync.manages(record.r#type))
+        .collect::<Vec<_>>();
+    let (skipped, records): (Vec<_>, Vec<_>) = records
+        .into_iter()
+        .partition(|record| protected.is_protected(record));
+
+    for record in &skipped {
+        info!(
+            zone = %cloudflare_zone.fqdn,
+            cf_zone_id = %cloudflare_zone.id,
+            record = %record.fqdn,
+            action = "delete",
+            "not deleting {} record {} with id {}, since the record is protected",
+            record.r#type, record.fqdn, record.id
+        );
+    }
+    if let Some(zone) = zone {
+        publish_protected_deletions(ctx, zone, &skipped).await;
+    }
+
+    let changes = records
+        .into_iter()
+        .inspect(|record| {
+            info!(
+                zone = %cloudflare_zone.fqdn,
+                cf_zone_id = %cloudflare_zone.id,
+                record = %record.fqdn,
+                action = "delete",
+                "deleting {} record {} with id {}",
+                record.r#type, record.fqdn, record.id
+            );
+        })
+        .map(RecordChange::Delete)
+        .collect::<Vec<_>>();
+
+    info!(
+        zone = %cloudflare_zone.fqdn,
+        cf_zone_id = %cloudflare_zone.id,
+        "found {} records in {} managed by {}",
+        changes.len(),
+        cloudflare_zone.fqdn,
+        ctx.owner
+    );
+
+    // No change set is recorded, since the zones are going away, along with their change sets.
+    let journal = Journal::default();
+    let result = apply_changes(ctx, cloudflare, cloudflare_zone, None, &changes, &journal).await;
+    ctx.caches.record_cache.invalidate(&cloudflare_zone.id);
+
+    result
+}
+
+/// Reconcile the zone for `sync-once`, without the cleanup finalizer.
+///
+/// No controller outlives a one-off sync to release the finalizer again, so
+/// adding it would keep the zone from ever being deleted. Zones which are
+/// already being deleted are left to the controller which added it, if any.
+async fn reconcile_once(zone: Arc<Zone>, ctx: &Context) -> Result<Action, Error> {
+    if zone.meta().deletion_timestamp.is_some() {
+        info!("not cleaning up records of zone {zone} in a one-off sync");
+        return Ok(Action::await_change());
+    }
+
+    apply(zone, ctx).await
+}
+
+/// Reconcile every zone in the cluster once.
+///
+/// Returns false if any of the zones failed to reconcile.
+async fn sync_once(ctx: Arc<Context>) -> Result<bool, Error> {
+    let zones = ctx.sync.list_zones(&ctx.kube).await?;
+
+    let mut failed = 0;
+    let total = zones.len();
+
+    for zone in zones {
+        let name = zone.to_string();
+
+        match reconcile_once(Arc::new(zone), &ctx).await {
+            Ok(_) => info!("reconciled zone {name}"),
+            Err(err) => {
+                error!(zone = %name, "zone {name} reconciliation encountered error: {err}");
+                failed += 1;
+            }
+        }
+    }
+
+    info!("reconciled {} of {total} zones", total - failed);
+
+    Ok(failed == 0)
+}
+
+/// Print the planned changes for every zone in the cluster, along with a summary.
+///
+/// Returns false if the changes for any of the zones could not be determined.
+async fn print_plan(ctx: &Context) -> Result<bool, Error> {
+    let zones = ctx.sync.list_zones(&ctx.kube).await?;
+
+    let mut complete = true;
+    let (mut created, mut updated, mut deleted, mut adopted) = (0, 0, 0, 0);
+
+    for zone in zones {
+        let Some(fqdn) = zone.fqdn() else {
+            println!("# skipping {zone}, since it has no fully qualified domain name yet\n");
+            continue;
+        };
+
+        let Ok((cloudflare, cloudflare_zone)) = ctx.find_cloudflare_zone(fqdn).await else {
+            println!("# skipping {zone}, since {fqdn} is not in cloudflare\n");
+            continue;
+        };
+
+        if !cloudflare_zone.is_active() {
+            println!(
+                "# skipping {zone}, since {}\n",
+                Error::ZoneNotActive(cloudflare_zone)
+            );
+            continue;
+        }
+
+        let records = match zone_records(ctx, &cloudflare, &cloudflare_zone, &zone, fqdn).await {
+            Ok(records) => records,
+            Err(err) => {
+                println!(
+                    "# failed to list records of {}: {err}\n",
+                    cloudflare_zone.fqdn
+                );
+                complete = false;
+                continue;
+            }
+        };
+
+        let Some(status) = zone.status.as_ref() else {
+            println!("# skipping {zone}, since it has no entries yet\n");
+            continue;
+        };
+
+        let zone_records = match annotations::zone_records(&ctx.sync.apis(&ctx.kube), &zone).await {
+            Ok(zone_records) => zone_records,
+            Err(err) => {
+                println!("# failed to list records of {zone}: {err}\n");
+                complete = false;
+                continue;
+            }
+        };
+
+        let config = ctx.accounts.zone_config(fqdn);
+        let proxied = Proxied::new(&zone, &zone_records, config.proxied);
+        let protected = Protected::new(&zone, &zone_records, &config.protected);
+        let plan = Plan::new(
+            &status.entries,
+            &records,
+            &ctx.sync.plan_options(
+                &ctx.owner,
+                &zone,
+                &config,
+                &cloudflare_zone.fqdn,
+                &proxied,
+                &protected,
+            ),
+        );
+
+        println!("{zone} ({}, {}):", cloudflare_zone.fqdn, cloudflare_zone.id);
+        if plan.changes.is_empty() {
+            println!("  no changes");
+        }
+        for change in &plan.changes {
+            println!("  {change}");
+        }
+        for change in &plan.protected {
+            println!("  # protected: {change}");
+        }
+        println!();
+
+        created += plan.count("create");
+        updated += plan.count("update");
+        deleted += plan.count("delete");
+        adopted += plan.count("adopt");
+    }
+
+    println!(
+        "Plan: {created} to create, {updated} to update, {deleted} to delete, {adopted} to adopt."
+    );
+
+    Ok(complete)
+}
+
+/// Report the result of a reconciliation by any of the controllers.
+fn reconciled<K, E: std::fmt::Display>(health: &Health, result: Result<(ObjectRef<K>, Action), E>)
+where
+    K: kube::Resource,
+    ObjectRef<K>: std::fmt::Debug,
+{
+    health.reconciled();
+
+    match result {
+        Ok(o) => info!("reconciled: {:?}", o),
+        Err(e) => warn!("reconciliation failed: {}", e),
+    }
+}
+
+/// Retry records which failed to sync, without tracking consecutive failures
+/// like for zones, since their zone is retried anyway.
+fn record_error_policy(record: Arc<Record>, error: &Error, ctx: Arc<Context>) -> Action {
+    let backoff = error.backoff(1, ctx.max_backoff);
+
+    warn!(
+        "record {record} reconciliation encountered error: {error}, retrying in {}s",
+        backoff.as_secs()
+    );
+    ctx.requeue(backoff)
+}
+
+fn error_policy(zone: Arc<Zone>, error: &Error, ctx: Arc<Context>) -> Action {
+    let status = match error {
+        Error::CloudFlare(err) => err.status().map(|status| status.as_u16()),
+        _ => None,
+    };
+
+    let failures = {
+        let mut failures = ctx.caches.failures.lock().unwrap();
+        let failures = failures.entry(zone.to_string()).or_default();
+        *failures += 1;
+        *failures
+    };
+    let backoff = error.backoff(failures, ctx.max_backoff);
+
+    error!(
+        zone = %zone.name_any(),
+        status,
+        failures,
+        "zone {} reconciliation encountered error: {error}, retrying in {}s",
+        zone.name_any(),
+        backoff.as_secs()
+    );
+    ctx.requeue(backoff)
+}
+
+impl CloudflareArgs {
+    /// Connect to every configured cloudflare account, exiting the process
+    /// if any of them cannot be used.
+    async fn connect(self, client: KubeClient) -> Accounts {
+        let default_source = match (
+            self.cf_api_key,
+            self.cf_api_token_secret,
+            self.cf_api_token_file,
+            self.cf_global_key.zip(self.cf_api_email),
+        ) {
+            (Some(key), _, _, _) => CredentialSource::Static(Credentials::Token(key)),
+            (None, Some(secret), _, _) => CredentialSource::Secret(secret),
+            (None, None, Some(path), _) => CredentialSource::File(path),
+            (None, None, None, Some((key, email))) => {
+                CredentialSource::Static(Credentials::GlobalKey { email, key })
+            }
+            (None, None, None, None) => {
+                unreachable!("clap requires one of the credential arguments")
+            }
+        };
+
+        let settings = account::Settings {
+            api_url: self.cf_api_url,
+            records_per_page: self.cf_records_per_page,
+            retry_policy: RetryPolicy {
+                max_retries: self.cf_max_retries,
+                ..Default::default()
+            },
+            zone_refresh_interval: Duration::from_secs(self.zone_refresh_secs),
+            zone_lookup: self.zone_lookup,
+            token_file_refresh_interval: Duration::from_secs(self.cf_api_token_file_refresh_secs),
+            token_verify_interval: Duration::from_secs(self.cf_api_token_verify_secs),
+        };
+
+        let connect = |source: CredentialSource, patterns| {
+            let client = client.clone();
+            let settings = &settings;
+
+            async move {
+                let description = source.to_string();
+                match Account::connect(client, source, patterns, settings).await {
+                    Ok(account) => account,
+                    Err(err) => {
+                        error!("failed to set up cloudflare account using {description}: {err}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+        };
+
+        let mut zone_accounts = Vec::new();
+        for credentials in self.cf_zone_credentials {
+            zone_accounts.push(connect(credentials.source, credentials.patterns).await);
+        }
+
+        let default_account = connect(default_source, Vec::new()).await;
+
+        let dynamic_accounts = DynamicAccounts::default();
+        if self.watch_credentials {
+            tokio::spawn(account::watch_credentials(
+                client.clone(),
+                dynamic_accounts.clone(),
+                settings.clone(),
+            ));
+        }
+
+        let zone_configs = ZoneConfigs::default();
+        if self.watch_zone_configs {
+            tokio::spawn(zone_configs.clone().watch(client.clone(), settings.clone()));
+
+            // Zones should not be synced without their configs.
+            if tokio::time::timeout(Duration::from_secs(30), zone_configs.listed())
+                .await
+                .is_err()
+            {
+                warn!("zone configs have not been listed yet, continuing without them");
+            }
+        }
+
+        Accounts::new(
+            zone_accounts,
+            dynamic_accounts,
+            zone_configs,
+            default_account,
+        )
+    }
+}
+
+/// Re-read the `--config` file every `interval`, sending the sync options
+/// whenever the changes to it reach them, so the controllers are restarted
+/// with them.
+async fn reload_sync_args(
+    kube: KubeClient,
+    mut reload: config::Reload,
+    interval: Duration,
+    tx: tokio::sync::watch::Sender<SyncArgs>,
+) {
+    let reloadable = <SyncArgs as clap::Args>::augment_args(clap::Command::new("sync"))
+        .get_arguments()
+        .filter_map(|arg| arg.get_long().map(String::from))
+        .collect::<HashSet<_>>();
+
+    loop {
+        let (changes, args) = reload.changed(interval).await;
+        let (reloaded, ignored) = changes
+            .into_iter()
+            .partition::<Vec<_>, _>(|option| reloadable.contains(option));
+
+        if !ignored.is_empty() {
+            warn!(
+                "changes to --{} only take effect once the controller is restarted",
+                ignored.join(", --")
+            );
+        }
+        let Command::Reconcile { sync, .. } = args.command else {
+            return;
+        };
+        if reloaded.is_empty() {
+            continue;
+        }
+
+        // The previous options are kept, rather than silently syncing nothing.
+        if let Err(err) = sync.validate() {
+            warn!("ignoring configuration changes: {err}");
+            publish_controller_warning(
+                &kube,
+                &sync.controller_name,
+                "InvalidConfiguration",
+                format!("ignoring configuration changes: {err}"),
+            )
+            .await;
+            continue;
+        }
+
+        info!("configuration changed: --{}", reloaded.join(", --"));
+        if tx.send(sync).is_err() {
+            return;
+        }
+    }
+}
+
+/// Publish a warning event on the pod of the controller, for problems which
+/// concern no zone in particular.
+///
+/// Outside of kubernetes there is no pod to publish it on, so nothing is published.
+async fn publish_controller_warning(
+    kube: &KubeClient,
+    controller_name: &str,
+    reason: &str,
+    note: String,
+) {
+    let Ok(pod) = std::env::var("HOSTNAME") else {
+        return;
+    };
+
+    let recorder = Recorder::new(
+        kube.clone(),
+        controller_name.to_string().into(),
+        ObjectReference {
+            api_version: Some("v1".to_string()),
+            kind: Some("Pod".to_string()),
+            name: Some(pod.clone()),
+            namespace: Some(kube.default_namespace().to_string()),
+            ..Default::default()
+        },
+    );
+
+    let event = Event {
+        type_: EventType::Warning,
+        reason: reason.to_string(),
+        note: Some(note),
+        action: "Reload".to_string(),
+        secondary: None,
+    };
+
+    if let Err(err) = recorder.publish(event).await {
+        warn!("failed to publish event for pod {pod}: {err}");
+    }
+}
+
+/// Open the audit log, exiting the process if its file cannot be opened.
+fn open_audit(sink: Option<&audit::Sink>) -> Arc<Audit> {
+    match Audit::open(sink) {
+        Ok(audit) => Arc::new(audit),
+        Err(err) => {
+            error!("failed to open audit log: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let args = config::parse(std::env::args_os().collect()).unwrap_or_else(|err| err.exit());
+    if let Some(Err(err)) = args.command.sync().map(SyncArgs::validate) {
+        Args::command()
+            .error(clap::error::ErrorKind::ValueValidation, err)
+            .exit();
+    }
+    args.log_format.init();
+
+    let runtime = match args.runtime.build(args.worker_threads) {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            error!("failed to start the {:?} runtime: {err}", args.runtime);
+            std::process::exit(1);
+        }
+    };
+
+    runtime.block_on(run(args.command));
+}
+
+async fn run(command: Command) {
+    match command {
+        Command::Reconcile {
+            cloudflare,
+            sync,
+            leader_election,
+            requeue_time_secs,
+            health_address,
+            stall_timeout_secs,
+            stats_refresh_secs,
+            max_backoff_secs,
+            requeue_jitter_percent,
+            reconcile_records,
+            record_cache_secs,
+            dry_run,
+            config_reload_secs,
+            audit_log,
+        } => {
+            let audit = open_audit(audit_log.as_ref());
+            let health = Health::new(Duration::from_secs(stall_timeout_secs));
+            tokio::spawn(health.clone().serve(health_address));
+
+            let client = KubeClient::try_default().await.unwrap();
+
+            let accounts = Arc::new(cloudflare.connect(client.clone()).await);
+            health.ready(accounts.clone());
+
+            let mut leader = leader_election.start(&client, &sync.controller_name, health.clone());
+
+            let (sync_tx, mut sync_rx) = tokio::sync::watch::channel(sync);
+            if let Some(reload) = config::Reload::new(std::env::args_os().collect())
+                .filter(|_| config_reload_secs > 0)
+            {
+                tokio::spawn(reload_sync_args(
+                    client.clone(),
+                    reload,
+                    Duration::from_secs(config_reload_secs),
+                    sync_tx,
+                ));
+            }
+
+            // Kept across restarts of the controllers, so reloading the options
+            // neither lists every zone again nor resets the backoff of failing zones.
+            let caches = Caches::new(Duration::from_secs(record_cache_secs));
+
+            loop {
+                let sync = sync_rx.borrow_and_update().clone();
+                let zone_watcher_config = sync.zone_watcher_config();
+                let apis = sync
+                    .apis::<Zone>(&client)
+                    .into_iter()
+                    .zip(sync.apis::<Record>(&client))
+                    .zip(
+                        sync.scopes()
+                            .into_iter()
+                            .map(|namespace| namespace.map(String::from)),
+                    )
+                    .collect::<Vec<_>>();
+
+                let context = Arc::new(Context {
+                    kube: client.clone(),
+                    owner: sync.owner(),
+                    sync,
+                    requeue_time: std::time::Duration::from_secs(requeue_time_secs),
+                    stats_refresh_time: Duration::from_secs(stats_refresh_secs),
+                    max_backoff: Duration::from_secs(max_backoff_secs),
+                    requeue_jitter: f64::from(requeue_jitter_percent) / 100.0,
+                    caches: caches.clone(),
+                    accounts: accounts.clone(),
+                    dry_run,
+                    audit: audit.clone(),
+                });
+
+                // Resolves once this replica is no longer the leader.
+                let lost = match &mut leader {
+                    Some(leader) => {
+                        if leader.wait_for(|leading| *leading).await.is_err() {
+                            error!("leader election stopped");
+                            std::process::exit(1);
+                        }
+
+                        let mut leader = leader.clone();
+                        async move {
+                            let _ = leader.wait_for(|leading| !*leading).await;
+                        }
+                        .boxed()
+                    }
+                    None => futures::future::pending().boxed(),
+                };
+
+                // Resolves once the sync options have been reloaded.
+                let mut reloaded = sync_rx.clone();
+                let reloaded = async move {
+                    if reloaded.changed().await.is_err() {
+                        futures::future::pending::<()>().await;
+                    }
+                };
+
+                // The controllers are stopped either way, and restarted as needed.
+                let lost = futures::future::select(lost, reloaded.boxed())
+                    .map(drop)
+                    .shared();
+
+                // One controller per namespace, since controllers watch a single api.
+                let zone_controllers = apis.iter().cloned().map(|((zones, records), namespace)| {
+                    // Only changes affecting the sync trigger a reconciliation,
+                    // rather than every update, such as of the sync status.
+                    let (reader, writer) = reflector::store();
+                    let zones = watcher(zones, zone_watcher_config.clone())
+                        .default_backoff()
+                        .reflect(writer)
+                        .applied_objects()
+                        .predicate_filter(predicates::zone_inputs);
+                    let records = watcher(records, watcher::Config::default())
+                        .default_backoff()
+                        .applied_objects()
+                        .predicate_filter(predicates::record_inputs);
+
+                    Controller::for_stream(zones, reader)
+                        .reconcile_on(found_zones(context.clone(), namespace))
+                        .reconcile_all_on(context.accounts.zone_configs().changes())
+                        // Changes to the annotations of records do not change the
+                        // entries of their zone, so watch them separately.
+                        .watches_stream(records, |record: Record| {
+                            parent_zone(&record)
+                                .map(|(name, namespace)| ObjectRef::new(name).within(namespace))
+                        })
+                        .shutdown_on_signal()
+                        .graceful_shutdown_on(lost.clone())
+                        .run(reconcile, error_policy, context.clone())
+                        .map(|res| reconciled(&health, res))
+                        .boxed()
+                });
+
+                let record_controllers =
+                    apis.iter()
+                        .filter(|_| reconcile_records)
+                        .cloned()
+                        .map(|((_, records), _)| {
+                            Controller::new(records, watcher::Config::default())
+                                .shutdown_on_signal()
+                                .graceful_shutdown_on(lost.clone())
+                                .run(reconcile_record, record_error_policy, context.clone())
+                                .map(|res| reconciled(&health, res))
+                                .boxed()
+                        });
+
+                futures::stream::select_all(zone_controllers.chain(record_controllers))
+                    .for_each(|()| async {})
+                    .await;
+
+                if sync_rx.has_changed().unwrap_or(false) {
+                    info!("restarting controllers with the reloaded configuration");
+                    continue;
+                }
+
+                // The controllers stopped because of a signal, rather than
+                // because leadership was lost.
+                if leader.as_ref().map_or(true, |leader| *leader.borrow()) {
+                    break;
+                }
+
+                info!("stopped reconciling zones until leadership is regained");
+            }
+        }
+        Command::Plan { cloudflare, sync } => {
+            let client = KubeClient::try_default().await.unwrap();
+
+            let accounts = cloudflare.connect(client.clone()).await;
+            let context = Context::detached(client, sync, accounts, true, Arc::default());
+
+            match print_plan(&context).await {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    error!("failed to plan changes: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::SyncOnce {
+            cloudflare,
+            sync,
+            dry_run,
+            audit_log,
+        } => {
+            let client = KubeClient::try_default().await.unwrap();
+            let audit = open_audit(audit_log.as_ref());
+
+            let accounts = cloudflare.connect(client.clone()).await;
+            let context = Context::detached(client, sync, accounts, dry_run, audit);
+
+            match sync_once(Arc::new(context)).await {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    error!("failed to list zones: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Cleanup {
+            cloudflare,
+            sync,
+            dry_run,
+            audit_log,
+        } => {
+            let client = KubeClient::try_default().await.unwrap();
+            let audit = open_audit(audit_log.as_ref());
+
+            let sync = sync.for_cleanup();
+
+            let accounts = cloudflare.connect(client.clone()).await;
+            let context = Context::detached(client, sync, accounts, dry_run, audit);
+
+            if !cleanup_all(&context).await {
+                std::process::exit(1);
+            }
+        }
+        Command::Verify { cloudflare, sync } => {
+            let client = KubeClient::try_default().await.unwrap();
+            let accounts = cloudflare.connect(client.clone()).await;
+
+            if !verify(client, &accounts, &sync).await {
+                std::process::exit(1);
+            }
+        }
+        Command::Doctor {
+            cloudflare,
+            sync,
+            leader_election,
+        } => {
+            let client = KubeClient::try_default().await.unwrap();
+            let mut report = doctor::Report::default();
+
+            println!("kubernetes:");
+            doctor::check_kube(
+                &mut report,
+                &client,
+                &doctor::permissions(&sync, &cloudflare),
+            )
+            .await;
+            if leader_election.leader_election {
+                let (namespace, name) = leader_election.lease(&client, &sync.controller_name);
+                doctor::check_lease(&mut report, &client, namespace, &name).await;
+            }
+
+            println!("\ncloudflare:");
+            let accounts = cloudflare.connect(client.clone()).await;
+            doctor::check_credentials(&mut report, &accounts).await;
+
+            println!("\nzones:");
+            doctor::check_zones(&mut report, &client, &accounts, &sync).await;
+
+            println!("\n{report}");
+            if !report.is_healthy() {
+                std::process::exit(1);
+            }
+        }
+        Command::List { cloudflare, sync } => {
+            let client = KubeClient::try_default().await.unwrap();
+            let accounts = cloudflare.connect(client.clone()).await;
+            let context = Context::detached(client, sync, accounts, true, Arc::default());
+
+            if let Err(err) = list(&context).await {
+                error!("failed to list zones: {err}");
+                std::process::exit(1);
+            }
+        }
+        Command::Export {
+            cloudflare,
+            sync,
+            all,
+            output_dir,
+        } => {
+            let client = KubeClient::try_default().await.unwrap();
+            let accounts = cloudflare.connect(client.clone()).await;
+
+            if let Err(err) = export(client, &accounts, &sync, all, output_dir).await {
+                error!("failed to export zones: {err}");
+                std::process::exit(1);
+            }
+        }
+        Command::Import {
+            cloudflare,
+            zone,
+            namespace,
+            apply,
+        } => {
+            let client = KubeClient::try_default().await.unwrap();
+            let accounts = cloudflare.connect(client.clone()).await;
+
+            if let Err(err) = import_zone(client, &accounts, &zone, &namespace, apply).await {
+                error!("failed to import {zone}: {err}");
+                std::process::exit(1);
+            }
+        }
+        Command::Webhook {
+            sync,
+            webhook_address,
+            tls_cert_file,
+            tls_key_file,
+            warn_only,
+        } => {
+            let client = KubeClient::try_default().await.unwrap();
+            let sync = Arc::new(sync);
+
+            let review = move |review| {
+                let (client, sync) = (client.clone(), sync.clone());
+                async move { review_admission(&client, &sync, warn_only, review).await }
+            };
+
+            if let Err(err) =
+                webhook::serve(webhook_address, tls_cert_file, tls_key_file, review).await
+            {
+                error!("failed to serve admission webhook: {err}");
+                std::process::exit(1);
+            }
+        }
+        Command::DumpCrds => {
+            print!(
+                "---\n{}",
+                serde_yaml::to_string(&CloudflareCredential::crd()).unwrap()
+            );
+            print!(
+                "---\n{}",
+                serde_yaml::to_string(&CloudflareZoneConfig::crd()).unwrap()
+            );
+            print!(
+                "---\n{}",
+                serde_yaml::to_string(&CloudflareChangeSet::crd()).unwrap()
+            );
+        }
+    };
+}
+
+/// Context of the `sync-once` or `cleanup` command with the arguments,
+/// against the fake cloudflare api.
+///
+/// The kubernetes api is unreachable, so anything depending on it fails.
+#[cfg(test)]
+async fn test_context(
+    mock: &cloudflare::mock::MockCloudFlare,
+    command: &str,
+    args: &[&str],
+) -> Context {
+    let args = Args::try_parse_from(
+        [
+            "kubizone-cloudflare",
+            command,
+            "--cf-api-key",
+            "token",
+            "--cf-api-url",
+            mock.url(),
+        ]
+        .iter()
+        .chain(args),
+    )
+    .unwrap();
+    let (cloudflare, sync, dry_run) = match args.command {
+        Command::SyncOnce {
+            cloudflare,
+            sync,
+            dry_run,
+            ..
+        } => (cloudflare, sync, dry_run),
+        Command::Cleanup {
+            cloudflare,
+            sync,
+            dry_run,
+            ..
+        } => (cloudflare, sync.for_cleanup(), dry_run),
+        command => unreachable!("no context for {command:?}"),
+    };
+
+    let kube =
+        KubeClient::try_from(kube::Config::new("http://127.0.0.1:9".parse().unwrap())).unwrap();
+
+    let accounts = cloudflare.connect(kube.clone()).await;
+    Context::detached(kube, sync, accounts, dry_run, Arc::default())
+}
+
+/// Zone named `kubi-zone` for `kubi.zone.`, which is being deleted.
+#[cfg(test)]
+fn deleted_zone(annotations: serde_json::Value) -> Zone {
+    serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {
+            "name": "kubi-zone",
+            "namespace": "dns",
+            "annotations": annotations,
+            "finalizers": [finalizer_name(DEFAULT_CONTROLLER_NAME)],
+            "deletionTimestamp": "2026-10-14T12:00:00Z"
+        },
+        "spec": {"domainName": "kubi.zone.", "delegations": []},
+        "status": {"fqdn": "kubi.zone.", "entries": []}
+    }))
+    .unwrap()
+}
+
+/// Fake cloudflare api serving the zone `kubi.zone`, with records of the
+/// controller given as name, type and content.
+///
+/// Returns the ids of the zone and of the records, in the order given.
+#[cfg(test)]
+async fn test_zone(
+    records: &[(&str, &str, &str)],
+) -> (cloudflare::mock::MockCloudFlare, ZoneId, Vec<String>) {
+    let mock = cloudflare::mock::MockCloudFlare::start().await;
+    let zone_id = mock.add_zone("kubi.zone");
+    let ids = records
+        .iter()
+        .map(|(name, type_, content)| {
+            mock.add_record(
+                &zone_id,
+                serde_json::json!({"name": name, "type": type_, "content": content, "ttl": 300, "comment": "managed-by:kubizone-cloudflare"}),
+            )
+        })
+        .collect();
+
+    (mock, zone_id, ids)
+}
+
+/// Names and types of the records left in the zone, sorted.
+#[cfg(test)]
+fn remaining_records(mock: &cloudflare::mock::MockCloudFlare, zone_id: &ZoneId) -> Vec<String> {
+    let mut remaining = mock
+        .records(zone_id)
+        .iter()
+        .map(|record| {
+            format!(
+                "{} {}",
+                record["name"].as_str().unwrap(),
+                record["type"].as_str().unwrap()
+            )
+        })
+        .collect::<Vec<_>>();
+    remaining.sort();
+    remaining
+}
+
+/// Bodies of the batches of record changes sent for the zone.
+#[cfg(test)]
+fn batches(mock: &cloudflare::mock::MockCloudFlare, zone_id: &ZoneId) -> Vec<serde_json::Value> {
+    mock.bodies(&format!("POST /zones/{zone_id}/dns_records/batch"))
+}
+
+/// Clean up the records of the deleted zone in its cloudflare zone.
+#[cfg(test)]
+async fn cleanup_deleted_zone(ctx: &Context, zone: &Zone, protected: &Protected) {
+    let (cloudflare, cloudflare_zone) = ctx
+        .find_cloudflare_zone(zone.fqdn().unwrap())
+        .await
+        .unwrap();
+
+    cleanup_zone(
+        ctx,
+        &cloudflare,
+        &cloudflare_zone,
+        Some(zone),
+        protected,
+        &[],
+    )
+    .await
+    .unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn paused_cleanup() {
+    let (mock, zone_id, _) = test_zone(&[("www.kubi.zone", "A", "127.0.0.1")]).await;
+
+    let ctx = test_context(
+        &mock,
+        "sync-once",
+        &["--mode", "delete", "--allow-zone-deletion"],
+    )
+    .await;
+    let zone = deleted_zone(serde_json::json!({
+        annotations::paused_annotation(): "true",
+        annotations::delete_zone_annotation(): "true",
+    }));
+
+    let requests = mock.requests().len();
+    assert!(matches!(
+        cleanup(Arc::new(zone), &ctx).await,
+        Err(Error::Paused)
+    ));
+
+    // Neither the records nor the zone have been touched.
+    assert_eq!(mock.requests().len(), requests);
+    assert_eq!(remaining_records(&mock, &zone_id), vec!["www.kubi.zone A"]);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn protected_cleanup() {
+    let (mock, zone_id, ids) = test_zone(&[
+        ("www.kubi.zone", "A", "127.0.0.1"),
+        ("api.kubi.zone", "A", "127.0.0.1"),
+        ("old.kubi.zone", "A", "127.0.0.1"),
+    ])
+    .await;
+
+    let ctx = test_context(&mock, "sync-once", &[]).await;
+    let zone = deleted_zone(serde_json::json!({
+        protection::protected_annotation(): "www.kubi.zone. A",
+    }));
+    let protected = Protected::new(&zone, &[], &["old.kubi.zone. A 127.0.0.1".to_string()]);
+    cleanup_deleted_zone(&ctx, &zone, &protected).await;
+
+    assert_eq!(
+        batches(&mock, &zone_id),
+        [serde_json::json!({"deletes": [{"id": ids[1]}]})]
+    );
+    assert_eq!(
+        remaining_records(&mock, &zone_id),
+        vec!["old.kubi.zone A", "www.kubi.zone A"]
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn cleanup_record_types() {
+    let (mock, zone_id, ids) = test_zone(&[
+        ("kubi.zone", "A", "127.0.0.1"),
+        ("kubi.zone", "MX", "10 mail.kubi.zone"),
+        ("kubi.zone", "TXT", "hello"),
+        ("kubi.zone", "CAA", "0 issue \"letsencrypt.org\""),
+    ])
+    .await;
+
+    let ctx = test_context(
+        &mock,
+        "sync-once",
+        &[
+            "--record-types",
+            "A,MX,TXT",
+            "--exclude-record-types",
+            "MX",
+            "--skip-types",
+            "TXT",
+        ],
+    )
+    .await;
+    cleanup_deleted_zone(
+        &ctx,
+        &deleted_zone(serde_json::json!({})),
+        &Protected::default(),
+    )
+    .await;
+
+    assert_eq!(
+        batches(&mock, &zone_id),
+        [serde_json::json!({"deletes": [{"id": ids[0]}]})]
+    );
+    assert_eq!(
+        remaining_records(&mock, &zone_id),
+        vec!["kubi.zone CAA", "kubi.zone MX", "kubi.zone TXT"]
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn cleanup_skips_ns_records() {
+    let (mock, zone_id, ids) = test_zone(&[
+        ("www.kubi.zone", "A", "127.0.0.1"),
+        ("kubi.zone", "NS", "ns1.kubi.zone"),
+        ("sub.kubi.zone", "NS", "ns1.sub.kubi.zone"),
+    ])
+    .await;
+
+    // NS records skipped through `--skip-types` are left alone, just like by the controller.
+    let ctx = test_context(&mock, "cleanup", &["--skip-types", "SOA,NS"]).await;
+    assert!(cleanup_all(&ctx).await);
+    assert_eq!(
+        remaining_records(&mock, &zone_id),
+        vec!["kubi.zone NS", "sub.kubi.zone NS"]
+    );
+
+    // Delegations are cleaned up by default, but the apex NS records only
+    // with `--delete-apex-ns`.
+    let ctx = test_context(&mock, "cleanup", &[]).await;
+    assert!(cleanup_all(&ctx).await);
+    assert_eq!(remaining_records(&mock, &zone_id), vec!["kubi.zone NS"]);
+    assert_eq!(
+        batches(&mock, &zone_id),
+        [
+            serde_json::json!({"deletes": [{"id": ids[0]}]}),
+            serde_json::json!({"deletes": [{"id": ids[2]}]}),
+        ]
+    );
+}
+
+/// In-memory provider serving the zone `kubi.zone`, with records given as
+/// name and the owner in their comment, if any.
+///
+/// Returns the cloudflare zone, along with the provider.
+#[cfg(test)]
+async fn fake_zone(records: &[(&str, Option<&str>)]) -> (provider::Fake, cloudflare::Zone) {
+    let fake = provider::Fake::default();
+    let zone_id = fake.add_zone("kubi.zone");
+    for (name, owner) in records {
+        fake.add_record(
+            &zone_id,
+            serde_json::json!({"name": name, "type": "A", "content": "127.0.0.1", "ttl": 300, "comment": owner.map(owner_marker)}),
+        );
+    }
+
+    let cloudflare_zone = fake.list_zones().await.unwrap().remove(0);
+    (fake, cloudflare_zone)
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn apply_changes_individually() {
+    let mock = cloudflare::mock::MockCloudFlare::start().await;
+    let ctx = test_context(&mock, "sync-once", &[]).await;
+    let (fake, cloudflare_zone) =
+        fake_zone(&[("www.kubi.zone", None), ("old.kubi.zone", Some(&ctx.owner))]).await;
+    let records = fake.records(&cloudflare_zone.id).await.unwrap();
+
+    let www = FullyQualifiedDomainName::try_from("www.kubi.zone.").unwrap();
+    let api = FullyQualifiedDomainName::try_from("api.kubi.zone.").unwrap();
+    let desired = |fqdn| Desired {
+        fqdn,
+        r#type: Type::A,
+        rdata: "127.0.0.1",
+        ttl: 300,
+        proxied: false,
+    };
+    let changes = [
+        RecordChange::Create(desired(&www)),
+        RecordChange::Create(desired(&api)),
+        RecordChange::Delete(&records[1]),
+    ];
+
+    // The batch is rejected, since the record of www already exists, so the
+    // changes are applied one by one, adopting the existing record instead.
+    let requests = fake.requests();
+    let journal = Journal::default();
+    apply_changes(&ctx, &fake, &cloudflare_zone, None, &changes, &journal)
+        .await
+        .unwrap();
+    assert_eq!(fake.requests(), requests + 6);
+
+    let mut current = fake.current(&cloudflare_zone.id);
+    current.sort_by_key(|record| record.fqdn.to_string());
+    assert_eq!(
+        current
+            .iter()
+            .map(|record| record.fqdn.to_string())
+            .collect::<Vec<_>>(),
+        ["api.kubi.zone.", "www.kubi.zone."]
+    );
+    assert!(current.iter().all(|record| record.is_marked_by(&ctx.owner)));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn cleanup_fake_zone() {
+    let mock = cloudflare::mock::MockCloudFlare::start().await;
+    let ctx = test_context(&mock, "cleanup", &[]).await;
+    let (fake, cloudflare_zone) = fake_zone(&[
+        ("www.kubi.zone", Some(&ctx.owner)),
+        ("api.kubi.zone", Some("someone-else")),
+        ("old.kubi.zone", None),
+    ])
+    .await;
+
+    cleanup_zone(
+        &ctx,
+        &fake,
+        &cloudflare_zone,
+        None,
+        &Protected::default(),
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let mut remaining = fake
+        .current(&cloudflare_zone.id)
+        .iter()
+        .map(|record| record.fqdn.to_string())
+        .collect::<Vec<_>>();
+    remaining.sort();
+    assert_eq!(remaining, ["api.kubi.zone.", "old.kubi.zone."]);
+
+    // Failed batches leave the records alone.
+    let (fake, cloudflare_zone) = fake_zone(&[("www.kubi.zone", Some(&ctx.owner))]).await;
+    fake.fail_next(cloudflare::Error::RateLimited(Duration::ZERO));
+    fake.fail_next(cloudflare::Error::RateLimited(Duration::ZERO));
+    assert!(cleanup_zone(
+        &ctx,
+        &fake,
+        &cloudflare_zone,
+        None,
+        &Protected::default(),
+        &[],
+    )
+    .await
+    .is_err());
+    assert_eq!(fake.current(&cloudflare_zone.id).len(), 1);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn dry_run_keeps_finalizers() {
+    let (mock, zone_id, _) = test_zone(&[("www.kubi.zone", "A", "127.0.0.1")]).await;
+
+    let ctx = test_context(&mock, "sync-once", &["--dry-run", "--mode", "delete"]).await;
+    let requests = mock.requests().len();
+
+    // Releasing the finalizer would fail, since the cluster is unreachable.
+    let action = reconcile(Arc::new(deleted_zone(serde_json::json!({}))), Arc::new(ctx))
+        .await
+        .unwrap();
+    assert_eq!(action, Action::await_change());
+    assert_eq!(mock.requests().len(), requests);
+    assert_eq!(remaining_records(&mock, &zone_id), vec!["www.kubi.zone A"]);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn sync_once_keeps_finalizers() {
+    let (mock, zone_id, _) = test_zone(&[("www.kubi.zone", "A", "127.0.0.1")]).await;
+    let ctx = test_context(&mock, "sync-once", &["--mode", "delete"]).await;
+
+    // Adding the finalizer would fail, since the cluster is unreachable.
+    let zone: Zone = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {"name": "kubi-zone", "namespace": "dns"},
+        "spec": {"domainName": "kubi.zone.", "delegations": []}
+    }))
+    .unwrap();
+    reconcile_once(Arc::new(zone), &ctx).await.unwrap();
+
+    // Zones being deleted are not cleaned up, nor are their finalizers released.
+    let requests = mock.requests().len();
+    let action = reconcile_once(Arc::new(deleted_zone(serde_json::json!({}))), &ctx)
+        .await
+        .unwrap();
+    assert_eq!(action, Action::await_change());
+    assert_eq!(mock.requests().len(), requests);
+    assert_eq!(remaining_records(&mock, &zone_id), vec!["www.kubi.zone A"]);
+}
+
+#[cfg(test)]
+#[test]
+fn parent_zone_label() {
+    let record = |parent: &str| -> Record {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "kubi.zone/v1alpha1",
+            "kind": "Record",
+            "metadata": {"name": "www", "namespace": "dns", "labels": {PARENT_ZONE_LABEL: parent}},
+            "spec": {"domainName": "www", "type": "A", "rdata": "192.0.2.1"}
+        }))
+        .unwrap()
+    };
+
+    assert_eq!(
+        parent_zone(&record("kubi-zone.dns")),
+        Some(("kubi-zone", "dns"))
+    );
+    assert_eq!(
+        parent_zone(&record("kubi.zone.dns")),
+        Some(("kubi.zone", "dns"))
+    );
+    assert_eq!(parent_zone(&record("kubi-zone")), None);
+}
+
+#[cfg(test)]
+#[test]
+fn validate_sync_args() {
+    let sync = |args: &[&str]| {
+        let args = Args::try_parse_from(
+            ["kubizone-cloudflare", "reconcile", "--cf-api-key", "token"]
+                .iter()
+                .chain(args),
+        )
+        .unwrap();
+        args.command.sync().unwrap().validate()
+    };
+
+    assert!(sync(&[]).is_ok());
+    assert!(sync(&["--shard-count", "3", "--shard-index", "2"]).is_ok());
+    assert_eq!(
+        sync(&["--shard-count", "3", "--shard-index", "3"]).unwrap_err(),
+        "--shard-index 3 must be below --shard-count 3"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn inspection_commands_select_zones() {
+    for command in ["verify", "list", "export"] {
+        let args = Args::try_parse_from([
+            "kubizone-cloudflare",
+            command,
+            "--cf-api-key",
+            "token",
+            "--namespaces",
+            "dns",
+            "--zone-label-selector",
+            "cloudflare=true",
+        ])
+        .unwrap();
+
+        let sync = args.command.sync().unwrap();
+        assert_eq!(sync.namespaces, ["dns"]);
+        assert_eq!(
+            sync.zone_list_params().label_selector.as_deref(),
+            Some("cloudflare=true")
+        );
+    }
 }
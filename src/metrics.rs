@@ -0,0 +1,68 @@
+use std::{
+    fmt::Write as _,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+/// Metrics of the controller, served on `/metrics` in the prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Whether this replica currently holds the leader election lease.
+    leader: AtomicBool,
+    /// Number of times this replica acquired or lost leadership.
+    leadership_transitions: AtomicU64,
+}
+
+impl Metrics {
+    /// Record whether this replica is the leader, counting a transition if it changed.
+    pub fn set_leader(&self, leader: bool) -> bool {
+        let changed = self.leader.swap(leader, Ordering::Relaxed) != leader;
+        if changed {
+            self.leadership_transitions.fetch_add(1, Ordering::Relaxed);
+        }
+
+        changed
+    }
+
+    /// Metrics in the prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        metric(
+            &mut out,
+            "kubizone_cloudflare_leader",
+            "gauge",
+            "Whether this replica is the leader, and reconciles zones.",
+            u64::from(self.leader.load(Ordering::Relaxed)),
+        );
+        metric(
+            &mut out,
+            "kubizone_cloudflare_leadership_transitions_total",
+            "counter",
+            "Number of times this replica acquired or lost leadership.",
+            self.leadership_transitions.load(Ordering::Relaxed),
+        );
+
+        out
+    }
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    // Unwrap safety: writing to a String cannot fail.
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {kind}").unwrap();
+    writeln!(out, "{name} {value}").unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn leadership_transitions() {
+    let metrics = Metrics::default();
+
+    assert!(metrics.set_leader(true));
+    assert!(!metrics.set_leader(true));
+    assert!(metrics.set_leader(false));
+
+    let rendered = metrics.render();
+    assert!(rendered.contains("\nkubizone_cloudflare_leader 0\n"));
+    assert!(rendered.contains("\nkubizone_cloudflare_leadership_transitions_total 2\n"));
+}
@@ -0,0 +1,391 @@
+use std::collections::{BTreeMap, HashSet};
+
+use clap::ValueEnum;
+use kube::ResourceExt as _;
+use kubizone_common::{Class, DomainSegment, FullyQualifiedDomainName, Type};
+use kubizone_crds::v1alpha1::{Zone, ZoneEntry};
+
+use crate::cloudflare::{Marker, Record};
+
+/// Scheme used to track which records in cloudflare are managed by the controller.
+#[derive(ValueEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ownership {
+    /// `managed-by:<controller-name>` in the comment of each record,
+    /// optionally followed by a description, see `--comment-template`.
+    #[default]
+    Comment,
+    /// `managed-by:<controller-name>` as a tag of each record.
+    ///
+    /// Tags are only available on some cloudflare plans, so in zones
+    /// rejecting them, the comment is used instead.
+    Tags,
+    /// Companion TXT records, in the format of external-dns' TXT registry.
+    ///
+    /// The record `www.example.org.` of type A is owned by whoever is named
+    /// in the TXT record `a-www.example.org.`.
+    TxtRegistry,
+}
+
+impl Ownership {
+    /// How records are marked when they are created or adopted by `owner`,
+    /// with the description added to comments.
+    pub fn marker<'a>(self, owner: &'a str, description: Option<&'a str>) -> Marker<'a> {
+        match self {
+            Ownership::Comment => Marker::Comment { owner, description },
+            Ownership::Tags => Marker::Tag(owner),
+            Ownership::TxtRegistry => Marker::Unmarked,
+        }
+    }
+}
+
+/// Owners of the records in a single cloudflare zone.
+pub struct Owners<'a> {
+    ownership: Ownership,
+    /// Name of the controller in the comment or tag marking records managed by us.
+    owner: String,
+    /// Name in markers written before the current one, which are still accepted as ours.
+    legacy_owner: Option<String>,
+    /// Names of other owners whose records we take ownership of.
+    take_over_from: Vec<String>,
+    /// Content of the registry records written by us.
+    registry_content: String,
+    /// Registry records found in the zone, keyed by the name and type they track.
+    registry: BTreeMap<(FullyQualifiedDomainName, Type), Vec<&'a str>>,
+}
+
+impl<'a> Owners<'a> {
+    pub fn new(ownership: Ownership, owner: &str, records: &'a [Record]) -> Self {
+        let mut registry = BTreeMap::<_, Vec<_>>::new();
+
+        if ownership == Ownership::TxtRegistry {
+            let tracked = records
+                .iter()
+                .map(|record| (record.fqdn.clone(), record.r#type))
+                .collect::<HashSet<_>>();
+
+            for (fqdn, r#type) in tracked {
+                let Some(name) = registry_name(&fqdn, r#type) else {
+                    continue;
+                };
+
+                let contents = records
+                    .iter()
+                    .filter(|record| record.r#type == Type::TXT && record.fqdn == name)
+                    .filter(|record| is_registry_content(&record.rdata))
+                    .map(|record| record.rdata.as_str())
+                    .collect::<Vec<_>>();
+
+                if !contents.is_empty() {
+                    registry.insert((fqdn, r#type), contents);
+                }
+            }
+        }
+
+        Owners {
+            ownership,
+            owner: owner.to_string(),
+            legacy_owner: None,
+            take_over_from: Vec::new(),
+            registry_content: registry_content(owner),
+            registry,
+        }
+    }
+
+    /// Also treat records marked as managed by `legacy_owner` as managed by us.
+    pub fn with_legacy_owner(mut self, legacy_owner: Option<&str>) -> Self {
+        self.legacy_owner = legacy_owner.map(String::from);
+        self
+    }
+
+    /// Take ownership of the records managed by any of the other owners.
+    ///
+    /// Their records are treated as managed by us, and their markers are
+    /// replaced with ours when the records are adopted.
+    pub fn with_take_over_from(mut self, owners: &[String]) -> Self {
+        self.take_over_from = owners.to_vec();
+        self
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.owner.as_str()).chain(self.legacy_owner.as_deref())
+    }
+
+    /// Returns true if the registry record content names us, or anyone we
+    /// take ownership from, as the owner.
+    fn is_own_content(&self, content: &str) -> bool {
+        self.names()
+            .chain(self.take_over_from.iter().map(String::as_str))
+            .any(|owner| same_content(content, &registry_content(owner)))
+    }
+
+    /// Returns true if the record is marked as managed by one of the owners
+    /// we take ownership from, rather than by us, so its marker must be replaced.
+    ///
+    /// Never true with TXT ownership, where we claim records by registering
+    /// them ourselves instead.
+    pub fn is_taken_over(&self, record: &Record) -> bool {
+        match self.ownership {
+            Ownership::Comment | Ownership::Tags => {
+                self.take_over_from
+                    .iter()
+                    .any(|owner| record.is_marked_by(owner))
+                    && !self.names().any(|owner| record.is_marked_by(owner))
+            }
+            Ownership::TxtRegistry => false,
+        }
+    }
+
+    /// Returns true if the record is managed by us.
+    pub fn is_managed(&self, record: &Record) -> bool {
+        match self.ownership {
+            Ownership::Comment => {
+                self.names().any(|owner| record.is_commented_by(owner))
+                    || self.is_taken_over(record)
+            }
+            Ownership::Tags => {
+                self.names().any(|owner| record.is_marked_by(owner)) || self.is_taken_over(record)
+            }
+            Ownership::TxtRegistry => {
+                self.is_own_registry(record)
+                    || self
+                        .registry
+                        .get(&(record.fqdn.clone(), record.r#type))
+                        .is_some_and(|contents| {
+                            contents.iter().any(|content| self.is_own_content(content))
+                        })
+            }
+        }
+    }
+
+    /// Returns true if the record is not claimed by anyone.
+    pub fn is_unowned(&self, record: &Record) -> bool {
+        match self.ownership {
+            Ownership::Comment | Ownership::Tags => record.managed_by().is_none(),
+            Ownership::TxtRegistry => {
+                !is_registry_content(&record.rdata)
+                    && !self
+                        .registry
+                        .contains_key(&(record.fqdn.clone(), record.r#type))
+            }
+        }
+    }
+
+    /// Returns true if the record is a registry record written by us.
+    pub fn is_own_registry(&self, record: &Record) -> bool {
+        record.r#type == Type::TXT && self.is_own_content(&record.rdata)
+    }
+
+    /// Registry records which should exist alongside the `entries`, so we own them.
+    ///
+    /// Names already claimed by someone else, or holding unowned records, are
+    /// only registered if `adopt` is set. Returns nothing unless ownership is tracked
+    /// through TXT records.
+    pub fn registry_entries<'e>(
+        &self,
+        entries: impl IntoIterator<Item = &'e ZoneEntry>,
+        records: &[Record],
+        adopt: bool,
+    ) -> Vec<ZoneEntry> {
+        if self.ownership != Ownership::TxtRegistry {
+            return Vec::new();
+        }
+
+        let mut registered = HashSet::new();
+        let mut registry = Vec::new();
+
+        for entry in entries.into_iter().filter(|entry| !entry.type_.is_soa()) {
+            let key = (entry.fqdn.clone(), entry.type_);
+            if registered.contains(&key) {
+                continue;
+            }
+
+            let Some(name) = registry_name(&entry.fqdn, entry.type_) else {
+                continue;
+            };
+
+            let claimed = self.registry.get(&key).is_some_and(|contents| {
+                contents.iter().any(|content| !self.is_own_content(content))
+            });
+
+            let unowned = !self.registry.contains_key(&key)
+                && records
+                    .iter()
+                    .any(|record| record.fqdn == entry.fqdn && record.r#type == entry.type_);
+
+            if (claimed || unowned) && !adopt {
+                continue;
+            }
+
+            registered.insert(key);
+            registry.push(ZoneEntry {
+                fqdn: name,
+                type_: Type::TXT,
+                class: Class::IN,
+                ttl: entry.ttl,
+                rdata: self.registry_content.clone(),
+            });
+        }
+
+        registry
+    }
+}
+
+/// Description of the zone's records for humans, following the ownership
+/// marker in their comments.
+///
+/// `{controller}`, `{cluster}`, `{namespace}` and `{zone}` in the template are
+/// replaced with the controller name, cluster name, and the namespace and name
+/// of the zone.
+pub fn describe(template: &str, controller: &str, cluster: Option<&str>, zone: &Zone) -> String {
+    template
+        .replace("{controller}", controller)
+        .replace("{cluster}", cluster.unwrap_or_default())
+        .replace("{namespace}", &zone.namespace().unwrap_or_default())
+        .replace("{zone}", &zone.name_any())
+}
+
+/// Name of the owner in the markers of records, which is the controller name,
+/// followed by `@<cluster-id>` if the cluster is identified.
+pub fn owner(controller_name: &str, cluster_id: Option<&str>) -> String {
+    match cluster_id {
+        Some(cluster_id) => format!("{controller_name}@{cluster_id}"),
+        None => controller_name.to_string(),
+    }
+}
+
+/// Name of the registry record tracking ownership of records with the given
+/// name and type, which is the name with the first segment prefixed by the type.
+///
+/// Returns None if the prefixed segment would not be a valid domain segment.
+fn registry_name(
+    fqdn: &FullyQualifiedDomainName,
+    r#type: Type,
+) -> Option<FullyQualifiedDomainName> {
+    let mut segments = fqdn.iter();
+    let first = segments.next()?;
+
+    let prefixed =
+        DomainSegment::try_from(format!("{}-{first}", r#type.to_string().to_lowercase())).ok()?;
+
+    Some(std::iter::once(prefixed).chain(segments.cloned()).collect())
+}
+
+/// Content of the registry records naming `owner` as the owner.
+fn registry_content(owner: &str) -> String {
+    format!("\"heritage=external-dns,external-dns/owner={owner}\"")
+}
+
+fn is_registry_content(content: &str) -> bool {
+    content
+        .trim_matches('"')
+        .starts_with("heritage=external-dns,")
+}
+
+/// Compare TXT contents, ignoring whether they are quoted.
+fn same_content(a: &str, b: &str) -> bool {
+    a.trim_matches('"') == b.trim_matches('"')
+}
+
+#[cfg(test)]
+#[test]
+fn txt_registry() {
+    let records: Vec<Record> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300},
+            {"id": "2", "name": "a-www.kubi.zone", "type": "TXT", "content": "\"heritage=external-dns,external-dns/owner=kubizone\"", "ttl": 300},
+            {"id": "3", "name": "api.kubi.zone", "type": "A", "content": "127.0.0.2", "ttl": 300},
+            {"id": "4", "name": "a-api.kubi.zone", "type": "TXT", "content": "heritage=external-dns,external-dns/owner=other", "ttl": 300},
+            {"id": "5", "name": "mail.kubi.zone", "type": "A", "content": "127.0.0.3", "ttl": 300}
+        ]"#,
+    )
+    .unwrap();
+
+    let owners = Owners::new(Ownership::TxtRegistry, "kubizone", &records);
+
+    let managed = records
+        .iter()
+        .filter(|record| owners.is_managed(record))
+        .map(|record| record.fqdn.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(managed, ["www.kubi.zone.", "a-www.kubi.zone."]);
+
+    let unowned = records
+        .iter()
+        .filter(|record| owners.is_unowned(record))
+        .map(|record| record.fqdn.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(unowned, ["mail.kubi.zone."]);
+
+    let entries: Vec<ZoneEntry> = serde_json::from_str(
+        r#"[
+            {"fqdn": "www.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.1"},
+            {"fqdn": "api.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.2"},
+            {"fqdn": "mail.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.3"},
+            {"fqdn": "new.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.4"}
+        ]"#,
+    )
+    .unwrap();
+
+    let registry = owners
+        .registry_entries(&entries, &records, false)
+        .into_iter()
+        .map(|entry| entry.fqdn.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(registry, ["a-www.kubi.zone.", "a-new.kubi.zone."]);
+}
+
+#[cfg(test)]
+#[test]
+fn described_comments() {
+    let zone: Zone = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {"name": "kubi-zone", "namespace": "dns"},
+        "spec": {"domainName": "kubi.zone.", "delegations": []}
+    }))
+    .unwrap();
+
+    let description = describe(
+        "managed by {controller} from {cluster}/{namespace}/{zone}",
+        "kubizone",
+        Some("production"),
+        &zone,
+    );
+    assert_eq!(
+        description,
+        "managed by kubizone from production/dns/kubi-zone"
+    );
+
+    let records: Vec<Record> = serde_json::from_value(serde_json::json!([
+        {"id": "1", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300, "comment": format!("managed-by:kubizone {description}")},
+        {"id": "2", "name": "api.kubi.zone", "type": "A", "content": "127.0.0.2", "ttl": 300, "comment": "managed-by:kubizone-staging"}
+    ]))
+    .unwrap();
+
+    let owners = Owners::new(Ownership::Comment, "kubizone", &records);
+    assert!(owners.is_managed(&records[0]));
+    assert!(!owners.is_managed(&records[1]));
+    assert_eq!(records[1].managed_by(), Some("kubizone-staging"));
+}
+
+#[cfg(test)]
+#[test]
+fn cluster_markers() {
+    let records: Vec<Record> = serde_json::from_value(serde_json::json!([
+        {"id": "1", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300, "comment": "managed-by:kubizone@production"},
+        {"id": "2", "name": "api.kubi.zone", "type": "A", "content": "127.0.0.2", "ttl": 300, "comment": "managed-by:kubizone@staging"},
+        {"id": "3", "name": "old.kubi.zone", "type": "A", "content": "127.0.0.3", "ttl": 300, "comment": "managed-by:kubizone"}
+    ]))
+    .unwrap();
+
+    let owner = owner("kubizone", Some("production"));
+    let owners = Owners::new(Ownership::Comment, &owner, &records);
+    assert!(owners.is_managed(&records[0]));
+    assert!(!owners.is_managed(&records[1]));
+    assert!(!owners.is_managed(&records[2]));
+
+    let owners = owners.with_legacy_owner(Some("kubizone"));
+    assert!(owners.is_managed(&records[0]));
+    assert!(!owners.is_managed(&records[1]));
+    assert!(owners.is_managed(&records[2]));
+}
@@ -0,0 +1,757 @@
+use std::{borrow::Cow, collections::HashMap, fmt::Display, sync::OnceLock};
+
+use kubizone_common::{FullyQualifiedDomainName, RecordIdent, Type};
+use kubizone_crds::v1alpha1::ZoneEntry;
+use tracing::{debug, info, trace};
+
+use crate::{
+    cloudflare::{self, Desired, Record, RecordChange},
+    ident,
+    ownership::{Owners, Ownership},
+    protection::Protected,
+    proxy::Proxied,
+};
+
+/// Entry of a zone, along with the cloudflare specific settings of its record.
+#[derive(Debug, Clone)]
+pub struct Target<'a> {
+    pub entry: Cow<'a, ZoneEntry>,
+    /// TTL of the record in cloudflare, which may differ from the entry's.
+    pub ttl: u32,
+    /// Proxy traffic for the record through cloudflare.
+    pub proxied: bool,
+}
+
+impl Target<'_> {
+    fn desired(&self) -> Desired<'_> {
+        Desired {
+            fqdn: &self.entry.fqdn,
+            r#type: self.entry.type_,
+            rdata: &self.entry.rdata,
+            ttl: self.ttl,
+            proxied: self.proxied,
+        }
+    }
+}
+
+/// Identity of an entry of a zone, see [`ident::ident`].
+pub fn entry_ident(entry: &ZoneEntry) -> RecordIdent {
+    ident::ident(&entry.fqdn, entry.type_, &entry.rdata)
+}
+
+/// Change needed to bring the records in cloudflare in line with a zone.
+#[derive(Debug)]
+pub enum PlannedChange<'a> {
+    /// Entry missing from cloudflare.
+    Create(Target<'a>),
+    /// Managed record whose ttl or proxy status differs from the entry.
+    Update(&'a Record, Target<'a>),
+    /// Unmanaged record matching the entry, which we take ownership of.
+    Adopt(&'a Record, Target<'a>),
+    /// Managed record without a corresponding entry.
+    Delete(&'a Record),
+}
+
+impl<'a> PlannedChange<'a> {
+    /// The request making this change in cloudflare.
+    pub fn change(&self) -> RecordChange<'_> {
+        match self {
+            PlannedChange::Create(target) => RecordChange::Create(target.desired()),
+            PlannedChange::Update(record, target) => RecordChange::Update(record, target.desired()),
+            PlannedChange::Adopt(record, target) => RecordChange::Adopt(record, target.desired()),
+            PlannedChange::Delete(record) => RecordChange::Delete(record),
+        }
+    }
+
+    /// Name of the action, as used in logs.
+    pub fn action(&self) -> &'static str {
+        match self {
+            PlannedChange::Create(_) => "create",
+            PlannedChange::Update(..) => "update",
+            PlannedChange::Adopt(..) => "adopt",
+            PlannedChange::Delete(_) => "delete",
+        }
+    }
+
+    /// Existing record in cloudflare being changed, if any.
+    pub fn record(&self) -> Option<&'a Record> {
+        match self {
+            PlannedChange::Create(_) => None,
+            PlannedChange::Update(record, _)
+            | PlannedChange::Adopt(record, _)
+            | PlannedChange::Delete(record) => Some(record),
+        }
+    }
+
+    /// Name of the record being changed.
+    pub fn fqdn(&self) -> &FullyQualifiedDomainName {
+        match self {
+            PlannedChange::Create(target)
+            | PlannedChange::Update(_, target)
+            | PlannedChange::Adopt(_, target) => &target.entry.fqdn,
+            PlannedChange::Delete(record) => &record.fqdn,
+        }
+    }
+
+    /// Entry the record is changed to match, unless it is being deleted.
+    pub fn entry(&self) -> Option<&ZoneEntry> {
+        match self {
+            PlannedChange::Create(target)
+            | PlannedChange::Update(_, target)
+            | PlannedChange::Adopt(_, target) => Some(&target.entry),
+            PlannedChange::Delete(_) => None,
+        }
+    }
+}
+
+/// Terraform-style description of the change, such as `+ www.example.org. 300 IN A 127.0.0.1`
+///
+/// Records proxied through cloudflare are suffixed with `(proxied)`.
+impl Display for PlannedChange<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlannedChange::Create(Target {
+                entry,
+                ttl,
+                proxied,
+            }) => write!(
+                f,
+                "+ {} {} IN {} {}{}",
+                entry.fqdn,
+                ttl,
+                entry.type_,
+                entry.rdata,
+                proxied_suffix(false, *proxied)
+            ),
+            PlannedChange::Update(
+                record,
+                Target {
+                    entry,
+                    ttl,
+                    proxied,
+                },
+            ) => write!(
+                f,
+                "~ {} {} => {} IN {} {}{}",
+                entry.fqdn,
+                record.ttl,
+                ttl,
+                entry.type_,
+                entry.rdata,
+                proxied_suffix(record.proxied, *proxied)
+            ),
+            PlannedChange::Adopt(
+                record,
+                Target {
+                    entry,
+                    ttl,
+                    proxied,
+                },
+            ) => write!(
+                f,
+                "& {} {} IN {} {}{}",
+                entry.fqdn,
+                ttl,
+                entry.type_,
+                entry.rdata,
+                proxied_suffix(record.proxied, *proxied)
+            ),
+            PlannedChange::Delete(record) => write!(
+                f,
+                "- {} {} IN {} {}",
+                record.fqdn, record.ttl, record.r#type, record.rdata
+            ),
+        }
+    }
+}
+
+fn proxied_suffix(current: bool, proxied: bool) -> &'static str {
+    match (current, proxied) {
+        (_, true) => " (proxied)",
+        (true, false) => " (no longer proxied)",
+        (false, false) => "",
+    }
+}
+
+/// Returns true if the record is an NS record at the apex of the zone,
+/// delegating it to cloudflare.
+pub fn is_apex_ns(record: &Record, apex: &FullyQualifiedDomainName) -> bool {
+    record.r#type == Type::NS && &record.fqdn == apex
+}
+
+/// Rules determining which changes are planned.
+#[derive(Debug, Clone, Copy)]
+pub struct Options<'a> {
+    /// Name of the owner whose records may be changed, see [`crate::ownership::owner`].
+    pub owner: &'a str,
+    /// Name of the owner in legacy markers, whose records may be changed as well.
+    pub legacy_owner: Option<&'a str>,
+    /// Names of other owners, whose records are adopted and then changed like our own.
+    pub take_over_from: &'a [String],
+    /// Delete managed records without a corresponding entry.
+    pub delete: bool,
+    /// Apex of the cloudflare zone.
+    pub apex: &'a FullyQualifiedDomainName,
+    /// Delete NS records at the apex, which are otherwise kept, even in delete mode.
+    pub delete_apex_ns: bool,
+    /// Take ownership of unmanaged records matching an entry.
+    pub adopt: bool,
+    /// How ownership of records is tracked.
+    pub ownership: Ownership,
+    /// Which records are proxied through cloudflare.
+    pub proxied: &'a Proxied,
+    /// Records which must never be changed or deleted.
+    pub protected: &'a Protected,
+    /// Lowest TTL of the records, entries with lower TTLs are raised to it.
+    pub min_ttl: Option<u32>,
+    /// Record types which are managed, or all types if empty.
+    pub types: &'a [Type],
+    /// Record types which are left alone, both in the zone and in cloudflare.
+    pub exclude_types: &'a [Type],
+    /// Types of zone entries which are not synced, in addition to SOA records.
+    /// Their records in cloudflare are left alone.
+    pub skip_types: &'a [Type],
+}
+
+/// Defaults of the controller: upserting the records of the controller with
+/// the default name, tracked in comments, at the root with nothing proxied
+/// or protected.
+impl Default for Options<'_> {
+    fn default() -> Self {
+        static APEX: OnceLock<FullyQualifiedDomainName> = OnceLock::new();
+        static PROXIED: OnceLock<Proxied> = OnceLock::new();
+        static PROTECTED: OnceLock<Protected> = OnceLock::new();
+
+        Options {
+            owner: crate::DEFAULT_CONTROLLER_NAME,
+            legacy_owner: None,
+            take_over_from: &[],
+            delete: false,
+            apex: APEX.get_or_init(Default::default),
+            delete_apex_ns: false,
+            adopt: false,
+            ownership: Ownership::Comment,
+            proxied: PROXIED.get_or_init(Default::default),
+            protected: PROTECTED.get_or_init(Default::default),
+            min_ttl: None,
+            types: &[],
+            exclude_types: &[],
+            skip_types: &[],
+        }
+    }
+}
+
+#[cfg(test)]
+impl<'a> Options<'a> {
+    /// Options of the tests, deleting the records of the `kubizone` owner in
+    /// the zone at `apex`.
+    pub fn test(apex: &'a FullyQualifiedDomainName) -> Self {
+        Options {
+            owner: "kubizone",
+            delete: true,
+            apex,
+            ..Default::default()
+        }
+    }
+}
+
+impl Options<'_> {
+    /// Returns true if records of the type are managed by the controller.
+    ///
+    /// SOA records never are, since cloudflare manages the SOA record of its zones.
+    pub fn manages(&self, type_: Type) -> bool {
+        manages(type_, self.types, self.exclude_types, self.skip_types)
+    }
+}
+
+/// Returns true if records of the type are managed, given the `types`,
+/// `exclude_types` and `skip_types` of [`Options`].
+pub fn manages(type_: Type, types: &[Type], exclude_types: &[Type], skip_types: &[Type]) -> bool {
+    !type_.is_soa()
+        && !skip_types.contains(&type_)
+        && (types.is_empty() || types.contains(&type_))
+        && !exclude_types.contains(&type_)
+}
+
+/// Changes needed to bring the records in a cloudflare zone in line with the
+/// entries of a kubizone zone.
+#[derive(Debug)]
+pub struct Plan<'a> {
+    pub changes: Vec<PlannedChange<'a>>,
+    /// Changes which are not made, since they would change protected records.
+    pub protected: Vec<PlannedChange<'a>>,
+    /// Records in cloudflare managed by this controller.
+    pub managed: usize,
+    /// Records in cloudflare managed by someone else.
+    pub unmanaged: usize,
+}
+
+impl<'a> Plan<'a> {
+    /// Compute the changes to the `records` in cloudflare needed to match the
+    /// `entries` of a zone.
+    ///
+    /// Only records managed by the controller are ever changed or deleted,
+    /// and unmanaged records are only adopted, if enabled in the `options`.
+    ///
+    /// If ownership is tracked through TXT records, the registry records
+    /// for the entries are planned like any other entry.
+    ///
+    /// Cloudflare permits several identical records, of which only one is
+    /// kept for each entry. The managed duplicates are deleted like any other
+    /// managed record without an entry.
+    ///
+    /// Entries and records of types which are not managed are left out entirely, other
+    /// than the registry records tracking ownership.
+    pub fn new(entries: &'a [ZoneEntry], records: &'a [Record], options: &Options) -> Self {
+        let owners = Owners::new(options.ownership, options.owner, records)
+            .with_legacy_owner(options.legacy_owner)
+            .with_take_over_from(options.take_over_from);
+
+        let entries = entries
+            .iter()
+            .filter(|entry| options.manages(entry.type_))
+            .collect::<Vec<_>>();
+        let registry = owners.registry_entries(entries.iter().copied(), records, options.adopt);
+
+        let records = records
+            .iter()
+            .filter(|record| options.manages(record.r#type) || owners.is_own_registry(record))
+            .collect::<Vec<_>>();
+
+        let managed = records
+            .iter()
+            .filter(|record| owners.is_managed(record))
+            .count();
+        let unmanaged = records.len() - managed;
+
+        // Collect all existing records in (RecordIdent, [Record]) map.
+        let mut records_by_ident = HashMap::<_, Vec<_>>::new();
+        for record in records {
+            records_by_ident
+                .entry(RecordIdent::from(record))
+                .or_default()
+                .push(record);
+        }
+        let records = records_by_ident;
+
+        // Collect all desired entries in (RecordIdent, ZoneEntry) map.
+        let entries = entries
+            .into_iter()
+            .map(Cow::Borrowed)
+            .chain(registry.into_iter().map(Cow::Owned))
+            .map(|entry| (entry_ident(&entry), entry))
+            .collect::<HashMap<_, _>>();
+
+        let mut changes = Vec::new();
+
+        // Create missing entries, and update records (that we manage) with new information
+        for (ident, entry) in &entries {
+            let proxied = options.proxied.is_proxied(entry);
+            let target = Target {
+                entry: entry.clone(),
+                ttl: cloudflare::ttl(entry.ttl.max(options.min_ttl.unwrap_or_default()), proxied),
+                proxied,
+            };
+
+            let Some(matching) = records.get(ident) else {
+                changes.push(PlannedChange::Create(target));
+                continue;
+            };
+
+            // Keep a managed record if there is one, so the duplicates are unmanaged if possible.
+            let record = matching
+                .iter()
+                .find(|record| owners.is_managed(record))
+                .unwrap_or(&matching[0]);
+
+            for duplicate in matching
+                .iter()
+                .filter(|duplicate| duplicate.id != record.id && owners.is_managed(duplicate))
+            {
+                if options.delete {
+                    changes.push(PlannedChange::Delete(duplicate));
+                } else {
+                    info!("not deleting duplicate of {ident:?}, since controller is running in 'upsert' mode");
+                }
+            }
+
+            // With TXT ownership, records are adopted by creating their registry records.
+            if (options.adopt
+                && options.ownership != Ownership::TxtRegistry
+                && owners.is_unowned(record))
+                || owners.is_taken_over(record)
+            {
+                changes.push(PlannedChange::Adopt(record, target));
+                continue;
+            }
+
+            if !owners.is_managed(record) {
+                info!("entry {ident:?} appears in zone, but the corresponding record in cloudflare is not managed by us");
+                continue;
+            }
+
+            // Matching identities means the rdata only differs in spelling, if at all.
+            if target.proxied == record.proxied && target.ttl == record.ttl {
+                trace!("record {ident:?} already up to date");
+                continue;
+            }
+
+            changes.push(PlannedChange::Update(record, target));
+        }
+
+        // Delete unexpected records (that we manage)
+        for (ident, unexpected_record) in records
+            .iter()
+            .filter(|(ident, _)| !entries.contains_key(ident))
+            .flat_map(|(ident, records)| records.iter().map(move |record| (ident, record)))
+        {
+            if !owners.is_managed(unexpected_record) {
+                debug!("unexpected record {ident:?} has no corresponding entry in zone, but record is not managed by us.");
+                continue;
+            }
+
+            if options.delete
+                && !options.delete_apex_ns
+                && is_apex_ns(unexpected_record, options.apex)
+            {
+                info!("not deleting {ident:?}, since it delegates the zone to cloudflare");
+            } else if options.delete {
+                changes.push(PlannedChange::Delete(unexpected_record));
+            } else {
+                info!("not deleting {ident:?}, since controller is running in 'upsert' mode");
+            }
+        }
+
+        let (protected, changes) = changes.into_iter().partition(|change| {
+            change
+                .record()
+                .is_some_and(|record| options.protected.is_protected(record))
+        });
+
+        Plan {
+            changes,
+            protected,
+            managed,
+            unmanaged,
+        }
+    }
+
+    /// Number of changes with the given action.
+    pub fn count(&self, action: &str) -> usize {
+        self.changes
+            .iter()
+            .filter(|change| change.action() == action)
+            .count()
+    }
+
+    /// Entries of the records written by the changes, whose TTL is raised to
+    /// the minimum of the `options`.
+    pub fn raised_ttls(&self, options: &Options) -> Vec<&ZoneEntry> {
+        let Some(min_ttl) = options.min_ttl else {
+            return Vec::new();
+        };
+
+        self.changes
+            .iter()
+            .filter_map(PlannedChange::entry)
+            .filter(|entry| options.manages(entry.type_) && entry.ttl < min_ttl)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn plan_changes() {
+    let entries: Vec<ZoneEntry> = serde_json::from_str(
+        r#"[
+            {"fqdn": "www.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.1"},
+            {"fqdn": "api.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.2"},
+            {"fqdn": "mail.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.3"}
+        ]"#,
+    )
+    .unwrap();
+
+    let records: Vec<Record> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "api.kubi.zone", "type": "A", "content": "127.0.0.2", "ttl": 60, "comment": "managed-by:kubizone"},
+            {"id": "2", "name": "mail.kubi.zone", "type": "A", "content": "127.0.0.3", "ttl": 60},
+            {"id": "3", "name": "old.kubi.zone", "type": "A", "content": "127.0.0.4", "ttl": 60, "comment": "managed-by:kubizone"},
+            {"id": "4", "name": "other.kubi.zone", "type": "A", "content": "127.0.0.5", "ttl": 60}
+        ]"#,
+    )
+    .unwrap();
+
+    let apex = FullyQualifiedDomainName::try_from("kubi.zone.").unwrap();
+    let options = Options::test(&apex);
+
+    let plan = Plan::new(&entries, &records, &options);
+    assert_eq!(plan.managed, 2);
+    assert_eq!(plan.unmanaged, 2);
+    assert_eq!(plan.count("create"), 1);
+    assert_eq!(plan.count("update"), 1);
+    assert_eq!(plan.count("delete"), 1);
+
+    let mut changes = plan
+        .changes
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    changes.sort();
+    assert_eq!(
+        changes,
+        [
+            "+ www.kubi.zone. 300 IN A 127.0.0.1",
+            "- old.kubi.zone. 60 IN A 127.0.0.4",
+            "~ api.kubi.zone. 60 => 300 IN A 127.0.0.2",
+        ]
+    );
+
+    let upsert = Plan::new(
+        &entries,
+        &records,
+        &Options {
+            delete: false,
+            ..options
+        },
+    );
+    assert_eq!(upsert.count("delete"), 0);
+
+    let adopt = Plan::new(
+        &entries,
+        &records,
+        &Options {
+            adopt: true,
+            ..options
+        },
+    );
+    assert_eq!(adopt.count("adopt"), 1);
+    assert_eq!(adopt.count("create"), 1);
+
+    let duplicates: Vec<Record> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300},
+            {"id": "2", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300, "comment": "managed-by:kubizone"},
+            {"id": "3", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300, "comment": "managed-by:kubizone"}
+        ]"#,
+    )
+    .unwrap();
+
+    let excluded = Plan::new(
+        &entries,
+        &records,
+        &Options {
+            exclude_types: &[Type::A],
+            ..options
+        },
+    );
+    assert!(excluded.changes.is_empty());
+    assert_eq!(excluded.managed + excluded.unmanaged, 0);
+
+    let restricted = Plan::new(
+        &entries,
+        &records,
+        &Options {
+            types: &[Type::AAAA],
+            ..options
+        },
+    );
+    assert!(restricted.changes.is_empty());
+
+    let plan = Plan::new(&entries[..1], &duplicates, &options);
+    assert_eq!(plan.managed, 2);
+    assert_eq!(plan.unmanaged, 1);
+    assert_eq!(
+        plan.changes
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        ["- www.kubi.zone. 300 IN A 127.0.0.1"]
+    );
+
+    let nameservers: Vec<Record> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "kubi.zone", "type": "NS", "content": "ns1.kubi.zone", "ttl": 300, "comment": "managed-by:kubizone"}
+        ]"#,
+    )
+    .unwrap();
+
+    let plan = Plan::new(&[], &nameservers, &options);
+    assert!(plan.changes.is_empty());
+
+    let plan = Plan::new(
+        &[],
+        &nameservers,
+        &Options {
+            delete_apex_ns: true,
+            ..options
+        },
+    );
+    assert_eq!(plan.count("delete"), 1);
+}
+
+#[cfg(test)]
+#[test]
+fn raised_ttls() {
+    let entries: Vec<ZoneEntry> = serde_json::from_str(
+        r#"[
+            {"fqdn": "www.kubi.zone.", "type": "A", "class": "IN", "ttl": 30, "rdata": "127.0.0.1"},
+            {"fqdn": "api.kubi.zone.", "type": "A", "class": "IN", "ttl": 30, "rdata": "127.0.0.2"},
+            {"fqdn": "kubi.zone.", "type": "MX", "class": "IN", "ttl": 30, "rdata": "10 mail.kubi.zone."}
+        ]"#,
+    )
+    .unwrap();
+
+    // Already synced with the raised ttl.
+    let records: Vec<Record> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "api.kubi.zone", "type": "A", "content": "127.0.0.2", "ttl": 300, "comment": "managed-by:kubizone"}
+        ]"#,
+    )
+    .unwrap();
+
+    let apex = FullyQualifiedDomainName::try_from("kubi.zone.").unwrap();
+    let options = Options {
+        min_ttl: Some(300),
+        skip_types: &[Type::MX],
+        ..Options::test(&apex)
+    };
+
+    let plan = Plan::new(&entries, &records, &options);
+    let raised = plan
+        .raised_ttls(&options)
+        .into_iter()
+        .map(|entry| entry.fqdn.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(raised, vec!["www.kubi.zone."]);
+
+    let options = Options {
+        min_ttl: None,
+        ..options
+    };
+    let plan = Plan::new(&entries, &records, &options);
+    assert!(plan.raised_ttls(&options).is_empty());
+}
+
+#[cfg(test)]
+#[test]
+fn take_over_records() {
+    let entries: Vec<ZoneEntry> = serde_json::from_str(
+        r#"[
+            {"fqdn": "www.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.1"},
+            {"fqdn": "api.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.2"}
+        ]"#,
+    )
+    .unwrap();
+
+    let records: Vec<Record> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300, "comment": "managed-by:old-kubizone"},
+            {"id": "2", "name": "api.kubi.zone", "type": "A", "content": "127.0.0.2", "ttl": 300, "comment": "managed-by:external-dns"},
+            {"id": "3", "name": "old.kubi.zone", "type": "A", "content": "127.0.0.3", "ttl": 300, "comment": "managed-by:old-kubizone"}
+        ]"#,
+    )
+    .unwrap();
+
+    let apex = FullyQualifiedDomainName::try_from("kubi.zone.").unwrap();
+    let take_over_from = ["old-kubizone".to_string()];
+    let options = Options {
+        adopt: true,
+        ..Options::test(&apex)
+    };
+
+    // Records of other owners are left alone, even when adopting records.
+    let plan = Plan::new(&entries, &records, &options);
+    assert!(plan.changes.is_empty());
+
+    let plan = Plan::new(
+        &entries,
+        &records,
+        &Options {
+            take_over_from: &take_over_from,
+            ..options
+        },
+    );
+    let mut changes = plan
+        .changes
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    changes.sort();
+    assert_eq!(
+        changes,
+        [
+            "& www.kubi.zone. 300 IN A 127.0.0.1",
+            "- old.kubi.zone. 300 IN A 127.0.0.3",
+        ]
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn sync_against_api() {
+    use cloudflare::{mock::MockCloudFlare, Marker};
+
+    let mock = MockCloudFlare::start().await;
+    let zone_id = mock.add_zone("kubi.zone");
+    mock.add_record(
+        &zone_id,
+        serde_json::json!({"name": "other.kubi.zone", "type": "A", "content": "127.0.0.9", "ttl": 60}),
+    );
+    let cloudflare = mock.client().with_records_per_page(2);
+
+    let apex = FullyQualifiedDomainName::try_from("kubi.zone.").unwrap();
+    let options = Options::test(&apex);
+
+    // Applies the plan for the entries in a single batch, returning the number of
+    // changes made, and makes sure nothing is left to change afterwards.
+    let sync = |entries: &'static str| {
+        let (cloudflare, zone_id) = (&cloudflare, &zone_id);
+        async move {
+            let entries: Vec<ZoneEntry> = serde_json::from_str(entries).unwrap();
+            let marker = Marker::Comment {
+                owner: "kubizone",
+                description: None,
+            };
+
+            let records = cloudflare.records(zone_id).await.unwrap();
+            let plan = Plan::new(&entries, &records, &options);
+            let changes = plan
+                .changes
+                .iter()
+                .map(PlannedChange::change)
+                .collect::<Vec<_>>();
+            cloudflare.batch(zone_id, marker, &changes).await.unwrap();
+
+            let records = cloudflare.records(zone_id).await.unwrap();
+            assert!(Plan::new(&entries, &records, &options).changes.is_empty());
+            changes.len()
+        }
+    };
+
+    let created = sync(
+        r#"[
+            {"fqdn": "www.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.1"},
+            {"fqdn": "api.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.2"},
+            {"fqdn": "kubi.zone.", "type": "TXT", "class": "IN", "ttl": 300, "rdata": "\"hello\""}
+        ]"#,
+    )
+    .await;
+    assert_eq!(created, 3);
+    assert_eq!(mock.records(&zone_id).len(), 4);
+
+    let updated = sync(
+        r#"[
+            {"fqdn": "www.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.1"},
+            {"fqdn": "api.kubi.zone.", "type": "A", "class": "IN", "ttl": 60, "rdata": "127.0.0.2"}
+        ]"#,
+    )
+    .await;
+    assert_eq!(updated, 2);
+
+    // Records of others outlive every entry.
+    let deleted = sync("[]").await;
+    assert_eq!(deleted, 2);
+    let remaining = mock.records(&zone_id);
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0]["name"], "other.kubi.zone");
+}
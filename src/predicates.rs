@@ -0,0 +1,100 @@
+//! Filters for the watch streams of the controller, so zones are only
+//! reconciled when something affecting their sync changed, rather than on
+//! every update, or every time the watch is restarted.
+//!
+//! Zones are still reconciled periodically through their requeue time.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+};
+
+use kube::{Resource, ResourceExt as _};
+use kubizone_crds::v1alpha1::{Record, Zone};
+
+use crate::{custom_hostnames, regional, status, workers};
+
+/// Hash of everything about the zone which affects its sync.
+///
+/// Covers the entries and fully qualified name in its status, the generation
+/// of its spec, its labels, its annotations other than the sync status we
+/// write ourselves, and its finalizers and deletion.
+pub fn zone_inputs(zone: &Zone) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+
+    metadata_inputs(zone, &mut hasher);
+    zone.metadata.generation.hash(&mut hasher);
+
+    if let Some(status) = &zone.status {
+        status.fqdn.hash(&mut hasher);
+        status.entries.hash(&mut hasher);
+    }
+
+    Some(hasher.finish())
+}
+
+/// Hash of the labels and annotations of the record, other than the sync
+/// status we write ourselves.
+///
+/// Changes to the spec of records reach their zone through its entries.
+pub fn record_inputs(record: &Record) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+
+    metadata_inputs(record, &mut hasher);
+
+    Some(hasher.finish())
+}
+
+fn metadata_inputs<K: Resource>(resource: &K, hasher: &mut DefaultHasher) {
+    let own = [
+        status::synced_annotation(),
+        status::stats_annotation(),
+        custom_hostnames::status_annotation(),
+        workers::status_annotation(),
+        regional::status_annotation(),
+    ];
+    let annotations = resource
+        .annotations()
+        .iter()
+        .filter(|(key, _)| !own.contains(key))
+        .collect::<BTreeMap<_, _>>();
+
+    annotations.hash(hasher);
+    resource.labels().hash(hasher);
+    resource.finalizers().hash(hasher);
+    resource
+        .meta()
+        .deletion_timestamp
+        .as_ref()
+        .map(|deleted| deleted.0)
+        .hash(hasher);
+}
+
+#[cfg(test)]
+#[test]
+fn ignore_own_status() {
+    let mut zone: Zone = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {"name": "kubi-zone", "namespace": "dns", "resourceVersion": "1"},
+        "spec": {"domainName": "kubi.zone.", "delegations": []},
+        "status": {"entries": [
+            {"fqdn": "www.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.1"}
+        ]}
+    }))
+    .unwrap();
+    let before = zone_inputs(&zone);
+
+    zone.metadata.resource_version = Some("2".to_string());
+    zone.annotations_mut()
+        .insert(status::synced_annotation(), "{}".to_string());
+    assert_eq!(zone_inputs(&zone), before);
+
+    zone.annotations_mut()
+        .insert(crate::annotations::paused_annotation(), "true".to_string());
+    let paused = zone_inputs(&zone);
+    assert_ne!(paused, before);
+
+    zone.status.as_mut().unwrap().entries[0].ttl = 60;
+    assert_ne!(zone_inputs(&zone), paused);
+}
@@ -0,0 +1,162 @@
+use kube::ResourceExt;
+use kubizone_common::{FullyQualifiedDomainName, Type};
+use kubizone_crds::v1alpha1::{DomainExt as _, Record, Zone};
+use tracing::warn;
+
+use crate::{annotations::bool_annotation, cloudflare, crds::GROUP, ident::canonical_rdata};
+
+/// Annotation protecting records in cloudflare from ever being changed or
+/// deleted by the controller, even in delete mode.
+///
+/// On a Record, `"true"` protects the record itself. On a Zone, the value is
+/// a comma or newline separated list of `<fqdn> <type> [rdata]`, such as
+/// `example.org. MX`, protecting every matching record in the zone.
+pub fn protected_annotation() -> String {
+    format!("{GROUP}/protected")
+}
+
+/// Records matching the name and type, and the rdata if given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Protection {
+    fqdn: FullyQualifiedDomainName,
+    r#type: Type,
+    /// Canonical rdata, see [`canonical_rdata`].
+    rdata: Option<String>,
+}
+
+impl Protection {
+    fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.trim().splitn(3, char::is_whitespace);
+        let fqdn = FullyQualifiedDomainName::try_from(fields.next()?).ok()?;
+        let r#type =
+            serde_json::from_value(serde_json::Value::String(fields.next()?.to_uppercase()))
+                .ok()?;
+        let rdata = fields.next().map(|rdata| canonical_rdata(r#type, rdata));
+
+        Some(Protection {
+            fqdn,
+            r#type,
+            rdata,
+        })
+    }
+
+    fn matches(&self, record: &cloudflare::Record) -> bool {
+        self.fqdn == record.fqdn
+            && self.r#type == record.r#type
+            && self.rdata.as_ref().map_or(true, |rdata| {
+                rdata == &canonical_rdata(record.r#type, &record.rdata)
+            })
+    }
+}
+
+/// Records of a zone which must never be changed or deleted.
+#[derive(Debug, Default)]
+pub struct Protected {
+    protections: Vec<Protection>,
+}
+
+impl Protected {
+    /// Collect the protected annotations of the zone, and the `records` belonging
+    /// to it, along with the protected records of the zone's config.
+    pub fn new(zone: &Zone, records: &[Record], config: &[String]) -> Self {
+        let annotation = protected_annotation();
+        let Protected { mut protections } = Protected::from_config(&zone.name_any(), config);
+
+        if let Some(value) = zone.annotations().get(&annotation) {
+            for protection in value
+                .split([',', '\n'])
+                .filter(|value| !value.trim().is_empty())
+            {
+                match Protection::parse(protection) {
+                    Some(protection) => protections.push(protection),
+                    None => warn!(
+                        "ignoring {protection:?} in {annotation} annotation of zone {}, since it is not `<fqdn> <type> [rdata]`",
+                        zone.name_any()
+                    ),
+                }
+            }
+        }
+
+        for record in records {
+            if !bool_annotation(record, &annotation, false) {
+                continue;
+            }
+            let Some(fqdn) = record.fqdn() else {
+                continue;
+            };
+
+            protections.push(Protection {
+                fqdn: fqdn.clone(),
+                r#type: record.spec.type_,
+                rdata: Some(canonical_rdata(record.spec.type_, &record.spec.rdata)),
+            });
+        }
+
+        Protected { protections }
+    }
+
+    /// Collect only the protected records of the zone config, such as for
+    /// cloudflare zones no Zone maps onto, named `name` in warnings.
+    pub fn from_config(name: &str, config: &[String]) -> Self {
+        let mut protections = Vec::new();
+
+        for protection in config {
+            match Protection::parse(protection) {
+                Some(protection) => protections.push(protection),
+                None => warn!(
+                    "ignoring {protection:?} in zone config of zone {name}, since it is not `<fqdn> <type> [rdata]`"
+                ),
+            }
+        }
+
+        Protected { protections }
+    }
+
+    /// Returns true if the record in cloudflare must not be changed or deleted.
+    pub fn is_protected(&self, record: &cloudflare::Record) -> bool {
+        self.protections
+            .iter()
+            .any(|protection| protection.matches(record))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn protected_records() {
+    let zone: Zone = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {"name": "kubi-zone", "annotations": {protected_annotation(): "kubi.zone. MX"}},
+        "spec": {"domainName": "kubi.zone.", "delegations": []}
+    }))
+    .unwrap();
+
+    let records: Vec<Record> = serde_json::from_value(serde_json::json!([
+        {
+            "apiVersion": "kubi.zone/v1alpha1",
+            "kind": "Record",
+            "metadata": {"name": "www", "annotations": {protected_annotation(): "true"}},
+            "spec": {"domainName": "www.kubi.zone.", "type": "A", "rdata": "127.0.0.1"},
+            "status": {"fqdn": "www.kubi.zone."}
+        }
+    ]))
+    .unwrap();
+
+    let protected = Protected::new(&zone, &records, &["old.kubi.zone. A 127.0.0.4".to_string()]);
+
+    let cloudflare_records: Vec<cloudflare::Record> = serde_json::from_str(
+        r#"[
+            {"id": "1", "name": "kubi.zone", "type": "MX", "content": "mail.kubi.zone", "priority": 10, "ttl": 300},
+            {"id": "2", "name": "old.kubi.zone", "type": "A", "content": "127.0.0.4", "ttl": 300},
+            {"id": "3", "name": "old.kubi.zone", "type": "A", "content": "127.0.0.5", "ttl": 300},
+            {"id": "4", "name": "www.kubi.zone", "type": "A", "content": "127.0.0.1", "ttl": 300}
+        ]"#,
+    )
+    .unwrap();
+
+    let protected = cloudflare_records
+        .iter()
+        .map(|record| protected.is_protected(record))
+        .collect::<Vec<_>>();
+    assert_eq!(protected, [true, true, false, true]);
+}
@@ -0,0 +1,496 @@
+//! Operations on dns records which reconciliation relies on, abstracted over
+//! the provider hosting the zones.
+//!
+//! [`CloudFlare`] is the only real provider, others such as the in-memory
+//! [`Fake`] exist so the reconcile logic can be exercised without it.
+
+use std::future::Future;
+
+use crate::cloudflare::{
+    Applied, CloudFlare, Desired, Error, Marker, Record, RecordChange, RecordFilter, RecordId,
+    Zone, ZoneId,
+};
+
+/// Provider of the zones and dns records which are reconciled.
+///
+/// Errors are those of the cloudflare api, so callers can keep telling conflicts,
+/// rate limiting and missing permissions apart regardless of the provider.
+pub trait DnsProvider: Sync {
+    /// Every zone accessible to the provider's credentials.
+    fn list_zones(&self) -> impl Future<Output = Result<Vec<Zone>, Error>> + Send;
+
+    /// Every record of the zone.
+    fn records(&self, zone_id: &ZoneId) -> impl Future<Output = Result<Vec<Record>, Error>> + Send;
+
+    /// Records of the zone matching the filter.
+    fn find_records(
+        &self,
+        zone_id: &ZoneId,
+        filter: &RecordFilter,
+    ) -> impl Future<Output = Result<Vec<Record>, Error>> + Send;
+
+    fn create_record(
+        &self,
+        zone_id: &ZoneId,
+        marker: Marker<'_>,
+        desired: Desired<'_>,
+    ) -> impl Future<Output = Result<Record, Error>> + Send;
+
+    /// Replace the record with the desired one, and mark it with the `marker`.
+    fn update_record(
+        &self,
+        zone_id: &ZoneId,
+        marker: Marker<'_>,
+        record: &Record,
+        desired: Desired<'_>,
+    ) -> impl Future<Output = Result<Record, Error>> + Send;
+
+    fn delete_record(
+        &self,
+        zone_id: &ZoneId,
+        record_id: &RecordId,
+    ) -> impl Future<Output = Result<RecordId, Error>> + Send;
+
+    /// Apply the changes atomically, so if this fails, none of them have been applied.
+    fn batch(
+        &self,
+        zone_id: &ZoneId,
+        marker: Marker<'_>,
+        changes: &[RecordChange<'_>],
+    ) -> impl Future<Output = Result<Vec<Applied>, Error>> + Send;
+
+    /// Apply a single change, outside of any batch.
+    fn apply(
+        &self,
+        zone_id: &ZoneId,
+        marker: Marker<'_>,
+        change: &RecordChange<'_>,
+    ) -> impl Future<Output = Result<Applied, Error>> + Send {
+        async move {
+            Ok(match change {
+                RecordChange::Create(desired) => {
+                    Applied::Written(self.create_record(zone_id, marker, *desired).await?)
+                }
+                RecordChange::Update(record, desired) | RecordChange::Adopt(record, desired) => {
+                    Applied::Written(
+                        self.update_record(zone_id, marker, record, *desired)
+                            .await?,
+                    )
+                }
+                RecordChange::Delete(record) => {
+                    Applied::Deleted(self.delete_record(zone_id, &record.id).await?)
+                }
+            })
+        }
+    }
+}
+
+impl DnsProvider for CloudFlare {
+    fn list_zones(&self) -> impl Future<Output = Result<Vec<Zone>, Error>> + Send {
+        CloudFlare::list_zones(self)
+    }
+
+    fn records(&self, zone_id: &ZoneId) -> impl Future<Output = Result<Vec<Record>, Error>> + Send {
+        CloudFlare::records(self, zone_id)
+    }
+
+    fn find_records(
+        &self,
+        zone_id: &ZoneId,
+        filter: &RecordFilter,
+    ) -> impl Future<Output = Result<Vec<Record>, Error>> + Send {
+        CloudFlare::find_records(self, zone_id, filter)
+    }
+
+    fn create_record(
+        &self,
+        zone_id: &ZoneId,
+        marker: Marker<'_>,
+        desired: Desired<'_>,
+    ) -> impl Future<Output = Result<Record, Error>> + Send {
+        CloudFlare::create_record(self, zone_id, marker, desired)
+    }
+
+    fn update_record(
+        &self,
+        zone_id: &ZoneId,
+        marker: Marker<'_>,
+        record: &Record,
+        desired: Desired<'_>,
+    ) -> impl Future<Output = Result<Record, Error>> + Send {
+        CloudFlare::update_record(self, zone_id, marker, record, desired)
+    }
+
+    fn delete_record(
+        &self,
+        zone_id: &ZoneId,
+        record_id: &RecordId,
+    ) -> impl Future<Output = Result<RecordId, Error>> + Send {
+        CloudFlare::delete_record(self, zone_id, record_id)
+    }
+
+    fn batch(
+        &self,
+        zone_id: &ZoneId,
+        marker: Marker<'_>,
+        changes: &[RecordChange<'_>],
+    ) -> impl Future<Output = Result<Vec<Applied>, Error>> + Send {
+        CloudFlare::batch(self, zone_id, marker, changes)
+    }
+}
+
+#[cfg(test)]
+pub use fake::Fake;
+
+#[cfg(test)]
+mod fake {
+    use std::{
+        collections::{HashMap, VecDeque},
+        sync::Mutex,
+    };
+
+    use kubizone_common::RecordIdent;
+
+    use super::*;
+    use reqwest::{Method, StatusCode};
+
+    use crate::cloudflare::{content::Content, ApiError, ApiErrors, Request};
+
+    /// In-memory provider, behaving like cloudflare as far as the records of
+    /// its zones are concerned.
+    #[derive(Default)]
+    pub struct Fake {
+        state: Mutex<State>,
+    }
+
+    #[derive(Default)]
+    struct State {
+        zones: Vec<Zone>,
+        records: HashMap<ZoneId, Vec<Record>>,
+        /// Errors returned by upcoming requests instead of handling them.
+        failures: VecDeque<Error>,
+        /// Number of requests received, including failed ones.
+        requests: usize,
+        next_id: u64,
+    }
+
+    fn api_error(code: u32, message: &str) -> Error {
+        Error::api(
+            ApiErrors(vec![ApiError {
+                code,
+                message: message.to_string(),
+            }]),
+            Request {
+                method: Method::POST,
+                url: "fake".to_string(),
+                status: StatusCode::BAD_REQUEST,
+            },
+        )
+    }
+
+    impl State {
+        fn id(&mut self) -> String {
+            self.next_id += 1;
+            format!("{:032x}", self.next_id)
+        }
+
+        /// Count the request, failing it if a failure is pending.
+        fn request(&mut self) -> Result<(), Error> {
+            self.requests += 1;
+            self.failures.pop_front().map_or(Ok(()), Err)
+        }
+
+        fn records(&mut self, zone_id: &ZoneId) -> Result<&mut Vec<Record>, Error> {
+            self.records
+                .get_mut(zone_id)
+                .ok_or_else(|| api_error(1003, "Invalid or missing zone id."))
+        }
+
+        /// Write the record, replacing the `existing` one if any, like cloudflare would.
+        fn write(
+            &mut self,
+            zone_id: &ZoneId,
+            marker: Marker<'_>,
+            existing: Option<&Record>,
+            desired: Desired<'_>,
+        ) -> Result<Record, Error> {
+            Content::new(desired.r#type, desired.rdata)?;
+
+            let id = match existing {
+                Some(existing) => existing.id.to_string(),
+                None => self.id(),
+            };
+            let mut tags = existing
+                .map(|record| record.tags.clone())
+                .unwrap_or_default();
+            for tag in marker.tags() {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+
+            let record = Record {
+                id: serde_json::from_value(serde_json::Value::String(id)).unwrap(),
+                fqdn: desired.fqdn.clone(),
+                r#type: desired.r#type,
+                rdata: desired.rdata.to_string(),
+                comment: marker
+                    .comment()
+                    .or_else(|| existing.and_then(|record| record.comment.clone())),
+                tags,
+                ttl: desired.ttl,
+                proxied: desired.proxied,
+            };
+
+            let records = self.records(zone_id)?;
+            match existing {
+                Some(existing) => {
+                    let Some(index) = records.iter().position(|record| record.id == existing.id)
+                    else {
+                        return Err(api_error(81044, "Record does not exist."));
+                    };
+                    records[index] = record.clone();
+                }
+                None => {
+                    let ident = desired.ident();
+                    if records
+                        .iter()
+                        .any(|record| RecordIdent::from(record) == ident)
+                    {
+                        return Err(api_error(81058, "An identical record already exists."));
+                    }
+                    records.push(record.clone());
+                }
+            }
+
+            Ok(record)
+        }
+
+        fn delete(&mut self, zone_id: &ZoneId, record_id: &RecordId) -> Result<RecordId, Error> {
+            let records = self.records(zone_id)?;
+            let Some(index) = records.iter().position(|record| &record.id == record_id) else {
+                return Err(api_error(81044, "Record does not exist."));
+            };
+
+            Ok(records.remove(index).id)
+        }
+    }
+
+    impl Fake {
+        /// Add an active zone with the name, without a trailing dot, returning its id.
+        pub fn add_zone(&self, name: &str) -> ZoneId {
+            let mut state = self.state.lock().unwrap();
+            let id = state.id();
+            let zone: Zone = serde_json::from_value(serde_json::json!({
+                "id": id, "name": name, "status": "active", "paused": false
+            }))
+            .unwrap();
+
+            state.records.insert(zone.id.clone(), Vec::new());
+            state.zones.push(zone);
+            serde_json::from_value(serde_json::Value::String(id)).unwrap()
+        }
+
+        /// Add a record to the zone, as returned by the api, assigning it an id.
+        pub fn add_record(&self, zone_id: &ZoneId, mut record: serde_json::Value) {
+            let mut state = self.state.lock().unwrap();
+            record["id"] = serde_json::Value::String(state.id());
+            let record = serde_json::from_value(record).unwrap();
+            state.records.get_mut(zone_id).unwrap().push(record);
+        }
+
+        /// Current records of the zone.
+        pub fn current(&self, zone_id: &ZoneId) -> Vec<Record> {
+            self.state.lock().unwrap().records[zone_id].clone()
+        }
+
+        /// Fail the next request which has not been failed yet with the error.
+        pub fn fail_next(&self, err: Error) {
+            self.state.lock().unwrap().failures.push_back(err);
+        }
+
+        /// Number of requests received so far.
+        pub fn requests(&self) -> usize {
+            self.state.lock().unwrap().requests
+        }
+    }
+
+    impl DnsProvider for Fake {
+        async fn list_zones(&self) -> Result<Vec<Zone>, Error> {
+            let mut state = self.state.lock().unwrap();
+            state.request()?;
+
+            Ok(state.zones.clone())
+        }
+
+        async fn records(&self, zone_id: &ZoneId) -> Result<Vec<Record>, Error> {
+            let mut state = self.state.lock().unwrap();
+            state.request()?;
+
+            Ok(state.records(zone_id)?.clone())
+        }
+
+        async fn find_records(
+            &self,
+            zone_id: &ZoneId,
+            filter: &RecordFilter,
+        ) -> Result<Vec<Record>, Error> {
+            let mut state = self.state.lock().unwrap();
+            state.request()?;
+
+            Ok(state
+                .records(zone_id)?
+                .iter()
+                .filter(|record| filter.matches(record))
+                .cloned()
+                .collect())
+        }
+
+        async fn create_record(
+            &self,
+            zone_id: &ZoneId,
+            marker: Marker<'_>,
+            desired: Desired<'_>,
+        ) -> Result<Record, Error> {
+            let mut state = self.state.lock().unwrap();
+            state.request()?;
+
+            state.write(zone_id, marker, None, desired)
+        }
+
+        async fn update_record(
+            &self,
+            zone_id: &ZoneId,
+            marker: Marker<'_>,
+            record: &Record,
+            desired: Desired<'_>,
+        ) -> Result<Record, Error> {
+            let mut state = self.state.lock().unwrap();
+            state.request()?;
+
+            state.write(zone_id, marker, Some(record), desired)
+        }
+
+        async fn delete_record(
+            &self,
+            zone_id: &ZoneId,
+            record_id: &RecordId,
+        ) -> Result<RecordId, Error> {
+            let mut state = self.state.lock().unwrap();
+            state.request()?;
+
+            state.delete(zone_id, record_id)
+        }
+
+        async fn batch(
+            &self,
+            zone_id: &ZoneId,
+            marker: Marker<'_>,
+            changes: &[RecordChange<'_>],
+        ) -> Result<Vec<Applied>, Error> {
+            let mut state = self.state.lock().unwrap();
+            state.request()?;
+
+            // Batches are atomic, so changes are applied to a copy of the zone,
+            // which only replaces it once every change succeeded.
+            let mut batch = State {
+                records: HashMap::from([(zone_id.clone(), state.records(zone_id)?.clone())]),
+                next_id: state.next_id,
+                ..State::default()
+            };
+
+            // Deletions go first and creations last, like cloudflare does.
+            let mut applied = Vec::new();
+            for change in changes {
+                if let RecordChange::Delete(record) = change {
+                    applied.push(Applied::Deleted(batch.delete(zone_id, &record.id)?));
+                }
+            }
+            for change in changes {
+                if let RecordChange::Update(record, desired)
+                | RecordChange::Adopt(record, desired) = change
+                {
+                    let written = batch.write(zone_id, marker, Some(record), *desired)?;
+                    applied.push(Applied::Written(written));
+                }
+            }
+            for change in changes {
+                if let RecordChange::Create(desired) = change {
+                    let written = batch.write(zone_id, marker, None, *desired)?;
+                    applied.push(Applied::Written(written));
+                }
+            }
+
+            state.next_id = batch.next_id;
+            state
+                .records
+                .insert(zone_id.clone(), batch.records.remove(zone_id).unwrap());
+            Ok(applied)
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn fake_provider() {
+    use kubizone_common::FullyQualifiedDomainName;
+    use kubizone_crds::v1alpha1::ZoneEntry;
+
+    use crate::{
+        ownership::Ownership,
+        plan::{Options, Plan, PlannedChange},
+    };
+
+    let fake = Fake::default();
+    let zone_id = fake.add_zone("kubi.zone");
+    fake.add_record(
+        &zone_id,
+        serde_json::json!({"name": "old.kubi.zone", "type": "A", "content": "127.0.0.9", "ttl": 60, "comment": "managed-by:kubizone"}),
+    );
+    assert_eq!(fake.list_zones().await.unwrap()[0].id, zone_id);
+
+    let entries: Vec<ZoneEntry> = serde_json::from_str(
+        r#"[
+            {"fqdn": "www.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.1"},
+            {"fqdn": "kubi.zone.", "type": "MX", "class": "IN", "ttl": 300, "rdata": "10 mail.kubi.zone."}
+        ]"#,
+    )
+    .unwrap();
+    let apex = FullyQualifiedDomainName::try_from("kubi.zone.").unwrap();
+    let options = Options {
+        ownership: Ownership::Tags,
+        ..Options::test(&apex)
+    };
+    let marker = Marker::Tag("kubizone");
+
+    let records = fake.records(&zone_id).await.unwrap();
+    let plan = Plan::new(&entries, &records, &options);
+    let changes = plan
+        .changes
+        .iter()
+        .map(PlannedChange::change)
+        .collect::<Vec<_>>();
+    assert_eq!(changes.len(), 3);
+
+    // Failed batches leave the zone untouched.
+    fake.fail_next(Error::RateLimited(std::time::Duration::ZERO));
+    assert!(fake.batch(&zone_id, marker, &changes).await.is_err());
+    assert_eq!(fake.current(&zone_id).len(), 1);
+
+    let applied = fake.batch(&zone_id, marker, &changes).await.unwrap();
+    assert_eq!(applied.len(), 3);
+
+    let records = fake.records(&zone_id).await.unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|record| record.is_marked_by("kubizone")));
+    assert!(Plan::new(&entries, &records, &options).changes.is_empty());
+
+    // Identical records are rejected, like cloudflare does.
+    let duplicate = changes
+        .iter()
+        .find(|change| matches!(change, RecordChange::Create(_)))
+        .unwrap();
+    let err = fake.apply(&zone_id, marker, duplicate).await.unwrap_err();
+    assert!(err.is_record_exists());
+    assert_eq!(fake.requests(), 6);
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use kube::ResourceExt;
+use kubizone_common::{RecordIdent, Type};
+use kubizone_crds::v1alpha1::{DomainExt as _, Record, Zone, ZoneEntry};
+use tracing::warn;
+
+use crate::{annotations::bool_annotation_value, crds::GROUP, ident};
+
+/// Annotation on a Record enabling (`"true"`) or disabling (`"false"`)
+/// proxying of its traffic through cloudflare.
+///
+/// On a Zone, the annotation sets the default for all of its records.
+pub fn proxied_annotation() -> String {
+    format!("{GROUP}/proxied")
+}
+
+/// Returns true if cloudflare is able to proxy records of the type.
+pub fn is_proxiable(type_: Type) -> bool {
+    matches!(type_, Type::A | Type::AAAA | Type::CNAME)
+}
+
+/// Which of the records of a zone are proxied through cloudflare.
+#[derive(Debug, Default)]
+pub struct Proxied {
+    /// Whether records without an annotation of their own are proxied.
+    default: bool,
+    records: HashMap<RecordIdent, bool>,
+}
+
+impl Proxied {
+    /// Collect the proxied annotations of the zone, and the `records` belonging to it.
+    ///
+    /// Records without a fully qualified domain name yet are not part of
+    /// the zone's entries, and are skipped. Zones without an annotation of
+    /// their own use `default`, if given.
+    pub fn new(zone: &Zone, records: &[Record], default: Option<bool>) -> Self {
+        let annotation = proxied_annotation();
+        let default = bool_annotation_value(zone, &annotation)
+            .or(default)
+            .unwrap_or_default();
+
+        let records = records
+            .iter()
+            .filter_map(|record| {
+                let proxied = bool_annotation_value(record, &annotation)?;
+
+                if proxied && !is_proxiable(record.spec.type_) {
+                    warn!(
+                        "ignoring {annotation} annotation of record {}, since cloudflare cannot proxy {} records",
+                        record.name_any(),
+                        record.spec.type_
+                    );
+                    return None;
+                }
+
+                let ident = ident::ident(record.fqdn()?, record.spec.type_, &record.spec.rdata);
+
+                Some((ident, proxied))
+            })
+            .collect();
+
+        Proxied { default, records }
+    }
+
+    /// Returns true if the record for the entry should be proxied.
+    ///
+    /// Records which cannot be proxied are never proxied, regardless of the zone default.
+    pub fn is_proxied(&self, entry: &ZoneEntry) -> bool {
+        is_proxiable(entry.type_)
+            && self
+                .records
+                .get(&crate::plan::entry_ident(entry))
+                .copied()
+                .unwrap_or(self.default)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn proxied_records() {
+    let records: Vec<Record> = serde_json::from_value(serde_json::json!([
+        {
+            "apiVersion": "kubi.zone/v1alpha1",
+            "kind": "Record",
+            "metadata": {"name": "www", "annotations": {proxied_annotation(): "true"}},
+            "spec": {"domainName": "www.kubi.zone.", "type": "A", "rdata": "127.0.0.1"},
+            "status": {"fqdn": "www.kubi.zone."}
+        },
+        {
+            "apiVersion": "kubi.zone/v1alpha1",
+            "kind": "Record",
+            "metadata": {"name": "mail", "annotations": {proxied_annotation(): "true"}},
+            "spec": {"domainName": "kubi.zone.", "type": "MX", "rdata": "10 mail.kubi.zone."},
+            "status": {"fqdn": "kubi.zone."}
+        },
+        {
+            "apiVersion": "kubi.zone/v1alpha1",
+            "kind": "Record",
+            "metadata": {"name": "internal", "annotations": {proxied_annotation(): "false"}},
+            "spec": {"domainName": "internal.kubi.zone.", "type": "A", "rdata": "10.0.0.1"},
+            "status": {"fqdn": "internal.kubi.zone."}
+        }
+    ]))
+    .unwrap();
+
+    let zone: Zone = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {"name": "kubi-zone", "annotations": {proxied_annotation(): "true"}},
+        "spec": {"domainName": "kubi.zone.", "delegations": []}
+    }))
+    .unwrap();
+
+    let proxied = Proxied::new(&zone, &records, Some(false));
+
+    let entries: Vec<ZoneEntry> = serde_json::from_str(
+        r#"[
+            {"fqdn": "www.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.1"},
+            {"fqdn": "kubi.zone.", "type": "MX", "class": "IN", "ttl": 300, "rdata": "10 mail.kubi.zone."},
+            {"fqdn": "api.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "127.0.0.2"},
+            {"fqdn": "internal.kubi.zone.", "type": "A", "class": "IN", "ttl": 300, "rdata": "10.0.0.1"}
+        ]"#,
+    )
+    .unwrap();
+
+    let proxied = entries
+        .iter()
+        .map(|entry| proxied.is_proxied(entry))
+        .collect::<Vec<_>>();
+    assert_eq!(proxied, [true, false, true, false]);
+}
@@ -0,0 +1,59 @@
+//! Regional hostnames (Data Localization), pinning hostnames of Records to a
+//! region through an annotation, synced with `--regional-hostnames`.
+
+use kube::ResourceExt as _;
+use kubizone_crds::v1alpha1::Record;
+
+use crate::crds::GROUP;
+
+/// Annotation on a Record pinning its hostname to a region, given by its
+/// region key, such as `eu` or `us`.
+pub fn region_annotation() -> String {
+    format!("{GROUP}/region")
+}
+
+/// Annotation holding the region key the hostname of a Record was last pinned
+/// to by the controller, so it is only ever unpinned by us.
+pub fn status_annotation() -> String {
+    format!("{GROUP}/region-status")
+}
+
+/// Region key declared by the record, if any.
+pub fn declared(record: &Record) -> Option<&str> {
+    record
+        .annotations()
+        .get(&region_annotation())
+        .map(|region| region.trim())
+        .filter(|region| !region.is_empty())
+}
+
+/// Region key the record was last pinned to, if any.
+pub fn current(record: &Record) -> Option<&str> {
+    record
+        .annotations()
+        .get(&status_annotation())
+        .map(String::as_str)
+        .filter(|region| !region.is_empty())
+}
+
+#[cfg(test)]
+#[test]
+fn declared_region() {
+    let record: Record = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Record",
+        "metadata": {
+            "name": "www",
+            "namespace": "dns",
+            "annotations": {
+                region_annotation(): " eu ",
+                status_annotation(): "",
+            }
+        },
+        "spec": {"domainName": "www.kubi.zone.", "type": "A", "rdata": "127.0.0.1"}
+    }))
+    .unwrap();
+
+    assert_eq!(declared(&record), Some("eu"));
+    assert_eq!(current(&record), None);
+}
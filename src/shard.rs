@@ -0,0 +1,59 @@
+//! Assignment of zones to shards, so several replicas can each reconcile a
+//! separate subset of the zones.
+
+/// Shard of `count` shards which the key is assigned to.
+///
+/// Uses jump consistent hashing, so changing the number of shards only moves
+/// the keys which have to move, rather than reassigning nearly all of them.
+pub fn shard(key: &str, count: u32) -> u32 {
+    let mut hash = fnv1a(key.as_bytes());
+    let (mut bucket, mut next) = (-1i64, 0i64);
+
+    while next < i64::from(count) {
+        bucket = next;
+        hash = hash.wrapping_mul(2862933555777941757).wrapping_add(1);
+        next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((hash >> 33) + 1) as f64)) as i64;
+    }
+
+    // A count of zero leaves the bucket at -1, which is treated as the only shard.
+    bucket.max(0) as u32
+}
+
+/// 64 bit FNV-1a hash, which unlike the hasher of the standard library is
+/// guaranteed to be the same regardless of the version the replicas were built with.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn consistent_shards() {
+    let keys = (0..1000)
+        .map(|i| format!("6a1f9e2c-{i:04}-4b8e-9d3a-0c5e7f1b2a94"))
+        .collect::<Vec<_>>();
+
+    for key in &keys {
+        assert_eq!(shard(key, 1), 0);
+        assert!(shard(key, 4) < 4);
+        assert_eq!(shard(key, 4), shard(key, 4));
+    }
+
+    // Keys only ever move to the new shard when adding one.
+    for key in &keys {
+        let before = shard(key, 4);
+        let after = shard(key, 5);
+        assert!(after == before || after == 4);
+    }
+
+    // Every shard receives a reasonable share of the keys.
+    let mut counts = [0; 4];
+    for key in &keys {
+        counts[shard(key, 4) as usize] += 1;
+    }
+    assert!(
+        counts.iter().all(|count| (150..350).contains(count)),
+        "{counts:?}"
+    );
+}
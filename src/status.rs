@@ -0,0 +1,278 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use k8s_openapi::{
+    apimachinery::pkg::apis::meta::v1::{Condition, Time},
+    chrono::{SubsecRound as _, Utc},
+};
+use kube::{
+    api::{Patch, PatchParams},
+    Api, Client as KubeClient, Resource, ResourceExt as _,
+};
+use kubizone_crds::v1alpha1::{Record, Zone};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::crds::GROUP;
+
+/// Type of the condition describing whether a zone or record matches cloudflare.
+pub const SYNCED: &str = "CloudflareSynced";
+
+/// Annotation the [`SYNCED`] condition is stored in, as JSON.
+///
+/// The Zone status schema is owned by kubizone, and the API server prunes any
+/// fields it does not know about, so the condition cannot live in `.status.conditions`.
+pub fn synced_annotation() -> String {
+    format!("{GROUP}/synced")
+}
+
+/// Annotation the [`SyncStats`] of the latest successful sync are stored in, as JSON.
+pub fn stats_annotation() -> String {
+    format!("{GROUP}/sync-stats")
+}
+
+/// Statistics about the latest successful sync of a zone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStats {
+    /// Records in cloudflare managed by this controller, before the sync.
+    pub managed: usize,
+    /// Records in cloudflare managed by someone else, before the sync.
+    pub unmanaged: usize,
+    /// Records changed by the sync itself.
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    #[serde(default)]
+    pub adopted: usize,
+    /// Changes needed, but not made, since the zone is paused.
+    #[serde(default)]
+    pub pending: usize,
+    pub last_successful_sync: Option<Time>,
+    /// Nameservers of the cloudflare zone, which the domain must be delegated to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub name_servers: Vec<String>,
+    /// DS record to add to the parent zone, once DNSSEC is enabled through the annotation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ds: Option<String>,
+}
+
+impl SyncStats {
+    /// Returns true if these stats differ from the zone's current stats in
+    /// anything but the sync time, or the current sync time is older than `refresh`.
+    ///
+    /// Writing the sync time on every reconcile would trigger a new
+    /// reconcile every time, so it is only refreshed periodically.
+    fn differs(&self, current: Option<&SyncStats>, refresh: Duration) -> bool {
+        let Some(current) = current else {
+            return true;
+        };
+
+        let counts = |stats: &SyncStats| {
+            (
+                stats.managed,
+                stats.unmanaged,
+                stats.created,
+                stats.updated,
+                stats.deleted,
+                stats.adopted,
+                stats.pending,
+            )
+        };
+
+        let stale = match (&self.last_successful_sync, &current.last_successful_sync) {
+            (Some(now), Some(last)) => (now.0 - last.0).to_std().unwrap_or_default() >= refresh,
+            (now, last) => now.is_some() != last.is_some(),
+        };
+
+        counts(self) != counts(current)
+            || self.name_servers != current.name_servers
+            || self.ds != current.ds
+            || stale
+    }
+}
+
+/// Build the [`SYNCED`] condition for the zone or record.
+///
+/// The transition time of the current condition is kept if the status
+/// has not changed.
+pub fn synced_condition<K: Resource>(
+    resource: &K,
+    synced: bool,
+    reason: &str,
+    message: String,
+) -> Condition {
+    let status = if synced { "True" } else { "False" };
+
+    let last_transition_time = current_condition(resource)
+        .filter(|current| current.status == status)
+        .map(|current| current.last_transition_time)
+        // Truncated, since conditions are serialized with second precision.
+        .unwrap_or_else(|| Time(Utc::now().trunc_subsecs(0)));
+
+    Condition {
+        type_: SYNCED.to_string(),
+        status: status.to_string(),
+        reason: reason.to_string(),
+        message,
+        last_transition_time,
+        observed_generation: resource.meta().generation,
+    }
+}
+
+/// Condition currently stored on the zone or record, if any.
+pub fn current_condition<K: Resource>(resource: &K) -> Option<Condition> {
+    resource
+        .annotations()
+        .get(&synced_annotation())
+        .and_then(|condition| serde_json::from_str(condition).ok())
+}
+
+/// Stats currently stored on the zone, if any.
+pub fn current_stats(zone: &Zone) -> Option<SyncStats> {
+    zone.annotations()
+        .get(&stats_annotation())
+        .and_then(|stats| serde_json::from_str(stats).ok())
+}
+
+/// Store the condition, and the stats if the sync succeeded, on the zone.
+///
+/// Values identical to the current ones are not written, since every write
+/// to the zone triggers another reconciliation. The sync time within the
+/// stats is only refreshed once it is older than `stats_refresh`.
+pub async fn update(
+    kube: KubeClient,
+    zone: &Zone,
+    condition: Condition,
+    stats: Option<SyncStats>,
+    stats_refresh: Duration,
+) -> Result<(), kube::Error> {
+    let mut annotations = BTreeMap::new();
+
+    if current_condition(zone).as_ref() != Some(&condition) {
+        annotations.insert(
+            synced_annotation(),
+            serde_json::to_string(&condition).unwrap(),
+        );
+    }
+
+    if let Some(stats) =
+        stats.filter(|stats| stats.differs(current_stats(zone).as_ref(), stats_refresh))
+    {
+        annotations.insert(stats_annotation(), serde_json::to_string(&stats).unwrap());
+    }
+
+    if annotations.is_empty() {
+        return Ok(());
+    }
+
+    let patch = json!({
+        "metadata": {
+            "annotations": annotations,
+        }
+    });
+
+    Api::<Zone>::namespaced(kube, &zone.namespace().unwrap_or_default())
+        .patch(
+            &zone.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(patch),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Store the condition on the record, unless it is identical to the current one.
+pub async fn update_record(
+    kube: KubeClient,
+    record: &Record,
+    condition: Condition,
+) -> Result<(), kube::Error> {
+    if current_condition(record).as_ref() == Some(&condition) {
+        return Ok(());
+    }
+
+    annotate_record(
+        kube,
+        record,
+        synced_annotation(),
+        serde_json::to_string(&condition).unwrap(),
+    )
+    .await
+}
+
+/// Set an annotation of the record, such as one holding the status of
+/// cloudflare resources belonging to it.
+pub async fn annotate_record(
+    kube: KubeClient,
+    record: &Record,
+    annotation: String,
+    value: String,
+) -> Result<(), kube::Error> {
+    let patch = json!({
+        "metadata": {
+            "annotations": {
+                annotation: value,
+            },
+        }
+    });
+
+    Api::<Record>::namespaced(kube, &record.namespace().unwrap_or_default())
+        .patch(
+            &record.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(patch),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn keep_transition_time() {
+    let mut zone = Zone::new("example", Default::default());
+    let failed = synced_condition(&zone, false, "ZoneNotFound", "not found".to_string());
+
+    zone.metadata.annotations = Some(BTreeMap::from([(
+        synced_annotation(),
+        serde_json::to_string(&failed).unwrap(),
+    )]));
+
+    let still_failing = synced_condition(&zone, false, "CloudflareError", "error".to_string());
+    assert_eq!(
+        still_failing.last_transition_time,
+        failed.last_transition_time
+    );
+
+    let synced = synced_condition(&zone, true, "Synced", "in sync".to_string());
+    assert!(synced.last_transition_time.0 >= failed.last_transition_time.0);
+    assert_eq!(synced.status, "True");
+}
+
+#[cfg(test)]
+#[test]
+fn refresh_stats_periodically() {
+    let last = Utc::now().trunc_subsecs(0);
+    let current = SyncStats {
+        managed: 3,
+        last_successful_sync: Some(Time(last)),
+        ..Default::default()
+    };
+
+    let at = |secs| SyncStats {
+        last_successful_sync: Some(Time(last + k8s_openapi::chrono::Duration::seconds(secs))),
+        ..current.clone()
+    };
+
+    let refresh = Duration::from_secs(300);
+    assert!(at(0).differs(None, refresh));
+    assert!(!at(30).differs(Some(&current), refresh));
+    assert!(at(300).differs(Some(&current), refresh));
+
+    let created = SyncStats {
+        created: 1,
+        ..at(30)
+    };
+    assert!(created.differs(Some(&current), refresh));
+}
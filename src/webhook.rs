@@ -0,0 +1,446 @@
+//! Validating admission webhook, rejecting Zones and Records which cloudflare
+//! would refuse at apply time, rather than failing to sync them later on.
+//!
+//! Records cloudflare cannot represent at all are denied, while those it
+//! accepts in a different form, such as with a clamped TTL, only cause a
+//! warning to be returned to the client.
+
+use std::{
+    future::Future,
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use axum::{
+    body::Bytes,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::post,
+    Router,
+};
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use kube::{
+    core::{
+        admission::{AdmissionRequest, AdmissionResponse, AdmissionReview},
+        DynamicObject,
+    },
+    ResourceExt as _,
+};
+use kubizone_crds::v1alpha1::{Record, Zone};
+use tokio_rustls::{rustls, TlsAcceptor};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    annotations,
+    cloudflare::{self, content::Content, owner_comment, owner_marker, MAX_COMMENT_LENGTH},
+    proxy,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{}: {1}", .0.display())]
+    Io(PathBuf, std::io::Error),
+    #[error("{} contains no private key", .0.display())]
+    NoPrivateKey(PathBuf),
+    #[error("tls: {0}")]
+    Tls(#[from] rustls::Error),
+}
+
+/// Problems with a resource, which cloudflare would either refuse or
+/// accept in a different form.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Findings {
+    /// Reasons the resource is denied.
+    pub denied: Vec<String>,
+    /// Warnings returned to the client, without denying the resource.
+    pub warnings: Vec<String>,
+}
+
+impl Findings {
+    /// Response to the request, denying it if anything was found which
+    /// cloudflare refuses, unless `warn_only` is set.
+    pub fn respond<T: kube::Resource>(
+        mut self,
+        request: &AdmissionRequest<T>,
+        warn_only: bool,
+    ) -> AdmissionResponse {
+        let mut response = AdmissionResponse::from(request);
+
+        if warn_only {
+            self.warnings.append(&mut self.denied);
+        } else if !self.denied.is_empty() {
+            response = response.deny(self.denied.join(", "));
+        }
+
+        if !self.warnings.is_empty() {
+            response.warnings = Some(self.warnings);
+        }
+
+        response
+    }
+}
+
+/// Check the record against the constraints of cloudflare.
+///
+/// Records of types which are not synced to cloudflare are always allowed.
+/// `at_apex` is true if the record is for the apex of a zone.
+pub fn check_record(record: &Record, synced: bool, at_apex: bool) -> Findings {
+    let mut findings = Findings::default();
+    if !synced {
+        return findings;
+    }
+
+    let type_ = record.spec.type_;
+    if !cloudflare::is_supported(type_) {
+        findings
+            .denied
+            .push(format!("cloudflare does not support {type_} records"));
+        return findings;
+    }
+
+    if let Err(err) = Content::new(type_, &record.spec.rdata) {
+        findings.denied.push(err.to_string());
+    }
+
+    let proxied = record
+        .annotations()
+        .get(&proxy::proxied_annotation())
+        .is_some_and(|proxied| proxied == "true");
+    if proxied && !proxy::is_proxiable(type_) {
+        findings.warnings.push(format!(
+            "cloudflare cannot proxy {type_} records, so the {} annotation is ignored",
+            proxy::proxied_annotation()
+        ));
+    }
+
+    match record.spec.ttl {
+        // Proxied records always use an automatic ttl.
+        _ if proxied && proxy::is_proxiable(type_) => {}
+        Some(ttl) if ttl > cloudflare::MAX_TTL => findings.warnings.push(format!(
+            "ttl {ttl} is above the maximum of {} accepted by cloudflare, which is used instead",
+            cloudflare::MAX_TTL
+        )),
+        Some(ttl) if ttl < cloudflare::MIN_TTL => findings.warnings.push(format!(
+            "ttl {ttl} is below the minimum of {} accepted by cloudflare, which is used instead",
+            cloudflare::MIN_TTL
+        )),
+        _ => {}
+    }
+
+    if at_apex && type_ == kubizone_common::Type::CNAME {
+        findings.warnings.push(format!(
+            "cloudflare flattens CNAME records at the apex of a zone, so {} resolves to the addresses of {} instead",
+            record.spec.domain_name, record.spec.rdata
+        ));
+    }
+
+    findings
+}
+
+/// Check the zone against the constraints of cloudflare, given the owner and
+/// description which end up in the comments of its records.
+pub fn check_zone(zone: &Zone, owner: &str, description: Option<&str>) -> Findings {
+    let mut findings = Findings::default();
+
+    if let Some(description) = description.map(str::trim_end) {
+        let full = owner_marker(owner).chars().count() + 1 + description.chars().count();
+        if owner_comment(owner, Some(description)).chars().count() < full {
+            findings.warnings.push(format!(
+                "comments of records are cut to the {MAX_COMMENT_LENGTH} characters accepted by cloudflare, leaving out part of the description {description:?}"
+            ));
+        }
+    }
+
+    if zone.spec.ttl > cloudflare::MAX_TTL {
+        findings.warnings.push(format!(
+            "ttl {} is above the maximum of {} accepted by cloudflare, which is used instead",
+            zone.spec.ttl,
+            cloudflare::MAX_TTL
+        ));
+    }
+
+    if let Some(min_ttl) = annotations::min_ttl(zone, None) {
+        if min_ttl > cloudflare::MAX_TTL {
+            findings.warnings.push(format!(
+                "{} annotation {min_ttl} is above the maximum of {} accepted by cloudflare",
+                annotations::min_ttl_annotation(),
+                cloudflare::MAX_TTL
+            ));
+        }
+    }
+
+    findings
+}
+
+/// Certificate and private key the webhook is served with, re-read whenever
+/// either of the files changes, so rotated certificates are picked up.
+struct Tls {
+    cert: PathBuf,
+    key: PathBuf,
+    current: Mutex<(SystemTime, Arc<rustls::ServerConfig>)>,
+}
+
+impl Tls {
+    fn load(cert: PathBuf, key: PathBuf) -> Result<Self, Error> {
+        let modified = modified(&cert).max(modified(&key));
+        let config = server_config(&cert, &key)?;
+
+        Ok(Tls {
+            cert,
+            key,
+            current: Mutex::new((modified, config)),
+        })
+    }
+
+    /// Current server config, reloaded if the files changed since.
+    ///
+    /// Keeps using the previous certificate if the new one cannot be loaded,
+    /// such as while only one of the files has been replaced yet.
+    fn config(&self) -> Arc<rustls::ServerConfig> {
+        let modified = modified(&self.cert).max(modified(&self.key));
+        let mut current = self.current.lock().unwrap();
+
+        if modified > current.0 {
+            match server_config(&self.cert, &self.key) {
+                Ok(config) => {
+                    info!("reloaded webhook certificate {}", self.cert.display());
+                    *current = (modified, config);
+                }
+                Err(err) => warn!("failed to reload webhook certificate: {err}"),
+            }
+        }
+
+        current.1.clone()
+    }
+}
+
+fn modified(path: &Path) -> SystemTime {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+fn server_config(cert: &Path, key: &Path) -> Result<Arc<rustls::ServerConfig>, Error> {
+    let open = |path: &Path| {
+        std::fs::File::open(path)
+            .map(BufReader::new)
+            .map_err(|err| Error::Io(path.to_path_buf(), err))
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(cert)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| Error::Io(cert.to_path_buf(), err))?;
+    let private_key = rustls_pemfile::private_key(&mut open(key)?)
+        .map_err(|err| Error::Io(key.to_path_buf(), err))?
+        .ok_or_else(|| Error::NoPrivateKey(key.to_path_buf()))?;
+
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, private_key)?;
+
+    Ok(Arc::new(config))
+}
+
+/// Serve the webhook on `/validate` over https, passing every admission
+/// review to `review`, until the process exits.
+pub async fn serve<F, Fut>(
+    address: SocketAddr,
+    cert: PathBuf,
+    key: PathBuf,
+    review: F,
+) -> Result<(), Error>
+where
+    F: Fn(AdmissionReview<DynamicObject>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = AdmissionResponse> + Send,
+{
+    let tls = Tls::load(cert, key)?;
+
+    let router = Router::new().route(
+        "/validate",
+        post(move |body: Bytes| async move {
+            let response = match serde_json::from_slice::<AdmissionReview<DynamicObject>>(&body) {
+                Ok(request) => review(request).await,
+                Err(err) => {
+                    warn!("failed to parse admission review: {err}");
+                    AdmissionResponse::invalid(err)
+                }
+            };
+
+            match serde_json::to_vec(&response.into_review()) {
+                Ok(body) => ([(header::CONTENT_TYPE, "application/json")], body).into_response(),
+                Err(err) => {
+                    error!("failed to serialize admission review: {err}");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .map_err(|err| Error::Io(PathBuf::from(address.to_string()), err))?;
+
+    info!("serving admission webhook on {address}");
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!("failed to accept webhook connection: {err}");
+                continue;
+            }
+        };
+
+        let acceptor = TlsAcceptor::from(tls.config());
+        let router = router.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("tls handshake with {peer} failed: {err}");
+                    return;
+                }
+            };
+
+            if let Err(err) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), TowerToHyperService::new(router))
+                .await
+            {
+                debug!("webhook connection with {peer} failed: {err}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn cloudflare_constraints() {
+    let record = |type_: &str, rdata: &str, ttl: u32, annotations: serde_json::Value| {
+        serde_json::from_value::<Record>(serde_json::json!({
+            "apiVersion": "kubi.zone/v1alpha1",
+            "kind": "Record",
+            "metadata": {"name": "www", "annotations": annotations},
+            "spec": {"domainName": "kubi.zone.", "type": type_, "ttl": ttl, "rdata": rdata}
+        }))
+        .unwrap()
+    };
+
+    let valid = record("A", "127.0.0.1", 300, serde_json::json!({}));
+    assert_eq!(check_record(&valid, true, false), Findings::default());
+
+    let unsupported = record("HINFO", "\"cpu\" \"os\"", 300, serde_json::json!({}));
+    assert_eq!(check_record(&unsupported, true, false).denied.len(), 1);
+    assert_eq!(
+        check_record(&unsupported, false, false),
+        Findings::default()
+    );
+
+    let invalid = record("MX", "mail.kubi.zone.", 300, serde_json::json!({}));
+    assert_eq!(check_record(&invalid, true, false).denied.len(), 1);
+
+    let apex = record("CNAME", "www.kubi.zone.", 30, serde_json::json!({}));
+    let findings = check_record(&apex, true, true);
+    assert!(findings.denied.is_empty());
+    assert_eq!(findings.warnings.len(), 2);
+
+    // Proxied records use an automatic ttl regardless.
+    let proxied = record(
+        "A",
+        "127.0.0.1",
+        30,
+        serde_json::json!({proxy::proxied_annotation(): "true"}),
+    );
+    assert_eq!(check_record(&proxied, true, false), Findings::default());
+
+    let zone: Zone = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {"name": "kubi-zone"},
+        "spec": {"domainName": "kubi.zone.", "delegations": [], "ttl": 172800}
+    }))
+    .unwrap();
+    assert_eq!(
+        check_zone(&zone, "kubizone", Some("kubi-zone"))
+            .warnings
+            .len(),
+        1
+    );
+    assert_eq!(
+        check_zone(&zone, "kubizone", Some(&"x".repeat(100)))
+            .warnings
+            .len(),
+        2
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn admission_responses() {
+    let review: AdmissionReview<DynamicObject> = serde_json::from_value(serde_json::json!({
+        "apiVersion": "admission.k8s.io/v1",
+        "kind": "AdmissionReview",
+        "request": {
+            "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+            "kind": {"group": "kubi.zone", "version": "v1alpha1", "kind": "Record"},
+            "resource": {"group": "kubi.zone", "version": "v1alpha1", "resource": "records"},
+            "operation": "CREATE",
+            "userInfo": {},
+            "name": "www",
+            "namespace": "dns"
+        }
+    }))
+    .unwrap();
+    let request: AdmissionRequest<DynamicObject> = review.try_into().unwrap();
+    let findings = || Findings {
+        denied: vec!["invalid rdata".to_string()],
+        warnings: vec!["ttl is raised".to_string()],
+    };
+
+    let response = findings().respond(&request, false);
+    assert!(!response.allowed);
+    assert_eq!(response.result.message, "invalid rdata");
+    assert_eq!(response.warnings, Some(vec!["ttl is raised".to_string()]));
+
+    // Reasons for denying are only passed on as warnings.
+    let response = findings().respond(&request, true);
+    assert!(response.allowed);
+    assert_eq!(
+        response.warnings,
+        Some(vec![
+            "ttl is raised".to_string(),
+            "invalid rdata".to_string()
+        ])
+    );
+
+    let response = Findings::default().respond(&request, false);
+    assert!(response.allowed);
+    assert_eq!(response.warnings, None);
+}
+
+#[cfg(test)]
+#[test]
+fn invalid_certificates() {
+    let dir = std::env::temp_dir().join(format!("webhook-{:08x}", rand::random::<u32>()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (cert, key) = (dir.join("tls.crt"), dir.join("tls.key"));
+
+    assert!(matches!(
+        server_config(&cert, &key),
+        Err(Error::Io(path, _)) if path == cert
+    ));
+
+    std::fs::write(&cert, "").unwrap();
+    std::fs::write(&key, "not a pem file\n").unwrap();
+    assert!(matches!(
+        server_config(&cert, &key),
+        Err(Error::NoPrivateKey(path)) if path == key
+    ));
+    assert!(Tls::load(cert, key).is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
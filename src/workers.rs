@@ -0,0 +1,180 @@
+//! Cloudflare Workers serving hostnames of Records, either through a custom
+//! domain or a route, declared through annotations and synced with `--workers`.
+//!
+//! The custom domain and route created for each Record are published in an
+//! annotation of the Record, which also tells the controller which of them it
+//! manages.
+
+use kube::ResourceExt as _;
+use kubizone_common::FullyQualifiedDomainName;
+use kubizone_crds::v1alpha1::{DomainExt as _, Record, ZoneEntry};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{crds::GROUP, load_balancer::hostname, proxy::is_proxiable};
+
+/// Annotation on a Record serving its hostname from a worker through a custom
+/// domain, as `<service>` or `<service>@<environment>`.
+///
+/// Cloudflare creates the DNS record of custom domains itself, so the entries
+/// of the hostname are not synced as plain records.
+pub fn worker_annotation() -> String {
+    format!("{GROUP}/worker")
+}
+
+/// Annotation on a Record routing requests to a worker script, as `<script>`
+/// for all paths of its hostname, or `<pattern>=<script>` such as
+/// `api.example.com/v1/*=api-v1`.
+///
+/// Routes only apply to proxied records.
+pub fn worker_route_annotation() -> String {
+    format!("{GROUP}/worker-route")
+}
+
+/// Annotation the [`WorkerStatus`] of a Record is stored in, as JSON.
+pub fn status_annotation() -> String {
+    format!("{GROUP}/workers-status")
+}
+
+/// Custom domain and route created for a Record.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<DomainStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<RouteStatus>,
+    /// Routes of earlier patterns, or of a removed annotation, which have not
+    /// been deleted yet since records are only deleted in delete mode.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_routes: Vec<RouteStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainStatus {
+    pub id: String,
+    pub service: String,
+    pub environment: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteStatus {
+    pub id: String,
+    pub pattern: String,
+    pub script: String,
+}
+
+/// Custom domain and route declared by a Record.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Declared {
+    /// Hostname of the record, without a trailing dot.
+    pub hostname: String,
+    /// Service and environment of the custom domain.
+    pub domain: Option<(String, String)>,
+    /// Pattern and script of the route.
+    pub route: Option<(String, String)>,
+}
+
+/// Custom domain and route declared by the record, if it has a fully
+/// qualified domain name yet.
+pub fn declared(record: &Record) -> Option<Declared> {
+    let hostname = hostname(record.fqdn()?);
+    let annotations = record.annotations();
+
+    let domain = annotations
+        .get(&worker_annotation())
+        .map(|value| match value.split_once('@') {
+            Some((service, environment)) => (service.to_string(), environment.to_string()),
+            None => (value.to_string(), "production".to_string()),
+        });
+
+    let route =
+        annotations
+            .get(&worker_route_annotation())
+            .map(|value| match value.rsplit_once('=') {
+                Some((pattern, script)) => (pattern.to_string(), script.to_string()),
+                None => (format!("{hostname}/*"), value.to_string()),
+            });
+
+    Some(Declared {
+        hostname,
+        domain,
+        route,
+    })
+}
+
+/// Custom domain and route created for the record, as last published.
+pub fn current(record: &Record) -> WorkerStatus {
+    let annotation = status_annotation();
+    let Some(value) = record.annotations().get(&annotation) else {
+        return WorkerStatus::default();
+    };
+
+    match serde_json::from_str(value) {
+        Ok(current) => current,
+        Err(err) => {
+            warn!(
+                "ignoring {annotation} annotation of record {}, since it is not valid: {err}",
+                record.name_any()
+            );
+            WorkerStatus::default()
+        }
+    }
+}
+
+/// Hostnames of the records served through worker custom domains.
+pub fn custom_domains(records: &[Record]) -> Vec<FullyQualifiedDomainName> {
+    let annotation = worker_annotation();
+
+    records
+        .iter()
+        .filter(|record| record.annotations().contains_key(&annotation))
+        .filter_map(|record| record.fqdn().cloned())
+        .collect()
+}
+
+/// Returns true if the entry is served by a worker custom domain, rather than a plain record.
+pub fn is_custom_domain(custom_domains: &[FullyQualifiedDomainName], entry: &ZoneEntry) -> bool {
+    is_proxiable(entry.type_) && custom_domains.contains(&entry.fqdn)
+}
+
+#[cfg(test)]
+#[test]
+fn declared_workers() {
+    let record = |annotations: serde_json::Value| -> Record {
+        serde_json::from_value(serde_json::json!({
+            "apiVersion": "kubi.zone/v1alpha1",
+            "kind": "Record",
+            "metadata": {"name": "api", "annotations": annotations},
+            "spec": {"domainName": "api.kubi.zone.", "type": "A", "rdata": "192.0.2.1"},
+            "status": {"fqdn": "api.kubi.zone."}
+        }))
+        .unwrap()
+    };
+
+    let both = declared(&record(serde_json::json!({
+        worker_annotation(): "api@staging",
+        worker_route_annotation(): "api-v1",
+    })))
+    .unwrap();
+    assert_eq!(
+        both.domain,
+        Some(("api".to_string(), "staging".to_string()))
+    );
+    assert_eq!(
+        both.route,
+        Some(("api.kubi.zone/*".to_string(), "api-v1".to_string()))
+    );
+
+    let route = declared(&record(serde_json::json!({
+        worker_route_annotation(): "api.kubi.zone/v1/*=api-v1",
+    })))
+    .unwrap();
+    assert_eq!(route.domain, None);
+    assert_eq!(
+        route.route,
+        Some(("api.kubi.zone/v1/*".to_string(), "api-v1".to_string()))
+    );
+}
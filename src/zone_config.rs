@@ -0,0 +1,250 @@
+//! Per-zone defaults declared through [`CloudflareZoneConfig`] resources,
+//! replacing flags and annotations repeated across zones.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, RwLock},
+};
+
+use futures::{Stream, StreamExt as _, TryStreamExt as _};
+use kube::{
+    runtime::{
+        watcher::{self, Event},
+        WatchStreamExt as _,
+    },
+    Api, Client as KubeClient, ResourceExt as _,
+};
+use kubizone_common::FullyQualifiedDomainName;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::{
+    account::{DynamicAccounts, Loader, Settings},
+    crds::{
+        CloudflareCredentialSpec, CloudflareZoneConfig, CloudflareZoneConfigSpec, SecretKeyRef,
+    },
+    credentials::SecretRef,
+};
+
+/// Defaults of a zone, merged from every config matching it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ZoneConfig {
+    pub proxied: Option<bool>,
+    pub min_ttl: Option<u32>,
+    pub prune: Option<bool>,
+    pub account_id: Option<String>,
+    pub protected: Vec<String>,
+}
+
+impl ZoneConfig {
+    /// Merge the configs matching the fqdn, in the order given.
+    ///
+    /// The first config setting a field wins, while the protected records
+    /// of all of them are combined.
+    fn merge<'a>(
+        configs: impl IntoIterator<Item = &'a CloudflareZoneConfigSpec>,
+        fqdn: &FullyQualifiedDomainName,
+    ) -> Self {
+        configs
+            .into_iter()
+            .filter(|config| {
+                config.zones.is_empty() || config.zones.iter().any(|pattern| pattern.matches(fqdn))
+            })
+            .fold(ZoneConfig::default(), |mut merged, config| {
+                merged.proxied = merged.proxied.or(config.proxied);
+                merged.min_ttl = merged.min_ttl.or(config.min_ttl);
+                merged.prune = merged.prune.or(config.prune);
+                merged.account_id = merged.account_id.or_else(|| config.account_id.clone());
+                merged.protected.extend(config.protected.iter().cloned());
+                merged
+            })
+    }
+}
+
+/// Configs of the cluster, kept up to date by [`ZoneConfigs::watch`].
+#[derive(Clone)]
+pub struct ZoneConfigs {
+    configs: Arc<RwLock<BTreeMap<String, CloudflareZoneConfigSpec>>>,
+    /// Accounts for the credentials referenced by configs, keyed by `cloudflarezoneconfig/name`.
+    accounts: DynamicAccounts,
+    /// Number of times the configs changed, so zones can be reconciled again.
+    generation: Arc<watch::Sender<u64>>,
+}
+
+impl Default for ZoneConfigs {
+    fn default() -> Self {
+        ZoneConfigs {
+            configs: Arc::default(),
+            accounts: DynamicAccounts::default(),
+            generation: Arc::new(watch::channel(0).0),
+        }
+    }
+}
+
+impl ZoneConfigs {
+    /// Defaults of the zone with the fqdn, merged from the configs matching
+    /// it in order of their names.
+    pub fn get(&self, fqdn: &FullyQualifiedDomainName) -> ZoneConfig {
+        ZoneConfig::merge(self.configs.read().unwrap().values(), fqdn)
+    }
+
+    /// Accounts for the credentials referenced by configs.
+    pub fn accounts(&self) -> &DynamicAccounts {
+        &self.accounts
+    }
+
+    /// Stream yielding every time the configs changed, starting after the
+    /// current state.
+    pub fn changes(&self) -> impl Stream<Item = ()> + Send + 'static {
+        let mut generation = self.generation.subscribe();
+        generation.mark_unchanged();
+
+        futures::stream::unfold(generation, |mut generation| async move {
+            generation.changed().await.ok()?;
+            Some(((), generation))
+        })
+    }
+
+    /// Wait until the configs have been listed at least once.
+    pub async fn listed(&self) {
+        let _ = self
+            .generation
+            .subscribe()
+            .wait_for(|generation| *generation > 0)
+            .await;
+    }
+
+    /// Watch [`CloudflareZoneConfig`] resources across the cluster, keeping
+    /// the configs and the accounts they reference in sync with them.
+    pub async fn watch(self, kube: KubeClient, settings: Settings) {
+        let api = Api::<CloudflareZoneConfig>::all(kube.clone());
+        let mut events = watcher::watcher(api, watcher::Config::default())
+            .default_backoff()
+            .boxed();
+
+        // Configs seen since the watch (re)started, used to prune deleted
+        // resources once the initial listing is done.
+        let mut seen = None;
+        let mut loader = Loader::default();
+
+        loop {
+            let config = match events.try_next().await {
+                Ok(Some(Event::Init)) => {
+                    seen = Some(BTreeSet::new());
+                    continue;
+                }
+                Ok(Some(Event::InitDone)) => {
+                    if let Some(seen) = seen.take() {
+                        self.configs.write().unwrap().retain(|name, _| {
+                            let keep = seen.contains(name);
+                            if !keep {
+                                info!("removing zone config {name}, which no longer exists");
+                            }
+                            keep
+                        });
+                        loader.retain(|key| seen.contains(config_name(key)));
+                        self.accounts
+                            .write()
+                            .unwrap()
+                            .retain(|key, _| seen.contains(config_name(key)));
+                    }
+                    self.changed();
+                    continue;
+                }
+                Ok(Some(Event::Delete(config))) => {
+                    let name = config.name_any();
+                    info!("removing zone config {name}");
+                    self.configs.write().unwrap().remove(&name);
+                    loader.cancel(&account_key(&name));
+                    self.accounts.write().unwrap().remove(&account_key(&name));
+                    self.changed();
+                    continue;
+                }
+                Ok(Some(Event::Apply(config) | Event::InitApply(config))) => config,
+                Ok(None) => return,
+                Err(err) => {
+                    warn!("watching cloudflare zone configs failed: {err}");
+                    continue;
+                }
+            };
+
+            let name = config.name_any();
+            if let Some(seen) = seen.as_mut() {
+                seen.insert(name.clone());
+            }
+
+            if self.configs.read().unwrap().get(&name) == Some(&config.spec) {
+                continue;
+            }
+
+            let key = account_key(&name);
+            match &config.spec.credential_ref {
+                Some(credential) => {
+                    let secret = SecretRef {
+                        namespace: credential.namespace.clone(),
+                        name: credential.name.clone(),
+                        key: credential.key.clone(),
+                    };
+                    let spec = CloudflareCredentialSpec {
+                        secret_ref: SecretKeyRef {
+                            name: credential.name.clone(),
+                            key: credential.key.clone(),
+                        },
+                        zones: config.spec.zones.clone(),
+                    };
+                    loader.load(&kube, &self.accounts, key, secret, spec, &settings);
+                }
+                None => {
+                    loader.cancel(&key);
+                    self.accounts.write().unwrap().remove(&key);
+                }
+            }
+
+            info!("loaded zone config {name}");
+            self.configs.write().unwrap().insert(name, config.spec);
+
+            // Changes during the initial listing are published once it is done.
+            if seen.is_none() {
+                self.changed();
+            }
+        }
+    }
+
+    fn changed(&self) {
+        self.generation.send_modify(|generation| *generation += 1);
+    }
+}
+
+fn account_key(name: &str) -> String {
+    format!("cloudflarezoneconfig/{name}")
+}
+
+fn config_name(key: &str) -> &str {
+    key.trim_start_matches("cloudflarezoneconfig/")
+}
+
+#[cfg(test)]
+#[test]
+fn merge_matching_configs() {
+    let configs: Vec<CloudflareZoneConfigSpec> = serde_json::from_value(serde_json::json!([
+        {"zones": ["*.kubi.zone."], "proxied": true, "protected": ["kubi.zone. MX"]},
+        {"zones": ["example.org."], "minTtl": 600},
+        {"minTtl": 300, "proxied": false, "prune": true, "protected": ["kubi.zone. TXT"]}
+    ]))
+    .unwrap();
+
+    let fqdn = FullyQualifiedDomainName::try_from("dev.kubi.zone.").unwrap();
+    assert_eq!(
+        ZoneConfig::merge(&configs, &fqdn),
+        ZoneConfig {
+            proxied: Some(true),
+            min_ttl: Some(300),
+            prune: Some(true),
+            account_id: None,
+            protected: vec!["kubi.zone. MX".to_string(), "kubi.zone. TXT".to_string()],
+        }
+    );
+
+    let fqdn = FullyQualifiedDomainName::try_from("example.org.").unwrap();
+    assert_eq!(ZoneConfig::merge(&configs, &fqdn).min_ttl, Some(600));
+}
@@ -0,0 +1,103 @@
+//! Cloudflare zone settings declared through annotations on Zones, and synced
+//! alongside their records with `--sync-zone-settings`.
+
+use kube::ResourceExt as _;
+use kubizone_crds::v1alpha1::Zone;
+use tracing::warn;
+
+use crate::crds::GROUP;
+
+/// A cloudflare zone setting, managed through an annotation of the same name.
+pub struct Setting {
+    /// Id of the setting in the cloudflare api.
+    pub id: &'static str,
+    annotation: &'static str,
+    values: &'static [&'static str],
+}
+
+/// Settings which can be managed through annotations.
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        id: "ssl",
+        annotation: "ssl",
+        values: &["off", "flexible", "full", "strict"],
+    },
+    Setting {
+        id: "always_use_https",
+        annotation: "always-use-https",
+        values: &["on", "off"],
+    },
+    Setting {
+        id: "min_tls_version",
+        annotation: "min-tls-version",
+        values: &["1.0", "1.1", "1.2", "1.3"],
+    },
+];
+
+impl Setting {
+    /// Annotation setting the value of this setting for the cloudflare zone,
+    /// such as `cloudflare.kubi.zone/ssl: strict`.
+    pub fn annotation(&self) -> String {
+        format!("{GROUP}/{}", self.annotation)
+    }
+
+    /// Value of the setting in the cloudflare api, accepting `true` and `false` for `on` and `off`.
+    fn parse(&self, value: &str) -> Option<&'static str> {
+        let value = match value {
+            "true" => "on",
+            "false" => "off",
+            value => value,
+        };
+
+        self.values
+            .iter()
+            .find(|allowed| allowed.eq_ignore_ascii_case(value))
+            .copied()
+    }
+}
+
+/// Settings declared through the annotations of the zone, along with their values.
+pub fn desired(zone: &Zone) -> Vec<(&'static Setting, &'static str)> {
+    SETTINGS
+        .iter()
+        .filter_map(|setting| {
+            let annotation = setting.annotation();
+            let value = zone.annotations().get(&annotation)?;
+
+            match setting.parse(value) {
+                Some(parsed) => Some((setting, parsed)),
+                None => {
+                    warn!(
+                        "ignoring {annotation} annotation of zone {}, since {value:?} is not one of {}",
+                        zone.name_any(),
+                        setting.values.join(", ")
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn parse_settings() {
+    let zone: Zone = serde_json::from_value(serde_json::json!({
+        "apiVersion": "kubi.zone/v1alpha1",
+        "kind": "Zone",
+        "metadata": {"name": "kubi-zone", "namespace": "dns", "annotations": {
+            format!("{GROUP}/ssl"): "Strict",
+            format!("{GROUP}/always-use-https"): "true",
+            format!("{GROUP}/min-tls-version"): "1.4"
+        }},
+        "spec": {"domainName": "kubi.zone.", "delegations": []}
+    }))
+    .unwrap();
+
+    let desired = desired(&zone)
+        .into_iter()
+        .map(|(setting, value)| (setting.id, value))
+        .collect::<Vec<_>>();
+
+    assert_eq!(desired, [("ssl", "strict"), ("always_use_https", "on")]);
+}